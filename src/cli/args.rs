@@ -1,6 +1,19 @@
+use super::logging::LogFormat;
 use super::parameters::ParameterTable;
-use clap::Parser;
+use crate::config::{ConfigFormat, ConfigLoader, ProxyConfig, ProxyType};
+use crate::core::{ScoreWeights, SortKey, SpeedTestResult};
+use crate::network::{
+    BackendKind, DEFAULT_AUTO_SIZE_TARGET, IpVersion, LatencyMethod, LatencyMode, LatencyTarget,
+    UploadPayload,
+};
+use crate::output::{
+    DEFAULT_TIMESTAMP_FORMAT, DisplayTimezone, ExportFormat, OutputFormat, SpeedTiers, WebhookSink,
+};
+use clap::{CommandFactory, Parser};
+use std::io::IsTerminal;
+use std::net::IpAddr;
 use std::time::Duration;
+use tracing::{info, warn};
 
 /// Command line arguments for the mihomo speedtest tool
 #[derive(Parser, Debug)]
@@ -9,10 +22,14 @@ use std::time::Duration;
 #[command(author = env!("CARGO_PKG_AUTHORS"))]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 pub struct Cli {
-    /// Config file path or HTTP(S) URL
+    /// Config file path, HTTP(S) URL, or - to read from stdin
     #[arg(short = 'c', long = "config", required_unless_present_any = ["show_author", "show_about"])]
     pub config_paths: Option<String>,
 
+    /// How to read --config: detect it, or force a Clash config, a base64 subscription or a plain proxy list
+    #[arg(long = "config-format", value_enum, default_value_t = ConfigFormat::Auto)]
+    pub config_format: ConfigFormat,
+
     /// Filter proxies by name using regex
     #[arg(short = 'f', long = "filter", default_value = ".+")]
     pub filter_regex: String,
@@ -21,18 +38,108 @@ pub struct Cli {
     #[arg(short = 'b', long = "block")]
     pub block_keywords: Option<String>,
 
-    /// Speed test server URL
+    /// Only test the proxy with exactly this name (repeatable), ignoring the other filters
+    #[arg(long = "only", value_name = "NAME")]
+    pub only: Vec<String>,
+
+    /// Only test these proxy types (comma separated, e.g. ss,vmess)
+    #[arg(long = "include-types", value_delimiter = ',', value_parser = parse_proxy_type)]
+    pub include_types: Vec<ProxyType>,
+
+    /// Skip these proxy types (comma separated, e.g. wireguard)
+    #[arg(long = "exclude-types", value_delimiter = ',', value_parser = parse_proxy_type)]
+    pub exclude_types: Vec<ProxyType>,
+
+    /// Remove duplicate proxies (same server, port, type and credentials) before testing
+    #[arg(long = "dedup")]
+    pub dedup: bool,
+
+    /// Only test a random sample of this many proxies (after filtering)
+    #[arg(long = "sample")]
+    pub sample: Option<usize>,
+
+    /// Seed for --sample so the same proxies are picked again (default: random, logged)
+    #[arg(long = "sample-seed", requires = "sample")]
+    pub sample_seed: Option<u64>,
+
+    /// Abort if any loaded proxy is missing required fields instead of skipping it
+    #[arg(long = "strict")]
+    pub strict: bool,
+
+    /// Exit 2 when no proxy passes the criteria and 3 when there are no proxies to test
+    #[arg(long = "strict-exit")]
+    pub strict_exit: bool,
+
+    /// Override the SNI of every loaded proxy (for debugging broken subscriptions)
+    #[arg(long = "force-sni")]
+    pub force_sni: Option<String>,
+
+    /// Skip TLS certificate verification on every loaded proxy
+    #[arg(long = "force-skip-cert-verify")]
+    pub force_skip_cert_verify: bool,
+
+    /// Skip checking that the speed test server is reachable before testing
+    #[arg(long = "skip-server-check")]
+    pub skip_server_check: bool,
+
+    /// Append each result to this JSON lines file and resume from it on the next run
+    #[arg(long = "checkpoint")]
+    pub checkpoint: Option<String>,
+
+    /// Ignore an existing checkpoint and test every proxy again
+    #[arg(long = "restart", requires = "checkpoint")]
+    pub restart: bool,
+
+    /// Load and filter proxies, print what would be tested and exit without testing
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Speed test server URL, comma-separated URLs are tried in order until one succeeds
     #[arg(long = "server-url", default_value = "https://speed.cloudflare.com")]
     pub server_url: String,
 
-    /// Download size in MB for testing (supports decimal like 0.5)
+    /// Speed test server flavor behind --server-url
+    #[arg(long = "backend", value_enum, default_value_t = BackendKind::Cloudflare)]
+    pub backend: BackendKind,
+
+    /// Download size in MB for testing (supports decimals like 0.5 or 0,5 and units like 500KB, 1GiB)
     #[arg(long = "download-size", default_value = "50", value_parser = parse_size_mb)]
     pub download_size: usize,
 
-    /// Upload size in MB for testing (supports decimal like 0.5)
+    /// Download for this long instead of a fixed size (seconds or duration like "10s"), overrides --download-size
+    #[arg(long = "download-duration", value_parser = parse_duration)]
+    pub download_duration: Option<Duration>,
+
+    /// Download this long before --download-duration starts measuring, so TCP slow-start isn't counted (e.g. "1s")
+    #[arg(long = "warmup", default_value = "0", value_parser = parse_duration, requires = "download_duration")]
+    pub warmup: Duration,
+
+    /// Ramp download connections from 1, doubling each --download-duration window (default 2s) until throughput stops improving
+    #[arg(long = "adaptive-download")]
+    pub adaptive_download: bool,
+
+    /// Probe 1 MB after the latency test and pick the download size that lasts about --auto-size-target, overrides --download-size
+    #[arg(long = "auto-size", conflicts_with_all = ["download_duration", "adaptive_download"])]
+    pub auto_size: bool,
+
+    /// Download duration --auto-size aims for (seconds or duration like "10s")
+    #[arg(long = "auto-size-target", default_value = "10", value_parser = parse_duration)]
+    pub auto_size_target: Duration,
+
+    /// Upload size in MB for testing (supports decimals like 0.5 or 0,5 and units like 500KB, 1GiB)
     #[arg(long = "upload-size", default_value = "20", value_parser = parse_size_mb)]
     pub upload_size: usize,
 
+    /// Timeout for connecting to a proxy in seconds (or duration like "3s"), so dead proxies fail fast.
+    /// With --use-mihomo it only limits the connection to mihomo's local listener, not
+    /// mihomo's own dial to the proxy
+    #[arg(long = "connect-timeout", default_value = "5", value_parser = parse_duration)]
+    pub connect_timeout: Duration,
+
+    /// Abandon a download connection that receives no data for this long (seconds or duration like "3s"), so one stuck connection doesn't stretch the measured duration
+    #[arg(long = "stall-timeout", default_value = "5", value_parser = parse_duration)]
+    pub stall_timeout: Duration,
+
     /// Download timeout in seconds (or duration like "10s", "1m")
     #[arg(long = "download-timeout", default_value = "10", value_parser = parse_duration)]
     pub download_timeout: Duration,
@@ -45,26 +152,162 @@ pub struct Cli {
     #[arg(long = "timeout", value_parser = parse_duration, help = "Set both download and upload timeout (overrides --download-timeout and --upload-timeout)")]
     pub timeout: Option<Duration>,
 
+    /// Overall wall-clock budget per proxy across latency, download and upload (seconds or duration like "45s")
+    #[arg(long = "max-proxy-time", value_parser = parse_duration)]
+    pub max_proxy_time: Option<Duration>,
+
+    /// Cap on download/upload connections open at once across all proxies tested concurrently
+    #[arg(
+        long = "max-total-connections",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub max_total_connections: Option<usize>,
+
     /// Number of concurrent connections for testing
     #[arg(long = "concurrent", default_value = "4")]
     pub concurrent: usize,
 
+    /// Number of connections the upload is split across
+    #[arg(long = "concurrent-upload", default_value = "1")]
+    pub concurrent_upload: usize,
+
     /// Output config file path
     #[arg(short = 'o', long = "output")]
     pub output: Option<String>,
 
+    /// Format of the exported config, picked from the --output extension when unset (.json sing-box, .conf Surge, else Clash)
+    #[arg(long = "output-format", value_enum)]
+    pub export_format: Option<ExportFormat>,
+
+    /// Also export proxies that failed or didn't meet the criteria, renamed with --dead-prefix
+    #[arg(long = "include-failed")]
+    pub include_failed: bool,
+
+    /// Name prefix for failed proxies exported with --include-failed (empty keeps names)
+    #[arg(long = "dead-prefix", default_value = "[DEAD] ")]
+    pub dead_prefix: String,
+
+    /// Export only passing proxies to Clash with generated groups: Fast and Medium by
+    /// download speed tier, Auto url-test over all of them (replaces the original groups and rules)
+    #[arg(long = "export-only-passing-groups", conflicts_with = "include_failed")]
+    pub export_only_passing_groups: bool,
+
+    /// Leave an existing Clash --output untouched when the exported config is unchanged,
+    /// so cron loops don't trigger needless reloads
+    #[arg(long = "only-changed")]
+    pub only_changed: bool,
+
+    /// Download speed (MB/s) from which a proxy joins the generated Fast group, above --medium-tier
+    #[arg(long = "fast-tier", default_value = "20", value_parser = parse_speed_mb)]
+    pub fast_tier: f64,
+
+    /// Download speed (MB/s) from which a proxy joins the generated Medium group
+    #[arg(long = "medium-tier", default_value = "5", value_parser = parse_speed_mb)]
+    pub medium_tier: f64,
+
+    /// Write results as a Prometheus textfile to this path
+    #[arg(long = "prometheus-out")]
+    pub prometheus_out: Option<String>,
+
+    /// Append each run's results with a timestamp to this JSON history file
+    #[arg(long = "output-append")]
+    pub output_append: Option<String>,
+
+    /// POST the results as JSON to this URL once testing finishes
+    #[arg(long = "webhook", value_parser = parse_http_url)]
+    pub webhook: Option<String>,
+
+    /// Extra header sent with the webhook POST, as "Name: value" (repeatable)
+    #[arg(long = "webhook-header", value_parser = parse_header, requires = "webhook")]
+    pub webhook_header: Vec<(String, String)>,
+
+    /// Upload test body: zeros or incompressible pseudo-random bytes
+    #[arg(long = "upload-payload", value_enum, default_value_t = UploadPayload::Zero)]
+    pub upload_payload: UploadPayload,
+
     /// Filter out proxies with latency greater than this (milliseconds or duration like "800ms")
     #[arg(long = "max-latency", default_value = "800", value_parser = parse_latency_duration)]
     pub max_latency: Duration,
 
-    /// Filter out proxies with download speed less than this (MB/s)
-    #[arg(long = "min-download-speed", default_value = "5")]
+    /// Filter out proxies with download speed less than this (MB/s, or with a unit like 500KB/s)
+    #[arg(long = "min-download-speed", default_value = "5", value_parser = parse_speed_mb)]
     pub min_download_speed: f64,
 
-    /// Filter out proxies with upload speed less than this (MB/s)
-    #[arg(long = "min-upload-speed", default_value = "2")]
+    /// Filter out proxies with upload speed less than this (MB/s, or with a unit like 500KB/s)
+    #[arg(long = "min-upload-speed", default_value = "2", value_parser = parse_speed_mb)]
     pub min_upload_speed: f64,
 
+    /// Stop at the first proxy that meets --max-latency and the minimum speeds, reporting just that one
+    #[arg(long = "first-match", alias = "fail-fast")]
+    pub first_match: bool,
+
+    /// Number of latency pings per proxy
+    #[arg(long = "ping-count", default_value = "6", value_parser = parse_ping_count)]
+    pub ping_count: usize,
+
+    /// Also measure latency while a download saturates the proxy (bufferbloat), shown as a Loaded column
+    #[arg(long = "latency-under-load")]
+    pub latency_under_load: bool,
+
+    /// IP family used to reach proxies (4, 6 or auto)
+    #[arg(long = "ip-version", value_enum, default_value_t = IpVersion::Auto)]
+    pub ip_version: IpVersion,
+
+    /// Local IP the direct client binds to, to force test traffic out one interface
+    /// (overrides --ip-version for direct testing; mihomo uses interface-name instead)
+    #[arg(long = "bind-address")]
+    pub bind_address: Option<IpAddr>,
+
+    /// DNS server resolving proxy hostnames in direct testing, instead of the system
+    /// resolver. Each hostname is resolved once before testing and cached
+    #[arg(long = "dns-server")]
+    pub dns_server: Option<IpAddr>,
+
+    /// Open a new connection for every request instead of reusing pooled ones.
+    /// Latency then includes the TCP/TLS handshake each time (worst case) instead of
+    /// only the first request paying it
+    #[arg(long = "fresh-connections")]
+    pub fresh_connections: bool,
+
+    /// Latency measurement mode: HTTP round trips or raw TCP connect to the proxy
+    #[arg(long = "latency-mode", value_enum, default_value_t = LatencyMode::Http)]
+    pub latency_mode: LatencyMode,
+
+    /// Latency target: the speed test server through the proxy, or with `proxy` also
+    /// the TCP connect time to the proxy's own server:port, shown in a Proxy RTT column
+    #[arg(long = "latency-target", value_enum, default_value_t = LatencyTarget::Server)]
+    pub latency_target: LatencyTarget,
+
+    /// HTTP method of latency pings: GET the empty-body URL or HEAD the server root,
+    /// falling back to GET if the server rejects HEAD
+    #[arg(long = "latency-method", value_enum, default_value_t = LatencyMethod::Get)]
+    pub latency_method: LatencyMethod,
+
+    /// Retry the latency test this many times on connection or timeout errors
+    #[arg(long = "retries", default_value = "0")]
+    pub retries: usize,
+
+    /// Initial backoff between retries, doubled on each attempt (milliseconds or duration like "500ms")
+    #[arg(long = "retry-backoff", default_value = "500", value_parser = parse_latency_duration)]
+    pub retry_backoff: Duration,
+
+    /// Run the full test suite this many times and report per-proxy stability
+    #[arg(long = "repeat", default_value = "1", value_parser = parse_repeat)]
+    pub repeat: usize,
+
+    /// Re-test every interval (e.g. 60s) and redraw the results until Ctrl-C
+    #[arg(
+        long = "watch",
+        value_parser = parse_duration,
+        conflicts_with_all = ["repeat", "checkpoint", "dry_run", "output", "jsonl_output"]
+    )]
+    pub watch: Option<Duration>,
+
+    /// Browse the results in an interactive terminal table after testing, sortable by
+    /// latency, download, upload or name (needs stdout to be a TTY)
+    #[arg(long = "tui", conflicts_with_all = ["watch", "dry_run"])]
+    pub tui: bool,
+
     /// Fast mode: only test latency
     #[arg(long = "fast")]
     pub fast_mode: bool,
@@ -73,6 +316,10 @@ pub struct Cli {
     #[arg(long = "rename")]
     pub rename_nodes: bool,
 
+    /// MaxMind DB (.mmdb) used to resolve node locations when renaming
+    #[arg(long = "geoip-db")]
+    pub geoip_db: Option<String>,
+
     /// Enable Stash compatibility mode
     #[arg(long = "stash-compatible")]
     pub stash_compatible: bool,
@@ -81,14 +328,124 @@ pub struct Cli {
     #[arg(short = 'j', long = "json")]
     pub json_output: bool,
 
+    /// Previous JSON results to compare against (shows latency/speed deltas)
+    #[arg(long = "baseline")]
+    pub baseline: Option<String>,
+
+    /// Output results in CSV format
+    #[arg(long = "csv", conflicts_with = "json_output")]
+    pub csv_output: bool,
+
+    /// Output results as a markdown table for pasting into issues/PRs
+    #[arg(long = "markdown", conflicts_with_all = ["json_output", "csv_output"])]
+    pub markdown_output: bool,
+
+    /// Stream each result as one JSON line as soon as it completes (suppresses the table and summary)
+    #[arg(
+        long = "jsonl",
+        conflicts_with_all = ["json_output", "csv_output", "markdown_output"]
+    )]
+    pub jsonl_output: bool,
+
+    /// Output results in YAML format
+    #[arg(
+        long = "yaml",
+        conflicts_with_all = ["json_output", "csv_output", "markdown_output", "jsonl_output"]
+    )]
+    pub yaml_output: bool,
+
+    /// Show one aligned line per proxy instead of the full table, e.g. for CI logs
+    #[arg(
+        long = "compact",
+        conflicts_with_all = ["json_output", "csv_output", "markdown_output", "jsonl_output", "yaml_output"]
+    )]
+    pub compact_output: bool,
+
+    /// Sort results before display and export (failed proxies always last)
+    #[arg(long = "sort-by", value_enum)]
+    pub sort_by: Option<SortKey>,
+
+    /// Sort in descending order
+    #[arg(long = "sort-desc", requires = "sort_by")]
+    pub sort_desc: bool,
+
+    /// Print the N best proxies by weighted score after testing
+    #[arg(long = "pick-best")]
+    pub pick_best: Option<usize>,
+
+    /// Export only the proxies selected by --pick-best
+    #[arg(long = "export-best-only", requires = "pick_best")]
+    pub export_best_only: bool,
+
+    /// Export only the N fastest successful proxies (by download) of each detected region
+    #[arg(long = "top-per-region")]
+    pub top_per_region: Option<usize>,
+
+    /// Weight of latency in the proxy score
+    #[arg(long = "weight-latency", default_value = "0.4")]
+    pub weight_latency: f64,
+
+    /// Weight of download speed in the proxy score
+    #[arg(long = "weight-download", default_value = "0.3")]
+    pub weight_download: f64,
+
+    /// Weight of upload speed in the proxy score
+    #[arg(long = "weight-upload", default_value = "0.2")]
+    pub weight_upload: f64,
+
+    /// Weight of packet loss in the proxy score
+    #[arg(long = "weight-packet-loss", default_value = "0.1")]
+    pub weight_packet_loss: f64,
+
+    /// Show a Min/Max latency column in the results table
+    #[arg(long = "show-latency-range")]
+    pub show_latency_range: bool,
+
+    /// Show a Stability column with the download throughput variation
+    #[arg(long = "show-stability")]
+    pub show_stability: bool,
+
+    /// Show a TTFB column with the time to the first downloaded byte
+    #[arg(long = "show-ttfb")]
+    pub show_ttfb: bool,
+
+    /// Time zone of timestamps in the table and summary (JSON stays RFC3339 UTC)
+    #[arg(long = "timezone", value_enum, default_value_t = DisplayTimezone::Local)]
+    pub timezone: DisplayTimezone,
+
+    /// strftime format of timestamps in the table and summary
+    #[arg(long = "timestamp-format", default_value = DEFAULT_TIMESTAMP_FORMAT, value_parser = parse_timestamp_format)]
+    pub timestamp_format: String,
+
+    /// Disable colored output (also disabled by NO_COLOR or when stdout is not a terminal)
+    #[arg(long = "no-color")]
+    pub no_color: bool,
+
     /// Verbose output
     #[arg(short = 'v', long = "verbose")]
     pub verbose: bool,
 
+    /// Only print the final results: warnings and errors only, no parameter table,
+    /// progress bar or summary
+    #[arg(short = 'q', long = "quiet", conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also append logs to this file, keeping the console output
+    #[arg(long = "log-file")]
+    pub log_file: Option<String>,
+
+    /// Format of --log-file records
+    #[arg(long = "log-format", value_enum, default_value_t = LogFormat::Text, requires = "log_file")]
+    pub log_format: LogFormat,
+
     /// Maximum number of proxies to test concurrently
     #[arg(long = "max-concurrent", default_value = "1")]
     pub max_concurrent: usize,
 
+    /// With --max-concurrent, never test two proxies on the same server at once so they don't compete for its bandwidth
+    #[arg(long = "isolate-servers")]
+    pub isolate_servers: bool,
+
     /// Use mihomo process for real proxy testing
     #[arg(long = "use-mihomo")]
     pub use_mihomo: bool,
@@ -105,6 +462,26 @@ pub struct Cli {
     #[arg(long = "mihomo-proxy-port", default_value = "17890")]
     pub mihomo_proxy_port: u16,
 
+    /// URL used by mihomo's delay test (default: http://www.gstatic.com/generate_204)
+    #[arg(long = "delay-test-url", value_parser = parse_http_url)]
+    pub delay_test_url: Option<String>,
+
+    /// Keep the generated mihomo config after the run (for debugging)
+    #[arg(long = "keep-mihomo-config")]
+    pub keep_mihomo_config: bool,
+
+    /// Skip checking the generated config with `mihomo -t` before starting
+    #[arg(long = "skip-mihomo-validate")]
+    pub skip_mihomo_validate: bool,
+
+    /// Upload bandwidth hint in Mbps for Hysteria proxies that don't set `up` (mihomo only)
+    #[arg(long = "hysteria-up")]
+    pub hysteria_up: Option<u32>,
+
+    /// Download bandwidth hint in Mbps for Hysteria proxies that don't set `down` (mihomo only)
+    #[arg(long = "hysteria-down")]
+    pub hysteria_down: Option<u32>,
+
     /// Mihomo config directory
     #[arg(long = "mihomo-config-dir", default_value = "./mihomo-temp")]
     pub mihomo_config_dir: String,
@@ -118,20 +495,122 @@ pub struct Cli {
     pub show_about: bool,
 }
 
-/// Parse size in MB from string (supports decimal values like 0.5)
-fn parse_size_mb(s: &str) -> Result<usize, String> {
-    // Parse as a floating-point number in MB
-    let mb = s
-        .parse::<f64>()
-        .map_err(|e| format!("Invalid size format: {e}"))?;
+/// Bytes in the MB a bare number and the speeds shown in results stand for
+/// (1 MB = 1024 * 1024 bytes)
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+/// Parse an amount of data into bytes. A bare number is in MB of `BYTES_PER_MB`, as
+/// before units were accepted. KB/MB/GB are decimal (1000ⁿ) and KiB/MiB/GiB binary
+/// (1024ⁿ), and `,` works as the decimal separator (`0,5`).
+fn parse_bytes(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
 
-    if mb < 0.0 {
+    let value = number
+        .trim()
+        .replace(',', ".")
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid size format '{s}': {e}"))?;
+    if value < 0.0 {
         return Err("Size cannot be negative".to_string());
     }
 
-    // Convert MB to bytes (1 MB = 1024 * 1024 bytes)
-    let bytes = (mb * 1024.0 * 1024.0) as usize;
-    Ok(bytes)
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" => BYTES_PER_MB,
+        "b" => 1.0,
+        "kb" | "k" => 1e3,
+        "mb" | "m" => 1e6,
+        "gb" | "g" => 1e9,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        _ => {
+            return Err(format!(
+                "Unknown size unit '{unit}', use B, KB, MB, GB, KiB, MiB or GiB"
+            ));
+        }
+    };
+    Ok(value * multiplier)
+}
+
+/// Parse a size into bytes (a bare number is in MB, see `parse_bytes`)
+fn parse_size_mb(s: &str) -> Result<usize, String> {
+    parse_bytes(s).map(|bytes| bytes as usize)
+}
+
+/// Parse a speed into MB/s. A bare number is already in MB/s, a unit with an optional
+/// `/s` is converted (`500KB/s`, `1.5MiB`).
+fn parse_speed_mb(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let amount = s.strip_suffix("/s").unwrap_or(s);
+    parse_bytes(amount).map(|bytes| bytes / BYTES_PER_MB)
+}
+
+/// Parse a ping count, which must be at least 1
+fn parse_ping_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("Ping count must be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Invalid ping count: {e}")),
+    }
+}
+
+/// Parse a repeat count, which must be at least 1
+fn parse_repeat(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("Repeat count must be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Invalid repeat count: {e}")),
+    }
+}
+
+/// Parse a proxy type name, listing the valid names on error
+fn parse_proxy_type(s: &str) -> Result<ProxyType, String> {
+    s.trim().parse::<ProxyType>().map_err(|_| {
+        format!(
+            "Unknown proxy type '{}', valid values: {}",
+            s.trim(),
+            ProxyType::NAMES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })
+}
+
+/// Parse and validate an http(s) URL
+fn parse_http_url(s: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(s).map_err(|e| format!("Invalid URL: {e}"))?;
+
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err("URL must be an http(s) URL with a host".to_string());
+    }
+
+    Ok(s.to_string())
+}
+
+/// Parse a "Name: value" HTTP header
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| "Header must look like \"Name: value\"".to_string())?;
+    let name = name.trim();
+    reqwest::header::HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| format!("Invalid header name: {name}"))?;
+
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Parse a strftime format, rejecting specifiers chrono can't render
+fn parse_timestamp_format(s: &str) -> Result<String, String> {
+    use chrono::format::{Item, StrftimeItems};
+
+    if StrftimeItems::new(s).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Invalid strftime format '{s}'"));
+    }
+    Ok(s.to_string())
 }
 
 /// Parse latency duration from either milliseconds (number) or duration string
@@ -157,6 +636,230 @@ fn parse_duration(s: &str) -> Result<Duration, String> {
 }
 
 impl Cli {
+    /// Parse the command line, exiting on errors like `Cli::parse`, including the
+    /// constraints between flags that clap can't express
+    pub fn parse_validated() -> Self {
+        let args = Self::parse();
+        if let Err(e) = args.validate() {
+            e.exit();
+        }
+        args
+    }
+
+    /// Check the constraints between flags that clap can't express
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        // Tiers must be strictly increasing or the group labels make no sense
+        if self.medium_tier >= self.fast_tier {
+            return Err(Self::command().error(
+                clap::error::ErrorKind::ValueValidation,
+                format!(
+                    "--medium-tier ({} MB/s) must be below --fast-tier ({} MB/s)",
+                    self.medium_tier, self.fast_tier
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Output format selected by the CLI flags
+    pub fn output_format(&self) -> OutputFormat {
+        if self.json_output {
+            OutputFormat::Json
+        } else if self.csv_output {
+            OutputFormat::Csv
+        } else if self.markdown_output {
+            OutputFormat::Markdown
+        } else if self.jsonl_output {
+            OutputFormat::JsonLines
+        } else if self.yaml_output {
+            OutputFormat::Yaml
+        } else if self.compact_output {
+            OutputFormat::Compact
+        } else {
+            OutputFormat::Table
+        }
+    }
+
+    /// Log level chosen by `--verbose` or `--quiet`
+    pub fn log_level(&self) -> tracing::Level {
+        if self.verbose {
+            tracing::Level::DEBUG
+        } else if self.quiet {
+            tracing::Level::WARN
+        } else {
+            tracing::Level::INFO
+        }
+    }
+
+    /// Whether to color the results: human output to a terminal, unless --no-color or NO_COLOR is set
+    pub fn use_colors(&self) -> bool {
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        !self.no_color
+            && !no_color_env
+            && !self.output_format().is_machine_readable()
+            && std::io::stdout().is_terminal()
+    }
+
+    /// Export format chosen explicitly, otherwise inferred from the output path with
+    /// Clash as the fallback
+    pub fn export_format(&self) -> ExportFormat {
+        self.export_format.unwrap_or_else(|| {
+            self.output
+                .as_deref()
+                .map_or(ExportFormat::Clash, ExportFormat::from_path)
+        })
+    }
+
+    /// Narrow the proxies to a `--sample`, seeded by `--sample-seed` or the clock
+    pub fn apply_sample(&self, proxies: &mut Vec<ProxyConfig>) {
+        let Some(count) = self.sample else {
+            return;
+        };
+        let seed = self.sample_seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        ConfigLoader::sample_proxies(proxies, count, seed);
+    }
+
+    /// Apply `--filter`, `--block` and the type filters to the loaded proxies, logging the count after each stage.
+    /// `--only` replaces all of them.
+    pub fn apply_filters(&self, proxies: &mut Vec<ProxyConfig>) -> crate::Result<()> {
+        if !self.only.is_empty() {
+            return self.apply_only(proxies);
+        }
+
+        // Apply name filtering
+        if self.filter_regex != ".+" {
+            let regex = regex::Regex::new(&self.filter_regex)?;
+            let original_count = proxies.len();
+            proxies.retain(|p| regex.is_match(&p.name));
+            info!(
+                "🔍 Filtered by regex '{}': {} -> {} proxies",
+                self.filter_regex,
+                original_count,
+                proxies.len()
+            );
+        }
+
+        // Apply keyword blocking
+        if let Some(ref keywords) = self.block_keywords {
+            let block_list: Vec<String> = keywords
+                .split('|')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect();
+
+            if !block_list.is_empty() {
+                let original_count = proxies.len();
+                proxies.retain(|p| {
+                    !block_list
+                        .iter()
+                        .any(|keyword| p.name.to_lowercase().contains(keyword))
+                });
+                info!(
+                    "🚫 Blocked keywords: {} -> {} proxies",
+                    original_count,
+                    proxies.len()
+                );
+            }
+        }
+
+        // Apply proxy type filtering
+        if !self.include_types.is_empty() || !self.exclude_types.is_empty() {
+            let original_count = proxies.len();
+            proxies.retain(|p| {
+                let included = self.include_types.is_empty()
+                    || self
+                        .include_types
+                        .iter()
+                        .any(|t| t.same_protocol(&p.proxy_type));
+                included
+                    && !self
+                        .exclude_types
+                        .iter()
+                        .any(|t| t.same_protocol(&p.proxy_type))
+            });
+            info!(
+                "🧩 Filtered by type: {} -> {} proxies",
+                original_count,
+                proxies.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Keep only the proxies named by `--only`, failing with the available names when
+    /// none of them exists
+    fn apply_only(&self, proxies: &mut Vec<ProxyConfig>) -> crate::Result<()> {
+        let available: Vec<_> = proxies.iter().map(|p| p.name.clone()).collect();
+        proxies.retain(|p| self.only.contains(&p.name));
+
+        if proxies.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No proxy named {} in the loaded configs. Available proxies: {}",
+                self.only.join(", "),
+                available.join(", ")
+            ));
+        }
+        for name in self.only.iter().filter(|name| !available.contains(name)) {
+            warn!("No proxy named '{}' in the loaded configs", name);
+        }
+
+        info!(
+            "🎯 Testing only {}: {} -> {} proxies",
+            self.only.join(", "),
+            available.len(),
+            proxies.len()
+        );
+        Ok(())
+    }
+
+    /// Whether a result passes --max-latency and, outside fast mode, the minimum speeds
+    pub fn meets_criteria(&self, result: &SpeedTestResult) -> bool {
+        self.to_speedtest_config().meets_criteria(result)
+    }
+
+    /// Apply the --force-sni and --force-skip-cert-verify overrides to every proxy
+    pub fn apply_tls_overrides(&self, proxies: &mut [ProxyConfig]) {
+        if let Some(ref sni) = self.force_sni {
+            info!("🔐 Forcing SNI '{}' on {} proxies", sni, proxies.len());
+            for proxy in proxies.iter_mut() {
+                proxy.config.sni = Some(sni.clone());
+            }
+        }
+
+        if self.force_skip_cert_verify {
+            info!(
+                "🔓 Skipping certificate verification on {} proxies",
+                proxies.len()
+            );
+            for proxy in proxies.iter_mut() {
+                proxy.config.skip_cert_verify = Some(true);
+            }
+        }
+    }
+
+    /// Score weights selected by the --weight-* flags
+    pub fn score_weights(&self) -> ScoreWeights {
+        ScoreWeights {
+            latency: self.weight_latency,
+            download: self.weight_download,
+            upload: self.weight_upload,
+            packet_loss: self.weight_packet_loss,
+        }
+    }
+
+    /// Group tiers selected by --fast-tier and --medium-tier, in bytes per second
+    pub fn speed_tiers(&self) -> SpeedTiers {
+        SpeedTiers {
+            fast: self.fast_tier * BYTES_PER_MB,
+            medium: self.medium_tier * BYTES_PER_MB,
+        }
+    }
+
     /// Convert CLI args to SpeedTestConfig
     pub fn to_speedtest_config(&self) -> crate::core::SpeedTestConfig {
         // Determine timeout values based on user input
@@ -169,17 +872,50 @@ impl Cli {
             (self.download_timeout, self.upload_timeout)
         };
 
+        let mut server_urls = self
+            .server_url
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string);
+
         crate::core::SpeedTestConfig {
-            server_url: self.server_url.clone(),
+            server_url: server_urls.next().unwrap_or_default(),
+            fallback_server_urls: server_urls.collect(),
+            backend: self.backend,
             download_timeout,
             upload_timeout,
+            connect_timeout: self.connect_timeout,
             concurrent: self.concurrent,
             download_size: self.download_size,
+            download_duration: self.download_duration,
+            warmup: self.warmup,
+            stall_timeout: self.stall_timeout,
+            adaptive_download: self.adaptive_download,
+            auto_size: self.auto_size.then_some(self.auto_size_target),
             upload_size: self.upload_size,
+            concurrent_upload: self.concurrent_upload,
             max_latency: Some(self.max_latency),
             min_download_speed: Some(self.min_download_speed * 1024.0 * 1024.0), // Convert MB/s to bytes/s
             min_upload_speed: Some(self.min_upload_speed * 1024.0 * 1024.0), // Convert MB/s to bytes/s
             fast_mode: self.fast_mode,
+            latency_mode: self.latency_mode,
+            latency_method: self.latency_method,
+            latency_target: self.latency_target,
+            retries: self.retries,
+            retry_backoff: self.retry_backoff,
+            delay_test_url: self.delay_test_url.clone(),
+            latency_iterations: self.ping_count,
+            latency_under_load: self.latency_under_load,
+            per_proxy_timeout: self.max_proxy_time,
+            max_total_connections: self.max_total_connections,
+            ip_version: self.ip_version,
+            upload_payload: self.upload_payload,
+            fresh_connections: self.fresh_connections,
+            bind_address: self.bind_address,
+            dns_server: self.dns_server,
+            first_match: self.first_match,
+            isolate_servers: self.isolate_servers,
         }
     }
 
@@ -195,6 +931,13 @@ impl Cli {
             "Configuration file path or URL",
         );
 
+        table.add_string_param(
+            "config-format",
+            "auto",
+            &self.config_format.to_string(),
+            "How the config is interpreted",
+        );
+
         table.add_string_param(
             "filter-regex",
             ".+",
@@ -209,12 +952,73 @@ impl Cli {
             "Block proxies by keywords",
         );
 
+        let only = (!self.only.is_empty()).then(|| self.only.join(", "));
+        table.add_optional_string_param("only", None, &only, "Only test these proxies");
+
+        table.add_bool_param(
+            "dry-run",
+            false,
+            self.dry_run,
+            "Print the proxies that would be tested and exit",
+        );
+
+        table.add_bool_param(
+            "dedup",
+            false,
+            self.dedup,
+            "Remove duplicate proxies before testing",
+        );
+
+        table.add_bool_param("strict", false, self.strict, "Abort on invalid proxies");
+
+        table.add_bool_param(
+            "strict-exit",
+            false,
+            self.strict_exit,
+            "Exit non-zero when nothing passes",
+        );
+
+        table.add_optional_string_param(
+            "sample",
+            None,
+            &self.sample.map(|count| count.to_string()),
+            "Random sample size",
+        );
+
+        table.add_optional_string_param(
+            "sample-seed",
+            None,
+            &self.sample_seed.map(|seed| seed.to_string()),
+            "Seed for the random sample",
+        );
+
+        table.add_optional_string_param(
+            "force-sni",
+            None,
+            &self.force_sni,
+            "Override SNI on every proxy",
+        );
+
+        table.add_bool_param(
+            "force-skip-cert-verify",
+            false,
+            self.force_skip_cert_verify,
+            "Skip certificate verification on every proxy",
+        );
+
         // Network configuration
         table.add_string_param(
             "server-url",
             "https://speed.cloudflare.com",
             &self.server_url,
-            "Speed test server URL",
+            "Speed test server URLs, tried in order",
+        );
+
+        table.add_string_param(
+            "backend",
+            "cloudflare",
+            &self.backend.to_string(),
+            "Speed test server flavor",
         );
 
         table.add_numeric_param(
@@ -224,6 +1028,41 @@ impl Cli {
             "Download size in MB for testing",
         );
 
+        table.add_optional_duration_param(
+            "download-duration",
+            None,
+            self.download_duration,
+            "Download time budget (overrides download-size)",
+        );
+
+        table.add_duration_param(
+            "warmup",
+            Duration::ZERO,
+            self.warmup,
+            "Discarded start of timed downloads",
+        );
+
+        table.add_bool_param(
+            "adaptive-download",
+            false,
+            self.adaptive_download,
+            "Ramp download concurrency to the peak",
+        );
+
+        table.add_bool_param(
+            "auto-size",
+            false,
+            self.auto_size,
+            "Pick download size from a probe",
+        );
+
+        table.add_duration_param(
+            "auto-size-target",
+            DEFAULT_AUTO_SIZE_TARGET,
+            self.auto_size_target,
+            "Download duration auto-size aims for",
+        );
+
         table.add_numeric_param(
             "upload-size",
             20_usize,
@@ -231,6 +1070,34 @@ impl Cli {
             "Upload size in MB for testing",
         );
 
+        table.add_optional_string_param(
+            "checkpoint",
+            None,
+            &self.checkpoint,
+            "Resumable progress checkpoint file",
+        );
+
+        table.add_bool_param(
+            "restart",
+            false,
+            self.restart,
+            "Ignore an existing checkpoint",
+        );
+
+        table.add_bool_param(
+            "skip-server-check",
+            false,
+            self.skip_server_check,
+            "Skip the speed test server reachability check",
+        );
+
+        table.add_string_param(
+            "upload-payload",
+            "zero",
+            &self.upload_payload.to_string(),
+            "Upload body (zero or random)",
+        );
+
         // Timeout configuration
         table.add_duration_param(
             "download-timeout",
@@ -246,6 +1113,20 @@ impl Cli {
             "Upload timeout",
         );
 
+        table.add_duration_param(
+            "connect-timeout",
+            Duration::from_secs(5),
+            self.connect_timeout,
+            "Proxy connect timeout",
+        );
+
+        table.add_duration_param(
+            "stall-timeout",
+            Duration::from_secs(5),
+            self.stall_timeout,
+            "Download stall timeout",
+        );
+
         table.add_optional_duration_param(
             "timeout",
             None,
@@ -253,6 +1134,13 @@ impl Cli {
             "Unified timeout (overrides individual timeouts)",
         );
 
+        table.add_optional_duration_param(
+            "max-proxy-time",
+            None,
+            self.max_proxy_time,
+            "Overall time budget per proxy",
+        );
+
         // Performance parameters
         table.add_numeric_param(
             "concurrent",
@@ -261,6 +1149,13 @@ impl Cli {
             "Number of concurrent connections",
         );
 
+        table.add_numeric_param(
+            "concurrent-upload",
+            1_usize,
+            self.concurrent_upload,
+            "Connections the upload is split across",
+        );
+
         table.add_numeric_param(
             "max-concurrent",
             1_usize,
@@ -268,6 +1163,34 @@ impl Cli {
             "Maximum proxies to test concurrently",
         );
 
+        table.add_bool_param(
+            "isolate-servers",
+            false,
+            self.isolate_servers,
+            "One concurrent test per server",
+        );
+
+        table.add_optional_string_param(
+            "max-total-connections",
+            None,
+            &self.max_total_connections.map(|n| n.to_string()),
+            "Cap on bandwidth connections across proxies",
+        );
+
+        table.add_numeric_param(
+            "retries",
+            0_usize,
+            self.retries,
+            "Latency test retries on transient errors",
+        );
+
+        table.add_duration_param(
+            "retry-backoff",
+            Duration::from_millis(500),
+            self.retry_backoff,
+            "Initial retry backoff (doubles per attempt)",
+        );
+
         // Filtering thresholds
         table.add_duration_param(
             "max-latency",
@@ -290,7 +1213,78 @@ impl Cli {
             "Minimum upload speed (MB/s)",
         );
 
+        table.add_bool_param(
+            "first-match",
+            false,
+            self.first_match,
+            "Stop at the first passing proxy",
+        );
+
         // Mode flags
+        table.add_numeric_param("ping-count", 6, self.ping_count, "Latency pings per proxy");
+
+        table.add_bool_param(
+            "latency-under-load",
+            false,
+            self.latency_under_load,
+            "Measure latency during a download",
+        );
+
+        table.add_string_param(
+            "ip-version",
+            "auto",
+            &self.ip_version.to_string(),
+            "IP family used to reach proxies",
+        );
+
+        table.add_optional_string_param(
+            "bind-address",
+            None,
+            &self.bind_address.map(|a| a.to_string()),
+            "Local IP for direct test traffic",
+        );
+
+        table.add_optional_string_param(
+            "dns-server",
+            None,
+            &self.dns_server.map(|a| a.to_string()),
+            "Resolver for proxy hostnames",
+        );
+
+        table.add_bool_param(
+            "fresh-connections",
+            false,
+            self.fresh_connections,
+            "Disable connection reuse",
+        );
+
+        table.add_string_param(
+            "latency-mode",
+            "http",
+            &self.latency_mode.to_string(),
+            "Latency measurement mode (http or tcp)",
+        );
+
+        table.add_string_param(
+            "latency-method",
+            "get",
+            &self.latency_method.to_string(),
+            "HTTP ping method (get or head)",
+        );
+
+        table.add_string_param(
+            "latency-target",
+            "server",
+            &self.latency_target.to_string(),
+            "Latency target (server or proxy)",
+        );
+
+        table.add_numeric_param("repeat", 1, self.repeat, "Test suite runs to aggregate");
+
+        table.add_optional_duration_param("watch", None, self.watch, "Re-test interval");
+
+        table.add_bool_param("tui", false, self.tui, "Browse results interactively");
+
         table.add_bool_param(
             "fast-mode",
             false,
@@ -305,6 +1299,12 @@ impl Cli {
             "Rename nodes with location and speed info",
         );
 
+        table.add_optional_string_param(
+            "geoip-db",
+            None,
+            &self.geoip_db,
+            "GeoIP database for node locations",
+        );
         table.add_bool_param(
             "stash-compatible",
             false,
@@ -320,10 +1320,225 @@ impl Cli {
             "Output results in JSON format",
         );
 
+        table.add_bool_param(
+            "csv-output",
+            false,
+            self.csv_output,
+            "Output results in CSV format",
+        );
+
+        table.add_bool_param(
+            "markdown-output",
+            false,
+            self.markdown_output,
+            "Output results as a markdown table",
+        );
+
+        table.add_bool_param(
+            "show-latency-range",
+            false,
+            self.show_latency_range,
+            "Show min/max latency column",
+        );
+
+        table.add_bool_param(
+            "show-stability",
+            false,
+            self.show_stability,
+            "Show download stability column",
+        );
+
+        table.add_bool_param(
+            "show-ttfb",
+            false,
+            self.show_ttfb,
+            "Show time to first byte column",
+        );
+
+        table.add_string_param(
+            "timezone",
+            "local",
+            &self.timezone.to_string(),
+            "Time zone of displayed timestamps",
+        );
+
+        table.add_string_param(
+            "timestamp-format",
+            DEFAULT_TIMESTAMP_FORMAT,
+            &self.timestamp_format,
+            "strftime format of displayed timestamps",
+        );
+
+        table.add_bool_param(
+            "jsonl-output",
+            false,
+            self.jsonl_output,
+            "Stream results as JSON lines",
+        );
+
+        table.add_bool_param("yaml-output", false, self.yaml_output, "Output YAML");
+
+        table.add_bool_param(
+            "compact",
+            false,
+            self.compact_output,
+            "One line per proxy instead of the table",
+        );
+
+        table.add_optional_string_param(
+            "sort-by",
+            None,
+            &self.sort_by.map(|key| key.to_string()),
+            "Sort results by latency, download, upload or name",
+        );
+
+        table.add_bool_param(
+            "sort-desc",
+            false,
+            self.sort_desc,
+            "Sort results in descending order",
+        );
+
+        table.add_optional_string_param(
+            "pick-best",
+            None,
+            &self.pick_best.map(|n| n.to_string()),
+            "Print the N best proxies by score",
+        );
+
+        table.add_bool_param(
+            "export-best-only",
+            false,
+            self.export_best_only,
+            "Export only the picked best proxies",
+        );
+
+        table.add_optional_string_param(
+            "top-per-region",
+            None,
+            &self.top_per_region.map(|count| count.to_string()),
+            "Fastest proxies exported per region",
+        );
+
+        table.add_string_param(
+            "score-weights",
+            "0.4/0.3/0.2/0.1",
+            &format!(
+                "{}/{}/{}/{}",
+                self.weight_latency,
+                self.weight_download,
+                self.weight_upload,
+                self.weight_packet_loss
+            ),
+            "Latency/download/upload/loss score weights",
+        );
+
+        table.add_optional_string_param(
+            "baseline",
+            None,
+            &self.baseline,
+            "Previous JSON results to diff against",
+        );
+
         table.add_bool_param("verbose", false, self.verbose, "Verbose output");
 
+        table.add_bool_param("quiet", false, self.quiet, "Only print final results");
+
+        table.add_optional_string_param("log-file", None, &self.log_file, "Log file path");
+
+        table.add_string_param(
+            "log-format",
+            "text",
+            &self.log_format.to_string(),
+            "Log file format (text or json)",
+        );
+
+        table.add_bool_param("no-color", false, self.no_color, "Disable colored output");
+
         table.add_optional_string_param("output", None, &self.output, "Output config file path");
 
+        table.add_optional_string_param(
+            "output-format",
+            None,
+            &self.export_format.map(|format| format.to_string()),
+            "Exported config format (clash, surge, singbox or auto)",
+        );
+
+        table.add_bool_param(
+            "include-failed",
+            false,
+            self.include_failed,
+            "Export failed proxies too",
+        );
+
+        table.add_string_param(
+            "dead-prefix",
+            "[DEAD] ",
+            &self.dead_prefix,
+            "Name prefix for exported failed proxies",
+        );
+
+        table.add_bool_param(
+            "export-only-passing-groups",
+            false,
+            self.export_only_passing_groups,
+            "Generate speed tier proxy groups on export",
+        );
+
+        table.add_bool_param(
+            "only-changed",
+            false,
+            self.only_changed,
+            "Skip rewriting an unchanged export",
+        );
+
+        table.add_numeric_param(
+            "fast-tier",
+            20.0,
+            self.fast_tier,
+            "Fast group download speed in MB/s",
+        );
+
+        table.add_numeric_param(
+            "medium-tier",
+            5.0,
+            self.medium_tier,
+            "Medium group download speed in MB/s",
+        );
+
+        table.add_optional_string_param(
+            "prometheus-out",
+            None,
+            &self.prometheus_out,
+            "Prometheus textfile output path",
+        );
+
+        table.add_optional_string_param(
+            "output-append",
+            None,
+            &self.output_append,
+            "JSON results history path",
+        );
+
+        // Webhook paths often carry tokens
+        let webhook = self.webhook.as_deref().map(WebhookSink::redact_url);
+        table.add_optional_string_param("webhook", None, &webhook, "Results webhook URL");
+
+        // Only header names, values often carry tokens
+        let webhook_headers = (!self.webhook_header.is_empty()).then(|| {
+            self.webhook_header
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        table.add_optional_string_param(
+            "webhook-header",
+            None,
+            &webhook_headers,
+            "Extra webhook headers",
+        );
+
         // Mihomo configuration
         table.add_bool_param(
             "use-mihomo",
@@ -353,6 +1568,13 @@ impl Cli {
             "Mihomo proxy port",
         );
 
+        table.add_optional_string_param(
+            "delay-test-url",
+            None,
+            &self.delay_test_url,
+            "Mihomo delay test URL",
+        );
+
         table.add_string_param(
             "mihomo-config-dir",
             "./mihomo-temp",
@@ -360,6 +1582,288 @@ impl Cli {
             "Mihomo config directory",
         );
 
+        table.add_bool_param(
+            "keep-mihomo-config",
+            false,
+            self.keep_mihomo_config,
+            "Keep generated mihomo config after the run",
+        );
+
+        table.add_bool_param(
+            "skip-mihomo-validate",
+            false,
+            self.skip_mihomo_validate,
+            "Skip validating the mihomo config before start",
+        );
+
+        table.add_optional_string_param(
+            "hysteria-up",
+            None,
+            &self.hysteria_up.map(|mbps| mbps.to_string()),
+            "Hysteria upload hint (Mbps)",
+        );
+
+        table.add_optional_string_param(
+            "hysteria-down",
+            None,
+            &self.hysteria_down.map(|mbps| mbps.to_string()),
+            "Hysteria download hint (Mbps)",
+        );
+
         table
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MihomoRunner;
+
+    #[test]
+    fn test_parse_size_units() {
+        // A bare number keeps meaning MB
+        assert_eq!(parse_size_mb("50"), Ok(50 * 1024 * 1024));
+        assert_eq!(parse_size_mb("0.5"), Ok(512 * 1024));
+        assert_eq!(parse_size_mb("0,5"), Ok(512 * 1024));
+        // KB/MB/GB are decimal, KiB/MiB/GiB binary
+        assert_eq!(parse_size_mb("500KB"), Ok(500_000));
+        assert_eq!(parse_size_mb("500 KiB"), Ok(500 * 1024));
+        assert_eq!(parse_size_mb("50MB"), Ok(50_000_000));
+        assert_eq!(parse_size_mb("50MiB"), parse_size_mb("50"));
+        assert_eq!(parse_size_mb("1GB"), Ok(1_000_000_000));
+        assert_eq!(parse_size_mb("1,5gib"), Ok(3 * 512 * 1024 * 1024));
+        assert!(parse_size_mb("10TB").is_err());
+        assert!(parse_size_mb("-1").is_err());
+        assert!(parse_size_mb("MB").is_err());
+
+        assert_eq!(parse_speed_mb("5"), Ok(5.0));
+        assert_eq!(parse_speed_mb("512KiB/s"), Ok(0.5));
+        assert_eq!(parse_speed_mb("1048,576KB/s"), Ok(1.0));
+        assert_eq!(parse_speed_mb("2,5MiB"), Ok(2.5));
+    }
+
+    fn typed_proxies() -> Vec<ProxyConfig> {
+        [
+            ("a", ProxyType::ShadowsocksShort),
+            ("b", ProxyType::VMess),
+            ("c", ProxyType::WireGuard),
+            ("d", ProxyType::Trojan),
+        ]
+        .into_iter()
+        .map(|(name, proxy_type)| ProxyConfig {
+            name: name.to_string(),
+            proxy_type,
+            server: format!("{name}.example.com"),
+            port: 443,
+            config: Default::default(),
+        })
+        .collect()
+    }
+
+    fn filtered_names(extra: &[&str]) -> Vec<String> {
+        let mut argv = vec!["mihomo-speedtest", "-c", "config.yaml"];
+        argv.extend_from_slice(extra);
+        let args = Cli::try_parse_from(argv).unwrap();
+
+        let mut proxies = typed_proxies();
+        args.apply_filters(&mut proxies).unwrap();
+        proxies.into_iter().map(|p| p.name).collect()
+    }
+
+    #[test]
+    fn test_quiet_mode_drops_info_logs() {
+        use crate::cli::logging::{LogFormat, file_layer};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let args =
+            Cli::try_parse_from(["mihomo-speedtest", "-c", "config.yaml", "--quiet"]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quiet.log");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing::level_filters::LevelFilter::from_level(
+                args.log_level(),
+            ))
+            .with(file_layer(&path, LogFormat::Text).unwrap());
+        tracing::subscriber::with_default(subscriber, || {
+            info!("🚀 Starting Mihomo SpeedTest");
+            warn!("Webhook answered with 500");
+        });
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(!log.contains("INFO"));
+        assert_eq!(log.lines().count(), 1);
+        assert!(log.contains("WARN"));
+
+        assert!(
+            Cli::try_parse_from(["mihomo-speedtest", "-c", "config.yaml", "-q", "-v"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_warmup_requires_download_duration() {
+        let parse = |extra: &[&str]| {
+            let argv = ["mihomo-speedtest", "-c", "config.yaml"];
+            Cli::try_parse_from(argv.iter().chain(extra))
+        };
+        assert!(parse(&["--warmup", "1s"]).is_err());
+        let args = parse(&["--warmup", "1s", "--download-duration", "5s"]).unwrap();
+        assert_eq!(args.warmup, Duration::from_secs(1));
+        assert_eq!(parse(&[]).unwrap().warmup, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_speed_tiers_must_increase() {
+        let parse = |extra: &[&str]| {
+            let argv = ["mihomo-speedtest", "-c", "config.yaml"];
+            Cli::try_parse_from(argv.iter().chain(extra)).unwrap()
+        };
+        assert!(parse(&[]).validate().is_ok());
+        let args = parse(&["--fast-tier", "8", "--medium-tier", "500KiB/s"]);
+        assert!(args.validate().is_ok());
+        assert_eq!(args.speed_tiers().medium, 500.0 * 1024.0);
+
+        // Swapped or equal tiers are rejected
+        assert!(parse(&["--fast-tier", "4"]).validate().is_err());
+        let error = parse(&["--fast-tier", "5"]).validate().unwrap_err();
+        assert!(error.to_string().contains("--medium-tier"), "{error}");
+
+        let argv = ["mihomo-speedtest", "-c", "config.yaml", "--fast-tier=-1"];
+        assert!(Cli::try_parse_from(argv).is_err());
+    }
+
+    #[test]
+    fn test_only_named_proxies() {
+        // --only wins over the regex filter
+        assert_eq!(
+            filtered_names(&["--only", "b", "--only", "d", "--filter", "^a$"]),
+            ["b", "d"]
+        );
+        assert_eq!(filtered_names(&["--only", "c", "--only", "missing"]), ["c"]);
+
+        let args =
+            Cli::try_parse_from(["mihomo-speedtest", "-c", "config.yaml", "--only", "x"]).unwrap();
+        let error = args.apply_filters(&mut typed_proxies()).unwrap_err();
+        assert!(error.to_string().ends_with("Available proxies: a, b, c, d"));
+    }
+
+    #[test]
+    fn test_export_format_inferred_from_output() {
+        let format = |extra: &[&str]| {
+            let mut argv = vec!["mihomo-speedtest", "-c", "config.yaml"];
+            argv.extend_from_slice(extra);
+            Cli::try_parse_from(argv).unwrap().export_format()
+        };
+        assert_eq!(format(&["-o", "out.json"]), ExportFormat::Singbox);
+        assert_eq!(format(&["-o", "out.CONF"]), ExportFormat::Surge);
+        assert_eq!(format(&["-o", "out.yaml"]), ExportFormat::Clash);
+        assert_eq!(format(&[]), ExportFormat::Clash);
+        // An explicit format wins over the extension
+        let explicit = ["-o", "out.json", "--output-format", "surge"];
+        assert_eq!(format(&explicit), ExportFormat::Surge);
+        assert!(Cli::try_parse_from(["mihomo-speedtest", "--output-format", "auto"]).is_err());
+    }
+
+    #[test]
+    fn test_max_total_connections_must_be_positive() {
+        let parse = |value: &str| {
+            Cli::try_parse_from([
+                "mihomo-speedtest",
+                "-c",
+                "config.yaml",
+                "--max-total-connections",
+                value,
+            ])
+        };
+        assert!(parse("0").is_err());
+        assert_eq!(parse("3").unwrap().max_total_connections, Some(3));
+    }
+
+    #[test]
+    fn test_include_types() {
+        assert_eq!(filtered_names(&["--include-types", "ss,vmess"]), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_exclude_types() {
+        assert_eq!(
+            filtered_names(&["--exclude-types", "wireguard"]),
+            ["a", "b", "d"]
+        );
+    }
+
+    #[test]
+    fn test_include_and_exclude_types() {
+        assert_eq!(
+            filtered_names(&[
+                "--include-types",
+                "ss,vmess,trojan",
+                "--exclude-types",
+                "vmess"
+            ]),
+            ["a", "d"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_type_lists_valid_values() {
+        let err = Cli::try_parse_from([
+            "mihomo-speedtest",
+            "-c",
+            "config.yaml",
+            "--include-types",
+            "ss,bogus",
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Unknown proxy type 'bogus'"));
+        assert!(err.contains("vmess"));
+    }
+
+    #[test]
+    fn test_tls_overrides_reach_mihomo_config() {
+        let args = Cli::try_parse_from([
+            "mihomo-speedtest",
+            "-c",
+            "config.yaml",
+            "--force-sni",
+            "fixed.example.com",
+            "--force-skip-cert-verify",
+        ])
+        .unwrap();
+
+        let mut proxies: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Trojan,
+                server: format!("{name}.example.com"),
+                port: 443,
+                config: crate::config::ProxyParameters {
+                    sni: Some("broken.invalid".to_string()),
+                    skip_cert_verify: Some(false),
+                    ..Default::default()
+                },
+            })
+            .collect();
+
+        args.apply_tls_overrides(&mut proxies);
+
+        for proxy in &proxies {
+            assert_eq!(proxy.config.sni.as_deref(), Some("fixed.example.com"));
+            assert_eq!(proxy.config.skip_cert_verify, Some(true));
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            19090,
+            17890,
+        )
+        .unwrap();
+        let yaml = serde_yaml::to_string(&runner.generate_config(&proxies).unwrap()).unwrap();
+        assert_eq!(yaml.matches("sni: fixed.example.com").count(), 2);
+        assert_eq!(yaml.matches("skip-cert-verify: true").count(), 2);
+        assert!(!yaml.contains("broken.invalid"));
+    }
+}
@@ -0,0 +1,102 @@
+use super::Cli;
+use crate::Result;
+use crate::config::{ConfigLoader, ProxyConfig};
+use comfy_table::{Cell, Color, ContentArrangement, Table, presets::UTF8_FULL};
+use tracing::info;
+
+/// Load and filter proxies exactly like a real run, then describe what would be tested.
+/// No speed tester or mihomo process is created.
+pub async fn dry_run(args: &Cli, config_paths: &str) -> Result<String> {
+    let loader = ConfigLoader::new().with_format(args.config_format);
+    let mut proxies = loader.load_from_paths(config_paths).await?;
+    info!("📋 Loaded {} proxies", proxies.len());
+
+    ConfigLoader::validate_proxies(&mut proxies, args.strict)?;
+
+    if args.dedup {
+        ConfigLoader::dedup_proxies(&mut proxies);
+    }
+
+    args.apply_filters(&mut proxies)?;
+    args.apply_sample(&mut proxies);
+
+    Ok(format!(
+        "{}\n🧪 {} proxies would be tested",
+        format_proxy_table(&proxies),
+        proxies.len()
+    ))
+}
+
+/// Format proxies as a name/type/server/port table
+pub fn format_proxy_table(proxies: &[ProxyConfig]) -> String {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Proxy Name").fg(Color::Blue),
+            Cell::new("Type").fg(Color::Blue),
+            Cell::new("Server").fg(Color::Blue),
+            Cell::new("Port").fg(Color::Blue),
+        ]);
+
+    for proxy in proxies {
+        table.add_row(vec![
+            Cell::new(&proxy.name),
+            Cell::new(proxy.proxy_type.to_string()),
+            Cell::new(&proxy.server),
+            Cell::new(proxy.port),
+        ]);
+    }
+
+    table.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[tokio::test]
+    async fn test_dry_run_lists_filtered_proxies() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            r#"
+proxies:
+  - { name: HK-01, type: http, server: hk.example.com, port: 8080 }
+  - { name: HK-02 expired, type: http, server: hk2.example.com, port: 8080 }
+  - { name: JP-01, type: socks5, server: jp.example.com, port: 1080 }
+"#,
+        )
+        .unwrap();
+        let config_path = config_path.to_string_lossy().to_string();
+
+        // Any attempt to test would fail: the binary and speed test server don't exist
+        let args = Cli::try_parse_from([
+            "mihomo-speedtest",
+            "-c",
+            &config_path,
+            "--dry-run",
+            "--filter",
+            "^HK",
+            "--block",
+            "expired",
+            "--use-mihomo",
+            "--mihomo-binary",
+            "/nonexistent/mihomo",
+            "--server-url",
+            "http://speedtest.invalid",
+        ])
+        .unwrap();
+
+        let output = dry_run(&args, &config_path).await.unwrap();
+
+        assert!(output.contains("HK-01"));
+        assert!(output.contains("hk.example.com"));
+        assert!(!output.contains("HK-02"));
+        assert!(!output.contains("JP-01"));
+        assert!(output.ends_with("1 proxies would be tested"));
+    }
+}
@@ -0,0 +1,80 @@
+use super::Cli;
+use crate::SpeedTestResult;
+
+/// Exit code with `--strict-exit` when no tested proxy met the performance criteria
+pub const EXIT_NO_PASSING: i32 = 2;
+
+/// Exit code with `--strict-exit` when there were no proxies to test
+pub const EXIT_NO_PROXIES: i32 = 3;
+
+/// How a run ended, decides the process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// Proxies were tested and `passed` of them met the criteria
+    Tested { passed: usize },
+    /// The config loaded no proxies, or filtering removed all of them
+    NoProxies,
+    /// Nothing was judged, e.g. a dry run or watch mode
+    Finished,
+}
+
+impl RunOutcome {
+    /// Outcome of a run that produced `results`, counting those `args` lets pass
+    pub fn tested<'a>(results: impl IntoIterator<Item = &'a SpeedTestResult>, args: &Cli) -> Self {
+        RunOutcome::Tested {
+            passed: results
+                .into_iter()
+                .filter(|result| args.meets_criteria(result))
+                .count(),
+        }
+    }
+
+    /// Process exit code, anything but an error exits 0 unless `strict` is set
+    pub fn exit_code(&self, strict: bool) -> i32 {
+        match self {
+            _ if !strict => 0,
+            RunOutcome::Tested { passed: 0 } => EXIT_NO_PASSING,
+            RunOutcome::NoProxies => EXIT_NO_PROXIES,
+            RunOutcome::Tested { .. } | RunOutcome::Finished => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use clap::Parser;
+    use std::time::Duration;
+
+    #[test]
+    fn test_exit_code_without_passing_proxies() {
+        let args = Cli::try_parse_from([
+            "mihomo-speedtest",
+            "-c",
+            "config.yaml",
+            "--strict-exit",
+            "--min-download-speed",
+            "10",
+        ])
+        .unwrap();
+
+        let mut slow = SpeedTestResult::failed("slow".to_string(), ProxyType::Http, String::new());
+        slow.error = None;
+        slow.latency = Some(Duration::from_millis(100));
+        slow.download_speed = 1024.0 * 1024.0;
+        slow.upload_speed = 10.0 * 1024.0 * 1024.0;
+        let dead = SpeedTestResult::failed("dead".to_string(), ProxyType::Http, "x".into());
+
+        let outcome = RunOutcome::tested(&[slow.clone(), dead], &args);
+        assert_eq!(outcome, RunOutcome::Tested { passed: 0 });
+        assert_eq!(outcome.exit_code(args.strict_exit), EXIT_NO_PASSING);
+        // Existing scripts keep seeing 0 without --strict-exit
+        assert_eq!(outcome.exit_code(false), 0);
+
+        slow.download_speed = 20.0 * 1024.0 * 1024.0;
+        let outcome = RunOutcome::tested(&[slow], &args);
+        assert_eq!(outcome.exit_code(args.strict_exit), 0);
+        assert_eq!(RunOutcome::NoProxies.exit_code(true), EXIT_NO_PROXIES);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::Result;
+use anyhow::Context;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::Level;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Record format of `--log-file`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// The same lines as the console, without colors
+    #[default]
+    Text,
+    /// One JSON object per event
+    Json,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Log to the console, and with a `log_file` also append every event to it
+pub fn init_logging(level: Level, log_file: Option<&Path>, format: LogFormat) -> Result<()> {
+    let file = log_file.map(|path| file_layer(path, format)).transpose()?;
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::from_level(level))
+        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(file)
+        .init();
+    Ok(())
+}
+
+/// Layer appending events to `path`. It never touches the terminal, so the progress
+/// bar isn't disturbed by the extra output.
+pub fn file_layer<S>(path: &Path, format: LogFormat) -> Result<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file {}", path.display()))?;
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(Arc::new(file))
+        .with_ansi(false);
+    Ok(match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("speedtest.log");
+
+        let subscriber =
+            tracing_subscriber::registry().with(file_layer(&path, LogFormat::Json).unwrap());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(proxy = "HK 01", "Download chunk failed");
+        });
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let event: serde_json::Value =
+            serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(event["level"], "WARN");
+        assert_eq!(event["fields"]["message"], "Download chunk failed");
+        assert_eq!(event["fields"]["proxy"], "HK 01");
+        assert!(event["timestamp"].is_string());
+    }
+}
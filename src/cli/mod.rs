@@ -1,6 +1,13 @@
 pub mod args;
+pub mod dry_run;
+pub mod exit;
+pub mod logging;
 pub mod parameters;
 pub mod progress;
+pub mod tui;
+pub mod watch;
 
 pub use args::Cli;
+pub use exit::RunOutcome;
+pub use logging::LogFormat;
 pub use parameters::ParameterTable;
@@ -1,5 +1,7 @@
 use crate::core::SpeedTestResult;
+use crate::core::speedtest::ProgressCallback;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Arc;
 
 /// Progress bar for speed testing
 pub struct SpeedTestProgress {
@@ -36,6 +38,13 @@ impl SpeedTestProgress {
         self.bar.set_message(status);
     }
 
+    /// Callback advancing this bar, for testers reporting each finished proxy. The bar
+    /// is shared, so concurrent tests finishing in any order all land on it.
+    pub fn callback(self: &Arc<Self>) -> ProgressCallback {
+        let progress = Arc::clone(self);
+        Box::new(move |result| progress.update(result))
+    }
+
     /// Set a custom message
     pub fn set_message(&self, msg: &str) {
         self.bar.set_message(msg.to_string());
@@ -0,0 +1,254 @@
+//! `--tui`: browse the results of a run in a scrollable, sortable terminal table
+
+use crate::Result;
+use crate::core::{SortKey, SpeedTestResult, sort_results};
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Row, Table, TableState};
+use std::io::IsTerminal;
+
+/// Rows PageUp/PageDown move the selection by
+const PAGE_ROWS: usize = 10;
+
+/// Error for `--tui` when the terminal can't host it
+pub fn require_terminal() -> Result<()> {
+    if !std::io::stdout().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "--tui needs an interactive terminal, but stdout is not a TTY"
+        ));
+    }
+    Ok(())
+}
+
+/// What the browser shows: the results in their current order and the selected row
+#[derive(Debug, Clone)]
+pub struct BrowserState {
+    results: Vec<SpeedTestResult>,
+    sort: SortKey,
+    descending: bool,
+    selected: usize,
+}
+
+impl BrowserState {
+    /// Browser over `results` sorted by `sort`, as `--sort-by` and `--sort-desc` ask
+    pub fn new(mut results: Vec<SpeedTestResult>, sort: SortKey, descending: bool) -> Self {
+        sort_results(&mut results, sort, descending);
+        Self {
+            results,
+            sort,
+            descending,
+            selected: 0,
+        }
+    }
+
+    pub fn results(&self) -> &[SpeedTestResult] {
+        &self.results
+    }
+
+    pub fn selected(&self) -> Option<&SpeedTestResult> {
+        self.results.get(self.selected)
+    }
+
+    /// Current sort key and whether it is descending
+    pub fn sort(&self) -> (SortKey, bool) {
+        (self.sort, self.descending)
+    }
+
+    /// Sort by `key`, best first. Choosing the current key again flips the direction.
+    /// The selection stays on the same proxy.
+    pub fn sort_by(&mut self, key: SortKey) {
+        self.descending = if key == self.sort {
+            !self.descending
+        } else {
+            // Higher speeds are better, lower latency and alphabetical names come first
+            matches!(key, SortKey::Download | SortKey::Upload)
+        };
+        self.sort = key;
+
+        let selected = self.selected().map(|r| r.proxy_name.clone());
+        sort_results(&mut self.results, self.sort, self.descending);
+        self.selected = selected
+            .and_then(|name| self.results.iter().position(|r| r.proxy_name == name))
+            .unwrap_or(0);
+    }
+
+    /// Move the selection by `delta` rows, stopping at either end
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.results.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Apply a key press, returning false once the browser should close
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(PAGE_ROWS as isize),
+            KeyCode::PageUp => self.move_selection(-(PAGE_ROWS as isize)),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX),
+            KeyCode::Char('l') => self.sort_by(SortKey::Latency),
+            KeyCode::Char('d') => self.sort_by(SortKey::Download),
+            KeyCode::Char('u') => self.sort_by(SortKey::Upload),
+            KeyCode::Char('n') => self.sort_by(SortKey::Name),
+            _ => {}
+        }
+        true
+    }
+
+    fn render(&self, frame: &mut ratatui::Frame) {
+        let [table_area, help_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+
+        let rows = self.results.iter().map(|result| {
+            let status = if result.is_successful() {
+                Line::from("✓").style(Style::default().fg(Color::Green))
+            } else {
+                Line::from("✗").style(Style::default().fg(Color::Red))
+            };
+            Row::new(vec![
+                Line::from(result.proxy_name.clone()),
+                Line::from(result.proxy_type.to_string()),
+                Line::from(result.format_latency()),
+                Line::from(result.format_download_speed()),
+                Line::from(result.format_upload_speed()),
+                status,
+            ])
+        });
+        let arrow = if self.descending { "↓" } else { "↑" };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(10),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(6),
+            ],
+        )
+        .header(
+            Row::new([
+                "Proxy Name",
+                "Type",
+                "Latency",
+                "Download",
+                "Upload",
+                "Status",
+            ])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            " {} results, sorted by {} {} ",
+            self.results.len(),
+            self.sort,
+            arrow
+        )));
+
+        let mut table_state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, table_area, &mut table_state);
+        frame.render_widget(
+            Line::from(" ↑/↓ move  l latency  d download  u upload  n name  q quit"),
+            help_area,
+        );
+    }
+}
+
+/// Show `results` until the user quits, starting in the given order. Fails without
+/// touching the terminal when stdout isn't a TTY.
+pub fn browse(results: Vec<SpeedTestResult>, sort: SortKey, descending: bool) -> Result<()> {
+    require_terminal()?;
+
+    let mut terminal = ratatui::try_init()?;
+    let outcome = run(&mut terminal, BrowserState::new(results, sort, descending));
+    ratatui::try_restore()?;
+    outcome
+}
+
+fn run(terminal: &mut DefaultTerminal, mut state: BrowserState) -> Result<()> {
+    loop {
+        terminal.draw(|frame| state.render(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !state.handle_key(key.code)
+        {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use std::time::Duration;
+
+    fn results() -> Vec<SpeedTestResult> {
+        let measured = |name: &str, latency_ms: u64, download: f64| SpeedTestResult {
+            latency: Some(Duration::from_millis(latency_ms)),
+            download_speed: download,
+            error: None,
+            ..SpeedTestResult::failed(name.to_string(), ProxyType::VMess, String::new())
+        };
+        vec![
+            measured("b", 300, 5.0),
+            SpeedTestResult::failed("a".to_string(), ProxyType::Trojan, "timeout".into()),
+            measured("c", 100, 1.0),
+            measured("d", 200, 9.0),
+        ]
+    }
+
+    fn names(state: &BrowserState) -> Vec<&str> {
+        state
+            .results()
+            .iter()
+            .map(|r| r.proxy_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_browser_sorts_and_keeps_selection() {
+        let mut state = BrowserState::new(results(), SortKey::Latency, false);
+        assert_eq!(names(&state), ["c", "d", "b", "a"]);
+        assert_eq!(state.sort(), (SortKey::Latency, false));
+
+        // Fastest download first, the same key again flips the order
+        assert!(state.handle_key(KeyCode::Char('d')));
+        assert_eq!(names(&state), ["d", "b", "c", "a"]);
+        state.handle_key(KeyCode::Char('d'));
+        assert_eq!(names(&state), ["c", "b", "d", "a"]);
+        assert_eq!(state.sort(), (SortKey::Download, false));
+
+        // The selected proxy follows re-sorting
+        state.handle_key(KeyCode::Down);
+        assert_eq!(state.selected().unwrap().proxy_name, "b");
+        state.handle_key(KeyCode::Char('n'));
+        assert_eq!(names(&state), ["b", "c", "d", "a"]);
+        assert_eq!(state.selected().unwrap().proxy_name, "b");
+
+        // Movement stops at both ends
+        state.handle_key(KeyCode::End);
+        assert_eq!(state.selected().unwrap().proxy_name, "a");
+        state.handle_key(KeyCode::Down);
+        assert_eq!(state.selected().unwrap().proxy_name, "a");
+        state.handle_key(KeyCode::PageUp);
+        assert_eq!(state.selected().unwrap().proxy_name, "b");
+
+        assert!(!state.handle_key(KeyCode::Char('q')));
+        assert!(
+            BrowserState::new(Vec::new(), SortKey::Latency, false)
+                .selected()
+                .is_none()
+        );
+
+        // --sort-by picks the starting order, failed proxies still come last
+        let state = BrowserState::new(results(), SortKey::Name, true);
+        assert_eq!(names(&state), ["d", "c", "b", "a"]);
+        assert_eq!(state.sort(), (SortKey::Name, true));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::Result;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// ANSI sequence that clears the screen and moves the cursor home
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Run `cycle` right away and then every `interval` until `cancel` fires, returning how
+/// many cycles completed. A failed cycle is logged and the next one still runs; a cycle
+/// still in flight when cancelled is dropped.
+pub async fn run_watch<F, Fut>(interval: Duration, cancel: CancellationToken, mut cycle: F) -> usize
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut ticker = tokio::time::interval(interval);
+    // A cycle longer than the interval delays the next one instead of firing a burst
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut completed = 0;
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+
+        let number = completed + 1;
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            result = cycle(number) => {
+                if let Err(e) = result {
+                    warn!("Watch cycle {} failed: {}", number, e);
+                }
+            }
+        }
+        completed = number;
+    }
+
+    completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_run_watch_until_cancelled() {
+        let cancel = CancellationToken::new();
+        let interval = Duration::from_millis(20);
+        let start = Instant::now();
+
+        let cycles = run_watch(interval, cancel.clone(), |n| {
+            let cancel = cancel.clone();
+            async move {
+                if n == 3 {
+                    cancel.cancel();
+                }
+                // A failing cycle doesn't stop the loop
+                if n == 2 {
+                    return Err(anyhow::anyhow!("server unreachable"));
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        assert_eq!(cycles, 3);
+        // The first cycle runs immediately, the next two wait one interval each
+        assert!(start.elapsed() >= interval * 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_watch_drops_cycle_in_flight() {
+        let cancel = CancellationToken::new();
+        tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                cancel.cancel();
+            }
+        });
+
+        let cycles = run_watch(Duration::from_millis(10), cancel, |_| async {
+            std::future::pending::<()>().await;
+            Ok(())
+        })
+        .await;
+
+        assert_eq!(cycles, 0);
+    }
+}
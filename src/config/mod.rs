@@ -1,10 +1,12 @@
 pub mod loader;
+pub mod subscription;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
 
-pub use loader::ConfigLoader;
+pub use loader::{ConfigFormat, ConfigLoader};
+pub use subscription::SubscriptionInfo;
 
 /// Supported proxy types
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -13,11 +15,14 @@ pub enum ProxyType {
     Shadowsocks,
     #[serde(rename = "ss")]
     ShadowsocksShort,
+    #[serde(rename = "ssr")]
+    ShadowsocksR,
     VMess,
     VLESS,
     Trojan,
     Hysteria,
     Hysteria2,
+    Tuic,
     #[serde(rename = "wireguard")]
     WireGuard,
     Socks5,
@@ -34,20 +39,57 @@ impl FromStr for ProxyType {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "shadowsocks" | "ss" => Ok(ProxyType::Shadowsocks),
-            "vmess" => Ok(ProxyType::VMess),
-            "vless" => Ok(ProxyType::VLESS),
-            "trojan" => Ok(ProxyType::Trojan),
-            "hysteria" => Ok(ProxyType::Hysteria),
-            "hysteria2" => Ok(ProxyType::Hysteria2),
-            "wireguard" | "wg" => Ok(ProxyType::WireGuard),
-            "socks5" | "socks" => Ok(ProxyType::Socks5),
-            "http" => Ok(ProxyType::Http),
-            "https" => Ok(ProxyType::Https),
-            "anytls" => Ok(ProxyType::AnyTLS),
-            _ => Err(format!("Unknown proxy type: {s}")),
+        let name = s.to_lowercase();
+        Self::NAMES
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(_, proxy_type)| proxy_type.clone())
+            .ok_or_else(|| format!("Unknown proxy type: {s}"))
+    }
+}
+
+impl ProxyType {
+    /// Every name `from_str` accepts, case-insensitively, with the type it stands for
+    pub const NAMES: &'static [(&'static str, ProxyType)] = &[
+        ("ss", ProxyType::Shadowsocks),
+        ("shadowsocks", ProxyType::Shadowsocks),
+        ("ssr", ProxyType::ShadowsocksR),
+        ("shadowsocksr", ProxyType::ShadowsocksR),
+        ("vmess", ProxyType::VMess),
+        ("vless", ProxyType::VLESS),
+        ("trojan", ProxyType::Trojan),
+        ("hysteria", ProxyType::Hysteria),
+        ("hysteria2", ProxyType::Hysteria2),
+        ("tuic", ProxyType::Tuic),
+        ("wireguard", ProxyType::WireGuard),
+        ("wg", ProxyType::WireGuard),
+        ("socks5", ProxyType::Socks5),
+        ("socks", ProxyType::Socks5),
+        ("http", ProxyType::Http),
+        ("https", ProxyType::Https),
+        ("anytls", ProxyType::AnyTLS),
+    ];
+
+    /// Well-known port assumed when a share link omits it
+    pub fn default_port(&self) -> Option<u16> {
+        match self {
+            ProxyType::Trojan | ProxyType::Https | ProxyType::Hysteria2 => Some(443),
+            ProxyType::Http => Some(80),
+            ProxyType::Socks5 | ProxyType::Socks => Some(1080),
+            _ => None,
+        }
+    }
+
+    /// Whether both types are the same protocol, treating `ss`/`socks` aliases as equal
+    pub fn same_protocol(&self, other: &ProxyType) -> bool {
+        fn canonical(t: &ProxyType) -> &ProxyType {
+            match t {
+                ProxyType::ShadowsocksShort => &ProxyType::Shadowsocks,
+                ProxyType::Socks => &ProxyType::Socks5,
+                other => other,
+            }
         }
+        canonical(self) == canonical(other)
     }
 }
 
@@ -55,11 +97,13 @@ impl std::fmt::Display for ProxyType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProxyType::Shadowsocks | ProxyType::ShadowsocksShort => write!(f, "Shadowsocks"),
+            ProxyType::ShadowsocksR => write!(f, "ShadowsocksR"),
             ProxyType::VMess => write!(f, "VMess"),
             ProxyType::VLESS => write!(f, "VLESS"),
             ProxyType::Trojan => write!(f, "Trojan"),
             ProxyType::Hysteria => write!(f, "Hysteria"),
             ProxyType::Hysteria2 => write!(f, "Hysteria2"),
+            ProxyType::Tuic => write!(f, "TUIC"),
             ProxyType::WireGuard => write!(f, "WireGuard"),
             ProxyType::Socks5 | ProxyType::Socks => write!(f, "SOCKS5"),
             ProxyType::Http => write!(f, "HTTP"),
@@ -82,6 +126,64 @@ pub struct ProxyConfig {
     pub config: ProxyParameters,
 }
 
+impl ProxyConfig {
+    /// Check the fields the protocol can't work without, returning every problem found
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let missing = |value: &Option<String>| value.as_deref().is_none_or(|v| v.trim().is_empty());
+        let mut problems = Vec::new();
+
+        if self.server.trim().is_empty() {
+            problems.push("missing server".to_string());
+        }
+        if self.port == 0 {
+            problems.push("port must not be 0".to_string());
+        }
+
+        match self.proxy_type {
+            ProxyType::VMess | ProxyType::VLESS if missing(&self.config.uuid) => {
+                problems.push(format!("{} requires a uuid", self.proxy_type));
+            }
+            ProxyType::Trojan if missing(&self.config.password) => {
+                problems.push("Trojan requires a password".to_string());
+            }
+            ProxyType::Shadowsocks | ProxyType::ShadowsocksShort | ProxyType::ShadowsocksR => {
+                if missing(&self.config.cipher) {
+                    problems.push(format!("{} requires a cipher", self.proxy_type));
+                }
+                if missing(&self.config.password) {
+                    problems.push(format!("{} requires a password", self.proxy_type));
+                }
+            }
+            _ => {}
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Feature of the proxy that only mihomo can reproduce, if any. Direct testing
+    /// fails such proxies rather than measuring something else.
+    pub fn mihomo_only_feature(&self) -> Option<String> {
+        if let Some(ref dialer) = self.config.dialer_proxy {
+            return Some(format!("dialer-proxy '{dialer}'"));
+        }
+        if self.proxy_type == ProxyType::ShadowsocksR {
+            return Some("ShadowsocksR".to_string());
+        }
+        if matches!(
+            self.proxy_type,
+            ProxyType::Shadowsocks | ProxyType::ShadowsocksShort
+        ) && self.config.plugin.as_deref() == Some("shadow-tls")
+        {
+            return Some("the shadow-tls plugin".to_string());
+        }
+        None
+    }
+}
+
 /// Proxy parameters that vary by protocol type
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ProxyParameters {
@@ -184,7 +286,7 @@ pub struct TransportConfig {
 }
 
 /// Root configuration structure for Clash config files
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClashConfig {
     pub proxies: Vec<ProxyConfig>,
     #[serde(rename = "proxy-providers")]
@@ -306,3 +408,94 @@ where
 
     deserializer.deserialize_any(StringOrNumberVisitor)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(proxy_type: ProxyType, config: ProxyParameters) -> ProxyConfig {
+        ProxyConfig {
+            name: "node".to_string(),
+            proxy_type,
+            server: "example.com".to_string(),
+            port: 443,
+            config,
+        }
+    }
+
+    #[test]
+    fn test_validate_required_fields() {
+        let uuid = ProxyParameters {
+            uuid: Some("b831381d-6324-4d53-ad4f-8cda48b30811".to_string()),
+            ..Default::default()
+        };
+        let password = ProxyParameters {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let shadowsocks = ProxyParameters {
+            cipher: Some("aes-128-gcm".to_string()),
+            ..password.clone()
+        };
+
+        for (proxy_type, valid) in [
+            (ProxyType::VMess, &uuid),
+            (ProxyType::VLESS, &uuid),
+            (ProxyType::Trojan, &password),
+            (ProxyType::Shadowsocks, &shadowsocks),
+        ] {
+            assert!(proxy(proxy_type.clone(), valid.clone()).validate().is_ok());
+            assert!(
+                proxy(proxy_type.clone(), ProxyParameters::default())
+                    .validate()
+                    .is_err(),
+                "{proxy_type} without credentials should be invalid"
+            );
+        }
+
+        assert_eq!(
+            proxy(ProxyType::VMess, ProxyParameters::default()).validate(),
+            Err(vec!["VMess requires a uuid".to_string()])
+        );
+        assert_eq!(
+            proxy(ProxyType::Trojan, ProxyParameters::default()).validate(),
+            Err(vec!["Trojan requires a password".to_string()])
+        );
+        // Every problem is reported, not just the first
+        assert_eq!(
+            proxy(ProxyType::Shadowsocks, ProxyParameters::default()).validate(),
+            Err(vec![
+                "Shadowsocks requires a cipher".to_string(),
+                "Shadowsocks requires a password".to_string(),
+            ])
+        );
+        // Blank strings count as missing
+        let blank = ProxyParameters {
+            password: Some("  ".to_string()),
+            ..Default::default()
+        };
+        assert!(proxy(ProxyType::Trojan, blank).validate().is_err());
+    }
+
+    #[test]
+    fn test_tuic_type_round_trip() {
+        let yaml = "name: tuic-node\ntype: tuic\nserver: example.com\nport: 443\nuuid: 00000000-0000-0000-0000-000000000001\npassword: secret\ncongestion-controller: bbr\n";
+        let proxy: ProxyConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(proxy.proxy_type, ProxyType::Tuic);
+        assert_eq!(
+            proxy.config.extra["congestion-controller"].as_str(),
+            Some("bbr")
+        );
+
+        let value = serde_yaml::to_value(&proxy).unwrap();
+        assert_eq!(value["type"].as_str(), Some("tuic"));
+        let reparsed: ProxyConfig = serde_yaml::from_value(value).unwrap();
+        assert_eq!(reparsed.proxy_type, ProxyType::Tuic);
+
+        assert_eq!("TUIC".parse::<ProxyType>(), Ok(ProxyType::Tuic));
+        assert_eq!(ProxyType::Tuic.to_string(), "TUIC");
+        assert!(ProxyType::NAMES.contains(&("tuic", ProxyType::Tuic)));
+        assert_eq!("WG".parse::<ProxyType>(), Ok(ProxyType::WireGuard));
+        assert!("nope".parse::<ProxyType>().is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Data quota reported by a subscription's `subscription-userinfo` header
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriptionInfo {
+    pub upload: Option<u64>,
+    pub download: Option<u64>,
+    pub total: Option<u64>,
+    pub expire: Option<DateTime<Utc>>,
+}
+
+impl SubscriptionInfo {
+    /// Parse `upload=...; download=...; total=...; expire=...`. Unknown or malformed fields are
+    /// skipped, returns None when nothing usable is left
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut info = Self::default();
+        let mut found = false;
+
+        for field in header.split(';') {
+            let Some((key, value)) = field.split_once('=') else {
+                continue;
+            };
+            // Some providers send floats like `total=1.073741824E11`
+            let Some(value) = value.trim().parse::<f64>().ok().filter(|v| *v >= 0.0) else {
+                continue;
+            };
+            let value = value as u64;
+
+            match key.trim().to_lowercase().as_str() {
+                "upload" => info.upload = Some(value),
+                "download" => info.download = Some(value),
+                "total" => info.total = Some(value),
+                // 0 means the subscription never expires
+                "expire" => {
+                    info.expire = DateTime::from_timestamp(value as i64, 0).filter(|_| value > 0)
+                }
+                _ => continue,
+            }
+            found = true;
+        }
+
+        found.then_some(info)
+    }
+
+    /// Bytes used so far (upload + download)
+    pub fn used(&self) -> u64 {
+        self.upload
+            .unwrap_or(0)
+            .saturating_add(self.download.unwrap_or(0))
+    }
+}
+
+impl std::fmt::Display for SubscriptionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gb = |bytes: u64| {
+            let value = bytes as f64 / GB;
+            if value.fract() == 0.0 {
+                format!("{value:.0}")
+            } else {
+                format!("{value:.1}")
+            }
+        };
+
+        match self.total {
+            Some(total) => write!(f, "{}/{} GB used", gb(self.used()), gb(total))?,
+            None => write!(f, "{} GB used", gb(self.used()))?,
+        }
+        match self.expire {
+            Some(expire) => write!(f, ", expires {}", expire.format("%Y-%m-%d")),
+            None => write!(f, ", no expiry"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_subscription_userinfo() {
+        let info = SubscriptionInfo::parse(
+            "upload=4294967296; download=44246753689; total=214748364800; expire=1735689600",
+        )
+        .unwrap();
+
+        assert_eq!(info.upload, Some(4 * 1024 * 1024 * 1024));
+        assert_eq!(info.total, Some(200 * 1024 * 1024 * 1024));
+        assert_eq!(
+            info.expire,
+            Some(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(info.to_string(), "45.2/200 GB used, expires 2025-01-01");
+    }
+
+    #[test]
+    fn test_parse_subscription_userinfo_leniently() {
+        let info = SubscriptionInfo::parse("download=1073741824;total=;bogus;expire=0").unwrap();
+        assert_eq!(info.upload, None);
+        assert_eq!(info.total, None);
+        assert_eq!(info.expire, None);
+        assert_eq!(info.to_string(), "1 GB used, no expiry");
+
+        assert_eq!(SubscriptionInfo::parse("nothing useful"), None);
+
+        // Values come from the provider, a bogus pair must not overflow
+        let info = SubscriptionInfo::parse(&format!("upload={0}; download={0}", u64::MAX)).unwrap();
+        assert_eq!(info.used(), u64::MAX);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::config::ProxyType;
+use crate::core::{SpeedTestResult, StatisticalAnalysis};
+use serde::{Deserialize, Serialize};
+
+/// Statistics for one proxy across repeated runs of the test suite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregatedResult {
+    pub proxy_name: String,
+    pub proxy_type: ProxyType,
+    pub runs: usize,
+    /// Fraction of runs that succeeded (0.0 - 1.0)
+    pub success_rate: f64,
+    /// Mean and standard deviation over successful runs, in milliseconds
+    pub latency_mean_ms: Option<f64>,
+    pub latency_std_dev_ms: Option<f64>,
+    /// Mean and standard deviation over successful runs, in bytes per second
+    pub download_mean: f64,
+    pub download_std_dev: f64,
+    /// Raw result of every run, in run order
+    pub results: Vec<SpeedTestResult>,
+}
+
+impl AggregatedResult {
+    /// Aggregate the results of a single proxy
+    fn from_results(results: Vec<SpeedTestResult>) -> Self {
+        let successful: Vec<_> = results.iter().filter(|r| r.is_successful()).collect();
+
+        let latencies: Vec<f64> = successful
+            .iter()
+            .filter_map(|r| r.latency)
+            .map(|l| l.as_secs_f64() * 1000.0)
+            .collect();
+        let (latency_mean_ms, latency_std_dev_ms) = if latencies.is_empty() {
+            (None, None)
+        } else {
+            let mean = StatisticalAnalysis::mean_f64(&latencies);
+            (
+                Some(mean),
+                Some(StatisticalAnalysis::std_deviation_f64(&latencies, mean)),
+            )
+        };
+
+        let downloads: Vec<f64> = successful.iter().map(|r| r.download_speed).collect();
+        let download_mean = StatisticalAnalysis::mean_f64(&downloads);
+
+        Self {
+            proxy_name: results[0].proxy_name.clone(),
+            proxy_type: results[0].proxy_type.clone(),
+            runs: results.len(),
+            success_rate: successful.len() as f64 / results.len() as f64,
+            latency_mean_ms,
+            latency_std_dev_ms,
+            download_mean,
+            download_std_dev: StatisticalAnalysis::std_deviation_f64(&downloads, download_mean),
+            results,
+        }
+    }
+}
+
+/// Group the results of every run by proxy name, keeping first-seen order
+pub fn aggregate_runs(runs: Vec<Vec<SpeedTestResult>>) -> Vec<AggregatedResult> {
+    let mut grouped: Vec<Vec<SpeedTestResult>> = Vec::new();
+
+    for result in runs.into_iter().flatten() {
+        match grouped
+            .iter_mut()
+            .find(|group| group[0].proxy_name == result.proxy_name)
+        {
+            Some(group) => group.push(result),
+            None => grouped.push(vec![result]),
+        }
+    }
+
+    grouped
+        .into_iter()
+        .map(AggregatedResult::from_results)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(latency_ms: Option<u64>, download: f64) -> Vec<SpeedTestResult> {
+        let mut result = SpeedTestResult::failed("node".to_string(), ProxyType::Http, "x".into());
+        if let Some(latency_ms) = latency_ms {
+            result.error = None;
+            result.latency = Some(Duration::from_millis(latency_ms));
+            result.download_speed = download;
+        }
+        vec![result]
+    }
+
+    #[test]
+    fn test_aggregate_three_runs() {
+        let runs = vec![run(Some(100), 10.0), run(Some(200), 30.0), run(None, 0.0)];
+
+        let aggregated = aggregate_runs(runs);
+        assert_eq!(aggregated.len(), 1);
+        let node = &aggregated[0];
+
+        assert_eq!(node.runs, 3);
+        assert_eq!(node.results.len(), 3);
+        assert!((node.success_rate - 2.0 / 3.0).abs() < 1e-9);
+        // Failed runs are left out of the mean and population standard deviation
+        assert!((node.latency_mean_ms.unwrap() - 150.0).abs() < 1e-9);
+        assert!((node.latency_std_dev_ms.unwrap() - 50.0).abs() < 1e-9);
+        assert!((node.download_mean - 20.0).abs() < 1e-9);
+        assert!((node.download_std_dev - 10.0).abs() < 1e-9);
+    }
+}
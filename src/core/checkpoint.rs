@@ -0,0 +1,197 @@
+use crate::Result;
+use crate::config::ProxyConfig;
+use crate::core::SpeedTestResult;
+use crate::output::ResultFormatter;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// JSON lines file recording every completed result so an interrupted run can resume.
+/// Results are keyed by proxy name: each proxy is recorded once, so `--repeat` runs
+/// don't add duplicates.
+pub struct Checkpoint {
+    file: Mutex<CheckpointFile>,
+}
+
+struct CheckpointFile {
+    file: File,
+    /// Names of the proxies with a result in the file
+    recorded: HashSet<String>,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint at `path`, returning the results it already holds.
+    ///
+    /// With `restart` any existing checkpoint is discarded and nothing is returned.
+    pub fn open<P: AsRef<Path>>(path: P, restart: bool) -> Result<(Self, Vec<SpeedTestResult>)> {
+        let path = path.as_ref();
+        let completed = if restart || !path.exists() {
+            Vec::new()
+        } else {
+            Self::read_results(path)?
+        };
+        // A write cut short by a crash leaves the last line unterminated
+        let unterminated = !restart
+            && std::fs::read(path).is_ok_and(|bytes| !bytes.is_empty() && !bytes.ends_with(b"\n"));
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(!restart)
+            .write(true)
+            .truncate(restart)
+            .open(path)?;
+
+        if unterminated {
+            file.write_all(b"\n")?;
+        }
+
+        if !completed.is_empty() {
+            info!(
+                "Resuming from checkpoint {} with {} completed proxies",
+                path.display(),
+                completed.len()
+            );
+        }
+
+        let recorded = completed.iter().map(|r| r.proxy_name.clone()).collect();
+        Ok((
+            Self {
+                file: Mutex::new(CheckpointFile { file, recorded }),
+            },
+            completed,
+        ))
+    }
+
+    /// Read the results stored in a checkpoint file, the first one of each proxy.
+    /// A truncated last line from a crash is skipped rather than failing the resume.
+    fn read_results(path: &Path) -> Result<Vec<SpeedTestResult>> {
+        let content = std::fs::read_to_string(path)?;
+        let mut results = Vec::new();
+        let mut names = HashSet::new();
+
+        for (line_num, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<SpeedTestResult>(line) {
+                Ok(result) => {
+                    if names.insert(result.proxy_name.clone()) {
+                        results.push(result);
+                    }
+                }
+                Err(e) => warn!(
+                    "Skipping unreadable checkpoint line {} in {}: {}",
+                    line_num + 1,
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Append a result and flush it to disk immediately. A proxy that already has a
+    /// result in the checkpoint is not written again.
+    pub fn append(&self, result: &SpeedTestResult) -> io::Result<()> {
+        let mut state = self.file.lock().unwrap();
+        if state.recorded.contains(&result.proxy_name) {
+            return Ok(());
+        }
+        ResultFormatter::write_json_line(&mut state.file, result)?;
+        state.file.flush()?;
+        state.recorded.insert(result.proxy_name.clone());
+        Ok(())
+    }
+
+    /// Drop proxies that already have a result in `completed`, matched by name
+    pub fn skip_completed(proxies: &mut Vec<ProxyConfig>, completed: &[SpeedTestResult]) {
+        let done: HashSet<_> = completed.iter().map(|r| r.proxy_name.as_str()).collect();
+        let original_count = proxies.len();
+        proxies.retain(|p| !done.contains(p.name.as_str()));
+
+        if proxies.len() < original_count {
+            info!(
+                "Skipping {} proxies already in the checkpoint",
+                original_count - proxies.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use crate::core::speedtest::tests::spawn_counting_proxy;
+    use crate::core::{SpeedTestConfig, SpeedTester};
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_resume_only_tests_remaining_proxies() {
+        let (port, requests) = spawn_counting_proxy(0).await;
+        let proxy = |name: &str| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.jsonl");
+        let previous = SpeedTestResult::failed("done".to_string(), ProxyType::Http, "x".into());
+        std::fs::write(
+            &path,
+            format!(
+                "{}\n{{\"truncated",
+                serde_json::to_string(&previous).unwrap()
+            ),
+        )
+        .unwrap();
+
+        let (checkpoint, completed) = Checkpoint::open(&path, false).unwrap();
+        assert_eq!(completed.len(), 1);
+
+        let mut proxies = vec![proxy("done"), proxy("todo")];
+        Checkpoint::skip_completed(&mut proxies, &completed);
+        assert_eq!(proxies.len(), 1);
+
+        let checkpoint = Arc::new(checkpoint);
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            latency_iterations: 2,
+            ..Default::default()
+        })
+        .unwrap()
+        .with_result_callback(Box::new({
+            let checkpoint = checkpoint.clone();
+            move |result| checkpoint.append(result).unwrap()
+        }));
+        let results = tester.test_proxies(proxies.clone(), None).await.unwrap();
+
+        assert_eq!(results[0].proxy_name, "todo");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // A repeated run records nothing new for proxies already in the checkpoint
+        tester.test_proxies(proxies, None).await.unwrap();
+        checkpoint.append(&previous).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.matches("\"proxy_name\"").count(), 2);
+
+        let (_, stored) = Checkpoint::open(&path, false).unwrap();
+        let names: Vec<_> = stored.iter().map(|r| r.proxy_name.as_str()).collect();
+        assert_eq!(names, ["done", "todo"]);
+
+        let (_, restarted) = Checkpoint::open(&path, true).unwrap();
+        assert!(restarted.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+}
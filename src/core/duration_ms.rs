@@ -0,0 +1,128 @@
+//! Serde helpers that store a `Duration` as whole milliseconds, so JSON and YAML
+//! results read `latency: 123` instead of `{secs, nanos}`. Results written before
+//! the switch still load, the old `{secs, nanos}` form is accepted too.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::Duration;
+
+/// Either representation found in saved results
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredDuration {
+    Millis(u64),
+    Legacy(Duration),
+}
+
+impl From<StoredDuration> for Duration {
+    fn from(stored: StoredDuration) -> Self {
+        match stored {
+            StoredDuration::Millis(millis) => Duration::from_millis(millis),
+            StoredDuration::Legacy(duration) => duration,
+        }
+    }
+}
+
+pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    (duration.as_millis() as u64).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    StoredDuration::deserialize(deserializer).map(Duration::from)
+}
+
+/// Same for `Option<Duration>`, `None` stays `null`
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        duration
+            .map(|duration| duration.as_millis() as u64)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<StoredDuration>::deserialize(deserializer)?.map(Duration::from))
+    }
+}
+
+/// Same for `Vec<Duration>`
+pub mod vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        durations: &[Duration],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(durations.iter().map(|d| d.as_millis() as u64))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Duration>, D::Error> {
+        Ok(Vec::<StoredDuration>::deserialize(deserializer)?
+            .into_iter()
+            .map(Duration::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ProxyType;
+    use crate::core::{PhaseTimings, SpeedTestResult};
+    use std::time::Duration;
+
+    #[test]
+    fn test_result_round_trip() {
+        let result = SpeedTestResult {
+            latency: Some(Duration::from_millis(123)),
+            jitter: Some(Duration::from_millis(4)),
+            latency_samples: vec![Duration::from_millis(120), Duration::from_millis(126)],
+            download_time: Some(Duration::from_millis(2500)),
+            timings: PhaseTimings {
+                latency: Duration::from_millis(800),
+                download: Some(Duration::from_secs(3)),
+                ..Default::default()
+            },
+            error: None,
+            ..SpeedTestResult::failed("node".to_string(), ProxyType::Http, String::new())
+        };
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["latency"], 123);
+        assert_eq!(json["jitter"], 4);
+        assert_eq!(json["latency_samples"], serde_json::json!([120, 126]));
+        assert_eq!(json["download_time"], 2500);
+        assert!(json["upload_time"].is_null());
+        assert_eq!(json["timings"]["latency"], 800);
+
+        let parsed: SpeedTestResult = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.latency, result.latency);
+        assert_eq!(parsed.jitter, result.jitter);
+        assert_eq!(parsed.latency_samples, result.latency_samples);
+        assert_eq!(parsed.download_time, result.download_time);
+        assert_eq!(parsed.upload_time, None);
+        assert_eq!(parsed.timings, result.timings);
+    }
+
+    #[test]
+    fn test_legacy_secs_nanos_still_parse() {
+        let mut json = serde_json::to_value(SpeedTestResult::failed(
+            "node".to_string(),
+            ProxyType::Http,
+            String::new(),
+        ))
+        .unwrap();
+        json["latency"] = serde_json::json!({"secs": 0, "nanos": 45_000_000});
+        json["timings"]["latency"] = serde_json::json!({"secs": 1, "nanos": 0});
+
+        let parsed: SpeedTestResult = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.latency, Some(Duration::from_millis(45)));
+        assert_eq!(parsed.timings.latency, Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,189 @@
+//! Coarse cause of a failed proxy test, recorded next to the error message so
+//! failures can be filtered and counted without parsing strings.
+
+use serde::{Deserialize, Serialize};
+
+/// Why a proxy test failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// A request or the whole test ran out of time
+    Timeout,
+    /// The proxy server's hostname didn't resolve
+    Dns,
+    /// The proxy or server couldn't be connected to
+    ConnectionRefused,
+    /// The TLS handshake failed
+    Tls,
+    /// mihomo couldn't switch to the proxy
+    ProxySwitch,
+    /// Latency above `--max-latency`
+    LatencyExceeded,
+    /// Download or upload below the configured minimum
+    SpeedBelowThreshold,
+    /// The speed test server answered with an error status
+    ServerError,
+    Other,
+}
+
+impl ErrorKind {
+    /// Classify an error from a test phase by the most specific cause in its chain
+    pub fn classify(error: &anyhow::Error) -> Self {
+        let causes: Vec<_> = error.chain().collect();
+
+        let is_timeout = causes.iter().any(|cause| {
+            cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout())
+                || cause.is::<tokio::time::error::Elapsed>()
+                || cause
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+        });
+        if is_timeout {
+            return ErrorKind::Timeout;
+        }
+
+        // TLS failures surface as connect errors, only their message tells them apart
+        let is_tls = causes.iter().any(|cause| {
+            let message = cause.to_string().to_lowercase();
+            ["tls", "ssl", "certificate"]
+                .iter()
+                .any(|needle| message.contains(needle))
+        });
+        if is_tls {
+            return ErrorKind::Tls;
+        }
+
+        let is_connect = causes.iter().any(|cause| {
+            cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_connect())
+                || cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+                    matches!(
+                        e.kind(),
+                        std::io::ErrorKind::ConnectionRefused
+                            | std::io::ErrorKind::ConnectionReset
+                            | std::io::ErrorKind::ConnectionAborted
+                    )
+                })
+        });
+        if is_connect {
+            return ErrorKind::ConnectionRefused;
+        }
+
+        let is_status = causes.iter().any(|cause| {
+            cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_status())
+        });
+        if is_status {
+            return ErrorKind::ServerError;
+        }
+
+        ErrorKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answer every connection with `response` after reading the request
+    async fn spawn_server(response: &'static [u8], delay: Duration) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    tokio::time::sleep(delay).await;
+                    let _ = socket.write_all(response).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .no_proxy()
+            .timeout(Duration::from_millis(300))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_classify_reqwest_errors() {
+        let slow = spawn_server(b"", Duration::from_secs(5)).await;
+        let error = client()
+            .get(format!("http://127.0.0.1:{slow}/"))
+            .send()
+            .await
+            .unwrap_err();
+        let error = anyhow::Error::new(error).context("All ping attempts failed");
+        assert_eq!(ErrorKind::classify(&error), ErrorKind::Timeout);
+
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let error = client()
+            .get(format!("http://127.0.0.1:{closed_port}/"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            ErrorKind::classify(&error.into()),
+            ErrorKind::ConnectionRefused
+        );
+
+        let unavailable = spawn_server(
+            b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            Duration::ZERO,
+        )
+        .await;
+        let error = client()
+            .get(format!("http://127.0.0.1:{unavailable}/"))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap_err();
+        assert_eq!(ErrorKind::classify(&error.into()), ErrorKind::ServerError);
+
+        // Plain HTTP where the client expects a TLS handshake
+        let plain = spawn_server(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+            Duration::ZERO,
+        )
+        .await;
+        let error = client()
+            .get(format!("https://127.0.0.1:{plain}/"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::classify(&error.into()), ErrorKind::Tls);
+    }
+
+    #[test]
+    fn test_classify_other_errors() {
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        let error = anyhow::Error::new(refused).context("All TCP connect attempts failed");
+        assert_eq!(ErrorKind::classify(&error), ErrorKind::ConnectionRefused);
+
+        let timed_out = std::io::Error::new(std::io::ErrorKind::TimedOut, "TCP connect timed out");
+        assert_eq!(ErrorKind::classify(&timed_out.into()), ErrorKind::Timeout);
+
+        let error = anyhow::anyhow!("All ping attempts failed");
+        assert_eq!(ErrorKind::classify(&error), ErrorKind::Other);
+
+        assert_eq!(
+            serde_json::to_string(&ErrorKind::SpeedBelowThreshold).unwrap(),
+            "\"speed_below_threshold\""
+        );
+    }
+}
@@ -1,13 +1,56 @@
 use crate::Result;
-use crate::config::ProxyConfig;
+use crate::config::{ProxyConfig, ProxyType};
+use crate::network::IpVersion;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 use std::time::Duration;
 use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 
+/// URL used by mihomo's delay test when none is configured
+pub const DEFAULT_DELAY_TEST_URL: &str = "http://www.gstatic.com/generate_204";
+
+/// Trailing lines of each mihomo output stream included in a startup failure
+const STARTUP_OUTPUT_LINES: usize = 20;
+
+/// Drains one of mihomo's output pipes for the life of the process, so it never
+/// blocks on a full pipe, keeping the last `STARTUP_OUTPUT_LINES` lines
+struct OutputTail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    reader: JoinHandle<()>,
+}
+
+impl OutputTail {
+    fn spawn(pipe: impl Read + Send + 'static) -> Self {
+        let lines = Arc::new(Mutex::new(VecDeque::with_capacity(STARTUP_OUTPUT_LINES)));
+        let tail = Arc::clone(&lines);
+        let reader = std::thread::spawn(move || {
+            let mut pipe = BufReader::new(pipe);
+            let mut line = Vec::new();
+            while matches!(pipe.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                let mut tail = tail.lock().unwrap();
+                if tail.len() == STARTUP_OUTPUT_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+                line.clear();
+            }
+        });
+        Self { lines, reader }
+    }
+
+    /// Wait for the pipe to close and return the kept lines
+    fn finish(self) -> Vec<String> {
+        let _ = self.reader.join();
+        self.lines.lock().unwrap().drain(..).collect()
+    }
+}
+
 /// Mihomo process manager for real proxy testing
 pub struct MihomoRunner {
     config_dir: PathBuf,
@@ -15,6 +58,14 @@ pub struct MihomoRunner {
     process: Option<Child>,
     api_port: u16,
     proxy_port: u16,
+    created_config_dir: bool,
+    keep_config: bool,
+    ip_version: IpVersion,
+    validate: bool,
+    fresh_connections: bool,
+    connect_timeout: Option<Duration>,
+    hysteria_up: Option<u32>,
+    hysteria_down: Option<u32>,
 }
 
 /// Mihomo configuration structure
@@ -29,12 +80,27 @@ pub struct MihomoConfig {
     pub log_level: String,
     #[serde(rename = "external-controller")]
     pub external_controller: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<bool>,
     pub proxies: Vec<ProxyConfig>,
     #[serde(rename = "proxy-groups")]
     pub proxy_groups: Vec<ProxyGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub listeners: Vec<Listener>,
     pub rules: Vec<String>,
 }
 
+/// Extra inbound listener pinned to a single proxy group
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Listener {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub listener_type: String,
+    pub port: u16,
+    pub listen: String,
+    pub proxy: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProxyGroup {
     pub name: String,
@@ -79,6 +145,7 @@ impl MihomoRunner {
         };
 
         // Create config directory if it doesn't exist
+        let created_config_dir = !config_dir.exists();
         std::fs::create_dir_all(&config_dir)?;
 
         Ok(Self {
@@ -87,9 +154,61 @@ impl MihomoRunner {
             process: None,
             api_port,
             proxy_port,
+            created_config_dir,
+            keep_config: false,
+            ip_version: IpVersion::Auto,
+            validate: true,
+            fresh_connections: false,
+            connect_timeout: None,
+            hysteria_up: None,
+            hysteria_down: None,
         })
     }
 
+    /// Keep the generated config on disk instead of removing it in `cleanup`
+    pub fn with_keep_config(mut self, keep: bool) -> Self {
+        self.keep_config = keep;
+        self
+    }
+
+    /// Make every proxy in the generated config connect over a single IP family
+    pub fn with_ip_version(mut self, ip_version: IpVersion) -> Self {
+        self.ip_version = ip_version;
+        self
+    }
+
+    /// Build proxy clients that open a new connection to mihomo for every request
+    pub fn with_fresh_connections(mut self, fresh: bool) -> Self {
+        self.fresh_connections = fresh;
+        self
+    }
+
+    /// Fail proxy client connections to mihomo that aren't established within `timeout`.
+    /// This is the local hop only, mihomo's dial to the proxy isn't bounded by it.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Bandwidth hints in Mbps for Hysteria proxies that leave `up`/`down` unset, which
+    /// otherwise start from mihomo's conservative defaults
+    pub fn with_hysteria_bandwidth(mut self, up: Option<u32>, down: Option<u32>) -> Self {
+        self.hysteria_up = up;
+        self.hysteria_down = down;
+        self
+    }
+
+    /// Whether proxy clients skip connection pooling
+    pub fn fresh_connections(&self) -> bool {
+        self.fresh_connections
+    }
+
+    /// Check the generated config with `mihomo -t` before every start
+    pub fn with_validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
     /// Find mihomo binary in system PATH or common locations
     fn find_mihomo_binary() -> Result<PathBuf> {
         let common_names = ["mihomo", "clash", "clash-meta"];
@@ -97,14 +216,14 @@ impl MihomoRunner {
 
         // First try system PATH
         for name in &common_names {
-            if let Ok(output) = Command::new("which").arg(name).output() {
-                if output.status.success() {
-                    let path_str = String::from_utf8_lossy(&output.stdout);
-                    let path = path_str.trim();
-                    if !path.is_empty() {
-                        info!("Found mihomo binary at: {}", path);
-                        return Ok(PathBuf::from(path));
-                    }
+            if let Ok(output) = Command::new("which").arg(name).output()
+                && output.status.success()
+            {
+                let path_str = String::from_utf8_lossy(&output.stdout);
+                let path = path_str.trim();
+                if !path.is_empty() {
+                    info!("Found mihomo binary at: {}", path);
+                    return Ok(PathBuf::from(path));
                 }
             }
         }
@@ -127,40 +246,120 @@ impl MihomoRunner {
 
     /// Generate mihomo configuration for testing
     pub fn generate_config(&self, proxies: &[ProxyConfig]) -> Result<MihomoConfig> {
+        self.generate_parallel_config(proxies, 1)
+    }
+
+    /// Generate mihomo configuration with `slots` independent selector groups
+    ///
+    /// Slot 0 is served by the mixed port, every further slot gets its own
+    /// listener on `proxy_port + slot` so proxies can be tested side by side.
+    pub fn generate_parallel_config(
+        &self,
+        proxies: &[ProxyConfig],
+        slots: usize,
+    ) -> Result<MihomoConfig> {
+        let slots = slots.max(1);
         let proxy_names: Vec<String> = proxies.iter().map(|p| p.name.clone()).collect();
 
+        if self.proxy_port as usize + slots - 1 > u16::MAX as usize {
+            return Err(anyhow::anyhow!(
+                "Not enough ports above {} for {} parallel slots",
+                self.proxy_port,
+                slots
+            ));
+        }
+        if (0..slots).any(|slot| self.slot_port(slot) == self.api_port) {
+            return Err(anyhow::anyhow!(
+                "API port {} overlaps with parallel proxy ports {}-{}",
+                self.api_port,
+                self.proxy_port,
+                self.slot_port(slots - 1)
+            ));
+        }
+
+        let mut proxy_groups: Vec<ProxyGroup> = (0..slots)
+            .map(|slot| ProxyGroup {
+                name: Self::slot_group(slot),
+                group_type: "select".to_string(),
+                proxies: proxy_names.clone(),
+                url: None,
+                interval: None,
+            })
+            .collect();
+        proxy_groups.push(ProxyGroup {
+            name: "AutoTest".to_string(),
+            group_type: "url-test".to_string(),
+            proxies: proxy_names,
+            url: Some(DEFAULT_DELAY_TEST_URL.to_string()),
+            interval: Some(300),
+        });
+
+        let listeners = (1..slots)
+            .map(|slot| Listener {
+                name: format!("speedtest-in-{slot}"),
+                listener_type: "mixed".to_string(),
+                port: self.slot_port(slot),
+                listen: "127.0.0.1".to_string(),
+                proxy: Self::slot_group(slot),
+            })
+            .collect();
+
+        let mut proxies = proxies.to_vec();
+        if let Some(ip_version) = self.ip_version.mihomo_ip_version() {
+            for proxy in &mut proxies {
+                proxy.config.ip_version = Some(ip_version.to_string());
+            }
+        }
+        for proxy in &mut proxies {
+            if !matches!(proxy.proxy_type, ProxyType::Hysteria | ProxyType::Hysteria2) {
+                continue;
+            }
+            if proxy.config.up.is_none() {
+                proxy.config.up = self.hysteria_up.map(|mbps| format!("{mbps} Mbps"));
+            }
+            if proxy.config.down.is_none() {
+                proxy.config.down = self.hysteria_down.map(|mbps| format!("{mbps} Mbps"));
+            }
+        }
+
         let config = MihomoConfig {
             mixed_port: self.proxy_port,
             allow_lan: false,
             mode: "rule".to_string(),
             log_level: "info".to_string(),
             external_controller: format!("127.0.0.1:{}", self.api_port),
-            proxies: proxies.to_vec(),
-            proxy_groups: vec![
-                ProxyGroup {
-                    name: "SpeedTest".to_string(),
-                    group_type: "select".to_string(),
-                    proxies: proxy_names.clone(),
-                    url: None,
-                    interval: None,
-                },
-                ProxyGroup {
-                    name: "AutoTest".to_string(),
-                    group_type: "url-test".to_string(),
-                    proxies: proxy_names,
-                    url: Some("http://www.gstatic.com/generate_204".to_string()),
-                    interval: Some(300),
-                },
-            ],
-            rules: vec!["MATCH,SpeedTest".to_string()],
+            ipv6: match self.ip_version {
+                IpVersion::Auto => None,
+                IpVersion::V4 => Some(false),
+                IpVersion::V6 => Some(true),
+            },
+            proxies,
+            proxy_groups,
+            listeners,
+            rules: vec![format!("MATCH,{}", Self::slot_group(0))],
         };
 
         Ok(config)
     }
 
+    /// Name of the selector group that backs a parallel slot
+    fn slot_group(slot: usize) -> String {
+        format!("SpeedTest-{slot}")
+    }
+
+    /// Local port that routes through a parallel slot
+    fn slot_port(&self, slot: usize) -> u16 {
+        self.proxy_port + slot as u16
+    }
+
+    /// Path of the generated configuration file
+    fn config_path(&self) -> PathBuf {
+        self.config_dir.join("speedtest-config.yaml")
+    }
+
     /// Write configuration to file
     pub fn write_config(&self, config: &MihomoConfig) -> Result<PathBuf> {
-        let config_path = self.config_dir.join("speedtest-config.yaml");
+        let config_path = self.config_path();
         let config_yaml = serde_yaml::to_string(config)?;
         std::fs::write(&config_path, config_yaml)?;
         info!("Generated mihomo config at: {}", config_path.display());
@@ -175,6 +374,9 @@ impl MihomoRunner {
         }
 
         let config_path = self.write_config(config)?;
+        if self.validate {
+            self.test_config_file(&config_path, config).await?;
+        }
 
         info!("Starting mihomo process...");
         debug!(
@@ -191,12 +393,19 @@ impl MihomoRunner {
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?;
+        let stdout = OutputTail::spawn(child.stdout.take().expect("stdout is piped"));
+        let stderr = OutputTail::spawn(child.stderr.take().expect("stderr is piped"));
 
         // Wait for mihomo to start up
         let mut retries = 30; // 3 seconds with 100ms intervals
         while retries > 0 {
-            if let Ok(Some(_)) = child.try_wait() {
-                return Err(anyhow::anyhow!("Mihomo process exited unexpectedly"));
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(Self::startup_failure(
+                    child,
+                    stdout,
+                    stderr,
+                    &format!("Mihomo process exited unexpectedly ({})", status),
+                ));
             }
 
             // Check if API is responding
@@ -212,10 +421,143 @@ impl MihomoRunner {
 
         // Kill the child process if it's still running
         let _ = child.kill();
-        Err(anyhow::anyhow!("Timeout waiting for mihomo to start"))
+        Err(Self::startup_failure(
+            child,
+            stdout,
+            stderr,
+            "Timeout waiting for mihomo to start",
+        ))
     }
 
-    /// Stop mihomo process
+    /// Build a startup error from `reason` plus the tail of what the finished
+    /// process printed, which usually holds mihomo's actual config error
+    fn startup_failure(
+        mut child: Child,
+        stdout: OutputTail,
+        stderr: OutputTail,
+        reason: &str,
+    ) -> anyhow::Error {
+        if let Err(e) = child.wait() {
+            return anyhow::anyhow!("{}: failed to wait for mihomo: {}", reason, e);
+        }
+
+        let mut message = reason.to_string();
+        for (stream, tail) in [("stderr", stderr), ("stdout", stdout)] {
+            let lines = tail.finish();
+            if lines.iter().any(|line| !line.trim().is_empty()) {
+                message.push_str(&format!("\nmihomo {}:\n{}", stream, lines.join("\n")));
+            }
+        }
+
+        anyhow::anyhow!(message)
+    }
+
+    /// Write the config and check it with mihomo's test flag (`-t`) without starting
+    pub async fn validate_config(&self, config: &MihomoConfig) -> Result<()> {
+        let config_path = self.write_config(config)?;
+        self.test_config_file(&config_path, config).await
+    }
+
+    /// Run `mihomo -t` on a written config, naming the offending proxy on failure
+    async fn test_config_file(&self, config_path: &Path, config: &MihomoConfig) -> Result<()> {
+        debug!(
+            "Validating config: {} -t -f {}",
+            self.mihomo_binary.display(),
+            config_path.display()
+        );
+
+        let output = tokio::process::Command::new(&self.mihomo_binary)
+            .arg("-t")
+            .arg("-f")
+            .arg(config_path)
+            .arg("-d")
+            .arg(&self.config_dir)
+            .output()
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to run {} to validate config: {}",
+                    self.mihomo_binary.display(),
+                    e
+                )
+            })?;
+
+        if output.status.success() {
+            debug!("Mihomo config is valid");
+            return Ok(());
+        }
+
+        let text = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Err(anyhow::anyhow!(Self::describe_validation_error(
+            &text, config
+        )))
+    }
+
+    /// Turn `mihomo -t` output into a message, mapping "proxy N:" to the proxy's name
+    fn describe_validation_error(output: &str, config: &MihomoConfig) -> String {
+        let log_msg = regex::Regex::new(r#"msg="((?:[^"\\]|\\.)*)""#).unwrap();
+        let proxy_index = regex::Regex::new(r"proxy (\d+): (.+)").unwrap();
+
+        let messages: Vec<String> = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match log_msg.captures(line) {
+                Some(caps) => caps[1].replace("\\\"", "\""),
+                None => line.to_string(),
+            })
+            .collect();
+
+        for message in &messages {
+            if let Some(caps) = proxy_index.captures(message)
+                && let Some(proxy) = caps[1]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| config.proxies.get(i))
+            {
+                return format!(
+                    "Mihomo rejected the generated config: proxy '{}' is invalid: {}",
+                    proxy.name, &caps[2]
+                );
+            }
+        }
+
+        if messages.is_empty() {
+            "Mihomo rejected the generated config".to_string()
+        } else {
+            format!(
+                "Mihomo rejected the generated config:\n{}",
+                messages.join("\n")
+            )
+        }
+    }
+
+    /// Whether the started mihomo process is still alive. One that exited on its own is
+    /// forgotten, so the next `start` launches a new process.
+    pub fn is_running(&mut self) -> bool {
+        let Some(process) = self.process.as_mut() else {
+            return false;
+        };
+        match process.try_wait() {
+            Ok(None) => true,
+            Ok(Some(status)) => {
+                warn!("Mihomo process exited unexpectedly ({})", status);
+                self.process = None;
+                false
+            }
+            Err(e) => {
+                warn!("Failed to check the mihomo process: {}", e);
+                self.process = None;
+                false
+            }
+        }
+    }
+
+    /// Stop mihomo process, which also closes every slot listener
     pub fn stop(&mut self) -> Result<()> {
         if let Some(mut process) = self.process.take() {
             info!("Stopping mihomo process...");
@@ -226,6 +568,28 @@ impl MihomoRunner {
         Ok(())
     }
 
+    /// Remove the generated config, and the config directory if we created it
+    pub fn cleanup(&self) -> Result<()> {
+        if self.keep_config {
+            info!("Keeping mihomo config at: {}", self.config_dir.display());
+            return Ok(());
+        }
+
+        if self.created_config_dir {
+            if self.config_dir.exists() {
+                std::fs::remove_dir_all(&self.config_dir)?;
+                debug!("Removed mihomo config dir: {}", self.config_dir.display());
+            }
+        } else {
+            let config_path = self.config_path();
+            if config_path.exists() {
+                std::fs::remove_file(&config_path)?;
+                debug!("Removed mihomo config: {}", config_path.display());
+            }
+        }
+        Ok(())
+    }
+
     /// Check if mihomo API is healthy
     async fn check_api_health(&self) -> Result<()> {
         let client = reqwest::Client::new();
@@ -245,8 +609,17 @@ impl MihomoRunner {
 
     /// Switch to a specific proxy
     pub async fn switch_proxy(&self, proxy_name: &str) -> Result<()> {
+        self.switch_slot_proxy(0, proxy_name).await
+    }
+
+    /// Switch the selector group of a parallel slot to a specific proxy
+    pub async fn switch_slot_proxy(&self, slot: usize, proxy_name: &str) -> Result<()> {
         let client = reqwest::Client::new();
-        let url = format!("http://127.0.0.1:{}/proxies/SpeedTest", self.api_port);
+        let url = format!(
+            "http://127.0.0.1:{}/proxies/{}",
+            self.api_port,
+            Self::slot_group(slot)
+        );
 
         let mut body = HashMap::new();
         body.insert("name", proxy_name);
@@ -259,7 +632,7 @@ impl MihomoRunner {
             .await?;
 
         if response.status().is_success() {
-            debug!("Switched to proxy: {}", proxy_name);
+            debug!("Switched slot {} to proxy: {}", slot, proxy_name);
             Ok(())
         } else {
             Err(anyhow::anyhow!(
@@ -294,13 +667,7 @@ impl MihomoRunner {
     /// Test proxy delay using mihomo's built-in delay test
     pub async fn test_proxy_delay(&self, proxy_name: &str, url: Option<&str>) -> Result<u32> {
         let client = reqwest::Client::new();
-        let test_url = url.unwrap_or("http://www.gstatic.com/generate_204");
-        let api_url = format!(
-            "http://127.0.0.1:{}/proxies/{}/delay?timeout=5000&url={}",
-            self.api_port,
-            proxy_name,
-            urlencoding::encode(test_url)
-        );
+        let api_url = self.delay_api_url(proxy_name, url);
 
         let response = client
             .get(&api_url)
@@ -324,6 +691,17 @@ impl MihomoRunner {
         }
     }
 
+    /// Build the delay API URL, percent-encoding the proxy name and test URL
+    fn delay_api_url(&self, proxy_name: &str, url: Option<&str>) -> String {
+        let test_url = url.unwrap_or(DEFAULT_DELAY_TEST_URL);
+        format!(
+            "http://127.0.0.1:{}/proxies/{}/delay?timeout=5000&url={}",
+            self.api_port,
+            urlencoding::encode(proxy_name),
+            urlencoding::encode(test_url)
+        )
+    }
+
     /// Get the proxy port for HTTP client configuration
     pub fn proxy_port(&self) -> u16 {
         self.proxy_port
@@ -331,16 +709,30 @@ impl MihomoRunner {
 
     /// Create an HTTP client configured to use mihomo proxy
     pub fn create_proxy_client(&self, timeout: Duration) -> Result<reqwest::Client> {
-        let proxy_url = format!("http://127.0.0.1:{}", self.proxy_port);
+        self.create_slot_proxy_client(0, timeout)
+    }
+
+    /// Create an HTTP client that routes through a parallel slot
+    pub fn create_slot_proxy_client(
+        &self,
+        slot: usize,
+        timeout: Duration,
+    ) -> Result<reqwest::Client> {
+        let proxy_url = format!("http://127.0.0.1:{}", self.slot_port(slot));
         let proxy = reqwest::Proxy::http(&proxy_url)?;
 
-        let client = reqwest::Client::builder()
+        let mut builder = reqwest::Client::builder()
             .proxy(proxy)
             .timeout(timeout)
-            .danger_accept_invalid_certs(true)
-            .build()?;
+            .danger_accept_invalid_certs(true);
+        if self.fresh_connections {
+            builder = builder.pool_max_idle_per_host(0);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
 
-        Ok(client)
+        Ok(builder.build()?)
     }
 }
 
@@ -349,5 +741,366 @@ impl Drop for MihomoRunner {
         if let Err(e) = self.stop() {
             error!("Failed to stop mihomo process: {}", e);
         }
+        if let Err(e) = self.cleanup() {
+            error!("Failed to clean up mihomo config: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cleanup_removes_generated_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("mihomo");
+
+        // A directory we created is removed entirely
+        let created = dir.path().join("mihomo-temp");
+        let runner =
+            MihomoRunner::new(created.as_path(), Some(binary.as_path()), 19090, 17890).unwrap();
+        let config_path = runner
+            .write_config(&runner.generate_config(&[]).unwrap())
+            .unwrap();
+        assert!(config_path.exists());
+        runner.cleanup().unwrap();
+        assert!(!config_path.exists());
+        assert!(!created.exists());
+
+        // A pre-existing directory only loses the generated file
+        let existing = dir.path().join("existing");
+        std::fs::create_dir(&existing).unwrap();
+        std::fs::write(existing.join("keep.txt"), "user data").unwrap();
+        let runner =
+            MihomoRunner::new(existing.as_path(), Some(binary.as_path()), 19090, 17890).unwrap();
+        let config_path = runner
+            .write_config(&runner.generate_config(&[]).unwrap())
+            .unwrap();
+        drop(runner);
+        assert!(!config_path.exists());
+        assert!(existing.join("keep.txt").exists());
+
+        // --keep-mihomo-config leaves everything in place
+        let runner = MihomoRunner::new(existing.as_path(), Some(binary.as_path()), 19090, 17890)
+            .unwrap()
+            .with_keep_config(true);
+        let config_path = runner
+            .write_config(&runner.generate_config(&[]).unwrap())
+            .unwrap();
+        runner.cleanup().unwrap();
+        assert!(config_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_running_notices_exited_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("mihomo");
+        let mut runner =
+            MihomoRunner::new(dir.path(), Some(binary.as_path()), 19090, 17890).unwrap();
+        assert!(!runner.is_running());
+
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        runner.process = Some(child);
+        assert!(!runner.is_running());
+        assert!(runner.process.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_tail_drains_chatty_process() {
+        // Far more than a pipe buffer holds; the child would block if nobody read it
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg("seq 1 100000")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let tail = OutputTail::spawn(child.stdout.take().unwrap());
+        assert!(child.wait().unwrap().success());
+
+        let lines = tail.finish();
+        assert_eq!(lines.len(), STARTUP_OUTPUT_LINES);
+        assert_eq!(lines.last().unwrap(), "100000");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_startup_failure_includes_process_output() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("mihomo");
+        let mut script = String::from("#!/bin/sh\n");
+        for i in 0..30 {
+            script.push_str(&format!("echo 'log line {}'\n", i));
+        }
+        script.push_str("echo 'parse config error: proxy 0: unsupported cipher' >&2\nexit 1\n");
+        std::fs::write(&binary, script).unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config_dir = dir.path().join("config");
+        let mut runner =
+            MihomoRunner::new(config_dir.as_path(), Some(binary.as_path()), 19391, 17391)
+                .unwrap()
+                .with_validate(false);
+        let config = runner.generate_config(&[]).unwrap();
+
+        let message = runner.start(&config).await.unwrap_err().to_string();
+        assert!(message.starts_with("Mihomo process exited unexpectedly"));
+        assert!(message.contains("unsupported cipher"));
+        // Only the tail of stdout is kept
+        assert!(message.contains("log line 29"));
+        assert!(message.contains("log line 10"));
+        assert!(!message.contains("log line 9\n"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_validate_config_names_offending_proxy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("mihomo");
+        // Emulates `mihomo -t`: the config is rejected if it mentions the bad cipher
+        std::fs::write(
+            &binary,
+            r#"#!/bin/sh
+[ "$1" = "-t" ] || exit 2
+if grep -q rc4-fake "$3"; then
+  echo 'time="2025-01-01T00:00:00Z" level=error msg="proxy 1: ss rc4-fake: unsupported cipher"'
+  echo 'configuration file test failed'
+  exit 1
+fi
+echo 'configuration file test is successful'
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let runner = MihomoRunner::new(dir.path(), Some(binary.as_path()), 19090, 17890).unwrap();
+        let proxy = |name: &str, cipher: &str| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Shadowsocks,
+            server: "127.0.0.1".to_string(),
+            port: 8388,
+            config: crate::config::ProxyParameters {
+                cipher: Some(cipher.to_string()),
+                password: Some("secret".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let good = runner
+            .generate_config(&[proxy("ok", "aes-128-gcm")])
+            .unwrap();
+        runner.validate_config(&good).await.unwrap();
+
+        let bad = runner
+            .generate_config(&[proxy("ok", "aes-128-gcm"), proxy("broken", "rc4-fake")])
+            .unwrap();
+        let message = runner.validate_config(&bad).await.unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "Mihomo rejected the generated config: proxy 'broken' is invalid: ss rc4-fake: unsupported cipher"
+        );
+    }
+
+    #[test]
+    fn test_generate_parallel_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            19090,
+            17890,
+        )
+        .unwrap();
+        let proxies = vec![ProxyConfig {
+            name: "node".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port: 8080,
+            config: Default::default(),
+        }];
+
+        let config = runner.generate_parallel_config(&proxies, 3).unwrap();
+        let groups: Vec<_> = config
+            .proxy_groups
+            .iter()
+            .map(|g| g.name.as_str())
+            .collect();
+        assert_eq!(
+            groups,
+            ["SpeedTest-0", "SpeedTest-1", "SpeedTest-2", "AutoTest"]
+        );
+        let listeners: Vec<_> = config
+            .listeners
+            .iter()
+            .map(|l| (l.port, l.proxy.as_str()))
+            .collect();
+        assert_eq!(listeners, [(17891, "SpeedTest-1"), (17892, "SpeedTest-2")]);
+        assert_eq!(config.rules, ["MATCH,SpeedTest-0"]);
+
+        let single = runner.generate_config(&proxies).unwrap();
+        assert!(single.listeners.is_empty());
+        assert!(
+            !serde_yaml::to_string(&single)
+                .unwrap()
+                .contains("listeners")
+        );
+
+        let clash = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            17891,
+            17890,
+        )
+        .unwrap();
+        assert!(clash.generate_parallel_config(&proxies, 2).is_err());
+    }
+
+    #[test]
+    fn test_parallel_config_keeps_ssr_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            19090,
+            17890,
+        )
+        .unwrap();
+        let link = "ssr.example.com:443:auth_aes128_md5:chacha20:tls1.2_ticket_auth:c2VjcmV0/?obfsparam=Y2RuLmV4YW1wbGUuY29t&protoparam=NjQ6dXNlcg";
+        let link = format!(
+            "ssr://{}",
+            base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, link)
+        );
+        let proxy = crate::config::ConfigLoader::new()
+            .parse_proxy_url(&link, 1)
+            .unwrap();
+
+        let config = runner.generate_parallel_config(&[proxy], 2).unwrap();
+        let yaml: serde_yaml::Value =
+            serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+        let node = &yaml["proxies"][0];
+        assert_eq!(node["type"].as_str(), Some("ssr"));
+        assert_eq!(node["cipher"].as_str(), Some("chacha20"));
+        assert_eq!(node["password"].as_str(), Some("secret"));
+        assert_eq!(node["protocol"].as_str(), Some("auth_aes128_md5"));
+        assert_eq!(node["protocol-param"].as_str(), Some("64:user"));
+        assert_eq!(node["obfs"].as_str(), Some("tls1.2_ticket_auth"));
+        assert_eq!(node["obfs-param"].as_str(), Some("cdn.example.com"));
+
+        // Mihomo reads it back as the same SSR node
+        let reparsed: ProxyConfig = serde_yaml::from_value(node.clone()).unwrap();
+        assert_eq!(reparsed.proxy_type, ProxyType::ShadowsocksR);
+    }
+
+    #[test]
+    fn test_ip_version_in_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let proxies = vec![ProxyConfig {
+            name: "node".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "example.com".to_string(),
+            port: 8080,
+            config: Default::default(),
+        }];
+
+        let yaml_for = |ip_version| {
+            let runner = MihomoRunner::new(
+                dir.path(),
+                Some(dir.path().join("mihomo").as_path()),
+                19090,
+                17890,
+            )
+            .unwrap()
+            .with_ip_version(ip_version);
+            serde_yaml::to_string(&runner.generate_config(&proxies).unwrap()).unwrap()
+        };
+
+        let auto = yaml_for(IpVersion::Auto);
+        assert!(!auto.contains("ipv6:"));
+        assert!(!auto.contains("ip-version: ipv"));
+
+        let v4 = yaml_for(IpVersion::V4);
+        assert!(v4.contains("ipv6: false"));
+        assert!(v4.contains("ip-version: ipv4"));
+
+        let v6 = yaml_for(IpVersion::V6);
+        assert!(v6.contains("ipv6: true"));
+        assert!(v6.contains("ip-version: ipv6"));
+    }
+
+    #[test]
+    fn test_hysteria_bandwidth_in_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let proxy = |name: &str, proxy_type, up: Option<&str>| ProxyConfig {
+            name: name.to_string(),
+            proxy_type,
+            server: "example.com".to_string(),
+            port: 443,
+            config: crate::config::ProxyParameters {
+                up: up.map(str::to_string),
+                ..Default::default()
+            },
+        };
+        let proxies = vec![
+            proxy("hy2", ProxyType::Hysteria2, None),
+            proxy("hy2-set", ProxyType::Hysteria2, Some("100 Mbps")),
+            proxy("trojan", ProxyType::Trojan, None),
+        ];
+
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            19090,
+            17890,
+        )
+        .unwrap()
+        .with_hysteria_bandwidth(Some(50), Some(200));
+        let yaml = serde_yaml::to_string(&runner.generate_config(&proxies).unwrap()).unwrap();
+        let config: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let field = |index: usize, key: &str| {
+            config["proxies"][index]
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+
+        assert_eq!(field(0, "up").as_deref(), Some("50 Mbps"));
+        assert_eq!(field(0, "down").as_deref(), Some("200 Mbps"));
+        // Values from the subscription win, a missing one is still filled in
+        assert_eq!(field(1, "up").as_deref(), Some("100 Mbps"));
+        assert_eq!(field(1, "down").as_deref(), Some("200 Mbps"));
+        assert_eq!(field(2, "up"), None);
+        assert_eq!(field(2, "down"), None);
+    }
+
+    #[test]
+    fn test_delay_api_url_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            19090,
+            17890,
+        )
+        .unwrap();
+
+        assert_eq!(
+            runner.delay_api_url(
+                "HK #1",
+                Some("http://cp.cloudflare.com/generate_204?a=b&c=d")
+            ),
+            "http://127.0.0.1:19090/proxies/HK%20%231/delay?timeout=5000&url=http%3A%2F%2Fcp.cloudflare.com%2Fgenerate_204%3Fa%3Db%26c%3Dd"
+        );
+        assert!(
+            runner
+                .delay_api_url("node", None)
+                .ends_with("url=http%3A%2F%2Fwww.gstatic.com%2Fgenerate_204")
+        );
     }
 }
@@ -1,9 +1,23 @@
+pub mod aggregate;
+pub mod checkpoint;
+pub mod duration_ms;
+pub mod error_kind;
 pub mod mihomo_runner;
 pub mod real_speedtest;
+pub mod scheduler;
+pub mod scoring;
 pub mod speedtest;
 pub mod statistics;
 
+pub use aggregate::{AggregatedResult, aggregate_runs};
+pub use checkpoint::Checkpoint;
+pub use error_kind::ErrorKind;
 pub use mihomo_runner::MihomoRunner;
 pub use real_speedtest::RealSpeedTester;
-pub use speedtest::{SpeedTestConfig, SpeedTestResult, SpeedTester};
+pub use scheduler::ProxyScheduler;
+pub use scoring::{ScoreRanges, ScoreWeights, pick_best};
+pub use speedtest::{
+    PER_PROXY_TIMEOUT_ERROR, PhaseTimings, ResultSink, SortKey, SpeedTestConfig, SpeedTestResult,
+    SpeedTester, sort_results,
+};
 pub use statistics::StatisticalAnalysis;
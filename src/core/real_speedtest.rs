@@ -1,76 +1,352 @@
 use crate::Result;
 use crate::config::ProxyConfig;
 use crate::core::mihomo_runner::MihomoRunner;
-use crate::core::{SpeedTestConfig, SpeedTestResult};
+use crate::core::speedtest::{
+    ProgressCallback, probe_server, server_check_client, server_unreachable,
+};
+use crate::core::{
+    ErrorKind, PER_PROXY_TIMEOUT_ERROR, PhaseTimings, ProxyScheduler, ResultSink, SpeedTestConfig,
+    SpeedTestResult, StatisticalAnalysis,
+};
+use crate::network::backend::with_failover;
+use crate::network::bandwidth::{
+    ChunkResult, ConnectionLimit, ThroughputSampler, acquire_connection, check_download_response,
+    download_request,
+};
+use crate::network::latency::tcp_connect_latency;
+use crate::network::{
+    BandwidthResult, DEFAULT_ADAPTIVE_WINDOW, DEFAULT_STALL_TIMEOUT, DnsCache, LatencyMode,
+    LatencyResult, LatencyTarget, PingRequest, SharedBackend, SpeedTestBackend, UploadPayload,
+};
 use chrono::Utc;
-use std::time::Duration;
+use futures::StreamExt;
+use futures::future::join_all;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
 /// Real proxy speed tester that uses mihomo process
 pub struct RealSpeedTester {
     mihomo_runner: MihomoRunner,
     config: SpeedTestConfig,
+    sink: Option<ResultSink>,
+    on_result: Option<ProgressCallback>,
+    server_check: bool,
+    connection_limit: Option<ConnectionLimit>,
+    backend: SharedBackend,
+    fallback_backends: Vec<SharedBackend>,
+    ping: PingRequest,
+    keep_running: bool,
+    /// Resolves proxy servers for the direct TCP connects to them
+    dns: DnsCache,
 }
 
+/// Proxies tried for the server check when the direct request fails
+const SERVER_CHECK_PROXY_ATTEMPTS: usize = 3;
+
 impl RealSpeedTester {
     /// Create a new real speed tester
     pub fn new(mihomo_runner: MihomoRunner, config: SpeedTestConfig) -> Self {
         Self {
+            connection_limit: config.connection_limit(),
+            backend: config.speed_test_backend(),
+            fallback_backends: config.fallback_backends(),
+            ping: PingRequest::new(config.latency_method),
             mihomo_runner,
             config,
+            sink: None,
+            on_result: None,
+            server_check: false,
+            keep_running: false,
+            dns: DnsCache::system(),
         }
     }
 
+    /// Resolve proxy servers through `dns` instead of the system resolver
+    pub fn with_dns_cache(mut self, dns: DnsCache) -> Self {
+        self.dns = dns;
+        self
+    }
+
+    /// Leave mihomo running between calls so repeated runs over the same proxies reuse it,
+    /// it is stopped when the tester is dropped
+    pub fn with_keep_running(mut self, keep: bool) -> Self {
+        self.keep_running = keep;
+        self
+    }
+
+    /// Check the speed test server is reachable once mihomo is up, before testing
+    pub fn with_server_check(mut self, check: bool) -> Self {
+        self.server_check = check;
+        self
+    }
+
+    /// Also push every completed result into `sink`
+    pub fn with_result_sink(mut self, sink: ResultSink) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Call `callback` with every result as soon as its test completes
+    pub fn with_result_callback(mut self, callback: ProgressCallback) -> Self {
+        self.on_result = Some(callback);
+        self
+    }
+
+    /// The primary speed test server followed by the fallbacks, in the order they are tried
+    fn backends(&self) -> impl Iterator<Item = &SharedBackend> {
+        std::iter::once(&self.backend).chain(&self.fallback_backends)
+    }
+
+    /// Probe each speed test server in turn through `client`, returning the URL of
+    /// the first one that answers
+    async fn probe_backends(&self, client: &reqwest::Client) -> Result<String> {
+        let ((), server) = with_failover(self.backends(), |backend| async move {
+            probe_server(client, backend.as_ref()).await
+        })
+        .await?;
+        Ok(server)
+    }
+
     /// Start mihomo and run speed tests
     pub async fn test_proxies(&mut self, proxies: &[ProxyConfig]) -> Result<Vec<SpeedTestResult>> {
+        self.test_proxies_concurrent(proxies, 1).await
+    }
+
+    /// Start mihomo and run up to `max_concurrent` speed tests at once
+    pub async fn test_proxies_concurrent(
+        &mut self,
+        proxies: &[ProxyConfig],
+        max_concurrent: usize,
+    ) -> Result<Vec<SpeedTestResult>> {
         info!("Starting real proxy speed tests with mihomo process");
 
-        // Generate and start mihomo with configuration
-        let mihomo_config = self.mihomo_runner.generate_config(proxies)?;
-        self.mihomo_runner.start(&mihomo_config).await?;
+        // One selector group and listener per concurrently tested proxy
+        let slots = max_concurrent.clamp(1, proxies.len().max(1));
+        if !(self.keep_running && self.mihomo_runner.is_running()) {
+            let mihomo_config = self
+                .mihomo_runner
+                .generate_parallel_config(proxies, slots)?;
+            self.mihomo_runner.start(&mihomo_config).await?;
+        }
 
-        let mut results = Vec::new();
+        // Run inside a block so mihomo is stopped even if the server check fails
+        let results = async {
+            if self.server_check {
+                self.check_server_reachable(proxies).await?;
+            }
+            Ok(self.run_slots(proxies, slots).await)
+        }
+        .await;
 
-        for proxy in proxies {
-            info!("Testing proxy: {}", proxy.name);
-            let result = self.test_single_proxy(proxy).await;
-            results.push(result);
+        if self.keep_running {
+            return results;
         }
 
         // Stop mihomo process
         if let Err(e) = self.mihomo_runner.stop() {
             warn!("Failed to stop mihomo process: {}", e);
         }
+        if let Err(e) = self.mihomo_runner.cleanup() {
+            warn!("Failed to clean up mihomo config: {}", e);
+        }
 
-        Ok(results)
+        results
+    }
+
+    /// Check the speed test server directly, falling back to the first proxies
+    /// that pass mihomo's delay test in case only direct access is blocked
+    async fn check_server_reachable(&self, proxies: &[ProxyConfig]) -> Result<()> {
+        let server_url = &self.config.server_url;
+        let client = server_check_client(self.config.download_timeout)?;
+        let direct_error = match self.probe_backends(&client).await {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+        warn!(
+            "Speed test server unreachable directly ({}), checking through proxies",
+            direct_error
+        );
+
+        let mut attempts = 0;
+        for proxy in proxies {
+            if attempts == SERVER_CHECK_PROXY_ATTEMPTS {
+                break;
+            }
+            let delay_url = self.config.delay_test_url.as_deref();
+            if self
+                .mihomo_runner
+                .test_proxy_delay(&proxy.name, delay_url)
+                .await
+                .is_err()
+            {
+                continue;
+            }
+
+            attempts += 1;
+            self.mihomo_runner.switch_slot_proxy(0, &proxy.name).await?;
+            let client = self
+                .mihomo_runner
+                .create_slot_proxy_client(0, self.config.download_timeout)?;
+            match self.probe_backends(&client).await {
+                Ok(server) => {
+                    info!(
+                        "Speed test server {} reachable through {}",
+                        server, proxy.name
+                    );
+                    return Ok(());
+                }
+                Err(e) => debug!("Server check through {} failed: {}", proxy.name, e),
+            }
+        }
+
+        Err(server_unreachable(server_url, direct_error))
+    }
+
+    /// Drain the proxy queue with one worker per slot, keeping input order. With
+    /// `isolate_servers` a worker whose remaining proxies all share a server with a
+    /// running test waits for it to finish. With `first_match` the first passing
+    /// result stops every worker, tests still in flight are dropped.
+    async fn run_slots(&self, proxies: &[ProxyConfig], slots: usize) -> Vec<SpeedTestResult> {
+        let scheduler = Mutex::new(ProxyScheduler::new(
+            proxies.to_vec(),
+            self.config.isolate_servers,
+        ));
+        let freed = Notify::new();
+        let matched = CancellationToken::new();
+
+        let workers = (0..slots).map(|slot| {
+            let (scheduler, freed, matched) = (&scheduler, &freed, &matched);
+            async move {
+                let mut done = Vec::new();
+                while !matched.is_cancelled() {
+                    // Registered before looking, a server freed in between still wakes us
+                    let notified = freed.notified();
+                    tokio::pin!(notified);
+                    notified.as_mut().enable();
+                    let next = {
+                        let mut scheduler = scheduler.lock().unwrap();
+                        match scheduler.next_indexed() {
+                            None if scheduler.is_empty() => break,
+                            next => next,
+                        }
+                    };
+                    let Some((index, proxy)) = next else {
+                        tokio::select! {
+                            _ = matched.cancelled() => break,
+                            _ = notified => continue,
+                        }
+                    };
+                    let proxy = &proxy;
+                    info!("Testing proxy: {} (slot {})", proxy.name, slot);
+                    let test = async {
+                        match self.config.per_proxy_timeout {
+                            Some(budget) => {
+                                tokio::time::timeout(budget, self.test_single_proxy(slot, proxy))
+                                    .await
+                                    .unwrap_or_else(|_| {
+                                        warn!("Test for {} exceeded {:?}", proxy.name, budget);
+                                        SpeedTestResult::failed(
+                                            proxy.name.clone(),
+                                            proxy.proxy_type.clone(),
+                                            PER_PROXY_TIMEOUT_ERROR.to_string(),
+                                        )
+                                        .with_error_kind(ErrorKind::Timeout)
+                                    })
+                            }
+                            None => self.test_single_proxy(slot, proxy).await,
+                        }
+                    };
+                    let result = tokio::select! {
+                        biased;
+                        _ = matched.cancelled() => break,
+                        result = test => result,
+                    };
+                    scheduler.lock().unwrap().finish(proxy);
+                    freed.notify_waiters();
+                    debug!(
+                        "Phase timings for {}: {:?}",
+                        result.proxy_name, result.timings
+                    );
+                    if let Some(sink) = &self.sink {
+                        sink.lock().unwrap().push(result.clone());
+                    }
+                    if let Some(callback) = &self.on_result {
+                        callback(&result);
+                    }
+                    if self.config.is_first_match(&result) {
+                        matched.cancel();
+                    }
+                    done.push((index, result));
+                }
+                done
+            }
+        });
+
+        let mut results: Vec<_> = join_all(workers).await.into_iter().flatten().collect();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
     }
 
     /// Test a single proxy through mihomo
-    async fn test_single_proxy(&mut self, proxy: &ProxyConfig) -> SpeedTestResult {
+    async fn test_single_proxy(&self, slot: usize, proxy: &ProxyConfig) -> SpeedTestResult {
         let start_time = Utc::now();
+        let mut timings = PhaseTimings::default();
 
         // Switch mihomo to use this proxy
-        if let Err(e) = self.mihomo_runner.switch_proxy(&proxy.name).await {
+        let switch_started = Instant::now();
+        if let Err(e) = self
+            .mihomo_runner
+            .switch_slot_proxy(slot, &proxy.name)
+            .await
+        {
             return SpeedTestResult {
                 proxy_name: proxy.name.clone(),
                 proxy_type: proxy.proxy_type.clone(),
                 latency: None,
                 jitter: None,
+                min_latency: None,
+                max_latency: None,
+                latency_idle: None,
+                latency_loaded: None,
+                proxy_latency: None,
+                latency_samples: Vec::new(),
                 packet_loss: 1.0,
                 download_speed: 0.0,
                 upload_speed: 0.0,
                 download_time: None,
+                ttfb: None,
                 upload_time: None,
+                download_speed_samples: Vec::new(),
+                upload_speed_samples: Vec::new(),
+                download_bytes: 0,
+                upload_bytes: 0,
+                download_concurrency: None,
+                auto_download_size: None,
+                download_stability: None,
+                server_used: None,
+                timings: PhaseTimings {
+                    switch: Some(switch_started.elapsed()),
+                    ..timings
+                },
                 error: Some(format!("Failed to switch proxy: {e}")),
+                error_kind: Some(ErrorKind::ProxySwitch),
                 timestamp: start_time,
             };
         }
 
         // Wait a moment for proxy to be ready
         tokio::time::sleep(Duration::from_millis(500)).await;
+        timings.switch = Some(switch_started.elapsed());
 
         // Test latency using mihomo's built-in delay test
-        let (latency, jitter, packet_loss) = match self.test_latency_through_mihomo(proxy).await {
+        let latency_started = Instant::now();
+        let stats = self.test_latency_through_mihomo(slot, proxy).await;
+        timings.latency = latency_started.elapsed();
+        let stats = match stats {
             Ok(result) => result,
             Err(e) => {
                 return SpeedTestResult {
@@ -78,111 +354,260 @@ impl RealSpeedTester {
                     proxy_type: proxy.proxy_type.clone(),
                     latency: None,
                     jitter: None,
+                    min_latency: None,
+                    max_latency: None,
+                    latency_idle: None,
+                    latency_loaded: None,
+                    proxy_latency: None,
+                    latency_samples: Vec::new(),
                     packet_loss: 1.0,
                     download_speed: 0.0,
                     upload_speed: 0.0,
                     download_time: None,
+                    ttfb: None,
                     upload_time: None,
+                    download_speed_samples: Vec::new(),
+                    upload_speed_samples: Vec::new(),
+                    download_bytes: 0,
+                    upload_bytes: 0,
+                    download_concurrency: None,
+                    auto_download_size: None,
+                    download_stability: None,
+                    server_used: None,
+                    timings,
                     error: Some(format!("Latency test failed: {e}")),
+                    error_kind: Some(ErrorKind::classify(&e)),
                     timestamp: start_time,
                 };
             }
         };
 
         // Check if latency exceeds threshold
-        if let Some(max_latency) = self.config.max_latency {
-            if let Some(avg_latency) = latency {
-                if avg_latency > max_latency {
-                    return SpeedTestResult {
-                        proxy_name: proxy.name.clone(),
-                        proxy_type: proxy.proxy_type.clone(),
-                        latency,
-                        jitter,
-                        packet_loss,
-                        download_speed: 0.0,
-                        upload_speed: 0.0,
-                        download_time: None,
-                        upload_time: None,
-                        error: Some(format!(
-                            "Latency {} exceeds threshold {:?}",
-                            avg_latency.as_millis(),
-                            max_latency.as_millis()
-                        )),
-                        timestamp: start_time,
-                    };
+        if let Some(max_latency) = self.config.max_latency
+            && let Some(avg_latency) = stats.latency
+            && avg_latency > max_latency
+        {
+            return SpeedTestResult {
+                proxy_name: proxy.name.clone(),
+                proxy_type: proxy.proxy_type.clone(),
+                latency: stats.latency,
+                jitter: stats.jitter,
+                min_latency: stats.min_latency,
+                max_latency: stats.max_latency,
+                latency_idle: None,
+                latency_loaded: None,
+                proxy_latency: None,
+                latency_samples: stats.samples,
+                packet_loss: stats.packet_loss,
+                download_speed: 0.0,
+                upload_speed: 0.0,
+                download_time: None,
+                ttfb: None,
+                upload_time: None,
+                download_speed_samples: Vec::new(),
+                upload_speed_samples: Vec::new(),
+                download_bytes: 0,
+                upload_bytes: 0,
+                download_concurrency: None,
+                auto_download_size: None,
+                download_stability: None,
+                server_used: stats.server,
+                timings,
+                error: Some(format!(
+                    "Latency {} exceeds threshold {:?}",
+                    avg_latency.as_millis(),
+                    max_latency.as_millis()
+                )),
+                error_kind: Some(ErrorKind::LatencyExceeded),
+                timestamp: start_time,
+            };
+        }
+
+        let (latency_idle, latency_loaded) = if self.config.latency_under_load {
+            let loaded_started = Instant::now();
+            let loaded = self.test_loaded_latency_through_mihomo(slot).await;
+            timings.latency += loaded_started.elapsed();
+            match loaded {
+                Ok(loaded) => (stats.latency, Some(loaded.avg_latency)),
+                Err(e) => {
+                    debug!("Loaded latency test failed for {}: {}", proxy.name, e);
+                    (stats.latency, None)
                 }
             }
-        }
+        } else {
+            (None, None)
+        };
+
+        // The hop to the proxy is a direct connect, mihomo isn't in the way. With
+        // `--latency-mode tcp` the latency test already measured exactly that.
+        let proxy_latency = if self.config.latency_mode == LatencyMode::Tcp {
+            stats
+                .latency
+                .filter(|_| self.config.latency_target == LatencyTarget::Proxy)
+        } else if self.config.latency_target == LatencyTarget::Proxy {
+            let hop_started = Instant::now();
+            let hop = tcp_connect_latency(
+                &self.dns,
+                &proxy.server,
+                proxy.port,
+                self.config.ip_version,
+                self.config.local_address(),
+                self.config.latency_iterations,
+                self.config.download_timeout,
+            )
+            .await;
+            timings.latency += hop_started.elapsed();
+            match hop {
+                Ok(hop) => Some(hop.avg_latency),
+                Err(e) => {
+                    debug!("Proxy hop latency test failed for {}: {}", proxy.name, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
         // If fast mode is enabled, skip bandwidth tests
         if self.config.fast_mode {
             return SpeedTestResult {
                 proxy_name: proxy.name.clone(),
                 proxy_type: proxy.proxy_type.clone(),
-                latency,
-                jitter,
-                packet_loss,
+                latency: stats.latency,
+                jitter: stats.jitter,
+                min_latency: stats.min_latency,
+                max_latency: stats.max_latency,
+                latency_idle,
+                latency_loaded,
+                proxy_latency,
+                latency_samples: stats.samples,
+                packet_loss: stats.packet_loss,
                 download_speed: 0.0,
                 upload_speed: 0.0,
                 download_time: None,
+                ttfb: None,
                 upload_time: None,
+                download_speed_samples: Vec::new(),
+                upload_speed_samples: Vec::new(),
+                download_bytes: 0,
+                upload_bytes: 0,
+                download_concurrency: None,
+                auto_download_size: None,
+                download_stability: None,
+                server_used: stats.server.clone(),
+                timings,
                 error: None,
+                error_kind: None,
                 timestamp: start_time,
             };
         }
 
         // Test bandwidth through mihomo proxy
-        let (download_speed, download_time, upload_speed, upload_time, bandwidth_error) =
-            self.test_bandwidth_through_mihomo().await;
+        let bandwidth = self
+            .test_bandwidth_through_mihomo(slot, stats.latency.unwrap_or_default())
+            .await;
+        timings.download = bandwidth.download_elapsed;
+        timings.upload = bandwidth.upload_elapsed;
 
         SpeedTestResult {
             proxy_name: proxy.name.clone(),
             proxy_type: proxy.proxy_type.clone(),
-            latency,
-            jitter,
-            packet_loss,
-            download_speed,
-            upload_speed,
-            download_time,
-            upload_time,
-            error: bandwidth_error,
+            latency: stats.latency,
+            jitter: stats.jitter,
+            min_latency: stats.min_latency,
+            max_latency: stats.max_latency,
+            latency_idle,
+            latency_loaded,
+            proxy_latency,
+            latency_samples: stats.samples,
+            packet_loss: stats.packet_loss,
+            download_speed: bandwidth.download.as_ref().map_or(0.0, |r| r.speed),
+            upload_speed: bandwidth.upload.as_ref().map_or(0.0, |r| r.speed),
+            download_time: bandwidth.download.as_ref().map(|r| r.duration),
+            ttfb: bandwidth.download.as_ref().and_then(|r| r.ttfb),
+            upload_time: bandwidth.upload.as_ref().map(|r| r.duration),
+            download_bytes: bandwidth.download.as_ref().map_or(0, |r| r.bytes as u64),
+            upload_bytes: bandwidth.upload.as_ref().map_or(0, |r| r.bytes as u64),
+            download_concurrency: bandwidth.download_concurrency,
+            auto_download_size: bandwidth.auto_download_size,
+            download_stability: bandwidth
+                .download
+                .as_ref()
+                .and_then(|r| StatisticalAnalysis::coefficient_of_variation(&r.samples)),
+            server_used: bandwidth
+                .download
+                .as_ref()
+                .or(bandwidth.upload.as_ref())
+                .and_then(|r| r.server.clone())
+                .or(stats.server),
+            download_speed_samples: bandwidth.download.map(|r| r.samples).unwrap_or_default(),
+            upload_speed_samples: bandwidth.upload.map(|r| r.samples).unwrap_or_default(),
+            timings,
+            error: bandwidth.error,
+            error_kind: bandwidth.error_kind,
             timestamp: start_time,
         }
     }
 
     /// Test latency through mihomo's delay test and our own latency test
     async fn test_latency_through_mihomo(
-        &mut self,
+        &self,
+        slot: usize,
         proxy: &ProxyConfig,
-    ) -> Result<(Option<Duration>, Option<Duration>, f64)> {
+    ) -> Result<LatencyStats> {
+        // The proxy's server:port is dialed directly, mihomo isn't in the way
+        if self.config.latency_mode == LatencyMode::Tcp {
+            let result = tcp_connect_latency(
+                &self.dns,
+                &proxy.server,
+                proxy.port,
+                self.config.ip_version,
+                self.config.local_address(),
+                self.config.latency_iterations,
+                self.config.download_timeout,
+            )
+            .await?;
+            return Ok(result.into());
+        }
+
         // First try mihomo's built-in delay test
-        match self.mihomo_runner.test_proxy_delay(&proxy.name, None).await {
+        match self
+            .mihomo_runner
+            .test_proxy_delay(&proxy.name, self.config.delay_test_url.as_deref())
+            .await
+        {
             Ok(delay_ms) => {
                 let latency = Duration::from_millis(delay_ms as u64);
                 debug!("Mihomo delay test result: {}ms", delay_ms);
 
                 // Also do our own detailed latency test for jitter calculation
-                match self.detailed_latency_test().await {
-                    Ok((_, jitter, packet_loss)) => Ok((Some(latency), jitter, packet_loss)),
+                match self.detailed_latency_test(slot).await {
+                    Ok(detailed) => Ok(LatencyStats {
+                        latency: Some(latency),
+                        ..detailed
+                    }),
                     Err(_) => {
                         // Fallback to mihomo result only
-                        Ok((Some(latency), None, 0.0))
+                        Ok(LatencyStats {
+                            latency: Some(latency),
+                            ..Default::default()
+                        })
                     }
                 }
             }
             Err(e) => {
                 debug!("Mihomo delay test failed: {}, trying detailed test", e);
                 // Fallback to detailed latency test
-                self.detailed_latency_test().await
+                self.detailed_latency_test(slot).await
             }
         }
     }
 
     /// Detailed latency test through mihomo proxy
-    async fn detailed_latency_test(&mut self) -> Result<(Option<Duration>, Option<Duration>, f64)> {
+    async fn detailed_latency_test(&self, slot: usize) -> Result<LatencyStats> {
         let proxy_client = self
             .mihomo_runner
-            .create_proxy_client(self.config.download_timeout)?;
+            .create_slot_proxy_client(slot, self.config.download_timeout)?;
 
         // Create a simple proxy config for the latency tester
         // We don't need the actual proxy details since we're going through mihomo
@@ -195,153 +620,313 @@ impl RealSpeedTester {
         };
 
         // Create custom latency tester that uses the mihomo proxy client
-        let latency_tester = CustomLatencyTester::new(proxy_client, self.config.server_url.clone());
-        let result = latency_tester.test_latency(6).await?;
+        let (result, server) = with_failover(self.backends(), |backend| {
+            let latency_tester =
+                CustomLatencyTester::new(proxy_client.clone(), backend, self.ping.for_proxy());
+            async move {
+                latency_tester
+                    .test_latency(self.config.latency_iterations)
+                    .await
+            }
+        })
+        .await?;
 
-        Ok((
-            Some(result.avg_latency),
-            Some(result.jitter),
-            result.packet_loss,
-        ))
+        Ok(result.with_server(server).into())
+    }
+
+    /// Ping through mihomo while a download keeps the proxy busy
+    async fn test_loaded_latency_through_mihomo(&self, slot: usize) -> Result<LatencyResult> {
+        let client = self
+            .mihomo_runner
+            .create_slot_proxy_client(slot, self.config.download_timeout)?;
+        let (result, server) = with_failover(self.backends(), |backend| {
+            let client = client.clone();
+            async move {
+                crate::network::latency::loaded_latency(
+                    &client,
+                    backend.as_ref(),
+                    self.config.latency_iterations,
+                    self.connection_limit.clone(),
+                )
+                .await
+            }
+        })
+        .await?;
+        Ok(result.with_server(server))
     }
 
     /// Test bandwidth through mihomo proxy
     async fn test_bandwidth_through_mihomo(
-        &mut self,
-    ) -> (f64, Option<Duration>, f64, Option<Duration>, Option<String>) {
+        &self,
+        slot: usize,
+        latency: Duration,
+    ) -> BandwidthOutcome {
         // Use download timeout for download tests
         let download_client = match self
             .mihomo_runner
-            .create_proxy_client(self.config.download_timeout)
+            .create_slot_proxy_client(slot, self.config.download_timeout)
         {
             Ok(client) => client,
             Err(e) => {
-                return (
-                    0.0,
-                    None,
-                    0.0,
-                    None,
-                    Some(format!("Failed to create proxy client: {e}")),
-                );
+                return BandwidthOutcome::failed(format!("Failed to create proxy client: {e}"));
             }
         };
 
         // Use upload timeout for upload tests
         let upload_client = match self
             .mihomo_runner
-            .create_proxy_client(self.config.upload_timeout)
+            .create_slot_proxy_client(slot, self.config.upload_timeout)
         {
             Ok(client) => client,
             Err(e) => {
-                return (
-                    0.0,
-                    None,
-                    0.0,
-                    None,
-                    Some(format!("Failed to create upload proxy client: {e}")),
-                );
+                return BandwidthOutcome::failed(format!(
+                    "Failed to create upload proxy client: {e}"
+                ));
             }
         };
 
-        // Create bandwidth testers
-        let download_tester =
-            CustomBandwidthTester::new(download_client, self.config.server_url.clone());
-        let upload_tester =
-            CustomBandwidthTester::new(upload_client, self.config.server_url.clone());
+        let download_tester = |backend| {
+            CustomBandwidthTester::new(
+                download_client.clone(),
+                backend,
+                self.connection_limit.clone(),
+                self.config.upload_payload,
+            )
+            .with_warmup(self.config.warmup)
+            .with_stall_timeout(self.config.stall_timeout)
+        };
+        let upload_tester = |backend| {
+            CustomBandwidthTester::new(
+                upload_client.clone(),
+                backend,
+                self.connection_limit.clone(),
+                self.config.upload_payload,
+            )
+        };
 
-        // Test download
-        let (download_speed, download_time) = match download_tester
-            .test_download(self.config.download_size, self.config.concurrent)
-            .await
-        {
-            Ok(result) => (result.speed, Some(result.duration)),
+        // Test download, a time budget takes precedence over a fixed size. Each server
+        // is tried in turn, returning the concurrency and size picked along the way.
+        let download_started = Instant::now();
+        let mut download_error = None;
+        let download_result = with_failover(self.backends(), |backend| {
+            let tester = download_tester(backend);
+            async move {
+                match self.config.download_duration {
+                    window if self.config.adaptive_download => tester
+                        .test_download_adaptive(window.unwrap_or(DEFAULT_ADAPTIVE_WINDOW))
+                        .await
+                        .map(|(result, concurrency)| (result, Some(concurrency), None)),
+                    Some(duration) => tester
+                        .test_download_timed(duration, self.config.concurrent)
+                        .await
+                        .map(|result| (result, None, None)),
+                    None if let Some(target) = self.config.auto_size => {
+                        let (size, probe_bytes) = tester
+                            .probe_download_size(
+                                latency,
+                                target,
+                                self.config.concurrent,
+                                self.config.download_timeout,
+                            )
+                            .await?;
+                        // The probe's data counts towards what the test used
+                        tester
+                            .test_download(size, self.config.concurrent)
+                            .await
+                            .map(|mut result| {
+                                result.bytes += probe_bytes;
+                                (result, None, Some(size as u64))
+                            })
+                    }
+                    None => tester
+                        .test_download(self.config.download_size, self.config.concurrent)
+                        .await
+                        .map(|result| (result, None, None)),
+                }
+            }
+        })
+        .await;
+        let download_elapsed = Some(download_started.elapsed());
+        let (download, download_concurrency, auto_download_size) = match download_result {
+            Ok(((result, concurrency, size), server)) => {
+                (Some(result.with_server(server)), concurrency, size)
+            }
             Err(e) => {
                 warn!("Download test failed: {}", e);
-                (0.0, None)
+                download_error = Some(e);
+                (None, None, None)
             }
         };
 
         // Test upload
-        let (upload_speed, upload_time) =
-            match upload_tester.test_upload(self.config.upload_size).await {
-                Ok(result) => (result.speed, Some(result.duration)),
-                Err(e) => {
-                    warn!("Upload test failed: {}", e);
-                    (0.0, None)
-                }
-            };
+        let upload_started = Instant::now();
+        let upload_result = with_failover(self.backends(), |backend| {
+            let tester = upload_tester(backend);
+            async move {
+                tester
+                    .test_upload(self.config.upload_size, self.config.concurrent_upload)
+                    .await
+            }
+        })
+        .await;
+        let upload_elapsed = Some(upload_started.elapsed());
+        let mut upload_error = None;
+        let upload = match upload_result {
+            Ok((result, server)) => Some(result.with_server(server)),
+            Err(e) => {
+                warn!("Upload test failed: {}", e);
+                upload_error = Some(e);
+                None
+            }
+        };
+
+        let download_speed = download.as_ref().map_or(0.0, |r| r.speed);
+        let upload_speed = upload.as_ref().map_or(0.0, |r| r.speed);
 
-        // Check speed thresholds
+        // Check speed thresholds, a transfer that failed outright reports why instead
         let mut errors = Vec::new();
 
-        if let Some(min_download) = self.config.min_download_speed {
-            if download_speed < min_download {
-                errors.push(format!(
-                    "Download speed {:.2} MB/s below threshold {:.2} MB/s",
-                    download_speed / 1_000_000.0,
-                    min_download / 1_000_000.0
-                ));
-            }
+        if let Some(min_download) = self.config.min_download_speed
+            && download_speed < min_download
+        {
+            errors.push(match &download_error {
+                Some(e) => (
+                    format!("Download test failed: {e:#}"),
+                    ErrorKind::classify(e),
+                ),
+                None => (
+                    format!(
+                        "Download speed {:.2} MB/s below threshold {:.2} MB/s",
+                        download_speed / 1_000_000.0,
+                        min_download / 1_000_000.0
+                    ),
+                    ErrorKind::SpeedBelowThreshold,
+                ),
+            });
         }
 
-        if let Some(min_upload) = self.config.min_upload_speed {
-            if upload_speed < min_upload {
-                errors.push(format!(
-                    "Upload speed {:.2} MB/s below threshold {:.2} MB/s",
-                    upload_speed / 1_000_000.0,
-                    min_upload / 1_000_000.0
-                ));
-            }
+        if let Some(min_upload) = self.config.min_upload_speed
+            && upload_speed < min_upload
+        {
+            errors.push(match &upload_error {
+                Some(e) => (format!("Upload test failed: {e:#}"), ErrorKind::classify(e)),
+                None => (
+                    format!(
+                        "Upload speed {:.2} MB/s below threshold {:.2} MB/s",
+                        upload_speed / 1_000_000.0,
+                        min_upload / 1_000_000.0
+                    ),
+                    ErrorKind::SpeedBelowThreshold,
+                ),
+            });
         }
 
-        let error = if errors.is_empty() {
-            None
-        } else {
-            Some(errors.join("; "))
-        };
-
-        (
-            download_speed,
-            download_time,
-            upload_speed,
-            upload_time,
+        let error_kind = errors.first().map(|(_, kind)| *kind);
+        let error = (!errors.is_empty()).then(|| {
+            errors
+                .into_iter()
+                .map(|(message, _)| message)
+                .collect::<Vec<_>>()
+                .join("; ")
+        });
+
+        BandwidthOutcome {
+            download,
+            upload,
+            download_concurrency,
+            auto_download_size,
+            download_elapsed,
+            upload_elapsed,
             error,
-        )
+            error_kind,
+        }
+    }
+}
+
+/// Latency figures gathered through mihomo
+#[derive(Default)]
+struct LatencyStats {
+    latency: Option<Duration>,
+    jitter: Option<Duration>,
+    min_latency: Option<Duration>,
+    max_latency: Option<Duration>,
+    packet_loss: f64,
+    samples: Vec<Duration>,
+    server: Option<String>,
+}
+
+impl From<LatencyResult> for LatencyStats {
+    fn from(result: LatencyResult) -> Self {
+        Self {
+            latency: Some(result.avg_latency),
+            jitter: Some(result.jitter),
+            min_latency: Some(result.min_latency),
+            max_latency: Some(result.max_latency),
+            packet_loss: result.packet_loss,
+            samples: result.samples,
+            server: result.server,
+        }
+    }
+}
+
+/// Outcome of the bandwidth phase through mihomo
+struct BandwidthOutcome {
+    download: Option<BandwidthResult>,
+    upload: Option<BandwidthResult>,
+    download_concurrency: Option<usize>,
+    auto_download_size: Option<u64>,
+    download_elapsed: Option<Duration>,
+    upload_elapsed: Option<Duration>,
+    error: Option<String>,
+    error_kind: Option<ErrorKind>,
+}
+
+impl BandwidthOutcome {
+    fn failed(error: String) -> Self {
+        Self {
+            download: None,
+            upload: None,
+            download_concurrency: None,
+            auto_download_size: None,
+            download_elapsed: None,
+            upload_elapsed: None,
+            error: Some(error),
+            error_kind: Some(ErrorKind::Other),
+        }
     }
 }
 
 /// Custom latency tester that works with mihomo proxy
 struct CustomLatencyTester {
     client: reqwest::Client,
-    server_url: String,
+    backend: SharedBackend,
+    ping: PingRequest,
 }
 
 impl CustomLatencyTester {
-    fn new(client: reqwest::Client, server_url: String) -> Self {
-        Self { client, server_url }
+    fn new(client: reqwest::Client, backend: SharedBackend, ping: PingRequest) -> Self {
+        Self {
+            client,
+            backend,
+            ping,
+        }
     }
 
     async fn test_latency(&self, iterations: usize) -> Result<crate::network::LatencyResult> {
         let mut latencies = Vec::new();
         let mut failed_count = 0;
+        let mut last_error = None;
 
         for i in 0..iterations {
-            let url = format!("{}/__down?bytes=0", self.server_url);
-            let start = std::time::Instant::now();
-
-            match self.client.get(&url).send().await {
-                Ok(response) if response.status().is_success() => {
-                    let latency = start.elapsed();
+            match self.ping.send(&self.client, self.backend.as_ref()).await {
+                Ok(latency) => {
                     latencies.push(latency);
                     debug!("Ping {}: {}ms", i + 1, latency.as_millis());
                 }
-                Ok(response) => {
-                    warn!("Ping {} failed with status: {}", i + 1, response.status());
-                    failed_count += 1;
-                }
                 Err(e) => {
                     warn!("Ping {} failed: {}", i + 1, e);
                     failed_count += 1;
+                    last_error = Some(e);
                 }
             }
 
@@ -351,8 +936,11 @@ impl CustomLatencyTester {
             }
         }
 
-        if latencies.is_empty() {
-            return Err(anyhow::anyhow!("All ping attempts failed"));
+        // Keep the underlying error so the failure can be classified
+        if latencies.is_empty()
+            && let Some(e) = last_error
+        {
+            return Err(e.context("All ping attempts failed"));
         }
 
         // Calculate statistics
@@ -377,6 +965,8 @@ impl CustomLatencyTester {
             packet_loss,
             min_latency: *latencies.iter().min().unwrap_or(&avg_latency),
             max_latency: *latencies.iter().max().unwrap_or(&avg_latency),
+            samples: latencies,
+            server: None,
         })
     }
 }
@@ -384,20 +974,45 @@ impl CustomLatencyTester {
 /// Custom bandwidth tester that works with mihomo proxy
 struct CustomBandwidthTester {
     client: reqwest::Client,
-    server_url: String,
+    backend: SharedBackend,
+    connection_limit: Option<ConnectionLimit>,
+    upload_payload: UploadPayload,
+    warmup: Duration,
+    stall_timeout: Duration,
 }
 
 impl CustomBandwidthTester {
-    fn new(client: reqwest::Client, server_url: String) -> Self {
-        Self { client, server_url }
+    fn new(
+        client: reqwest::Client,
+        backend: SharedBackend,
+        connection_limit: Option<ConnectionLimit>,
+        upload_payload: UploadPayload,
+    ) -> Self {
+        Self {
+            client,
+            backend,
+            connection_limit,
+            upload_payload,
+            warmup: Duration::ZERO,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+        }
     }
 
-    async fn test_download(
-        &self,
-        size: usize,
-        concurrent: usize,
-    ) -> Result<crate::network::BandwidthResult> {
+    fn with_warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    async fn test_download(&self, size: usize, concurrent: usize) -> Result<BandwidthResult> {
+        // The clock starts once the first connection has a slot
+        let mut first_permit = Some(acquire_connection(&self.connection_limit).await);
         let start = std::time::Instant::now();
+        let sampler = ThroughputSampler::start();
 
         // For real proxy testing, use more conservative concurrency
         let actual_concurrent = std::cmp::min(concurrent, 2);
@@ -410,10 +1025,18 @@ impl CustomBandwidthTester {
 
         let mut tasks = Vec::new();
         for i in 0..actual_concurrent {
+            let permit = first_permit.take();
             let client = self.client.clone();
-            let server_url = self.server_url.clone();
+            let backend = self.backend.clone();
+            let counter = sampler.counter();
+            let limit = self.connection_limit.clone();
+            let stall_timeout = self.stall_timeout;
 
             let task = tokio::spawn(async move {
+                let _permit = match permit {
+                    Some(permit) => permit,
+                    None => acquire_connection(&limit).await,
+                };
                 // Try downloading with retries
                 for attempt in 1..=3 {
                     debug!(
@@ -424,8 +1047,10 @@ impl CustomBandwidthTester {
                     );
                     match Self::download_chunk_with_retry(
                         &client,
-                        &server_url,
+                        backend.as_ref(),
                         chunk_size,
+                        stall_timeout,
+                        &counter,
                         i + 1,
                         attempt,
                     )
@@ -461,28 +1086,34 @@ impl CustomBandwidthTester {
             tasks.push(task);
         }
 
-        let mut total_bytes = 0;
-        let mut successful_chunks = 0;
+        let mut chunks = Vec::new();
+        let mut last_error = None;
         for (i, task) in tasks.into_iter().enumerate() {
             match task.await? {
-                Ok(bytes) => {
-                    total_bytes += bytes;
-                    successful_chunks += 1;
+                Ok(chunk) => {
+                    chunks.push(chunk);
                     debug!("Download chunk {} completed successfully", i + 1);
                 }
                 Err(e) => {
                     warn!("Download chunk {} failed permanently: {}", i + 1, e);
                     // Continue with other chunks instead of failing entirely
+                    last_error = Some(e);
                 }
             }
         }
 
+        let total_bytes: usize = chunks.iter().map(|c| c.bytes).sum();
+        let successful_chunks = chunks.len();
         if successful_chunks == 0 {
-            return Err(anyhow::anyhow!("All download chunks failed"));
+            return Err(last_error.map_or_else(
+                || anyhow::anyhow!("All download chunks failed"),
+                |e| e.context("All download chunks failed"),
+            ));
         }
 
         let duration = start.elapsed();
         let speed = total_bytes as f64 / duration.as_secs_f64();
+        let samples = sampler.finish().await;
 
         debug!(
             "Download completed: {}/{} chunks successful, {} bytes in {:?} ({:.2} MB/s)",
@@ -493,23 +1124,77 @@ impl CustomBandwidthTester {
             total_bytes as f64 / (1024.0 * 1024.0) / duration.as_secs_f64()
         );
 
-        Ok(crate::network::BandwidthResult {
+        Ok(BandwidthResult {
             bytes: total_bytes,
             speed,
             duration,
+            samples,
+            server: None,
+            ttfb: ChunkResult::mean_ttfb(&chunks),
         })
     }
 
+    async fn test_download_timed(
+        &self,
+        duration: Duration,
+        concurrent: usize,
+    ) -> Result<BandwidthResult> {
+        // Same conservative concurrency as the fixed-size download
+        let actual_concurrent = std::cmp::min(concurrent, 2);
+        crate::network::bandwidth::timed_download(
+            &self.client,
+            self.backend.as_ref(),
+            duration,
+            self.warmup,
+            actual_concurrent,
+            self.connection_limit.clone(),
+            self.stall_timeout,
+        )
+        .await
+    }
+
+    async fn test_download_adaptive(&self, window: Duration) -> Result<(BandwidthResult, usize)> {
+        crate::network::bandwidth::adaptive_download(
+            &self.client,
+            self.backend.as_ref(),
+            window,
+            self.connection_limit.clone(),
+            self.stall_timeout,
+        )
+        .await
+    }
+
+    async fn probe_download_size(
+        &self,
+        latency: Duration,
+        target: Duration,
+        concurrent: usize,
+        timeout: Duration,
+    ) -> Result<(usize, usize)> {
+        crate::network::bandwidth::probe_download_size(
+            &self.client,
+            self.backend.as_ref(),
+            latency,
+            target,
+            concurrent,
+            timeout,
+        )
+        .await
+    }
+
     async fn download_chunk_with_retry(
         client: &reqwest::Client,
-        server_url: &str,
+        backend: &dyn SpeedTestBackend,
         chunk_size: usize,
+        stall_timeout: Duration,
+        counter: &AtomicUsize,
         chunk_id: usize,
         attempt: usize,
-    ) -> Result<usize> {
-        let url = format!("{server_url}/__down?bytes={chunk_size}");
+    ) -> Result<ChunkResult> {
+        let url = backend.download_url(chunk_size);
 
-        match client.get(&url).send().await {
+        let sent = Instant::now();
+        match download_request(client, &url).send().await {
             Ok(response) => {
                 debug!(
                     "Download chunk {} attempt {} response status: {}",
@@ -540,80 +1225,399 @@ impl CustomBandwidthTester {
                     }
                 }
 
-                match response.bytes().await {
-                    Ok(bytes) => {
-                        debug!(
-                            "Download chunk {} attempt {} successfully received {} bytes",
+                check_download_response(&response, backend.download_bytes(chunk_size));
+
+                let mut chunk = ChunkResult::default();
+                let mut stream = response.bytes_stream();
+                loop {
+                    // A connection receiving nothing is abandoned instead of holding the
+                    // test until the download timeout
+                    let Ok(next) = tokio::time::timeout(stall_timeout, stream.next()).await else {
+                        return Err(anyhow::anyhow!(
+                            "Download chunk {} stalled: no data for {:?}",
                             chunk_id,
-                            attempt,
-                            bytes.len()
-                        );
-                        Ok(bytes.len())
+                            stall_timeout
+                        ));
+                    };
+                    let Some(bytes) = next else {
+                        break;
+                    };
+                    let bytes = bytes.map_err(|e| {
+                        anyhow::Error::new(e).context(format!(
+                            "Download chunk {chunk_id} failed to decode response body"
+                        ))
+                    })?;
+                    if chunk.ttfb.is_none() && !bytes.is_empty() {
+                        chunk.ttfb = Some(sent.elapsed());
                     }
-                    Err(e) => Err(anyhow::anyhow!(
-                        "Download chunk {} failed to decode response body: {}",
-                        chunk_id,
-                        e
-                    )),
+                    chunk.bytes += bytes.len();
+                    counter.fetch_add(bytes.len(), Ordering::Relaxed);
                 }
+                debug!(
+                    "Download chunk {} attempt {} successfully received {} bytes",
+                    chunk_id, attempt, chunk.bytes
+                );
+                Ok(chunk)
+            }
+            Err(e) => {
+                Err(anyhow::Error::new(e)
+                    .context(format!("Download chunk {chunk_id} request failed")))
             }
-            Err(e) => Err(anyhow::anyhow!(
-                "Download chunk {} request failed: {}",
-                chunk_id,
-                e
-            )),
         }
     }
 
-    async fn test_upload(&self, size: usize) -> Result<crate::network::BandwidthResult> {
-        let start = std::time::Instant::now();
-        let url = format!("{}/__up", self.server_url);
-
-        // Create dummy data
-        let data = vec![0u8; size];
+    async fn test_upload(&self, size: usize, concurrent: usize) -> Result<BandwidthResult> {
+        crate::network::bandwidth::concurrent_upload(
+            &self.client,
+            self.backend.as_ref(),
+            self.upload_payload,
+            size,
+            concurrent,
+            self.connection_limit.clone(),
+        )
+        .await
+    }
+}
 
-        debug!("Starting upload of {} bytes", size);
-        let response = match self.client.post(&url).body(data).send().await {
-            Ok(resp) => {
-                debug!("Upload response status: {}", resp.status());
-                debug!("Upload response headers: {:?}", resp.headers());
-                resp
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Read one HTTP request and return its request line
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> Option<String> {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 1024];
+        let header_end = loop {
+            let n = socket.read(&mut chunk).await.ok()?;
+            if n == 0 {
+                return None;
             }
-            Err(e) => {
-                warn!("Upload request failed: {}", e);
-                return Err(anyhow::anyhow!("Upload request failed: {}", e));
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
             }
         };
 
-        if !response.status().is_success() {
-            let status = response.status();
-            // Read response body for error details
-            match response.text().await {
-                Ok(body) => {
-                    warn!("Upload failed with status {}, body: {}", status, body);
-                    return Err(anyhow::anyhow!(
-                        "Upload failed with status: {}, body: {}",
-                        status,
-                        body
-                    ));
-                }
-                Err(body_err) => {
-                    warn!(
-                        "Upload failed with status {} and couldn't read body: {}",
-                        status, body_err
-                    );
-                    return Err(anyhow::anyhow!("Upload failed with status: {}", status));
-                }
+        let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+        let content_length = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        while buf.len() < header_end + content_length {
+            let n = socket.read(&mut chunk).await.ok()?;
+            if n == 0 {
+                break;
             }
+            buf.extend_from_slice(&chunk[..n]);
         }
 
-        let duration = start.elapsed();
-        let speed = size as f64 / duration.as_secs_f64();
+        head.lines().next().map(str::to_string)
+    }
 
-        Ok(crate::network::BandwidthResult {
-            bytes: size,
-            speed,
-            duration,
-        })
+    /// Stub of the mihomo API that records group switches and answers delay tests
+    async fn spawn_stub_api(switches: Arc<Mutex<Vec<String>>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let switches = switches.clone();
+                tokio::spawn(async move {
+                    let Some(request_line) = read_request(&mut socket).await else {
+                        return;
+                    };
+                    let path = request_line.split(' ').nth(1).unwrap_or_default();
+                    let response: &[u8] = if request_line.starts_with("PUT ") {
+                        let group = path.trim_start_matches("/proxies/").to_string();
+                        switches.lock().unwrap().push(group);
+                        b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n"
+                    } else if path.contains("/delay") {
+                        b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 13\r\nConnection: close\r\n\r\n{\"delay\": 42}"
+                    } else {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    };
+                    let _ = socket.write_all(response).await;
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_parallel_slots_with_stub_api() {
+        let switches = Arc::new(Mutex::new(Vec::new()));
+        let api_port = spawn_stub_api(switches.clone()).await;
+
+        // Nothing listens on the proxy ports, so only the delay API answers
+        let proxy_port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            api_port,
+            proxy_port,
+        )
+        .unwrap();
+        let tester = RealSpeedTester::new(
+            runner,
+            SpeedTestConfig {
+                server_url: "http://speedtest.invalid".to_string(),
+                download_timeout: Duration::from_secs(1),
+                fast_mode: true,
+                ..Default::default()
+            },
+        );
+
+        let proxies: Vec<_> = (0..4)
+            .map(|i| ProxyConfig {
+                name: format!("node-{i}"),
+                proxy_type: ProxyType::Http,
+                server: "127.0.0.1".to_string(),
+                port: 8080,
+                config: Default::default(),
+            })
+            .collect();
+
+        let results = tester.run_slots(&proxies, 2).await;
+
+        let names: Vec<_> = results.iter().map(|r| r.proxy_name.as_str()).collect();
+        assert_eq!(names, ["node-0", "node-1", "node-2", "node-3"]);
+        for result in &results {
+            assert!(result.error.is_none(), "{:?}", result.error);
+            assert_eq!(result.latency, Some(Duration::from_millis(42)));
+        }
+
+        let mut switches = switches.lock().unwrap().clone();
+        assert_eq!(switches.len(), 4);
+        switches.sort();
+        switches.dedup();
+        assert_eq!(switches, ["SpeedTest-0", "SpeedTest-1"]);
+
+        // Every proxy shares one server, the second slot waits its turn instead of stopping
+        let tester = stub_tester(
+            proxy_port,
+            SpeedTestConfig {
+                server_url: "http://speedtest.invalid".to_string(),
+                download_timeout: Duration::from_secs(1),
+                fast_mode: true,
+                isolate_servers: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        let results = tester.run_slots(&proxies[..2], 2).await;
+        let names: Vec<_> = results.iter().map(|r| r.proxy_name.as_str()).collect();
+        assert_eq!(names, ["node-0", "node-1"]);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_hop_latency_through_mihomo() {
+        // Stands in for the proxy server, only the TCP connect is timed
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = ProxyConfig {
+            name: "node".to_string(),
+            proxy_type: ProxyType::VMess,
+            server: "127.0.0.1".to_string(),
+            port: server.local_addr().unwrap().port(),
+            config: Default::default(),
+        };
+        let config = SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(1),
+            fast_mode: true,
+            latency_iterations: 2,
+            ..Default::default()
+        };
+
+        let tester = stub_tester(0, config.clone()).await;
+        let result = tester.test_single_proxy(0, &proxy).await;
+        assert!(result.error.is_none(), "{:?}", result.error);
+        assert!(result.proxy_latency.is_none());
+
+        let tester = stub_tester(
+            0,
+            SpeedTestConfig {
+                latency_target: LatencyTarget::Proxy,
+                ..config.clone()
+            },
+        )
+        .await;
+        let result = tester.test_single_proxy(0, &proxy).await;
+        assert!(result.proxy_latency.unwrap() < Duration::from_millis(100));
+
+        // `--latency-mode tcp` times the connect to the proxy instead of pinging through it
+        let tester = stub_tester(
+            0,
+            SpeedTestConfig {
+                latency_mode: LatencyMode::Tcp,
+                ..config
+            },
+        )
+        .await;
+        let result = tester.test_single_proxy(0, &proxy).await;
+        assert!(result.error.is_none(), "{:?}", result.error);
+        assert!(result.latency.unwrap() < Duration::from_millis(100));
+        assert_eq!(result.latency_samples.len(), 2);
+        assert!(result.server_used.is_none());
+
+        drop(server);
+        let result = tester.test_single_proxy(0, &proxy).await;
+        assert!(result.error.unwrap().starts_with("Latency test failed"));
+    }
+
+    #[tokio::test]
+    async fn test_download_records_ttfb() {
+        // Headers at once, the 64KB body only after the delay
+        let delay = Duration::from_millis(300);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let _ = read_request(&mut socket).await;
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 65536\r\n\r\n")
+                        .await;
+                    tokio::time::sleep(delay).await;
+                    let _ = socket.write_all(&[0u8; 64 * 1024]).await;
+                });
+            }
+        });
+
+        let tester = CustomBandwidthTester::new(
+            reqwest::Client::builder().no_proxy().build().unwrap(),
+            Arc::new(crate::network::CloudflareBackend::new(&server_url)),
+            None,
+            UploadPayload::Zero,
+        );
+        let result = tester.test_download(64 * 1024, 1).await.unwrap();
+        assert_eq!(result.bytes, 64 * 1024);
+        let ttfb = result.ttfb.unwrap();
+        assert!(ttfb >= delay, "{ttfb:?}");
+        assert!(ttfb < delay + Duration::from_millis(250), "{ttfb:?}");
+        // Nothing arrives before the delay, so the first windows sample zero
+        assert!(result.samples.len() >= 2, "{:?}", result.samples);
+        assert_eq!(result.samples[0], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_download_fails_fast() {
+        // Headers and 1KB of a 1MB body, then nothing
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let _ = read_request(&mut socket).await;
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1048576\r\n\r\n")
+                        .await;
+                    let _ = socket.write_all(&[0u8; 1024]).await;
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                });
+            }
+        });
+
+        let tester = CustomBandwidthTester::new(
+            reqwest::Client::builder().no_proxy().build().unwrap(),
+            Arc::new(crate::network::CloudflareBackend::new(&server_url)),
+            None,
+            UploadPayload::Zero,
+        )
+        .with_stall_timeout(Duration::from_millis(300));
+        let started = Instant::now();
+        let error = tester.test_download(1024 * 1024, 1).await.unwrap_err();
+        assert!(format!("{error:#}").contains("stalled"), "{error:#}");
+        // Three attempts, each given up after the stall timeout
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    /// Tester whose slot 0 proxy listens on `proxy_port`, talking to a stub API
+    async fn stub_tester(proxy_port: u16, config: SpeedTestConfig) -> RealSpeedTester {
+        let api_port = spawn_stub_api(Arc::new(Mutex::new(Vec::new()))).await;
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MihomoRunner::new(
+            dir.path(),
+            Some(dir.path().join("mihomo").as_path()),
+            api_port,
+            proxy_port,
+        )
+        .unwrap();
+        RealSpeedTester::new(runner, config)
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_failures_are_classified() {
+        let config = SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_millis(300),
+            upload_timeout: Duration::from_millis(300),
+            latency_iterations: 1,
+            download_size: 1024,
+            upload_size: 1024,
+            min_download_speed: Some(1.0),
+            ..Default::default()
+        };
+        let proxy = ProxyConfig {
+            name: "node".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port: 8080,
+            config: Default::default(),
+        };
+
+        // Nothing listens on the slot port
+        let refused_port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let tester = stub_tester(refused_port, config.clone()).await;
+        let result = tester.test_single_proxy(0, &proxy).await;
+        assert_eq!(result.error_kind, Some(ErrorKind::ConnectionRefused));
+        assert!(
+            result
+                .error
+                .as_deref()
+                .unwrap()
+                .starts_with("Download test failed"),
+            "{:?}",
+            result.error
+        );
+
+        // The slot port accepts but never answers
+        let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent_port = silent.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = silent.accept().await {
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    drop(socket);
+                });
+            }
+        });
+        let tester = stub_tester(silent_port, config).await;
+        let result = tester.test_single_proxy(0, &proxy).await;
+        assert_eq!(
+            result.error_kind,
+            Some(ErrorKind::Timeout),
+            "{:?}",
+            result.error
+        );
     }
 }
@@ -0,0 +1,142 @@
+//! Order in which concurrent proxy tests start. Nodes of a subscription often share a
+//! server, and testing them at the same time makes them compete for its bandwidth.
+
+use crate::config::ProxyConfig;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Hands out proxies to test. With `isolate_servers` the proxies are bucketed by
+/// server, the buckets take turns, and a server with a test running is skipped until
+/// `finish` is called for it. Otherwise proxies come out in input order.
+#[derive(Debug)]
+pub struct ProxyScheduler {
+    /// Proxies still to hand out with their position in the input
+    buckets: VecDeque<(String, VecDeque<(usize, ProxyConfig)>)>,
+    busy: HashSet<String>,
+    isolate_servers: bool,
+}
+
+impl ProxyScheduler {
+    pub fn new(proxies: Vec<ProxyConfig>, isolate_servers: bool) -> Self {
+        let mut buckets: VecDeque<(String, VecDeque<(usize, ProxyConfig)>)> = VecDeque::new();
+        let proxies = proxies.into_iter().enumerate();
+        if isolate_servers {
+            // Buckets keep the order servers first appear in
+            let mut index = HashMap::new();
+            for (position, proxy) in proxies {
+                let key = Self::server_key(&proxy);
+                let slot = *index.entry(key.clone()).or_insert_with(|| {
+                    buckets.push_back((key, VecDeque::new()));
+                    buckets.len() - 1
+                });
+                buckets[slot].1.push_back((position, proxy));
+            }
+        } else if proxies.len() > 0 {
+            buckets.push_back((String::new(), proxies.collect()));
+        }
+
+        Self {
+            buckets,
+            busy: HashSet::new(),
+            isolate_servers,
+        }
+    }
+
+    /// Next proxy that may start now, `None` when every remaining proxy waits for its
+    /// server or none are left
+    pub fn next_proxy(&mut self) -> Option<ProxyConfig> {
+        self.next_indexed().map(|(_, proxy)| proxy)
+    }
+
+    /// Like `next_proxy`, also returning the proxy's position in the input
+    pub fn next_indexed(&mut self) -> Option<(usize, ProxyConfig)> {
+        for _ in 0..self.buckets.len() {
+            let (server, mut queue) = self.buckets.pop_front()?;
+            if self.busy.contains(&server) {
+                self.buckets.push_back((server, queue));
+                continue;
+            }
+
+            let proxy = queue.pop_front();
+            if self.isolate_servers {
+                self.busy.insert(server.clone());
+                if !queue.is_empty() {
+                    self.buckets.push_back((server, queue));
+                }
+            } else if !queue.is_empty() {
+                self.buckets.push_front((server, queue));
+            }
+            return proxy;
+        }
+        None
+    }
+
+    /// Whether every proxy was handed out, as opposed to waiting for its server
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Mark the test of `proxy` as done, freeing its server
+    pub fn finish(&mut self, proxy: &ProxyConfig) {
+        self.busy.remove(&Self::server_key(proxy));
+    }
+
+    /// Hostnames are case-insensitive
+    fn server_key(proxy: &ProxyConfig) -> String {
+        proxy.server.to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+
+    fn proxy(name: &str, server: &str) -> ProxyConfig {
+        ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Http,
+            server: server.to_string(),
+            port: 8080,
+            config: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_scheduler_round_robins_idle_servers() {
+        let proxies = vec![
+            proxy("a1", "a.test"),
+            proxy("a2", "A.test"),
+            proxy("a3", "a.test"),
+            proxy("b1", "b.test"),
+            proxy("c1", "c.test"),
+        ];
+        let mut scheduler = ProxyScheduler::new(proxies.clone(), true);
+        let mut name = || scheduler.next_proxy().map(|p| p.name);
+        assert_eq!(name().as_deref(), Some("a1"));
+        assert_eq!(name().as_deref(), Some("b1"));
+        assert_eq!(name().as_deref(), Some("c1"));
+        // a.test is still busy
+        assert_eq!(name(), None);
+        assert!(!scheduler.is_empty());
+
+        scheduler.finish(&proxies[0]);
+        assert_eq!(scheduler.next_proxy().unwrap().name, "a2");
+        assert!(scheduler.next_proxy().is_none());
+        scheduler.finish(&proxies[1]);
+        assert_eq!(scheduler.next_proxy().unwrap().name, "a3");
+        scheduler.finish(&proxies[2]);
+        assert!(scheduler.next_proxy().is_none());
+        assert!(scheduler.is_empty());
+
+        // Without isolation the input order is kept and nothing waits
+        let mut scheduler = ProxyScheduler::new(proxies.clone(), false);
+        let names: Vec<_> = std::iter::from_fn(|| scheduler.next_proxy().map(|p| p.name)).collect();
+        assert_eq!(names, ["a1", "a2", "a3", "b1", "c1"]);
+
+        // Positions refer to the input, whatever order the proxies come out in
+        let mut scheduler = ProxyScheduler::new(proxies, true);
+        let positions: Vec<_> =
+            std::iter::from_fn(|| scheduler.next_indexed().map(|(i, _)| i)).collect();
+        assert_eq!(positions, [0, 3, 4]);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::core::SpeedTestResult;
+
+/// Relative importance of each metric in a proxy's score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreWeights {
+    pub latency: f64,
+    pub download: f64,
+    pub upload: f64,
+    pub packet_loss: f64,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            latency: 0.4,
+            download: 0.3,
+            upload: 0.2,
+            packet_loss: 0.1,
+        }
+    }
+}
+
+/// Min/max of each metric across the successful results, used to normalize scores
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRanges {
+    latency: (f64, f64),
+    download: (f64, f64),
+    upload: (f64, f64),
+    packet_loss: (f64, f64),
+}
+
+impl ScoreRanges {
+    /// Collect metric ranges over the successful results
+    pub fn from_results(results: &[SpeedTestResult]) -> Self {
+        let successful: Vec<_> = results.iter().filter(|r| r.is_successful()).collect();
+        let range = |metric: fn(&SpeedTestResult) -> f64| {
+            successful
+                .iter()
+                .map(|r| metric(r))
+                .fold(None, |range: Option<(f64, f64)>, value| match range {
+                    Some((min, max)) => Some((min.min(value), max.max(value))),
+                    None => Some((value, value)),
+                })
+                .unwrap_or((0.0, 0.0))
+        };
+
+        Self {
+            latency: range(latency_ms),
+            download: range(|r| r.download_speed),
+            upload: range(|r| r.upload_speed),
+            packet_loss: range(|r| r.packet_loss),
+        }
+    }
+}
+
+fn latency_ms(result: &SpeedTestResult) -> f64 {
+    result.latency.map_or(0.0, |l| l.as_secs_f64() * 1000.0)
+}
+
+/// Map `value` into 0..=1 within `(min, max)`, flipped when lower is better.
+/// A metric where every proxy is equal doesn't separate them, so it counts as 1.
+fn normalize(value: f64, (min, max): (f64, f64), lower_is_better: bool) -> f64 {
+    if max <= min {
+        return 1.0;
+    }
+    let position = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    if lower_is_better {
+        1.0 - position
+    } else {
+        position
+    }
+}
+
+impl SpeedTestResult {
+    /// Score from 0 to 100 combining latency, download, upload and packet loss.
+    ///
+    /// Each metric is min-max normalized across the result set described by
+    /// `ranges` (lower latency and loss are better), then averaged using
+    /// `weights`. Failed results score 0.
+    pub fn score(&self, weights: &ScoreWeights, ranges: &ScoreRanges) -> f64 {
+        let total_weight =
+            weights.latency + weights.download + weights.upload + weights.packet_loss;
+        if !self.is_successful() || total_weight <= 0.0 {
+            return 0.0;
+        }
+
+        let weighted = weights.latency * normalize(latency_ms(self), ranges.latency, true)
+            + weights.download * normalize(self.download_speed, ranges.download, false)
+            + weights.upload * normalize(self.upload_speed, ranges.upload, false)
+            + weights.packet_loss * normalize(self.packet_loss, ranges.packet_loss, true);
+
+        100.0 * weighted / total_weight
+    }
+}
+
+/// The `n` best successful results by score, highest first
+pub fn pick_best<'a>(
+    results: &'a [SpeedTestResult],
+    weights: &ScoreWeights,
+    n: usize,
+) -> Vec<(&'a SpeedTestResult, f64)> {
+    let ranges = ScoreRanges::from_results(results);
+    let mut scored: Vec<_> = results
+        .iter()
+        .filter(|r| r.is_successful())
+        .map(|r| (r, r.score(weights, &ranges)))
+        .collect();
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(n);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use std::time::Duration;
+
+    fn result(
+        name: &str,
+        latency_ms: u64,
+        download: f64,
+        upload: f64,
+        loss: f64,
+    ) -> SpeedTestResult {
+        let mut result = SpeedTestResult::failed(name.to_string(), ProxyType::Http, String::new());
+        result.error = None;
+        result.latency = Some(Duration::from_millis(latency_ms));
+        result.download_speed = download;
+        result.upload_speed = upload;
+        result.packet_loss = loss;
+        result
+    }
+
+    #[test]
+    fn test_score_min_max_normalization() {
+        let results = vec![
+            result("fast", 100, 30.0, 10.0, 0.0),
+            result("mid", 200, 20.0, 20.0, 10.0),
+            result("slow", 300, 10.0, 0.0, 20.0),
+        ];
+        let ranges = ScoreRanges::from_results(&results);
+        let weights = ScoreWeights::default();
+
+        // fast: 0.4*1 + 0.3*1 + 0.2*0.5 + 0.1*1 = 0.9
+        assert!((results[0].score(&weights, &ranges) - 90.0).abs() < 1e-9);
+        // mid: 0.4*0.5 + 0.3*0.5 + 0.2*1 + 0.1*0.5 = 0.6
+        assert!((results[1].score(&weights, &ranges) - 60.0).abs() < 1e-9);
+        // slow: 0.4*0 + 0.3*0 + 0.2*0 + 0.1*0 = 0
+        assert!(results[2].score(&weights, &ranges).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_score_custom_weights_and_equal_metrics() {
+        let results = vec![
+            result("a", 100, 10.0, 5.0, 0.0),
+            result("b", 150, 20.0, 5.0, 0.0),
+        ];
+        let ranges = ScoreRanges::from_results(&results);
+        // Weights are relative: latency counts twice as much as download
+        let weights = ScoreWeights {
+            latency: 2.0,
+            download: 1.0,
+            upload: 0.0,
+            packet_loss: 1.0,
+        };
+
+        // a: (2*1 + 1*0 + 1*1) / 4 = 0.75, equal loss counts as 1
+        assert!((results[0].score(&weights, &ranges) - 75.0).abs() < 1e-9);
+        // b: (2*0 + 1*1 + 1*1) / 4 = 0.5
+        assert!((results[1].score(&weights, &ranges) - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pick_best_skips_failures() {
+        let results = vec![
+            result("mid", 200, 20.0, 20.0, 10.0),
+            SpeedTestResult::failed("broken".to_string(), ProxyType::Http, "down".to_string()),
+            result("fast", 100, 30.0, 10.0, 0.0),
+            result("slow", 300, 10.0, 0.0, 20.0),
+        ];
+
+        let best = pick_best(&results, &ScoreWeights::default(), 2);
+        let names: Vec<_> = best.iter().map(|(r, _)| r.proxy_name.as_str()).collect();
+        assert_eq!(names, vec!["fast", "mid"]);
+    }
+}
@@ -1,59 +1,259 @@
 use crate::Result;
 use crate::config::ProxyConfig;
-use crate::network::NetworkTester;
+use crate::core::{ErrorKind, ProxyScheduler, StatisticalAnalysis, duration_ms};
+use crate::network::{
+    BackendKind, ConnectionLimit, DEFAULT_ADAPTIVE_WINDOW, DEFAULT_STALL_TIMEOUT, DnsCache,
+    IpVersion, LatencyMethod, LatencyMode, LatencyResult, LatencyTarget, NetworkTester,
+    SharedBackend, SpeedTestBackend, UploadPayload,
+};
+use anyhow::Context;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
 /// Type alias for progress callback
 pub type ProgressCallback = Box<dyn Fn(&SpeedTestResult) + Send + Sync>;
 
+/// Shared list that receives each result as soon as its test completes
+pub type ResultSink = Arc<Mutex<Vec<SpeedTestResult>>>;
+
+/// Upper bound on how long the server reachability check may take
+const SERVER_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error recorded when a proxy exceeds `SpeedTestConfig::per_proxy_timeout`
+pub const PER_PROXY_TIMEOUT_ERROR: &str = "per-proxy timeout exceeded";
+
 /// Configuration for speed testing
 #[derive(Debug, Clone)]
 pub struct SpeedTestConfig {
     pub server_url: String,
+    pub backend: BackendKind, // which endpoints `server_url` serves
+    pub fallback_server_urls: Vec<String>, // tried in order when `server_url` fails
     pub download_timeout: Duration, // 下载超时时间
-    pub upload_timeout: Duration,   // 上传超时时间
+    pub upload_timeout: Duration, // 上传超时时间
+    pub connect_timeout: Duration, // 连接代理超时时间
     pub concurrent: usize,
     pub download_size: usize,
+    pub download_duration: Option<Duration>,
+    pub warmup: Duration, // discarded start of a timed download, lets TCP slow-start settle
+    pub stall_timeout: Duration, // abandon a download chunk receiving nothing for this long
+    /// Ramp download concurrency instead of using `concurrent`, each step lasting
+    /// `download_duration` or `DEFAULT_ADAPTIVE_WINDOW`
+    pub adaptive_download: bool,
+    /// Pick the download size from a 1 MB probe so the download lasts about this long,
+    /// replaces `download_size` but yields to `download_duration`
+    pub auto_size: Option<Duration>,
     pub upload_size: usize,
+    pub concurrent_upload: usize, // connections the upload is split across
     pub max_latency: Option<Duration>,
     pub min_download_speed: Option<f64>,
     pub min_upload_speed: Option<f64>,
     pub fast_mode: bool,
+    pub latency_mode: LatencyMode,
+    pub latency_method: LatencyMethod, // GET or HEAD for HTTP pings
+    pub latency_target: LatencyTarget, // also time the hop to the proxy itself
+    pub retries: usize,
+    pub retry_backoff: Duration,
+    pub delay_test_url: Option<String>, // mihomo delay API test URL
+    pub latency_iterations: usize,      // pings per latency test
+    pub latency_under_load: bool,       // also ping while a download saturates the link
+    pub per_proxy_timeout: Option<Duration>, // wall-clock budget for all phases of one proxy
+    pub max_total_connections: Option<usize>, // bandwidth connections open at once across proxies, at least 1
+    pub ip_version: IpVersion,
+    pub upload_payload: UploadPayload,
+    pub fresh_connections: bool, // disable connection reuse, every request does a fresh handshake
+    pub bind_address: Option<IpAddr>, // local address the direct client binds to
+    pub dns_server: Option<IpAddr>, // resolver for proxy hostnames instead of the system one
+    pub first_match: bool,       // stop the run once a proxy meets the criteria
+    pub isolate_servers: bool,   // never test two proxies of one server at the same time
+}
+
+impl SpeedTestConfig {
+    /// Endpoints of the configured speed test server
+    pub fn speed_test_backend(&self) -> SharedBackend {
+        self.backend.backend(&self.server_url)
+    }
+
+    /// Endpoints of the fallback servers, in the order they are tried
+    pub fn fallback_backends(&self) -> Vec<SharedBackend> {
+        self.fallback_server_urls
+            .iter()
+            .map(|url| self.backend.backend(url))
+            .collect()
+    }
+
+    /// New semaphore enforcing `max_total_connections`, a tester creates one and shares it
+    /// across every proxy it tests
+    pub fn connection_limit(&self) -> Option<ConnectionLimit> {
+        self.max_total_connections
+            .map(|max| Arc::new(tokio::sync::Semaphore::new(max)))
+    }
+
+    /// Whether a result passes `max_latency` and, outside fast mode, the minimum speeds
+    pub fn meets_criteria(&self, result: &SpeedTestResult) -> bool {
+        if !result.is_successful() {
+            return false;
+        }
+
+        if let (Some(max_latency), Some(latency)) = (self.max_latency, result.latency)
+            && latency > max_latency
+        {
+            return false;
+        }
+
+        if self.fast_mode {
+            return true;
+        }
+
+        self.min_download_speed
+            .is_none_or(|min| result.download_speed >= min)
+            && self
+                .min_upload_speed
+                .is_none_or(|min| result.upload_speed >= min)
+    }
+
+    /// Local address direct connections bind to, if any, see `ClientOptions::local_address`
+    pub(crate) fn local_address(&self) -> Option<IpAddr> {
+        self.bind_address
+            .or_else(|| self.ip_version.local_address())
+    }
+
+    /// Whether `result` ends a `first_match` run
+    pub(crate) fn is_first_match(&self, result: &SpeedTestResult) -> bool {
+        if self.first_match && self.meets_criteria(result) {
+            info!(
+                "🎯 {} meets the criteria, skipping the rest",
+                result.proxy_name
+            );
+            return true;
+        }
+        false
+    }
 }
 
 impl Default for SpeedTestConfig {
     fn default() -> Self {
         Self {
             server_url: "https://speed.cloudflare.com".to_string(),
+            backend: BackendKind::Cloudflare,
+            fallback_server_urls: Vec::new(),
             download_timeout: Duration::from_secs(10), // 下载超时10秒
             upload_timeout: Duration::from_secs(30),   // 上传超时30秒
+            connect_timeout: Duration::from_secs(5),   // 连接超时5秒
             concurrent: 4,
             download_size: 50 * 1024 * 1024, // 50MB
-            upload_size: 20 * 1024 * 1024,   // 20MB
+            download_duration: None,
+            warmup: Duration::ZERO,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            adaptive_download: false,
+            auto_size: None,
+            upload_size: 20 * 1024 * 1024, // 20MB
+            concurrent_upload: 1,
             max_latency: Some(Duration::from_millis(800)),
             min_download_speed: Some(5.0 * 1024.0 * 1024.0), // 5MB/s
             min_upload_speed: Some(2.0 * 1024.0 * 1024.0),   // 2MB/s
             fast_mode: false,
+            latency_mode: LatencyMode::Http,
+            latency_method: LatencyMethod::Get,
+            latency_target: LatencyTarget::Server,
+            retries: 0,
+            retry_backoff: Duration::from_millis(500),
+            delay_test_url: None,
+            latency_iterations: 6,
+            latency_under_load: false,
+            per_proxy_timeout: None,
+            max_total_connections: None,
+            ip_version: IpVersion::Auto,
+            upload_payload: UploadPayload::Zero,
+            fresh_connections: false,
+            bind_address: None,
+            dns_server: None,
+            first_match: false,
+            isolate_servers: false,
         }
     }
 }
 
+/// Wall-clock time spent in each phase of a proxy test
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    /// Switching mihomo to the proxy, including the settle delay (mihomo only)
+    #[serde(default, with = "duration_ms::option")]
+    pub switch: Option<Duration>,
+    #[serde(with = "duration_ms")]
+    pub latency: Duration,
+    #[serde(default, with = "duration_ms::option")]
+    pub download: Option<Duration>,
+    #[serde(default, with = "duration_ms::option")]
+    pub upload: Option<Duration>,
+}
+
 /// Result of a speed test for a single proxy
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpeedTestResult {
     pub proxy_name: String,
     pub proxy_type: crate::config::ProxyType,
+    #[serde(default, with = "duration_ms::option")]
     pub latency: Option<Duration>,
+    #[serde(default, with = "duration_ms::option")]
     pub jitter: Option<Duration>,
+    #[serde(default, with = "duration_ms::option")]
+    pub min_latency: Option<Duration>,
+    #[serde(default, with = "duration_ms::option")]
+    pub max_latency: Option<Duration>,
+    /// Latency with the link idle and while a download saturates it (`--latency-under-load`)
+    #[serde(default, with = "duration_ms::option")]
+    pub latency_idle: Option<Duration>,
+    #[serde(default, with = "duration_ms::option")]
+    pub latency_loaded: Option<Duration>,
+    /// TCP connect time to the proxy's own server:port (`--latency-target proxy`)
+    #[serde(default, with = "duration_ms::option")]
+    pub proxy_latency: Option<Duration>,
+    /// Round-trip time of every successful ping
+    #[serde(default, with = "duration_ms::vec")]
+    pub latency_samples: Vec<Duration>,
     pub packet_loss: f64,
     pub download_speed: f64, // bytes per second
     pub upload_speed: f64,   // bytes per second
+    #[serde(default, with = "duration_ms::option")]
     pub download_time: Option<Duration>,
+    /// Mean time from sending a download request to the first byte of its body
+    #[serde(default, with = "duration_ms::option")]
+    pub ttfb: Option<Duration>,
+    #[serde(default, with = "duration_ms::option")]
     pub upload_time: Option<Duration>,
+    /// Instantaneous download throughput captured every ~200ms (bytes per second)
+    #[serde(default)]
+    pub download_speed_samples: Vec<f64>,
+    /// Instantaneous upload throughput captured the same way (bytes per second)
+    #[serde(default)]
+    pub upload_speed_samples: Vec<f64>,
+    /// Bytes moved by the bandwidth tests
+    #[serde(default)]
+    pub download_bytes: u64,
+    #[serde(default)]
+    pub upload_bytes: u64,
+    /// Connections that reached the peak in an adaptive download
+    #[serde(default)]
+    pub download_concurrency: Option<usize>,
+    /// Download size `--auto-size` picked from the bandwidth probe
+    #[serde(default)]
+    pub auto_download_size: Option<u64>,
+    /// Coefficient of variation of the download samples, lower means steadier throughput
+    #[serde(default)]
+    pub download_stability: Option<f64>,
+    /// Speed test server the bandwidth tests ran against
+    #[serde(default)]
+    pub server_used: Option<String>,
+    #[serde(default)]
+    pub timings: PhaseTimings,
     pub error: Option<String>,
+    /// Cause of `error`, for filtering and counting failures
+    #[serde(default)]
+    pub error_kind: Option<ErrorKind>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -65,16 +265,39 @@ impl SpeedTestResult {
             proxy_type,
             latency: None,
             jitter: None,
+            min_latency: None,
+            max_latency: None,
+            latency_idle: None,
+            latency_loaded: None,
+            proxy_latency: None,
+            latency_samples: Vec::new(),
             packet_loss: 100.0,
             download_speed: 0.0,
             upload_speed: 0.0,
             download_time: None,
+            ttfb: None,
             upload_time: None,
+            download_speed_samples: Vec::new(),
+            upload_speed_samples: Vec::new(),
+            download_bytes: 0,
+            upload_bytes: 0,
+            download_concurrency: None,
+            auto_download_size: None,
+            download_stability: None,
+            server_used: None,
+            timings: PhaseTimings::default(),
             error: Some(error),
+            error_kind: Some(ErrorKind::Other),
             timestamp: Utc::now(),
         }
     }
 
+    /// Set the cause of the failure
+    pub fn with_error_kind(mut self, kind: ErrorKind) -> Self {
+        self.error_kind = Some(kind);
+        self
+    }
+
     /// Format latency for display
     pub fn format_latency(&self) -> String {
         match self.latency {
@@ -103,51 +326,353 @@ impl SpeedTestResult {
         }
     }
 
+    /// Percentile of the instantaneous download throughput samples (bytes per second)
+    pub fn download_speed_percentile(&self, percentile: f64) -> Option<f64> {
+        StatisticalAnalysis::percentile_f64(&mut self.download_speed_samples.clone(), percentile)
+    }
+
+    /// Percentile of the instantaneous upload throughput samples (bytes per second)
+    pub fn upload_speed_percentile(&self, percentile: f64) -> Option<f64> {
+        StatisticalAnalysis::percentile_f64(&mut self.upload_speed_samples.clone(), percentile)
+    }
+
+    /// Standard deviation of the instantaneous download throughput samples (bytes per second)
+    pub fn download_speed_std_deviation(&self) -> Option<f64> {
+        if self.download_speed_samples.is_empty() {
+            return None;
+        }
+        let mean = StatisticalAnalysis::mean_f64(&self.download_speed_samples);
+        Some(StatisticalAnalysis::std_deviation_f64(
+            &self.download_speed_samples,
+            mean,
+        ))
+    }
+
+    /// How much latency rose while the link was loaded
+    pub fn latency_increase(&self) -> Option<Duration> {
+        Some(self.latency_loaded?.saturating_sub(self.latency_idle?))
+    }
+
+    /// Format loaded latency and its increase over idle for display
+    pub fn format_loaded_latency(&self) -> String {
+        match (self.latency_loaded, self.latency_increase()) {
+            (Some(loaded), Some(increase)) => {
+                format!("{}ms (+{}ms)", loaded.as_millis(), increase.as_millis())
+            }
+            (Some(loaded), None) => format!("{}ms", loaded.as_millis()),
+            _ => "-".to_string(),
+        }
+    }
+
+    /// Format time to first byte for display
+    pub fn format_ttfb(&self) -> String {
+        match self.ttfb {
+            Some(ttfb) => format!("{}ms", ttfb.as_millis()),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Format the latency to the proxy itself for display
+    pub fn format_proxy_latency(&self) -> String {
+        match self.proxy_latency {
+            Some(latency) => format!("{}ms", latency.as_millis()),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Format download stability for display
+    pub fn format_download_stability(&self) -> String {
+        match self.download_stability {
+            Some(cv) => format!("±{:.1}%", cv * 100.0),
+            None => "-".to_string(),
+        }
+    }
+
     /// Check if the test was successful
     pub fn is_successful(&self) -> bool {
         self.error.is_none() && self.latency.is_some()
     }
 }
 
+/// Key used to order results before display and export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    Latency,
+    Download,
+    Upload,
+    Name,
+}
+
+impl std::fmt::Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortKey::Latency => write!(f, "latency"),
+            SortKey::Download => write!(f, "download"),
+            SortKey::Upload => write!(f, "upload"),
+            SortKey::Name => write!(f, "name"),
+        }
+    }
+}
+
+/// Sort results by `key`, failed proxies always go last regardless of direction
+pub fn sort_results(results: &mut [SpeedTestResult], key: SortKey, descending: bool) {
+    results.sort_by(|a, b| {
+        let by_key = || {
+            let ordering = match key {
+                SortKey::Latency => a.latency.cmp(&b.latency),
+                SortKey::Download => a.download_speed.total_cmp(&b.download_speed),
+                SortKey::Upload => a.upload_speed.total_cmp(&b.upload_speed),
+                SortKey::Name => a.proxy_name.cmp(&b.proxy_name),
+            };
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        };
+
+        b.is_successful().cmp(&a.is_successful()).then_with(by_key)
+    });
+}
+
+/// Request the speed test server's empty latency endpoint through `client`
+pub(crate) async fn probe_server(
+    client: &reqwest::Client,
+    backend: &dyn SpeedTestBackend,
+) -> Result<()> {
+    client
+        .get(backend.latency_url())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Client for the server reachability check, bounded by `timeout`
+pub(crate) fn server_check_client(timeout: Duration) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(timeout.min(SERVER_CHECK_TIMEOUT))
+        .danger_accept_invalid_certs(true)
+        .no_proxy()
+        .build()?)
+}
+
+/// Error reported when the speed test server can't be reached at all
+pub(crate) fn server_unreachable(server_url: &str, error: anyhow::Error) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Speed test server {} is unreachable ({}). Every proxy would fail; try another server with --server-url or pass --skip-server-check",
+        server_url,
+        error
+    )
+}
+
+/// Whether an error is a connection or timeout failure worth retrying
+fn is_transient_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            e.is_connect() || e.is_timeout()
+        } else if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::TimedOut
+            )
+        } else {
+            false
+        }
+    })
+}
+
 /// Main speed testing engine
 pub struct SpeedTester {
     config: SpeedTestConfig,
     network_tester: NetworkTester,
+    dns: DnsCache,
+    sink: Option<ResultSink>,
+    on_result: Option<ProgressCallback>,
 }
 
 impl SpeedTester {
-    /// Create a new speed tester with the given configuration
-    pub fn new(config: SpeedTestConfig) -> Self {
+    /// Create a new speed tester with the given configuration. Fails when the resolver
+    /// for `dns_server` can't be built, rather than quietly using the system one.
+    pub fn new(config: SpeedTestConfig) -> Result<Self> {
+        let dns = DnsCache::for_server(config.dns_server).with_context(|| {
+            format!(
+                "Can't use DNS server {}",
+                config
+                    .dns_server
+                    .map_or_else(String::new, |ip| ip.to_string())
+            )
+        })?;
         let network_tester = NetworkTester::new(
-            config.server_url.clone(),
+            config.speed_test_backend(),
             config.download_timeout,
             config.upload_timeout,
-        );
-        Self {
+        )
+        .with_connection_limit(config.connection_limit())
+        .with_ip_version(config.ip_version)
+        .with_upload_payload(config.upload_payload)
+        .with_fresh_connections(config.fresh_connections)
+        .with_connect_timeout(config.connect_timeout)
+        .with_latency_method(config.latency_method)
+        .with_bind_address(config.bind_address)
+        .with_warmup(config.warmup)
+        .with_stall_timeout(config.stall_timeout)
+        .with_fallback_backends(config.fallback_backends())
+        .with_dns_cache(dns.clone());
+        Ok(Self {
             config,
             network_tester,
+            dns,
+            sink: None,
+            on_result: None,
+        })
+    }
+
+    /// Resolve proxy servers through `dns` instead of the resolver picked by `dns_server`
+    pub fn with_dns_cache(mut self, dns: DnsCache) -> Self {
+        self.network_tester = self.network_tester.with_dns_cache(dns.clone());
+        self.dns = dns;
+        self
+    }
+
+    /// Also push every completed result into `sink`
+    pub fn with_result_sink(mut self, sink: ResultSink) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Call `callback` with every result as soon as its test completes
+    pub fn with_result_callback(mut self, callback: ProgressCallback) -> Self {
+        self.on_result = Some(callback);
+        self
+    }
+
+    /// Check that a speed test server answers a direct (non-proxied) request, trying
+    /// the fallback servers when the primary one doesn't
+    pub async fn check_server_reachable(&self) -> Result<()> {
+        let client = server_check_client(self.config.download_timeout)?;
+        let primary_error =
+            match probe_server(&client, self.config.speed_test_backend().as_ref()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+        for backend in self.config.fallback_backends() {
+            if probe_server(&client, backend.as_ref()).await.is_ok() {
+                warn!(
+                    "Speed test server {} is unreachable, falling back to {}",
+                    self.config.server_url,
+                    backend.server_url()
+                );
+                return Ok(());
+            }
         }
+        Err(server_unreachable(&self.config.server_url, primary_error))
     }
 
-    /// Test a single proxy
+    /// Hand a completed result to the sink and result callback, if any
+    fn record(&self, result: &SpeedTestResult) {
+        debug!(
+            "Phase timings for {}: {:?}",
+            result.proxy_name, result.timings
+        );
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().push(result.clone());
+        }
+        if let Some(callback) = &self.on_result {
+            callback(result);
+        }
+    }
+
+    /// Test a single proxy, bounded by the per-proxy timeout if one is configured
     pub async fn test_proxy(&self, proxy: &ProxyConfig) -> Result<SpeedTestResult> {
+        let Some(budget) = self.config.per_proxy_timeout else {
+            return self.run_proxy_test(proxy).await;
+        };
+
+        match tokio::time::timeout(budget, self.run_proxy_test(proxy)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("Test for {} exceeded {:?}", proxy.name, budget);
+                Ok(SpeedTestResult::failed(
+                    proxy.name.clone(),
+                    proxy.proxy_type.clone(),
+                    PER_PROXY_TIMEOUT_ERROR.to_string(),
+                )
+                .with_error_kind(ErrorKind::Timeout))
+            }
+        }
+    }
+
+    /// Run latency, download and upload phases for a single proxy
+    async fn run_proxy_test(&self, proxy: &ProxyConfig) -> Result<SpeedTestResult> {
         info!("Testing proxy: {}", proxy.name);
 
+        // Usually answered from the cache `pre_resolve` filled
+        if let Err(e) = self.dns.resolve(&proxy.server).await {
+            warn!("DNS resolution failed for {}: {}", proxy.name, e);
+            return Ok(SpeedTestResult::failed(
+                proxy.name.clone(),
+                proxy.proxy_type.clone(),
+                format!("DNS resolution failed: {e}"),
+            )
+            .with_error_kind(ErrorKind::Dns));
+        }
+
         let start_time = Utc::now();
 
         // Test latency first
-        let latency_result = match self.network_tester.test_latency(proxy, 6).await {
+        let latency_started = Instant::now();
+        let latency_result = self.test_latency_with_retry(proxy).await;
+        let mut timings = PhaseTimings {
+            latency: latency_started.elapsed(),
+            ..Default::default()
+        };
+        let latency_result = match latency_result {
             Ok(result) => result,
             Err(e) => {
                 warn!("Latency test failed for {}: {}", proxy.name, e);
-                return Ok(SpeedTestResult::failed(
-                    proxy.name.clone(),
-                    proxy.proxy_type.clone(),
-                    format!("Latency test failed: {e}"),
-                ));
+                return Ok(SpeedTestResult {
+                    timings,
+                    ..SpeedTestResult::failed(
+                        proxy.name.clone(),
+                        proxy.proxy_type.clone(),
+                        format!("Latency test failed: {e}"),
+                    )
+                    .with_error_kind(ErrorKind::classify(&e))
+                });
             }
         };
 
+        let (latency_idle, latency_loaded) = if self.config.latency_under_load {
+            let loaded_started = Instant::now();
+            let loaded = self
+                .network_tester
+                .test_latency_under_load(proxy, self.config.latency_iterations)
+                .await;
+            timings.latency += loaded_started.elapsed();
+            match loaded {
+                Ok(loaded) => (Some(latency_result.avg_latency), Some(loaded.avg_latency)),
+                Err(e) => {
+                    debug!("Loaded latency test failed for {}: {}", proxy.name, e);
+                    (Some(latency_result.avg_latency), None)
+                }
+            }
+        } else {
+            (None, None)
+        };
+
+        let proxy_latency = if self.config.latency_target == LatencyTarget::Proxy {
+            let hop_started = Instant::now();
+            let hop = self.test_proxy_hop_latency(proxy, &latency_result).await;
+            timings.latency += hop_started.elapsed();
+            hop
+        } else {
+            None
+        };
+
         // If fast mode is enabled, only test latency
         if self.config.fast_mode {
             return Ok(SpeedTestResult {
@@ -155,40 +680,112 @@ impl SpeedTester {
                 proxy_type: proxy.proxy_type.clone(),
                 latency: Some(latency_result.avg_latency),
                 jitter: Some(latency_result.jitter),
+                min_latency: Some(latency_result.min_latency),
+                max_latency: Some(latency_result.max_latency),
+                latency_idle,
+                latency_loaded,
+                proxy_latency,
+                latency_samples: latency_result.samples,
                 packet_loss: latency_result.packet_loss,
                 download_speed: 0.0,
                 upload_speed: 0.0,
                 download_time: None,
+                ttfb: None,
                 upload_time: None,
+                download_speed_samples: Vec::new(),
+                upload_speed_samples: Vec::new(),
+                download_bytes: 0,
+                upload_bytes: 0,
+                download_concurrency: None,
+                auto_download_size: None,
+                download_stability: None,
+                server_used: latency_result.server,
+                timings,
                 error: None,
+                error_kind: None,
                 timestamp: start_time,
             });
         }
 
-        // Test download speed
-        let download_result = if self.config.download_size > 0 {
-            match self
-                .network_tester
-                .test_download(proxy, self.config.download_size, self.config.concurrent)
-                .await
-            {
-                Ok(result) => Some(result),
-                Err(e) => {
-                    debug!("Download test failed for {}: {}", proxy.name, e);
-                    None
-                }
+        // Test download speed, a time budget takes precedence over a fixed size
+        let download_started = Instant::now();
+        let mut download_concurrency = None;
+        let mut auto_download_size = None;
+        let download_result = match self.config.download_duration {
+            window if self.config.adaptive_download => Some(
+                self.network_tester
+                    .test_download_adaptive(proxy, window.unwrap_or(DEFAULT_ADAPTIVE_WINDOW))
+                    .await
+                    .map(|(result, concurrency)| {
+                        download_concurrency = Some(concurrency);
+                        result
+                    }),
+            ),
+            Some(duration) => Some(
+                self.network_tester
+                    .test_download_timed(proxy, duration, self.config.concurrent)
+                    .await,
+            ),
+            None if let Some(target) = self.config.auto_size => Some(
+                match self
+                    .network_tester
+                    .probe_download_size(
+                        proxy,
+                        latency_result.avg_latency,
+                        target,
+                        self.config.concurrent,
+                    )
+                    .await
+                {
+                    Ok((size, probe_bytes)) => {
+                        auto_download_size = Some(size as u64);
+                        // The probe's data counts towards what the test used
+                        self.network_tester
+                            .test_download(proxy, size, self.config.concurrent)
+                            .await
+                            .map(|mut result| {
+                                result.bytes += probe_bytes;
+                                result
+                            })
+                    }
+                    Err(e) => Err(e),
+                },
+            ),
+            None if self.config.download_size > 0 => Some(
+                self.network_tester
+                    .test_download(proxy, self.config.download_size, self.config.concurrent)
+                    .await,
+            ),
+            None => None,
+        }
+        .and_then(|result| match result {
+            Ok(result) => Some(result),
+            Err(e) => {
+                debug!("Download test failed for {}: {}", proxy.name, e);
+                None
             }
-        } else {
-            None
-        };
+        });
+        if self.config.adaptive_download
+            || self.config.download_duration.is_some()
+            || self.config.auto_size.is_some()
+            || self.config.download_size > 0
+        {
+            timings.download = Some(download_started.elapsed());
+        }
 
         // Test upload speed
         let upload_result = if self.config.upload_size > 0 {
-            match self
+            let upload_started = Instant::now();
+            let result = self
                 .network_tester
-                .test_upload(proxy, self.config.upload_size)
-                .await
-            {
+                .test_upload(
+                    proxy,
+                    self.config.upload_size,
+                    self.config.concurrent_upload,
+                )
+                .await;
+            timings.upload = Some(upload_started.elapsed());
+            match result {
                 Ok(result) => Some(result),
                 Err(e) => {
                     debug!("Upload test failed for {}: {}", proxy.name, e);
@@ -204,16 +801,99 @@ impl SpeedTester {
             proxy_type: proxy.proxy_type.clone(),
             latency: Some(latency_result.avg_latency),
             jitter: Some(latency_result.jitter),
+            min_latency: Some(latency_result.min_latency),
+            max_latency: Some(latency_result.max_latency),
+            latency_idle,
+            latency_loaded,
+            proxy_latency,
+            latency_samples: latency_result.samples,
             packet_loss: latency_result.packet_loss,
             download_speed: download_result.as_ref().map_or(0.0, |r| r.speed),
             upload_speed: upload_result.as_ref().map_or(0.0, |r| r.speed),
             download_time: download_result.as_ref().map(|r| r.duration),
+            ttfb: download_result.as_ref().and_then(|r| r.ttfb),
             upload_time: upload_result.as_ref().map(|r| r.duration),
+            download_bytes: download_result.as_ref().map_or(0, |r| r.bytes as u64),
+            upload_bytes: upload_result.as_ref().map_or(0, |r| r.bytes as u64),
+            download_concurrency,
+            auto_download_size,
+            download_stability: download_result
+                .as_ref()
+                .and_then(|r| StatisticalAnalysis::coefficient_of_variation(&r.samples)),
+            server_used: download_result
+                .as_ref()
+                .or(upload_result.as_ref())
+                .and_then(|r| r.server.clone())
+                .or(latency_result.server),
+            download_speed_samples: download_result.map(|r| r.samples).unwrap_or_default(),
+            upload_speed_samples: upload_result.map(|r| r.samples).unwrap_or_default(),
+            timings,
             error: None,
+            error_kind: None,
             timestamp: start_time,
         })
     }
 
+    /// Run the latency phase, retrying transient failures with exponential backoff
+    async fn test_latency_with_retry(&self, proxy: &ProxyConfig) -> Result<LatencyResult> {
+        let mut attempt = 0;
+
+        loop {
+            let result = match self.config.latency_mode {
+                LatencyMode::Http => {
+                    self.network_tester
+                        .test_latency(proxy, self.config.latency_iterations)
+                        .await
+                }
+                LatencyMode::Tcp => {
+                    self.network_tester
+                        .test_tcp_latency(proxy, self.config.latency_iterations)
+                        .await
+                }
+            };
+
+            match result {
+                Err(e) if attempt < self.config.retries && is_transient_error(&e) => {
+                    let backoff = self
+                        .config
+                        .retry_backoff
+                        .saturating_mul(1 << attempt.min(16));
+                    attempt += 1;
+                    warn!(
+                        "Latency test for {} failed ({}), retry {}/{} in {:?}",
+                        proxy.name, e, attempt, self.config.retries, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    /// TCP connect time from this machine to the proxy's server:port. With
+    /// `--latency-mode tcp` the latency test already measured exactly that.
+    async fn test_proxy_hop_latency(
+        &self,
+        proxy: &ProxyConfig,
+        latency_result: &LatencyResult,
+    ) -> Option<Duration> {
+        if self.config.latency_mode == LatencyMode::Tcp {
+            return Some(latency_result.avg_latency);
+        }
+
+        match self
+            .network_tester
+            .test_tcp_latency(proxy, self.config.latency_iterations)
+            .await
+        {
+            Ok(result) => Some(result.avg_latency),
+            Err(e) => {
+                debug!("Proxy hop latency test failed for {}: {}", proxy.name, e);
+                None
+            }
+        }
+    }
+
     /// Test multiple proxies with optional progress callback
     pub async fn test_proxies(
         &self,
@@ -223,6 +903,7 @@ impl SpeedTester {
         let mut results = Vec::with_capacity(proxies.len());
 
         info!("Starting speed test for {} proxies", proxies.len());
+        self.pre_resolve(&proxies).await;
 
         for (index, proxy) in proxies.iter().enumerate() {
             debug!(
@@ -233,33 +914,831 @@ impl SpeedTester {
             );
 
             let result = self.test_proxy(proxy).await?;
+            self.record(&result);
 
             if let Some(ref callback) = callback {
                 callback(&result);
             }
 
+            let matched = self.config.is_first_match(&result);
             results.push(result);
+            if matched {
+                break;
+            }
         }
 
         info!("Completed testing {} proxies", results.len());
         Ok(results)
     }
 
-    /// Test multiple proxies concurrently
+    /// Resolve every proxy server up front, so proxies sharing a domain look it up once
+    /// and unresolvable ones fail before any connection attempt. A `first_match` run
+    /// likely stops after a few proxies, its servers are resolved as they are tested.
+    async fn pre_resolve(&self, proxies: &[ProxyConfig]) {
+        if self.config.first_match {
+            return;
+        }
+        let unresolved = self.dns.pre_resolve(proxies).await;
+        if !unresolved.is_empty() {
+            let mut hosts: Vec<_> = unresolved.into_iter().collect();
+            hosts.sort();
+            warn!(
+                "{} proxy servers don't resolve: {}",
+                hosts.len(),
+                hosts.join(", ")
+            );
+        }
+    }
+
+    /// Test multiple proxies concurrently, calling `callback` as each one finishes. With
+    /// `isolate_servers` proxies sharing a server take turns instead of running together.
     pub async fn test_proxies_concurrent(
         &self,
         proxies: Vec<ProxyConfig>,
         max_concurrent: usize,
+        callback: Option<ProgressCallback>,
     ) -> Result<Vec<SpeedTestResult>> {
-        use futures::stream::{StreamExt, iter};
+        use futures::stream::{FuturesUnordered, StreamExt};
+
+        self.pre_resolve(&proxies).await;
+        let mut scheduler = ProxyScheduler::new(proxies, self.config.isolate_servers);
+        let mut tests = FuturesUnordered::new();
+
+        let mut results = Vec::new();
+        loop {
+            while tests.len() < max_concurrent
+                && let Some(proxy) = scheduler.next_proxy()
+            {
+                tests.push(async move {
+                    let result = self.test_proxy(&proxy).await;
+                    if let Ok(result) = &result {
+                        self.record(result);
+                    }
+                    (proxy, result)
+                });
+            }
+            let Some((proxy, result)) = tests.next().await else {
+                break;
+            };
+            scheduler.finish(&proxy);
+            let result = result?;
+            if let Some(ref callback) = callback {
+                callback(&result);
+            }
+            let matched = self.config.is_first_match(&result);
+            results.push(result);
+            // Dropping the stream cancels the tests still in flight
+            if matched {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// HTTP proxy that drops the first `failures` requests and answers the rest
+    async fn spawn_flaky_proxy(failures: usize) -> u16 {
+        spawn_counting_proxy(failures).await.0
+    }
+
+    /// Like `spawn_flaky_proxy`, also returning the number of requests received
+    pub(crate) async fn spawn_counting_proxy(failures: usize) -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if requests.fetch_add(1, Ordering::SeqCst) >= failures {
+                        let _ = socket
+                            .write_all(
+                                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            )
+                            .await;
+                    }
+                });
+            }
+        });
+
+        (port, counter)
+    }
+
+    #[tokio::test]
+    async fn test_phase_timings_recorded() {
+        let port = spawn_flaky_proxy(0).await;
+        let proxy = ProxyConfig {
+            name: "timed".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            upload_timeout: Duration::from_secs(2),
+            download_size: 1024,
+            upload_size: 1024,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        assert!(result.is_successful(), "{:?}", result.error);
+        let timings = &result.timings;
+        assert!(timings.switch.is_none());
+        assert!(timings.latency > Duration::ZERO);
+        assert!(timings.download.is_some_and(|d| d > Duration::ZERO));
+        assert!(timings.upload.is_some_and(|d| d > Duration::ZERO));
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.contains("\"timings\""));
+    }
+
+    #[tokio::test]
+    async fn test_server_check() {
+        let (port, requests) = spawn_counting_proxy(0).await;
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: format!("http://127.0.0.1:{port}"),
+            ..Default::default()
+        })
+        .unwrap();
+        tester.check_server_reachable().await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Nothing listens on a port whose listener was just dropped
+        let closed_port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: format!("http://127.0.0.1:{closed_port}"),
+            download_timeout: Duration::from_secs(2),
+            ..Default::default()
+        })
+        .unwrap();
+        let error = tester.check_server_reachable().await.unwrap_err();
+        assert!(error.to_string().contains("--server-url"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_per_proxy_timeout_bounds_stalled_proxy() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let proxy = ProxyConfig {
+            name: "stalled".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(30),
+            per_proxy_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let started = std::time::Instant::now();
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(result.error.as_deref(), Some(PER_PROXY_TIMEOUT_ERROR));
+        assert_eq!(result.error_kind, Some(ErrorKind::Timeout));
+    }
+
+    #[tokio::test]
+    async fn test_latency_uses_configured_ping_count() {
+        let (port, requests) = spawn_counting_proxy(0).await;
+        let proxy = ProxyConfig {
+            name: "counted".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            latency_iterations: 3,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        assert!(result.is_successful(), "{:?}", result.error);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_latency_retries_transient_failures() {
+        // Connections are refused until the proxy comes up, after the first full
+        // latency attempt of 6 pings
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(700)).await;
+            let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                });
+            }
+        });
+        let proxy = ProxyConfig {
+            name: "flaky".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            retries: 3,
+            retry_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        assert!(result.is_successful(), "{:?}", result.error);
+    }
 
-        let results = iter(proxies)
-            .map(|proxy| async move { self.test_proxy(&proxy).await })
-            .buffer_unordered(max_concurrent)
-            .collect::<Vec<_>>()
+    #[tokio::test]
+    async fn test_latency_range_is_carried_into_result() {
+        let port = spawn_flaky_proxy(0).await;
+        let proxy = ProxyConfig {
+            name: "steady".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        let (min, avg, max) = (
+            result.min_latency.unwrap(),
+            result.latency.unwrap(),
+            result.max_latency.unwrap(),
+        );
+        assert!(min <= avg && avg <= max, "{min:?} <= {avg:?} <= {max:?}");
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("min_latency").is_some_and(|v| !v.is_null()));
+        assert!(json.get("max_latency").is_some_and(|v| !v.is_null()));
+    }
+
+    #[tokio::test]
+    async fn test_latency_without_retries_fails() {
+        let port = spawn_flaky_proxy(12).await;
+        let proxy = ProxyConfig {
+            name: "flaky".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        assert!(!result.is_successful());
+
+        // A dropped request isn't a timeout or connect error, retrying won't help.
+        // Giving up still records the proxy, with every ping lost.
+        let (port, requests) = spawn_counting_proxy(usize::MAX).await;
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            retries: 3,
+            retry_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .unwrap();
+        let results = tester
+            .test_proxies(vec![ProxyConfig { port, ..proxy }], None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].packet_loss, 100.0);
+        assert!(results[0].latency.is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn test_result_sink_keeps_completed_results_on_cancel() {
+        let ok_port = spawn_flaky_proxy(0).await;
+        // Never accepted, so requests through it hang until cancelled
+        let stalled = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stalled_port = stalled.local_addr().unwrap().port();
+
+        let proxies: Vec<_> = [("ok", ok_port), ("stalled", stalled_port)]
+            .into_iter()
+            .map(|(name, port)| ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Http,
+                server: "127.0.0.1".to_string(),
+                port,
+                config: Default::default(),
+            })
+            .collect();
+
+        let sink = ResultSink::default();
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(30),
+            fast_mode: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .with_result_sink(sink.clone());
+
+        let run = tester.test_proxies(proxies, None);
+        assert!(
+            tokio::time::timeout(Duration::from_secs(3), run)
+                .await
+                .is_err()
+        );
+
+        let collected = sink.lock().unwrap();
+        assert_eq!(collected.len(), 1);
+        assert_eq!(collected[0].proxy_name, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_first_match_stops_the_run() {
+        let (ok_port, _) = spawn_counting_proxy(0).await;
+        let (unused_port, unused_requests) = spawn_counting_proxy(0).await;
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        // Never accepted, so requests through it hang until cancelled
+        let stalled = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stalled_port = stalled.local_addr().unwrap().port();
+
+        let proxies = |ports: &[(&str, u16)]| -> Vec<ProxyConfig> {
+            ports
+                .iter()
+                .map(|&(name, port)| ProxyConfig {
+                    name: name.to_string(),
+                    proxy_type: ProxyType::Http,
+                    server: "127.0.0.1".to_string(),
+                    port,
+                    config: Default::default(),
+                })
+                .collect()
+        };
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(30),
+            fast_mode: true,
+            latency_iterations: 1,
+            first_match: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let results = tester
+            .test_proxies(
+                proxies(&[
+                    ("dead", closed_port),
+                    ("ok", ok_port),
+                    ("unused", unused_port),
+                ]),
+                None,
+            )
+            .await
+            .unwrap();
+        let names: Vec<_> = results.iter().map(|r| r.proxy_name.as_str()).collect();
+        assert_eq!(names, ["dead", "ok"]);
+        assert_eq!(unused_requests.load(Ordering::SeqCst), 0);
+
+        // The stalled proxies still in flight are cancelled instead of awaited
+        let run = tester.test_proxies_concurrent(
+            proxies(&[
+                ("stalled", stalled_port),
+                ("ok", ok_port),
+                ("queued", stalled_port),
+            ]),
+            2,
+            None,
+        );
+        let results = tokio::time::timeout(Duration::from_secs(3), run)
+            .await
+            .expect("first match should end the run")
+            .unwrap();
+        let names: Vec<_> = results.iter().map(|r| r.proxy_name.as_str()).collect();
+        assert_eq!(names, ["ok"]);
+    }
+
+    fn sample_results() -> Vec<SpeedTestResult> {
+        let measured = |name: &str, latency_ms: u64, download: f64, upload: f64| SpeedTestResult {
+            latency: Some(Duration::from_millis(latency_ms)),
+            download_speed: download,
+            upload_speed: upload,
+            error: None,
+            ..SpeedTestResult::failed(name.to_string(), ProxyType::Http, String::new())
+        };
+
+        vec![
+            measured("b", 300, 5.0, 1.0),
+            SpeedTestResult::failed("a".to_string(), ProxyType::Http, "timeout".to_string()),
+            measured("c", 100, 1.0, 3.0),
+            measured("d", 200, 9.0, 2.0),
+        ]
+    }
+
+    fn sorted_names(key: SortKey, descending: bool) -> Vec<String> {
+        let mut results = sample_results();
+        sort_results(&mut results, key, descending);
+        results.into_iter().map(|r| r.proxy_name).collect()
+    }
+
+    #[test]
+    fn test_sort_results_by_key() {
+        assert_eq!(sorted_names(SortKey::Latency, false), ["c", "d", "b", "a"]);
+        assert_eq!(sorted_names(SortKey::Download, false), ["c", "b", "d", "a"]);
+        assert_eq!(sorted_names(SortKey::Upload, false), ["b", "d", "c", "a"]);
+        assert_eq!(sorted_names(SortKey::Name, false), ["b", "c", "d", "a"]);
+    }
+
+    #[test]
+    fn test_sort_results_failed_last_when_descending() {
+        assert_eq!(sorted_names(SortKey::Latency, true), ["b", "d", "c", "a"]);
+        assert_eq!(sorted_names(SortKey::Download, true), ["d", "b", "c", "a"]);
+        assert_eq!(sorted_names(SortKey::Name, true), ["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_download_stability_coefficient_of_variation() {
+        let bursty = [10.0, 20.0, 30.0, 40.0];
+        let cv = StatisticalAnalysis::coefficient_of_variation(&bursty).unwrap();
+        assert!((cv - 125f64.sqrt() / 25.0).abs() < 1e-9);
+
+        let steady = StatisticalAnalysis::coefficient_of_variation(&[20.0, 21.0, 19.0]).unwrap();
+        assert!(steady < cv);
+
+        assert_eq!(StatisticalAnalysis::coefficient_of_variation(&[30.0]), None);
+
+        let result = SpeedTestResult {
+            download_stability: Some(cv),
+            ..SpeedTestResult::failed("a".to_string(), ProxyType::Http, String::new())
+        };
+        assert_eq!(result.format_download_stability(), "±44.7%");
+        assert!(
+            serde_json::to_string(&result)
+                .unwrap()
+                .contains("\"download_stability\":0.447")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_result_callback_streams_json_lines() {
+        let port = spawn_flaky_proxy(0).await;
+        let proxies: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|name| ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Http,
+                server: "127.0.0.1".to_string(),
+                port,
+                config: Default::default(),
+            })
+            .collect();
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .with_result_callback(Box::new({
+            let output = output.clone();
+            move |result| {
+                let mut output = output.lock().unwrap();
+                crate::output::ResultFormatter::write_json_line(&mut *output, result).unwrap();
+            }
+        }));
+
+        tester
+            .test_proxies_concurrent(proxies, 2, None)
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let mut names: Vec<_> = output
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<SpeedTestResult>(line)
+                    .unwrap()
+                    .proxy_name
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, ["first", "second"]);
+        assert!(output.ends_with('\n'));
+    }
+
+    #[tokio::test]
+    async fn test_latency_target_proxy_measures_connect_time() {
+        let port = spawn_flaky_proxy(0).await;
+        let proxy = ProxyConfig {
+            name: "local".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        let config = SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            ..Default::default()
+        };
+
+        let result = SpeedTester::new(config.clone())
+            .unwrap()
+            .test_proxy(&proxy)
+            .await
+            .unwrap();
+        assert!(result.is_successful(), "{:?}", result.error);
+        assert!(result.proxy_latency.is_none());
+
+        let tester = SpeedTester::new(SpeedTestConfig {
+            latency_target: LatencyTarget::Proxy,
+            ..config
+        })
+        .unwrap();
+        let result = tester.test_proxy(&proxy).await.unwrap();
+        assert!(result.is_successful(), "{:?}", result.error);
+        // Connecting to a local listener is near-instant
+        let proxy_latency = result.proxy_latency.unwrap();
+        assert!(proxy_latency < Duration::from_millis(100));
+        assert!(result.latency.is_some());
+        assert_eq!(
+            result.format_proxy_latency(),
+            format!("{}ms", proxy_latency.as_millis())
+        );
+
+        // Nothing listens on the port any more, the hop is left out rather than failing the proxy
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let hop = tester
+            .test_proxy_hop_latency(
+                &ProxyConfig {
+                    port: closed_port,
+                    ..proxy
+                },
+                &LatencyResult {
+                    avg_latency: Duration::from_millis(5),
+                    jitter: Duration::ZERO,
+                    packet_loss: 0.0,
+                    min_latency: Duration::from_millis(5),
+                    max_latency: Duration::from_millis(5),
+                    samples: Vec::new(),
+                    server: None,
+                },
+            )
             .await;
+        assert_eq!(hop, None);
+    }
+
+    /// When each request to a proxy started and ended
+    type RequestLog = Arc<Mutex<Vec<(String, Instant, Instant)>>>;
+
+    /// HTTP proxy answering every request after 50ms, logging it under `name`
+    async fn spawn_logging_proxy(name: &str, log: RequestLog) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let name = name.to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (name, log) = (name.clone(), log.clone());
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let started = Instant::now();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                    log.lock().unwrap().push((name, started, Instant::now()));
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_isolate_servers_never_overlaps_a_server() {
+        let log = RequestLog::default();
+        let mut proxies = Vec::new();
+        for (name, server) in [
+            ("a1", "127.0.0.1"),
+            ("a2", "127.0.0.1"),
+            ("b1", "localhost"),
+            ("b2", "localhost"),
+        ] {
+            // Two names for the loopback address are two servers to the scheduler
+            proxies.push(ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Http,
+                server: server.to_string(),
+                port: spawn_logging_proxy(name, log.clone()).await,
+                config: Default::default(),
+            });
+        }
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            isolate_servers: true,
+            ..Default::default()
+        })
+        .unwrap();
 
-        // Convert Vec<Result<T>> to Result<Vec<T>>
-        results.into_iter().collect()
+        let results = tester
+            .test_proxies_concurrent(proxies, 2, None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|r| r.is_successful()));
+
+        // From the first request of each proxy to the end of its last one
+        let span = |name: &str| {
+            let log = log.lock().unwrap();
+            let requests = log.iter().filter(|(n, _, _)| n == name);
+            let start = requests.clone().map(|r| r.1).min().unwrap();
+            let end = requests.map(|r| r.2).max().unwrap();
+            (start, end)
+        };
+        let overlaps = |a: &str, b: &str| {
+            let ((a_start, a_end), (b_start, b_end)) = (span(a), span(b));
+            a_start < b_end && b_start < a_end
+        };
+        assert!(!overlaps("a1", "a2"));
+        assert!(!overlaps("b1", "b2"));
+        // The two servers were still tested side by side
+        assert!(overlaps("a1", "b1"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_progress_fires_once_per_proxy() {
+        let ok_port = spawn_flaky_proxy(0).await;
+        // Drops its first requests, so it finishes after retrying
+        let flaky_port = spawn_flaky_proxy(1).await;
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+
+        let proxies: Vec<_> = [
+            ("ok", ok_port),
+            ("flaky", flaky_port),
+            ("dead", closed_port),
+        ]
+        .into_iter()
+        .map(|(name, port)| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        })
+        .collect();
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let callback: ProgressCallback = Box::new({
+            let reported = reported.clone();
+            move |result| reported.lock().unwrap().push(result.proxy_name.clone())
+        });
+        let results = tester
+            .test_proxies_concurrent(proxies, 3, Some(callback))
+            .await
+            .unwrap();
+
+        let mut reported = reported.lock().unwrap().clone();
+        assert_eq!(reported.len(), results.len());
+        reported.sort();
+        assert_eq!(reported, ["dead", "flaky", "ok"]);
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_server_fails_before_connecting() {
+        struct NxDomain;
+        impl crate::network::HostResolver for NxDomain {
+            fn lookup<'a>(
+                &'a self,
+                _host: &'a str,
+            ) -> futures::future::BoxFuture<'a, Result<crate::network::Lookup>> {
+                Box::pin(async { Err(anyhow::anyhow!("NXDOMAIN")) })
+            }
+        }
+
+        let port = spawn_flaky_proxy(0).await;
+        let proxies: Vec<_> = [("local", "127.0.0.1"), ("ghost", "ghost.test")]
+            .into_iter()
+            .map(|(name, server)| ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Http,
+                server: server.to_string(),
+                port,
+                config: Default::default(),
+            })
+            .collect();
+        let tester = SpeedTester::new(SpeedTestConfig {
+            server_url: "http://speedtest.invalid".to_string(),
+            download_timeout: Duration::from_secs(2),
+            fast_mode: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .with_dns_cache(DnsCache::new(NxDomain));
+
+        let results = tester.test_proxies(proxies, None).await.unwrap();
+        assert!(results[0].is_successful());
+        let ghost = &results[1];
+        assert!(
+            ghost
+                .error
+                .as_deref()
+                .unwrap()
+                .starts_with("DNS resolution failed: ghost.test: NXDOMAIN")
+        );
+        assert_eq!(ghost.error_kind, Some(ErrorKind::Dns));
+        assert!(ghost.latency.is_none());
     }
 }
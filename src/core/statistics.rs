@@ -49,7 +49,7 @@ impl StatisticalAnalysis {
         values.sort();
         let len = values.len();
 
-        if len % 2 == 0 {
+        if len.is_multiple_of(2) {
             let mid1 = values[len / 2 - 1];
             let mid2 = values[len / 2];
             Some((mid1 + mid2) / 2)
@@ -68,4 +68,52 @@ impl StatisticalAnalysis {
         let index = (percentile / 100.0 * (values.len() - 1) as f64).round() as usize;
         Some(values[index])
     }
+
+    /// Calculate mean of a set of values
+    pub fn mean_f64(values: &[f64]) -> f64 {
+        if values.is_empty() {
+            return 0.0;
+        }
+
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    /// Calculate standard deviation of values
+    pub fn std_deviation_f64(values: &[f64], mean: f64) -> f64 {
+        if values.len() <= 1 {
+            return 0.0;
+        }
+
+        let variance: f64 = values
+            .iter()
+            .map(|&val| {
+                let diff = val - mean;
+                diff * diff
+            })
+            .sum::<f64>()
+            / values.len() as f64;
+
+        variance.sqrt()
+    }
+
+    /// Coefficient of variation (std deviation / mean), None without at least two samples
+    pub fn coefficient_of_variation(values: &[f64]) -> Option<f64> {
+        let mean = Self::mean_f64(values);
+        if values.len() <= 1 || mean <= 0.0 {
+            return None;
+        }
+
+        Some(Self::std_deviation_f64(values, mean) / mean)
+    }
+
+    /// Calculate percentile of values
+    pub fn percentile_f64(values: &mut [f64], percentile: f64) -> Option<f64> {
+        if values.is_empty() || !(0.0..=100.0).contains(&percentile) {
+            return None;
+        }
+
+        values.sort_by(|a, b| a.total_cmp(b));
+        let index = (percentile / 100.0 * (values.len() - 1) as f64).round() as usize;
+        Some(values[index])
+    }
 }
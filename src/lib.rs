@@ -11,6 +11,7 @@ pub mod config;
 pub mod core;
 pub mod network;
 pub mod output;
+mod utils;
 
 // Re-export commonly used types for convenience
 pub use config::{ProxyConfig, ProxyParameters, ProxyType};
@@ -1,17 +1,37 @@
-use clap::Parser;
 use mihomo_speedtest_rs::{
-    cli::{Cli, progress::SpeedTestProgress},
-    config::ConfigLoader,
-    core::{MihomoRunner, RealSpeedTester, SpeedTester},
-    output::{ConfigExporter, ResultFormatter},
+    cli::{
+        Cli, RunOutcome,
+        dry_run::dry_run,
+        logging::init_logging,
+        progress::SpeedTestProgress,
+        tui,
+        watch::{CLEAR_SCREEN, run_watch},
+    },
+    config::{ConfigLoader, ProxyConfig},
+    core::{
+        Checkpoint, MihomoRunner, RealSpeedTester, ResultSink, SortKey, SpeedTestConfig,
+        SpeedTestResult, SpeedTester, aggregate_runs, pick_best, sort_results,
+        speedtest::ProgressCallback,
+    },
+    network::LatencyTarget,
+    output::{
+        ConfigExporter, ExportFormat, GeoIpDb, HistorySink, OutputFormat, OutputSink,
+        PrometheusSink, ResultFormatter, RunTotals, WebhookSink,
+    },
 };
+use std::cell::RefCell;
+use std::io::{self, IsTerminal};
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
     // Parse command line arguments
-    let args = Cli::parse();
+    let args = Cli::parse_validated();
 
     // Handle info commands first
     if args.show_author {
@@ -34,28 +54,34 @@ async fn main() {
     };
 
     // Initialize logging
-    let log_level = if args.verbose {
-        tracing::Level::DEBUG
-    } else {
-        tracing::Level::INFO
-    };
-
-    tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .with_target(false)
-        .init();
-
-    if let Err(e) = run(args, &config_paths).await {
-        error!("Application error: {}", e);
+    let log_file = args.log_file.as_deref().map(Path::new);
+    if let Err(e) = init_logging(args.log_level(), log_file, args.log_format) {
+        eprintln!("Error: {e:#}");
         process::exit(1);
     }
+
+    let strict_exit = args.strict_exit;
+    match run(args, &config_paths).await {
+        Ok(outcome) => {
+            let code = outcome.exit_code(strict_exit);
+            if code != 0 {
+                process::exit(code);
+            }
+        }
+        Err(e) => {
+            error!("Application error: {}", e);
+            process::exit(1);
+        }
+    }
 }
 
-async fn run(args: Cli, config_paths: &str) -> mihomo_speedtest_rs::Result<()> {
+async fn run(args: Cli, config_paths: &str) -> mihomo_speedtest_rs::Result<RunOutcome> {
     info!("🚀 Starting Mihomo SpeedTest");
 
-    // Display parameter table unless in JSON output mode
-    if !args.json_output {
+    let output_format = args.output_format();
+
+    // Display parameter table unless in a machine-readable output mode or quiet
+    if !output_format.is_machine_readable() && !args.quiet {
         let param_table = args.create_parameter_table();
         println!("\n📋 Configuration Parameters");
         println!("{}", param_table.format_table());
@@ -66,166 +92,618 @@ async fn run(args: Cli, config_paths: &str) -> mihomo_speedtest_rs::Result<()> {
         );
     }
 
+    // Only show what would be tested
+    if args.dry_run {
+        println!("{}", dry_run(&args, config_paths).await?);
+        return Ok(RunOutcome::Finished);
+    }
+
+    let webhook = webhook_sink(&args)?;
+    // Fail before testing rather than after when the browser can't be shown
+    if args.tui {
+        tui::require_terminal()?;
+    }
+
     // Load configuration
-    let loader = ConfigLoader::new();
+    let loader = ConfigLoader::new().with_format(args.config_format);
     let mut proxies = loader.load_from_paths(config_paths).await?;
 
     if proxies.is_empty() {
         warn!("No proxies loaded from configuration");
-        return Ok(());
+        return Ok(RunOutcome::NoProxies);
     }
 
     info!("📋 Loaded {} proxies", proxies.len());
-
-    // Apply name filtering
-    if args.filter_regex != ".+" {
-        let regex = regex::Regex::new(&args.filter_regex)?;
-        let original_count = proxies.len();
-        proxies.retain(|p| regex.is_match(&p.name));
-        info!(
-            "🔍 Filtered by regex '{}': {} -> {} proxies",
-            args.filter_regex,
-            original_count,
-            proxies.len()
-        );
+    for (_, subscription) in loader.subscription_info() {
+        info!("📊 Subscription: {}", subscription);
     }
 
-    // Apply keyword blocking
-    if let Some(ref keywords) = args.block_keywords {
-        let block_list: Vec<String> = keywords
-            .split('|')
-            .map(|s| s.trim().to_lowercase())
-            .filter(|s| !s.is_empty())
-            .collect();
+    // Skip proxies missing required fields, or abort with --strict
+    ConfigLoader::validate_proxies(&mut proxies, args.strict)?;
 
-        if !block_list.is_empty() {
-            let original_count = proxies.len();
-            proxies.retain(|p| {
-                !block_list
-                    .iter()
-                    .any(|keyword| p.name.to_lowercase().contains(keyword))
-            });
-            info!(
-                "🚫 Blocked keywords: {} -> {} proxies",
-                original_count,
-                proxies.len()
-            );
-        }
+    // Remove duplicates across subscriptions
+    if args.dedup {
+        ConfigLoader::dedup_proxies(&mut proxies);
     }
 
+    // Apply name filtering and keyword blocking
+    args.apply_filters(&mut proxies)?;
+    args.apply_sample(&mut proxies);
+
+    // TLS overrides apply to both the direct client and the mihomo config
+    args.apply_tls_overrides(&mut proxies);
+
     if proxies.is_empty() {
         warn!("No proxies remaining after filtering");
-        return Ok(());
+        return Ok(RunOutcome::NoProxies);
+    }
+
+    // Resume from a checkpoint, only testing proxies without a stored result
+    let (checkpoint, completed) = match args.checkpoint {
+        Some(ref path) => {
+            let (checkpoint, completed) = Checkpoint::open(path, args.restart)?;
+            (Some(Arc::new(checkpoint)), completed)
+        }
+        None => (None, Vec::new()),
+    };
+    // The full list is still needed to export the resumed results
+    let mut pending = proxies.clone();
+    Checkpoint::skip_completed(&mut pending, &completed);
+
+    let mihomo_only = pending
+        .iter()
+        .filter(|p| p.mihomo_only_feature().is_some())
+        .count();
+    if mihomo_only > 0 && !args.use_mihomo {
+        warn!(
+            "⚠️ {} proxies use dialer-proxy, shadow-tls or ShadowsocksR and will fail without --use-mihomo",
+            mihomo_only
+        );
     }
 
     // Create speed tester
     let config = args.to_speedtest_config();
+    let pipeline = Pipeline::new(&args, config_paths, &loader, &proxies, webhook);
+
+    if let Some(interval) = args.watch {
+        watch(&pipeline, config, &pending, interval).await?;
+        return Ok(RunOutcome::Finished);
+    }
 
     // Test proxies
-    info!("🧪 Starting speed tests for {} proxies", proxies.len());
-    let results = if args.use_mihomo {
-        // Use mihomo for real proxy testing
-        info!("🔧 Using mihomo process for real proxy testing");
-
-        let mihomo_runner = MihomoRunner::new(
-            &args.mihomo_config_dir,
-            args.mihomo_binary.as_ref(),
-            args.mihomo_api_port,
-            args.mihomo_proxy_port,
-        )?;
-
-        let mut real_tester = RealSpeedTester::new(mihomo_runner, config);
-        real_tester.test_proxies(&proxies).await?
-    } else {
-        // Use original direct testing method
-        let tester = SpeedTester::new(config);
-
-        if args.max_concurrent > 1 {
-            let progress = SpeedTestProgress::new(proxies.len() as u64);
-            let results = tester
-                .test_proxies_concurrent(proxies.clone(), args.max_concurrent)
-                .await?;
-            progress.finish_with_message("Speed tests completed!");
-            results
-        } else {
-            let progress = SpeedTestProgress::new(proxies.len() as u64);
-            let results = tester
-                .test_proxies(
-                    proxies.clone(),
-                    Some(Box::new({
-                        let progress = SpeedTestProgress::new(proxies.len() as u64);
-                        move |result| {
-                            progress.update(result);
-                        }
-                    })),
-                )
-                .await?;
-            progress.finish_with_message("Speed tests completed!");
-            results
+    info!("🧪 Starting speed tests for {} proxies", pending.len());
+    let sink = ResultSink::default();
+    let stream_json_lines = output_format == OutputFormat::JsonLines;
+    let result_callback = || -> Option<ProgressCallback> {
+        if !stream_json_lines && checkpoint.is_none() {
+            return None;
         }
+        let checkpoint = checkpoint.clone();
+        Some(Box::new(move |result| {
+            if stream_json_lines {
+                stream_json_line(result);
+            }
+            if let Some(ref checkpoint) = checkpoint
+                && let Err(e) = checkpoint.append(result)
+            {
+                warn!("Failed to write checkpoint: {}", e);
+            }
+        }))
     };
+    let started = Instant::now();
+    // Finished runs live outside the test future so an interruption keeps them, the
+    // sink only ever holds the results of the run in progress
+    let runs = RefCell::new(Vec::with_capacity(args.repeat));
+    let mut testing = Box::pin(async {
+        if pending.is_empty() {
+            info!("✅ Every proxy already has a result in the checkpoint");
+            return Ok(());
+        }
 
-    // Filter results based on performance criteria
-    let filtered_results: Vec<_> = results
-        .into_iter()
-        .filter(|result| {
-            if !result.is_successful() {
-                return false;
+        let mut tester =
+            Tester::new(&args, config, Some(sink.clone()), result_callback(), false).await?;
+        for run in 1..=args.repeat {
+            if args.repeat > 1 {
+                info!("🔁 Run {}/{}", run, args.repeat);
             }
+            let results = tester.test(&args, &pending).await?;
+            runs.borrow_mut().push(results);
+            sink.lock().unwrap().clear();
+        }
 
-            // Check latency
-            if let Some(latency) = result.latency {
-                if latency > args.max_latency {
-                    return false;
+        Ok::<_, anyhow::Error>(())
+    });
+
+    // The first Ctrl-C cancels the remaining tests, a second one exits immediately
+    tokio::select! {
+        finished = &mut testing => finished?,
+        _ = tokio::signal::ctrl_c() => {
+            warn!("🛑 Interrupted, stopping remaining tests (press Ctrl-C again to force exit)");
+            tokio::spawn(async {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    process::exit(130);
                 }
+            });
+
+            // Dropping the cancelled tests also stops the mihomo process
+            drop(testing);
+            let partial = std::mem::take(&mut *sink.lock().unwrap());
+            info!("📋 Showing {} results collected before interruption", partial.len());
+            if !partial.is_empty() {
+                runs.borrow_mut().push(partial);
             }
+        }
+    };
+    let runs = runs.take();
+
+    let totals = RunTotals::new(runs.iter().flatten(), started.elapsed());
+    let (all_results, outcome) = pipeline.report(runs, completed, &totals).await?;
 
-            // Check download speed (convert MB/s to bytes/s)
-            let min_download_bytes = args.min_download_speed * 1024.0 * 1024.0;
-            if result.download_speed < min_download_bytes && !args.fast_mode {
-                return false;
+    if args.tui {
+        let sort = args.sort_by.unwrap_or(SortKey::Latency);
+        let descending = args.sort_desc;
+        tokio::task::spawn_blocking(move || tui::browse(all_results, sort, descending)).await??;
+    }
+
+    info!("🎉 All tasks completed successfully!");
+    Ok(outcome)
+}
+
+/// Speed tester for either testing path
+enum Tester {
+    Direct(SpeedTester),
+    Mihomo(RealSpeedTester),
+}
+
+impl Tester {
+    /// Tester picked by `--use-mihomo`. With `keep_running` mihomo stays up between
+    /// calls to `test` and stops when the tester is dropped.
+    async fn new(
+        args: &Cli,
+        config: SpeedTestConfig,
+        sink: Option<ResultSink>,
+        callback: Option<ProgressCallback>,
+        keep_running: bool,
+    ) -> mihomo_speedtest_rs::Result<Self> {
+        if args.use_mihomo {
+            // Use mihomo for real proxy testing
+            info!("🔧 Using mihomo process for real proxy testing");
+
+            let mut tester = RealSpeedTester::new(mihomo_runner(args)?, config)
+                .with_server_check(!args.skip_server_check)
+                .with_keep_running(keep_running);
+            if let Some(sink) = sink {
+                tester = tester.with_result_sink(sink);
+            }
+            if let Some(callback) = callback {
+                tester = tester.with_result_callback(callback);
             }
+            Ok(Tester::Mihomo(tester))
+        } else {
+            // Use original direct testing method
+            let mut tester = SpeedTester::new(config)?;
+            if !args.skip_server_check {
+                tester.check_server_reachable().await?;
+            }
+            if let Some(sink) = sink {
+                tester = tester.with_result_sink(sink);
+            }
+            if let Some(callback) = callback {
+                tester = tester.with_result_callback(callback);
+            }
+            Ok(Tester::Direct(tester))
+        }
+    }
 
-            // Check upload speed (convert MB/s to bytes/s)
-            let min_upload_bytes = args.min_upload_speed * 1024.0 * 1024.0;
-            if result.upload_speed < min_upload_bytes && !args.fast_mode {
-                return false;
+    /// Test every proxy once
+    async fn test(
+        &mut self,
+        args: &Cli,
+        proxies: &[ProxyConfig],
+    ) -> mihomo_speedtest_rs::Result<Vec<SpeedTestResult>> {
+        match self {
+            Tester::Mihomo(tester) => {
+                tester
+                    .test_proxies_concurrent(proxies, args.max_concurrent)
+                    .await
+            }
+            Tester::Direct(tester) => {
+                let progress = progress_bar(args, proxies.len());
+                let callback = progress.as_ref().map(SpeedTestProgress::callback);
+                let results = if args.max_concurrent > 1 {
+                    tester
+                        .test_proxies_concurrent(proxies.to_vec(), args.max_concurrent, callback)
+                        .await?
+                } else {
+                    tester.test_proxies(proxies.to_vec(), callback).await?
+                };
+                if let Some(progress) = progress {
+                    progress.finish_with_message("Speed tests completed!");
+                }
+                Ok(results)
             }
+        }
+    }
+}
 
-            true
-        })
-        .collect();
+/// Everything after testing that single runs and watch cycles share: filtering,
+/// display, picking the best proxies and the exports
+struct Pipeline<'a> {
+    args: &'a Cli,
+    config_paths: &'a str,
+    loader: &'a ConfigLoader,
+    proxies: &'a [ProxyConfig],
+    output_format: OutputFormat,
+    formatter: ResultFormatter,
+    webhook: Option<WebhookSink>,
+}
 
-    info!(
-        "✅ {} proxies passed performance criteria",
-        filtered_results.len()
-    );
+impl<'a> Pipeline<'a> {
+    fn new(
+        args: &'a Cli,
+        config_paths: &'a str,
+        loader: &'a ConfigLoader,
+        proxies: &'a [ProxyConfig],
+        webhook: Option<WebhookSink>,
+    ) -> Self {
+        let output_format = args.output_format();
+        let formatter = ResultFormatter::new(output_format, args.use_colors())
+            .with_latency_range(args.show_latency_range)
+            .with_stability(args.show_stability)
+            .with_ttfb(args.show_ttfb)
+            .with_loaded_latency(args.latency_under_load)
+            .with_proxy_latency(args.latency_target == LatencyTarget::Proxy)
+            .with_timestamps(args.timezone, &args.timestamp_format);
+        Self {
+            args,
+            config_paths,
+            loader,
+            proxies,
+            output_format,
+            formatter,
+            webhook,
+        }
+    }
 
-    // Format and display results
-    let formatter = ResultFormatter::new(args.json_output, !args.json_output);
-    let output = formatter.format_results(&filtered_results);
-    println!("{output}");
+    /// Report one run. `runs` holds each of its repeats, the last one is filtered and
+    /// exported; `completed` are results resumed from a checkpoint. Returns every result
+    /// of the run, passing the criteria or not.
+    async fn report(
+        &self,
+        mut runs: Vec<Vec<SpeedTestResult>>,
+        completed: Vec<SpeedTestResult>,
+        totals: &RunTotals,
+    ) -> mihomo_speedtest_rs::Result<(Vec<SpeedTestResult>, RunOutcome)> {
+        let Self {
+            args,
+            config_paths,
+            loader,
+            proxies,
+            output_format,
+            ref formatter,
+            ref webhook,
+        } = *self;
+
+        // Repeated runs are summarized per proxy, filtering and export use the last run
+        let aggregated = (runs.len() > 1).then(|| aggregate_runs(runs.clone()));
+        let results = runs.pop().unwrap_or_default();
+
+        // Filter results based on performance criteria
+        let all_results: Vec<_> = completed.into_iter().chain(results).collect();
+        let outcome = RunOutcome::tested(&all_results, args);
+        let mut filtered_results: Vec<_> = all_results
+            .iter()
+            .filter(|result| args.meets_criteria(result))
+            .cloned()
+            .collect();
 
-    if !args.json_output {
-        println!("{}", formatter.format_summary(&filtered_results));
-    }
+        info!(
+            "✅ {} proxies passed performance criteria",
+            filtered_results.len()
+        );
+
+        if let Some(sort_key) = args.sort_by {
+            sort_results(&mut filtered_results, sort_key, args.sort_desc);
+        }
+
+        // Format and display results
+        if let Some(ref aggregated) = aggregated
+            && output_format.is_machine_readable()
+        {
+            // Machine-readable output carries every raw run alongside the aggregate
+            println!("{}", formatter.format_aggregated(aggregated));
+        } else if output_format != OutputFormat::JsonLines {
+            // JSON lines were already streamed as each test completed
+            let output = formatter.format_results(&filtered_results);
+            println!("{output}");
+        }
+
+        if let Some(ref aggregated) = aggregated
+            && !output_format.is_machine_readable()
+        {
+            println!("\n🔁 Stability across {} runs", args.repeat);
+            println!("{}", formatter.format_aggregated(aggregated));
+        }
+
+        if output_format.shows_summary() {
+            if args.verbose
+                && filtered_results
+                    .iter()
+                    .any(|r| !r.latency_samples.is_empty())
+            {
+                println!("\n📶 Latency samples");
+                println!("{}", formatter.format_latency_samples(&filtered_results));
+            }
+
+            if !args.quiet {
+                println!("{}", formatter.format_summary(&filtered_results, totals));
+                let by_type = formatter.format_summary_by_type(&filtered_results);
+                if !by_type.is_empty() {
+                    println!("{by_type}");
+                }
+            }
+
+            // Compare against a previous run if requested
+            if let Some(ref baseline_path) = args.baseline {
+                match ConfigExporter::load_json(baseline_path).await {
+                    Ok(baseline) => {
+                        println!("\n📈 Changes since baseline ({baseline_path})");
+                        println!("{}", formatter.format_diff(&filtered_results, &baseline));
+                    }
+                    Err(e) => warn!("Failed to load baseline {}: {}", baseline_path, e),
+                }
+            }
+        }
+
+        // Pick the best proxies by weighted score
+        let best_names = args.pick_best.map(|n| {
+            let best = pick_best(&filtered_results, &args.score_weights(), n);
+            if !output_format.is_machine_readable() {
+                println!("\n🏆 Best {} proxies by score", best.len());
+                println!("{}", formatter.format_best(&best));
+            }
+            best.iter()
+                .map(|(result, _)| result.proxy_name.clone())
+                .collect::<Vec<_>>()
+        });
+        let mut export_results: Vec<_> = match best_names {
+            Some(ref names) if args.export_best_only => filtered_results
+                .iter()
+                .filter(|r| names.contains(&r.proxy_name))
+                .cloned()
+                .collect(),
+            _ => filtered_results.clone(),
+        };
+        if let Some(count) = args.top_per_region {
+            export_results = ConfigExporter::top_per_region(&export_results, proxies, count);
+        }
+        // Export results if requested
+        if let Some(ref output_path) = args.output {
+            info!("💾 Exporting results to: {}", output_path);
+
+            let renamed_proxies = if args.rename_nodes {
+                let geoip = match args.geoip_db {
+                    Some(ref path) => match GeoIpDb::open(path).await {
+                        Ok(db) => Some(db),
+                        Err(e) => {
+                            warn!(
+                                "Failed to load GeoIP database, using name heuristics: {}",
+                                e
+                            );
+                            None
+                        }
+                    },
+                    None => None,
+                };
+
+                Some(match geoip {
+                    Some(ref geoip) => {
+                        ConfigExporter::rename_proxies_with_geoip(proxies, &export_results, geoip)
+                            .await
+                    }
+                    None => ConfigExporter::rename_proxies_with_stats(proxies, &export_results),
+                })
+            } else {
+                None
+            };
+            let export_proxies = renamed_proxies.as_deref().unwrap_or(proxies);
+            // Results still carry the old names, match them to the renamed proxies
+            if let Some(ref renamed) = renamed_proxies {
+                export_results = ConfigExporter::rename_results(&export_results, proxies, renamed);
+            }
+
+            if args.include_failed && args.export_format() != ExportFormat::Clash {
+                warn!("⚠️ --include-failed only applies to Clash exports");
+            }
+            if args.export_only_passing_groups && args.export_format() != ExportFormat::Clash {
+                warn!("⚠️ --export-only-passing-groups only applies to Clash exports");
+            }
+            if args.only_changed && args.export_format() != ExportFormat::Clash {
+                warn!("⚠️ --only-changed only applies to Clash exports");
+            }
+            if args.export_format() != ExportFormat::Clash {
+                warn!("⚠️ Proxy groups and rules are only kept in Clash exports");
+            }
+
+            match args.export_format() {
+                ExportFormat::Singbox => {
+                    ConfigExporter::export_singbox(&export_results, export_proxies, output_path)
+                        .await?
+                }
+                ExportFormat::Surge => {
+                    ConfigExporter::export_surge(&export_results, export_proxies, output_path)
+                        .await?
+                }
+                ExportFormat::Clash if args.export_only_passing_groups => {
+                    ConfigExporter::export_clash_config_with_groups(
+                        &export_results,
+                        export_proxies,
+                        output_path,
+                        args.speed_tiers(),
+                        args.only_changed,
+                    )
+                    .await?;
+                }
+                ExportFormat::Clash => {
+                    // A single full Clash config keeps its proxy groups and rules
+                    let original_config = if config_paths.contains(',') {
+                        None
+                    } else {
+                        loader.clash_config(config_paths.trim())
+                    };
+                    let original_config = match (original_config, &renamed_proxies) {
+                        (Some(config), Some(renamed)) => Some(
+                            ConfigExporter::rename_config_references(config, proxies, renamed),
+                        ),
+                        (config, _) => config,
+                    };
+
+                    match original_config {
+                        Some(original_config) => {
+                            ConfigExporter::export_clash_config_preserving(
+                                &original_config,
+                                &export_results,
+                                export_proxies,
+                                output_path,
+                                args.include_failed,
+                                &args.dead_prefix,
+                                args.only_changed,
+                            )
+                            .await?;
+                        }
+                        None => {
+                            ConfigExporter::export_clash_config(
+                                &export_results,
+                                export_proxies,
+                                output_path,
+                                args.include_failed,
+                                &args.dead_prefix,
+                                args.only_changed,
+                            )
+                            .await?;
+                        }
+                    }
+                }
+            }
 
-    // Export results if requested
-    if let Some(ref output_path) = args.output {
-        info!("💾 Exporting results to: {}", output_path);
+            info!("✅ Export completed");
+        }
 
-        if args.rename_nodes {
-            let renamed_proxies =
-                ConfigExporter::rename_proxies_with_stats(&proxies, &filtered_results);
-            ConfigExporter::export_clash_config(&filtered_results, &renamed_proxies, output_path)
+        // Export Prometheus metrics if requested
+        if let Some(ref prometheus_path) = args.prometheus_out {
+            info!("📡 Writing Prometheus metrics to: {}", prometheus_path);
+            PrometheusSink::new(prometheus_path)
+                .emit(&filtered_results)
                 .await?;
-        } else {
-            ConfigExporter::export_clash_config(&filtered_results, &proxies, output_path).await?;
         }
 
-        info!("✅ Export completed");
+        if let Some(ref history_path) = args.output_append {
+            info!("🗂️ Appending results to history: {}", history_path);
+            HistorySink::new(history_path)
+                .emit(&filtered_results)
+                .await?;
+        }
+
+        if let Some(webhook) = webhook {
+            emit_webhook(webhook, &filtered_results).await;
+        }
+
+        Ok((all_results, outcome))
     }
+}
 
-    info!("🎉 All tasks completed successfully!");
+/// Print a finished result as a JSON line, JSON lines output is streamed as it comes in
+fn stream_json_line(result: &SpeedTestResult) {
+    if let Err(e) = ResultFormatter::write_json_line(&mut io::stdout().lock(), result) {
+        warn!("Failed to write result line: {}", e);
+    }
+}
+
+/// Progress bar for a direct test run, none with `--quiet`
+fn progress_bar(args: &Cli, total: usize) -> Option<Arc<SpeedTestProgress>> {
+    (!args.quiet).then(|| Arc::new(SpeedTestProgress::new(total as u64)))
+}
+
+/// Webhook sink for `--webhook`, if one was given
+fn webhook_sink(args: &Cli) -> mihomo_speedtest_rs::Result<Option<WebhookSink>> {
+    args.webhook
+        .as_deref()
+        .map(|url| WebhookSink::new(url, &args.webhook_header))
+        .transpose()
+}
+
+/// Post results to the webhook; an unreachable receiver shouldn't fail the run
+async fn emit_webhook(webhook: &WebhookSink, results: &[SpeedTestResult]) {
+    if let Err(e) = webhook.emit(results).await {
+        warn!("{:#}", e);
+    }
+}
+
+/// Mihomo runner configured from the CLI flags
+fn mihomo_runner(args: &Cli) -> mihomo_speedtest_rs::Result<MihomoRunner> {
+    Ok(MihomoRunner::new(
+        &args.mihomo_config_dir,
+        args.mihomo_binary.as_ref(),
+        args.mihomo_api_port,
+        args.mihomo_proxy_port,
+    )?
+    .with_keep_config(args.keep_mihomo_config)
+    .with_ip_version(args.ip_version)
+    .with_fresh_connections(args.fresh_connections)
+    .with_connect_timeout(args.connect_timeout)
+    .with_hysteria_bandwidth(args.hysteria_up, args.hysteria_down)
+    .with_validate(!args.skip_mihomo_validate))
+}
+
+/// Re-test `proxies` every `interval`, redrawing the results until Ctrl-C
+async fn watch(
+    pipeline: &Pipeline<'_>,
+    config: SpeedTestConfig,
+    proxies: &[ProxyConfig],
+    interval: Duration,
+) -> mihomo_speedtest_rs::Result<()> {
+    let args = pipeline.args;
+    let callback = (pipeline.output_format == OutputFormat::JsonLines)
+        .then(|| Box::new(stream_json_line) as ProgressCallback);
+    // mihomo stays up across cycles and stops when the tester is dropped
+    let tester = tokio::sync::Mutex::new(Tester::new(args, config, None, callback, true).await?);
+
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        }
+    });
+
+    let output_format = pipeline.output_format;
+    let redraw = !output_format.is_machine_readable() && io::stdout().is_terminal();
+
+    let cycles = run_watch(interval, cancel, |cycle| {
+        let tester = &tester;
+        async move {
+            let started = Instant::now();
+            let results = tester.lock().await.test(args, proxies).await?;
+            let totals = RunTotals::new(&results, started.elapsed());
+
+            if redraw {
+                print!("{CLEAR_SCREEN}");
+            }
+            if !output_format.is_machine_readable() && !args.quiet {
+                println!(
+                    "🔄 Cycle {} | every {} | Ctrl-C to exit",
+                    cycle,
+                    humantime::format_duration(interval)
+                );
+            }
+            pipeline.report(vec![results], Vec::new(), &totals).await?;
+            Ok(())
+        }
+    })
+    .await;
+
+    info!("👋 Watch stopped after {} cycles", cycles);
     Ok(())
 }
@@ -0,0 +1,200 @@
+use crate::Result;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Bytes per LibreSpeed `garbage.php` chunk
+const LIBRESPEED_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Speed test server flavor selected with `--backend`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BackendKind {
+    /// Cloudflare's `/__down` and `/__up` endpoints
+    #[default]
+    Cloudflare,
+    /// Self-hosted LibreSpeed, `--server-url` points at the directory holding `garbage.php`
+    Librespeed,
+}
+
+impl BackendKind {
+    /// Backend for this flavor rooted at `server_url`
+    pub fn backend(&self, server_url: &str) -> SharedBackend {
+        match self {
+            BackendKind::Cloudflare => Arc::new(CloudflareBackend::new(server_url)),
+            BackendKind::Librespeed => Arc::new(LibreSpeedBackend::new(server_url)),
+        }
+    }
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendKind::Cloudflare => write!(f, "cloudflare"),
+            BackendKind::Librespeed => write!(f, "librespeed"),
+        }
+    }
+}
+
+/// Endpoints of a speed test server
+pub trait SpeedTestBackend: Send + Sync {
+    /// Base URL of the server, recorded on results as the server used
+    fn server_url(&self) -> &str;
+
+    /// URL that serves at least `size` bytes
+    fn download_url(&self, size: usize) -> String;
+
+    /// Bytes the download URL for `size` actually serves
+    fn download_bytes(&self, size: usize) -> usize {
+        size
+    }
+
+    /// URL that accepts an uploaded body
+    fn upload_url(&self) -> String;
+
+    /// URL answering with an empty body, used for pings and the reachability check
+    fn latency_url(&self) -> String;
+}
+
+/// Backend shared by every tester of a run
+pub type SharedBackend = Arc<dyn SpeedTestBackend>;
+
+/// speed.cloudflare.com and compatible servers
+#[derive(Debug, Clone)]
+pub struct CloudflareBackend {
+    server_url: String,
+}
+
+impl CloudflareBackend {
+    pub fn new(server_url: &str) -> Self {
+        Self {
+            server_url: server_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl SpeedTestBackend for CloudflareBackend {
+    fn server_url(&self) -> &str {
+        &self.server_url
+    }
+
+    fn download_url(&self, size: usize) -> String {
+        format!("{}/__down?bytes={}", self.server_url, size)
+    }
+
+    fn upload_url(&self) -> String {
+        format!("{}/__up", self.server_url)
+    }
+
+    fn latency_url(&self) -> String {
+        self.download_url(0)
+    }
+}
+
+/// LibreSpeed backend, which serves whole 1 MiB chunks
+#[derive(Debug, Clone)]
+pub struct LibreSpeedBackend {
+    server_url: String,
+}
+
+impl LibreSpeedBackend {
+    pub fn new(server_url: &str) -> Self {
+        Self {
+            server_url: server_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl SpeedTestBackend for LibreSpeedBackend {
+    fn server_url(&self) -> &str {
+        &self.server_url
+    }
+
+    fn download_url(&self, size: usize) -> String {
+        format!("{}/garbage.php?ckSize={}", self.server_url, chunks(size))
+    }
+
+    fn download_bytes(&self, size: usize) -> usize {
+        chunks(size) * LIBRESPEED_CHUNK_SIZE
+    }
+
+    fn upload_url(&self) -> String {
+        format!("{}/empty.php", self.server_url)
+    }
+
+    fn latency_url(&self) -> String {
+        format!("{}/empty.php", self.server_url)
+    }
+}
+
+/// Run `test` against each of `backends` in turn until one succeeds, returning the
+/// result and the URL of the server that produced it. The last server's error
+/// surfaces when every one fails.
+pub(crate) async fn with_failover<'a, T, F, Fut>(
+    backends: impl IntoIterator<Item = &'a SharedBackend>,
+    test: F,
+) -> Result<(T, String)>
+where
+    F: Fn(SharedBackend) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backends = backends.into_iter().peekable();
+    while let Some(backend) = backends.next() {
+        match test(backend.clone()).await {
+            Ok(result) => return Ok((result, backend.server_url().to_string())),
+            Err(e) if backends.peek().is_some() => {
+                warn!(
+                    "Speed test server {} failed ({}), trying the next one",
+                    backend.server_url(),
+                    e
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(anyhow::anyhow!("No speed test server configured"))
+}
+
+/// Whole LibreSpeed chunks covering `size` bytes
+fn chunks(size: usize) -> usize {
+    size.div_ceil(LIBRESPEED_CHUNK_SIZE).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cloudflare_urls() {
+        let backend = BackendKind::Cloudflare.backend("https://speed.cloudflare.com/");
+        assert_eq!(
+            backend.download_url(1024),
+            "https://speed.cloudflare.com/__down?bytes=1024"
+        );
+        assert_eq!(backend.upload_url(), "https://speed.cloudflare.com/__up");
+        assert_eq!(
+            backend.latency_url(),
+            "https://speed.cloudflare.com/__down?bytes=0"
+        );
+    }
+
+    #[test]
+    fn test_librespeed_urls() {
+        let backend = BackendKind::Librespeed.backend("http://10.0.0.2/backend");
+        assert_eq!(
+            backend.download_url(25 * 1024 * 1024),
+            "http://10.0.0.2/backend/garbage.php?ckSize=25"
+        );
+        // Partial chunks round up so at least the requested size is served
+        assert_eq!(
+            backend.download_url(1024 * 1024 + 1),
+            "http://10.0.0.2/backend/garbage.php?ckSize=2"
+        );
+        assert_eq!(
+            backend.download_url(0),
+            "http://10.0.0.2/backend/garbage.php?ckSize=1"
+        );
+        assert_eq!(backend.download_bytes(1024 * 1024 + 1), 2 * 1024 * 1024);
+        assert_eq!(backend.upload_url(), "http://10.0.0.2/backend/empty.php");
+        assert_eq!(backend.latency_url(), "http://10.0.0.2/backend/empty.php");
+    }
+}
@@ -1,8 +1,60 @@
 use crate::Result;
-use crate::network::{ProxyClient, ZeroReader};
+use crate::network::{ProxyClient, SharedBackend, SpeedTestBackend, UploadPayload};
+use futures::StreamExt;
 use futures::future::try_join_all;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
-use tracing::debug;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Bytes requested per request during a timed download; streams that finish
+/// before the deadline simply issue another request
+const TIMED_DOWNLOAD_REQUEST_SIZE: usize = 50 * 1024 * 1024;
+
+/// Interval between instantaneous throughput samples
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Measurement window per concurrency step of the adaptive download when no
+/// `--download-duration` is given
+pub const DEFAULT_ADAPTIVE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Relative throughput gain a doubling must bring for the adaptive ramp to continue
+const ADAPTIVE_MIN_GAIN: f64 = 0.1;
+
+/// Concurrency the adaptive ramp never goes beyond
+const ADAPTIVE_MAX_CONCURRENCY: usize = 64;
+
+/// Bytes downloaded by the `--auto-size` probe
+const AUTO_SIZE_PROBE_BYTES: usize = 1024 * 1024;
+
+/// Largest download `--auto-size` picks, however fast the probe was
+const AUTO_SIZE_MAX_BYTES: usize = 1024 * 1024 * 1024;
+
+/// Default test duration `--auto-size` aims for
+pub const DEFAULT_AUTO_SIZE_TARGET: Duration = Duration::from_secs(10);
+
+/// Share of the download timeout an `--auto-size` download may take, so a connection
+/// slightly slower than the probe still finishes in time
+const AUTO_SIZE_TIMEOUT_SHARE: f64 = 0.8;
+
+/// Default `--stall-timeout`: how long a download chunk may receive nothing before it is abandoned
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shared cap on download and upload connections open at once across all tests
+pub type ConnectionLimit = Arc<Semaphore>;
+
+/// Wait for a connection slot if a limit is configured, the slot is held until the permit drops
+pub(crate) async fn acquire_connection(
+    limit: &Option<ConnectionLimit>,
+) -> Option<OwnedSemaphorePermit> {
+    match limit {
+        Some(limit) => limit.clone().acquire_owned().await.ok(),
+        None => None,
+    }
+}
 
 /// Result of bandwidth testing
 #[derive(Debug, Clone)]
@@ -10,6 +62,12 @@ pub struct BandwidthResult {
     pub bytes: usize,
     pub duration: Duration,
     pub speed: f64, // bytes per second
+    /// Instantaneous throughput captured every ~200ms (bytes per second)
+    pub samples: Vec<f64>,
+    /// Speed test server that served the transfer, when more than one was available
+    pub server: Option<String>,
+    /// Mean time from sending a request to the first byte of its body
+    pub ttfb: Option<Duration>,
 }
 
 impl BandwidthResult {
@@ -25,9 +83,30 @@ impl BandwidthResult {
             bytes,
             duration,
             speed,
+            samples: Vec::new(),
+            server: None,
+            ttfb: None,
         }
     }
 
+    /// Record the speed test server that served the transfer
+    pub fn with_server(mut self, server: String) -> Self {
+        self.server = Some(server);
+        self
+    }
+
+    /// Attach throughput samples to the result
+    pub fn with_samples(mut self, samples: Vec<f64>) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Record the time to first byte of the transfer
+    pub fn with_ttfb(mut self, ttfb: Option<Duration>) -> Self {
+        self.ttfb = ttfb;
+        self
+    }
+
     /// Get speed in MB/s
     pub fn speed_mbps(&self) -> f64 {
         self.speed / (1024.0 * 1024.0)
@@ -37,13 +116,48 @@ impl BandwidthResult {
 /// Bandwidth tester for measuring download and upload speeds
 pub struct BandwidthTester {
     client: ProxyClient,
-    server_url: String,
+    backend: SharedBackend,
+    connection_limit: Option<ConnectionLimit>,
+    upload_payload: UploadPayload,
+    warmup: Duration,
+    stall_timeout: Duration,
 }
 
 impl BandwidthTester {
     /// Create a new bandwidth tester
-    pub fn new(client: ProxyClient, server_url: String) -> Self {
-        Self { client, server_url }
+    pub fn new(client: ProxyClient, backend: SharedBackend) -> Self {
+        Self {
+            client,
+            backend,
+            connection_limit: None,
+            upload_payload: UploadPayload::Zero,
+            warmup: Duration::ZERO,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+        }
+    }
+
+    /// Abandon a download chunk that receives no data for `timeout`
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Discard what timed downloads receive during their first `warmup`
+    pub fn with_warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Choose the bytes sent by the upload test
+    pub fn with_upload_payload(mut self, payload: UploadPayload) -> Self {
+        self.upload_payload = payload;
+        self
+    }
+
+    /// Share a connection cap with other testers
+    pub fn with_connection_limit(mut self, limit: Option<ConnectionLimit>) -> Self {
+        self.connection_limit = limit;
+        self
     }
 
     /// Test download speed with concurrent connections
@@ -56,12 +170,20 @@ impl BandwidthTester {
         let chunk_size = size / concurrent;
         let mut tasks = Vec::new();
 
+        // The clock starts once the first connection has a slot, time spent queued
+        // behind other proxies' transfers isn't part of this one
+        let mut first_permit = Some(acquire_connection(&self.connection_limit).await);
         let start = Instant::now();
+        let sampler = ThroughputSampler::start();
 
         // Create concurrent download tasks
         for i in 0..concurrent {
+            let permit = first_permit.take();
             let client = self.client.clone();
-            let server_url = self.server_url.clone();
+            let backend = self.backend.clone();
+            let counter = sampler.counter();
+            let limit = self.connection_limit.clone();
+            let stall_timeout = self.stall_timeout;
             let actual_chunk_size = if i == concurrent - 1 {
                 // Last chunk gets any remaining bytes
                 size - (chunk_size * (concurrent - 1))
@@ -70,13 +192,25 @@ impl BandwidthTester {
             };
 
             tasks.push(tokio::spawn(async move {
-                Self::download_chunk(&client, &server_url, actual_chunk_size).await
+                let _permit = match permit {
+                    Some(permit) => permit,
+                    None => acquire_connection(&limit).await,
+                };
+                Self::download_chunk(
+                    &client,
+                    backend.as_ref(),
+                    actual_chunk_size,
+                    stall_timeout,
+                    &counter,
+                )
+                .await
             }));
         }
 
         // Wait for all downloads to complete
         let results = try_join_all(tasks).await?;
         let total_duration = start.elapsed();
+        let samples = sampler.finish().await;
 
         // Calculate total bytes downloaded
         let total_bytes: usize = results
@@ -91,74 +225,103 @@ impl BandwidthTester {
             return Err(anyhow::anyhow!("All download chunks failed"));
         }
 
+        let ttfb = ChunkResult::mean_ttfb(&successful_results);
+
         debug!(
-            "Download completed: {} bytes in {:?} ({:.2} MB/s)",
+            "Download completed: {} bytes in {:?} ({:.2} MB/s, ttfb {:?})",
             total_bytes,
             total_duration,
-            total_bytes as f64 / (1024.0 * 1024.0) / total_duration.as_secs_f64()
+            total_bytes as f64 / (1024.0 * 1024.0) / total_duration.as_secs_f64(),
+            ttfb
         );
 
-        Ok(BandwidthResult::new(total_bytes, total_duration))
+        Ok(BandwidthResult::new(total_bytes, total_duration)
+            .with_samples(samples)
+            .with_ttfb(ttfb))
     }
 
-    /// Test upload speed
-    pub async fn test_upload(&self, size: usize) -> Result<BandwidthResult> {
-        debug!("Starting upload test: {} bytes", size);
-
-        let url = format!("{}/__up", self.server_url);
-        let data = ZeroReader::new(size);
-
-        let start = Instant::now();
-        let response = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/octet-stream")
-            .body(data)
-            .send()
-            .await?;
-
-        let duration = start.elapsed();
-        debug!("Upload response status: {}", response.status());
-        debug!("Upload response headers: {:?}", response.headers());
+    /// Test download speed by streaming over concurrent connections until the time budget
+    /// elapses, after the warm-up window if one is set
+    pub async fn test_download_timed(
+        &self,
+        duration: Duration,
+        concurrent: usize,
+    ) -> Result<BandwidthResult> {
+        timed_download(
+            self.client.client(),
+            self.backend.as_ref(),
+            duration,
+            self.warmup,
+            concurrent,
+            self.connection_limit.clone(),
+            self.stall_timeout,
+        )
+        .await
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            // Try to read response body for error details
-            match response.text().await {
-                Ok(body) => {
-                    debug!("Upload failed with status {}, body: {}", status, body);
-                    return Err(anyhow::anyhow!(
-                        "Upload failed with status: {}, body: {}",
-                        status,
-                        body
-                    ));
-                }
-                Err(_) => {
-                    return Err(anyhow::anyhow!("Upload failed with status: {}", status));
-                }
-            }
-        }
+    /// Find the peak download throughput by doubling concurrency every `window`
+    /// until it stops improving, returning the best result and its concurrency
+    pub async fn test_download_adaptive(
+        &self,
+        window: Duration,
+    ) -> Result<(BandwidthResult, usize)> {
+        adaptive_download(
+            self.client.client(),
+            self.backend.as_ref(),
+            window,
+            self.connection_limit.clone(),
+            self.stall_timeout,
+        )
+        .await
+    }
 
-        debug!(
-            "Upload completed: {} bytes in {:?} ({:.2} MB/s)",
+    /// Test upload speed, splitting `size` bytes across concurrent connections
+    pub async fn test_upload(&self, size: usize, concurrent: usize) -> Result<BandwidthResult> {
+        concurrent_upload(
+            self.client.client(),
+            self.backend.as_ref(),
+            self.upload_payload,
             size,
-            duration,
-            size as f64 / (1024.0 * 1024.0) / duration.as_secs_f64()
-        );
+            concurrent,
+            self.connection_limit.clone(),
+        )
+        .await
+    }
 
-        Ok(BandwidthResult::new(size, duration))
+    /// Download a small probe and pick the size a download over `concurrent`
+    /// connections should take to last about `target`, see `probe_download_size`
+    pub async fn probe_download_size(
+        &self,
+        latency: Duration,
+        target: Duration,
+        concurrent: usize,
+        timeout: Duration,
+    ) -> Result<(usize, usize)> {
+        probe_download_size(
+            self.client.client(),
+            self.backend.as_ref(),
+            latency,
+            target,
+            concurrent,
+            timeout,
+        )
+        .await
     }
 
-    /// Download a single chunk
+    /// Download a single chunk, failing it once no data arrives for `stall_timeout` so a
+    /// stuck connection doesn't hold the whole test until the download timeout. Also
+    /// records the time from sending the request to the first byte of the body.
     async fn download_chunk(
         client: &ProxyClient,
-        server_url: &str,
+        backend: &dyn SpeedTestBackend,
         size: usize,
+        stall_timeout: Duration,
+        counter: &AtomicUsize,
     ) -> Result<ChunkResult> {
-        let url = format!("{server_url}/__down?bytes={size}");
-        let _start = Instant::now();
+        let url = backend.download_url(size);
 
-        let response = client.get(&url).await?;
+        let sent = Instant::now();
+        let response = download_request(client.client(), &url).send().await?;
         debug!("Download chunk response status: {}", response.status());
         debug!("Download chunk response headers: {:?}", response.headers());
 
@@ -186,24 +349,1277 @@ impl BandwidthTester {
             }
         }
 
-        match response.bytes().await {
-            Ok(bytes) => {
-                debug!("Download chunk successfully received {} bytes", bytes.len());
-                Ok(ChunkResult { bytes: bytes.len() })
+        check_download_response(&response, backend.download_bytes(size));
+
+        let mut received = 0;
+        let mut ttfb = None;
+        let mut stream = response.bytes_stream();
+        loop {
+            let Ok(next) = tokio::time::timeout(stall_timeout, stream.next()).await else {
+                debug!(
+                    "Download chunk stalled after {} bytes, nothing received for {:?}",
+                    received, stall_timeout
+                );
+                return Err(anyhow::anyhow!(
+                    "Download chunk stalled: no data for {:?}",
+                    stall_timeout
+                ));
+            };
+            let Some(chunk) = next else {
+                break;
+            };
+            match chunk {
+                Ok(bytes) => {
+                    if ttfb.is_none() && !bytes.is_empty() {
+                        ttfb = Some(sent.elapsed());
+                    }
+                    received += bytes.len();
+                    counter.fetch_add(bytes.len(), Ordering::Relaxed);
+                }
+                Err(e) => {
+                    debug!("Download chunk failed to decode response body: {}", e);
+                    return Err(anyhow::anyhow!(
+                        "Download chunk failed to decode response body: {}",
+                        e
+                    ));
+                }
             }
-            Err(e) => {
-                debug!("Download chunk failed to decode response body: {}", e);
-                Err(anyhow::anyhow!(
-                    "Download chunk failed to decode response body: {}",
-                    e
-                ))
+        }
+
+        debug!("Download chunk successfully received {} bytes", received);
+        Ok(ChunkResult {
+            bytes: received,
+            ttfb,
+        })
+    }
+}
+
+/// GET a download payload, asking for it uncompressed. A compressed body would be
+/// counted at its decoded or compressed size, neither of which is what the
+/// speed test server meant to send.
+pub(crate) fn download_request(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    client.get(url).header(ACCEPT_ENCODING, "identity")
+}
+
+/// Warn when a download response isn't the `expected` bytes uncompressed, the
+/// measured speed then doesn't reflect the requested payload
+pub(crate) fn check_download_response(response: &reqwest::Response, expected: usize) {
+    if let Some(encoding) = response.headers().get(CONTENT_ENCODING)
+        && encoding != "identity"
+    {
+        warn!(
+            "Download from {} is {:?}-encoded despite asking for identity, measured speed may be off",
+            response.url(),
+            encoding
+        );
+    }
+    if let Some(length) = response.content_length()
+        && length != expected as u64
+    {
+        warn!(
+            "Download from {} sent {} bytes instead of the {} requested",
+            response.url(),
+            length,
+            expected
+        );
+    }
+}
+
+/// Stream downloads over `concurrent` connections until `duration` elapses,
+/// measuring speed from the bytes actually received. The streams first run for
+/// `warmup` so TCP slow-start doesn't drag the result down, bytes received
+/// during that window aren't counted.
+pub(crate) async fn timed_download(
+    client: &reqwest::Client,
+    backend: &dyn SpeedTestBackend,
+    duration: Duration,
+    warmup: Duration,
+    concurrent: usize,
+    limit: Option<ConnectionLimit>,
+    stall_timeout: Duration,
+) -> Result<BandwidthResult> {
+    let concurrent = concurrent.max(1);
+    debug!(
+        "Starting timed download test: {:?} (+{:?} warm-up) with {} concurrent connections",
+        duration, warmup, concurrent
+    );
+
+    // The window opens once the first connection has a slot, time spent queued behind
+    // other proxies' transfers isn't part of it
+    let mut first_permit = Some(acquire_connection(&limit).await);
+    let start = tokio::time::Instant::now() + warmup;
+    let deadline = start + duration;
+    let sampler = ThroughputSampler::start_at(start);
+
+    let tasks: Vec<_> = (0..concurrent)
+        .map(|_| {
+            let permit = first_permit.take();
+            let client = client.clone();
+            let url = backend.download_url(TIMED_DOWNLOAD_REQUEST_SIZE);
+            let counter = sampler.counter();
+            let limit = limit.clone();
+            tokio::spawn(async move {
+                // Waiting for further connection slots counts against the time budget
+                let _permit = match permit {
+                    Some(permit) => permit,
+                    None => {
+                        match tokio::time::timeout_at(deadline, acquire_connection(&limit)).await {
+                            Ok(permit) => permit,
+                            Err(_) => return Ok(ChunkResult::default()),
+                        }
+                    }
+                };
+                stream_until(&client, &url, start, deadline, stall_timeout, &counter).await
+            })
+        })
+        .collect();
+
+    let results = try_join_all(tasks).await?;
+    let total_duration = tokio::time::Instant::now()
+        .saturating_duration_since(start)
+        .min(duration);
+    let samples = sampler.finish().await;
+
+    let streams: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+    let total_bytes: usize = streams.iter().map(|c| c.bytes).sum();
+    let ttfb = ChunkResult::mean_ttfb(streams.iter().copied());
+
+    if total_bytes == 0 {
+        return Err(match results.into_iter().find_map(|r| r.err()) {
+            Some(e) => anyhow::anyhow!("All download streams failed: {}", e),
+            None => anyhow::anyhow!("No data received before the deadline"),
+        });
+    }
+
+    debug!(
+        "Timed download completed: {} bytes in {:?} ({:.2} MB/s)",
+        total_bytes,
+        total_duration,
+        total_bytes as f64 / (1024.0 * 1024.0) / total_duration.as_secs_f64()
+    );
+
+    Ok(BandwidthResult::new(total_bytes, total_duration)
+        .with_samples(samples)
+        .with_ttfb(ttfb))
+}
+
+/// Run timed downloads starting from one connection and doubling concurrency each
+/// `window` while throughput grows by at least `ADAPTIVE_MIN_GAIN`. The result has the
+/// speed of the fastest step and the bytes of all of them, every step used the proxy.
+pub(crate) async fn adaptive_download(
+    client: &reqwest::Client,
+    backend: &dyn SpeedTestBackend,
+    window: Duration,
+    limit: Option<ConnectionLimit>,
+    stall_timeout: Duration,
+) -> Result<(BandwidthResult, usize)> {
+    let mut best: Option<(BandwidthResult, usize)> = None;
+    let mut concurrent = 1;
+    let mut total_bytes = 0;
+
+    while concurrent <= ADAPTIVE_MAX_CONCURRENCY {
+        let result = match timed_download(
+            client,
+            backend,
+            window,
+            Duration::ZERO,
+            concurrent,
+            limit.clone(),
+            stall_timeout,
+        )
+        .await
+        {
+            Ok(result) => result,
+            // A failing step ends the ramp, only the first one has nothing to fall back on
+            Err(e) if best.is_some() => {
+                debug!(
+                    "Adaptive download stopped at {} connections: {}",
+                    concurrent, e
+                );
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        debug!(
+            "Adaptive download: {} connections reached {:.2} MB/s",
+            concurrent,
+            result.speed_mbps()
+        );
+        total_bytes += result.bytes;
+
+        if let Some((ref peak, _)) = best
+            && result.speed < peak.speed * (1.0 + ADAPTIVE_MIN_GAIN)
+        {
+            // Keep the faster of the two even if the gain was too small to continue
+            if result.speed > peak.speed {
+                best = Some((result, concurrent));
             }
+            break;
         }
+        best = Some((result, concurrent));
+        concurrent *= 2;
     }
+
+    let (mut peak, concurrent) = best.expect("the first ramp step either succeeds or returns");
+    peak.bytes = total_bytes;
+    Ok((peak, concurrent))
 }
 
-/// Result of downloading a single chunk
-#[derive(Debug)]
-struct ChunkResult {
+/// Upload `size` bytes split across `concurrent` connections, the last one taking any
+/// remainder, and measure the combined speed. Connections that fail don't count
+/// towards the total, the test only fails if all of them do.
+pub(crate) async fn concurrent_upload(
+    client: &reqwest::Client,
+    backend: &dyn SpeedTestBackend,
+    payload: UploadPayload,
+    size: usize,
+    concurrent: usize,
+    limit: Option<ConnectionLimit>,
+) -> Result<BandwidthResult> {
+    let concurrent = concurrent.max(1);
+    debug!(
+        "Starting upload test: {} bytes with {} concurrent connections",
+        size, concurrent
+    );
+
+    let chunk_size = size / concurrent;
+    // Like downloads, the clock starts once the first connection has a slot
+    let mut first_permit = Some(acquire_connection(&limit).await);
+    let start = Instant::now();
+    let sampler = ThroughputSampler::start();
+
+    let tasks: Vec<_> = (0..concurrent)
+        .map(|i| {
+            let permit = first_permit.take();
+            let client = client.clone();
+            let url = backend.upload_url();
+            let limit = limit.clone();
+            let counter = sampler.counter();
+            let actual_chunk_size = if i == concurrent - 1 {
+                size - chunk_size * (concurrent - 1)
+            } else {
+                chunk_size
+            };
+            tokio::spawn(async move {
+                let _permit = match permit {
+                    Some(permit) => permit,
+                    None => acquire_connection(&limit).await,
+                };
+                upload_chunk(&client, &url, payload, actual_chunk_size, counter).await
+            })
+        })
+        .collect();
+
+    let results = try_join_all(tasks).await?;
+    let duration = start.elapsed();
+    let samples = sampler.finish().await;
+
+    let total_bytes: usize = results.iter().filter_map(|r| r.as_ref().ok()).sum();
+    if results.iter().all(|r| r.is_err()) {
+        let error = results.into_iter().find_map(|r| r.err());
+        return Err(error.unwrap_or_else(|| anyhow::anyhow!("All upload connections failed")));
+    }
+
+    debug!(
+        "Upload completed: {} bytes in {:?} ({:.2} MB/s)",
+        total_bytes,
+        duration,
+        total_bytes as f64 / (1024.0 * 1024.0) / duration.as_secs_f64()
+    );
+
+    Ok(BandwidthResult::new(total_bytes, duration).with_samples(samples))
+}
+
+/// Upload one `size`-byte body, adding the bytes handed to the connection to
+/// `counter` as they go, and return the bytes sent. The body is streamed to count it
+/// but still declares its length, so the request isn't chunked.
+async fn upload_chunk(
+    client: &reqwest::Client,
+    url: &str,
+    payload: UploadPayload,
+    size: usize,
+    counter: Arc<AtomicUsize>,
+) -> Result<usize> {
+    let response = client
+        .post(url)
+        .header("Content-Type", "application/octet-stream")
+        .header(CONTENT_LENGTH, size)
+        .body(payload.counted_body(size, counter))
+        .send()
+        .await?;
+    debug!("Upload response status: {}", response.status());
+    debug!("Upload response headers: {:?}", response.headers());
+
+    if !response.status().is_success() {
+        let status = response.status();
+        // Try to read response body for error details
+        return Err(match response.text().await {
+            Ok(body) => {
+                debug!("Upload failed with status {}, body: {}", status, body);
+                anyhow::anyhow!("Upload failed with status: {}, body: {}", status, body)
+            }
+            Err(_) => anyhow::anyhow!("Upload failed with status: {}", status),
+        });
+    }
+
+    Ok(size)
+}
+
+/// Download `AUTO_SIZE_PROBE_BYTES` and extrapolate the download size that takes
+/// about `target` at the probed speed, returning it with the bytes the probe used.
+/// `latency` is the round trip measured before, it is taken out of the probe time since
+/// a 1 MB probe is dominated by it on slow links. The probe runs on one connection, so
+/// the size grows with `concurrent`, and `target` is capped below the download
+/// `timeout` each connection's request has to finish within.
+pub(crate) async fn probe_download_size(
+    client: &reqwest::Client,
+    backend: &dyn SpeedTestBackend,
+    latency: Duration,
+    target: Duration,
+    concurrent: usize,
+    timeout: Duration,
+) -> Result<(usize, usize)> {
+    let start = Instant::now();
+    let response = download_request(client, &backend.download_url(AUTO_SIZE_PROBE_BYTES))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut received = 0;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        received += chunk?.len();
+    }
+    let elapsed = start.elapsed();
+
+    let size = auto_download_size(received, elapsed, latency, target, concurrent, timeout);
+    debug!(
+        "Auto-size probe: {} bytes in {:?}, downloading {} bytes over {} connections to last {:?}",
+        received, elapsed, size, concurrent, target
+    );
+    Ok((size, received))
+}
+
+/// Download size that lasts about `target` over `concurrent` connections at the
+/// speed of a probe that received `bytes` in `elapsed` on one of them, never smaller
+/// than the probe itself
+fn auto_download_size(
     bytes: usize,
+    elapsed: Duration,
+    latency: Duration,
+    target: Duration,
+    concurrent: usize,
+    timeout: Duration,
+) -> usize {
+    let target = target.min(timeout.mul_f64(AUTO_SIZE_TIMEOUT_SHARE));
+    // Both the probe and the real download pay one round trip before the first byte
+    let transfer = elapsed
+        .saturating_sub(latency)
+        .max(Duration::from_millis(1));
+    let speed = bytes as f64 / transfer.as_secs_f64();
+    let size = speed * target.saturating_sub(latency).as_secs_f64() * concurrent.max(1) as f64;
+    (size as usize).clamp(AUTO_SIZE_PROBE_BYTES, AUTO_SIZE_MAX_BYTES)
+}
+
+/// Keep streaming response bodies from `url` until the deadline, returning the bytes
+/// received from `measure_from` on and the time to the first byte of the first body.
+/// A body receiving nothing for `stall_timeout` ends the stream early.
+pub(crate) async fn stream_until(
+    client: &reqwest::Client,
+    url: &str,
+    measure_from: tokio::time::Instant,
+    deadline: tokio::time::Instant,
+    stall_timeout: Duration,
+    counter: &AtomicUsize,
+) -> Result<ChunkResult> {
+    let mut total_bytes = 0;
+    let mut ttfb = None;
+
+    'requests: while tokio::time::Instant::now() < deadline {
+        let sent = Instant::now();
+        let response =
+            match tokio::time::timeout_at(deadline, download_request(client, url).send()).await {
+                // Keep what the earlier bodies measured
+                Ok(Err(e)) if total_bytes > 0 => {
+                    debug!("Download stream request failed: {}", e);
+                    break;
+                }
+                Ok(response) => response?,
+                Err(_) => break,
+            };
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Download stream failed with status: {}",
+                response.status()
+            ));
+        }
+
+        let mut stream = response.bytes_stream();
+        loop {
+            let wait_until = deadline.min(tokio::time::Instant::now() + stall_timeout);
+            let next = tokio::time::timeout_at(wait_until, stream.next()).await;
+            if ttfb.is_none()
+                && let Ok(Some(Ok(ref chunk))) = next
+                && !chunk.is_empty()
+            {
+                ttfb = Some(sent.elapsed());
+            }
+            match next {
+                Ok(Some(Ok(chunk))) if tokio::time::Instant::now() >= measure_from => {
+                    total_bytes += chunk.len();
+                    counter.fetch_add(chunk.len(), Ordering::Relaxed);
+                }
+                // Still warming up
+                Ok(Some(Ok(_))) => {}
+                Ok(Some(Err(e))) if total_bytes == 0 => {
+                    return Err(anyhow::anyhow!("Download stream failed: {}", e));
+                }
+                Ok(Some(Err(e))) => {
+                    // Keep what we measured so far
+                    debug!("Download stream interrupted: {}", e);
+                    break 'requests;
+                }
+                // Body finished before the deadline, request another one
+                Ok(None) => break,
+                Err(_) if tokio::time::Instant::now() < deadline => {
+                    debug!(
+                        "Download stream stalled after {} bytes, nothing received for {:?}",
+                        total_bytes, stall_timeout
+                    );
+                    if total_bytes == 0 {
+                        return Err(anyhow::anyhow!(
+                            "Download stream stalled: no data for {:?}",
+                            stall_timeout
+                        ));
+                    }
+                    break 'requests;
+                }
+                Err(_) => break 'requests,
+            }
+        }
+    }
+
+    Ok(ChunkResult {
+        bytes: total_bytes,
+        ttfb,
+    })
+}
+
+/// Records instantaneous throughput from a shared byte counter at fixed intervals
+pub(crate) struct ThroughputSampler {
+    counter: Arc<AtomicUsize>,
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<Vec<f64>>,
+}
+
+impl ThroughputSampler {
+    /// Start sampling in the background
+    pub(crate) fn start() -> Self {
+        Self::start_at(tokio::time::Instant::now())
+    }
+
+    /// Start sampling in the background with the first window beginning at `start`
+    pub(crate) fn start_at(start: tokio::time::Instant) -> Self {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let (stop, mut stopped) = oneshot::channel();
+
+        let handle = tokio::spawn({
+            let counter = counter.clone();
+            async move {
+                let mut samples = Vec::new();
+                let mut last_bytes = 0;
+                let mut last_instant = start;
+                let mut ticker =
+                    tokio::time::interval_at(last_instant + SAMPLE_INTERVAL, SAMPLE_INTERVAL);
+
+                loop {
+                    let stopping = tokio::select! {
+                        _ = ticker.tick() => false,
+                        _ = &mut stopped => true,
+                    };
+
+                    let now = tokio::time::Instant::now();
+                    let bytes = counter.load(Ordering::Relaxed);
+                    let elapsed = now.saturating_duration_since(last_instant).as_secs_f64();
+                    // Skip a trailing window too short to be meaningful
+                    if elapsed > SAMPLE_INTERVAL.as_secs_f64() / 4.0 {
+                        samples.push((bytes - last_bytes) as f64 / elapsed);
+                    }
+                    last_bytes = bytes;
+                    last_instant = now;
+
+                    if stopping {
+                        return samples;
+                    }
+                }
+            }
+        });
+
+        Self {
+            counter,
+            stop,
+            handle,
+        }
+    }
+
+    /// Counter that downloads add received bytes to
+    pub(crate) fn counter(&self) -> Arc<AtomicUsize> {
+        self.counter.clone()
+    }
+
+    /// Stop sampling and return the collected samples (bytes per second)
+    pub(crate) async fn finish(self) -> Vec<f64> {
+        let _ = self.stop.send(());
+        self.handle.await.unwrap_or_default()
+    }
+}
+
+/// Result of downloading a single chunk
+#[derive(Debug, Default)]
+pub(crate) struct ChunkResult {
+    pub(crate) bytes: usize,
+    /// Time until the first body byte, `None` for an empty body
+    pub(crate) ttfb: Option<Duration>,
+}
+
+impl ChunkResult {
+    /// Mean time to first byte of the chunks that received one
+    pub(crate) fn mean_ttfb<'a>(
+        chunks: impl IntoIterator<Item = &'a ChunkResult>,
+    ) -> Option<Duration> {
+        let ttfbs: Vec<Duration> = chunks.into_iter().filter_map(|c| c.ttfb).collect();
+        (!ttfbs.is_empty()).then(|| ttfbs.iter().sum::<Duration>() / ttfbs.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::CloudflareBackend;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve a chunked body that trickles 1KB every 50ms for 5 seconds
+    async fn spawn_slow_server() -> String {
+        spawn_trickle_server(0).await
+    }
+
+    /// Like `spawn_slow_server` but sending `burst` bytes right away first
+    async fn spawn_trickle_server(burst: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Type: application/octet-stream\r\n\r\n",
+                        )
+                        .await;
+
+                    if burst > 0 {
+                        let header = format!("{burst:x}\r\n");
+                        if socket.write_all(header.as_bytes()).await.is_err()
+                            || socket.write_all(&vec![0u8; burst]).await.is_err()
+                            || socket.write_all(b"\r\n").await.is_err()
+                        {
+                            return;
+                        }
+                    }
+
+                    let chunk = vec![0u8; 1024];
+                    for _ in 0..100 {
+                        let header = format!("{:x}\r\n", chunk.len());
+                        if socket.write_all(header.as_bytes()).await.is_err()
+                            || socket.write_all(&chunk).await.is_err()
+                            || socket.write_all(b"\r\n").await.is_err()
+                        {
+                            return;
+                        }
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                    let _ = socket.write_all(b"0\r\n\r\n").await;
+                });
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    /// Answer each request with 1KB after 100ms, tracking the peak of requests in flight
+    async fn spawn_tracking_server() -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let observed = peak.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let mut response =
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 1024\r\nConnection: close\r\n\r\n"
+                            .to_vec();
+                    response.extend_from_slice(&[0u8; 1024]);
+                    let _ = socket.write_all(&response).await;
+                });
+            }
+        });
+
+        (port, observed)
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_caps_requests_in_flight() {
+        let (port, peak) = spawn_tracking_server().await;
+        let limit: ConnectionLimit = Arc::new(Semaphore::new(2));
+        let tester = || {
+            let proxy = crate::config::ProxyConfig {
+                name: "tracked".to_string(),
+                proxy_type: crate::config::ProxyType::Http,
+                server: "127.0.0.1".to_string(),
+                port,
+                config: Default::default(),
+            };
+            let client = ProxyClient::new(proxy, Duration::from_secs(5)).unwrap();
+            BandwidthTester::new(
+                client,
+                Arc::new(CloudflareBackend::new("http://speedtest.invalid")),
+            )
+            .with_connection_limit(Some(limit.clone()))
+        };
+
+        // Two testers with 3 connections each would open 6 sockets without the cap
+        let (first, second) = (tester(), tester());
+        let (a, b) = tokio::join!(
+            first.test_download(3 * 1024, 3),
+            second.test_download(3 * 1024, 3)
+        );
+
+        assert_eq!(a.unwrap().bytes, 3 * 1024);
+        assert_eq!(b.unwrap().bytes, 3 * 1024);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_for_a_connection_slot_is_not_timed() {
+        let (port, _) = spawn_tracking_server().await;
+        let limit: ConnectionLimit = Arc::new(Semaphore::new(1));
+        let proxy = crate::config::ProxyConfig {
+            name: "queued".to_string(),
+            proxy_type: crate::config::ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        let client = ProxyClient::new(proxy, Duration::from_secs(5)).unwrap();
+        let tester = BandwidthTester::new(
+            client,
+            Arc::new(CloudflareBackend::new("http://speedtest.invalid")),
+        )
+        .with_connection_limit(Some(limit.clone()));
+
+        // Another proxy's transfer holds the only slot for 500ms
+        let held = limit.clone().acquire_owned().await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            drop(held);
+        });
+
+        let started = Instant::now();
+        let result = tester.test_download(1024, 1).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        // Only the ~100ms the server takes to answer is measured
+        assert!(
+            result.duration < Duration::from_millis(400),
+            "{:?}",
+            result.duration
+        );
+    }
+
+    /// HTTP proxy that serves the requested bytes plainly when asked for identity, and a
+    /// short gzip body otherwise
+    async fn spawn_compressing_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let Ok(n) = socket.read(&mut buf).await else {
+                        return;
+                    };
+                    let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                    let size: usize = request
+                        .split("bytes=")
+                        .nth(1)
+                        .and_then(|rest| rest.split_whitespace().next())
+                        .and_then(|size| size.parse().ok())
+                        .unwrap_or(0);
+
+                    let response = if request.contains("accept-encoding: identity") {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {size}\r\nConnection: close\r\n\r\n"
+                        )
+                        .into_bytes();
+                        response.resize(response.len() + size, 0);
+                        response
+                    } else {
+                        b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 4\r\nConnection: close\r\n\r\n\x1f\x8b\x08\x00"
+                            .to_vec()
+                    };
+                    let _ = socket.write_all(&response).await;
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_download_requests_identity_encoding() {
+        let port = spawn_compressing_server().await;
+        let proxy = crate::config::ProxyConfig {
+            name: "compressing".to_string(),
+            proxy_type: crate::config::ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        let client = ProxyClient::new(proxy, Duration::from_secs(5)).unwrap();
+        let tester = BandwidthTester::new(
+            client,
+            Arc::new(CloudflareBackend::new("http://speedtest.invalid")),
+        );
+
+        let result = tester.test_download(100_000, 2).await.unwrap();
+        assert_eq!(result.bytes, 100_000);
+    }
+
+    /// Send response headers and the first 1KB of a 1MB body, then hang without closing
+    async fn spawn_stalling_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1048576\r\n\r\n")
+                        .await;
+                    let _ = socket.write_all(&[0u8; 1024]).await;
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_stalled_download_chunk_fails_fast() {
+        let port = spawn_stalling_server().await;
+        let proxy = crate::config::ProxyConfig {
+            name: "stalling".to_string(),
+            proxy_type: crate::config::ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        // The request timeout alone would wait 30 seconds
+        let client = ProxyClient::new(proxy, Duration::from_secs(30)).unwrap();
+        let backend = CloudflareBackend::new("http://speedtest.invalid");
+        let counter = AtomicUsize::new(0);
+
+        let started = Instant::now();
+        let error = BandwidthTester::download_chunk(
+            &client,
+            &backend,
+            1024 * 1024,
+            Duration::from_millis(300),
+            &counter,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert!(error.to_string().contains("stalled"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // What arrived before the stall still counts towards the throughput samples
+        assert_eq!(counter.load(Ordering::Relaxed), 1024);
+
+        // A time budget doesn't wait out the stall either, and keeps what arrived
+        let started = Instant::now();
+        let result = timed_download(
+            client.client(),
+            &backend,
+            Duration::from_secs(10),
+            Duration::ZERO,
+            1,
+            None,
+            Duration::from_millis(300),
+        )
+        .await
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(result.bytes, 1024);
+    }
+
+    /// Send response headers at once, hold the body back for `delay`, then send 512KB
+    /// in 8 pieces 50ms apart
+    async fn spawn_slow_first_byte_server(delay: Duration) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 524288\r\nConnection: close\r\n\r\n")
+                        .await;
+                    tokio::time::sleep(delay).await;
+                    for _ in 0..8 {
+                        let _ = socket.write_all(&[0u8; 64 * 1024]).await;
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_ttfb_measures_delay_not_transfer() {
+        let delay = Duration::from_millis(400);
+        let port = spawn_slow_first_byte_server(delay).await;
+        let proxy = crate::config::ProxyConfig {
+            name: "slow-start".to_string(),
+            proxy_type: crate::config::ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        let client = ProxyClient::new(proxy, Duration::from_secs(5)).unwrap();
+        let tester = BandwidthTester::new(
+            client,
+            Arc::new(CloudflareBackend::new("http://speedtest.invalid")),
+        );
+
+        let result = tester.test_download(512 * 1024, 1).await.unwrap();
+        let ttfb = result.ttfb.unwrap();
+        assert_eq!(result.bytes, 512 * 1024);
+        assert!(ttfb >= delay, "{ttfb:?}");
+        // The ~350ms spent streaming the body is not part of the TTFB
+        assert!(ttfb < delay + Duration::from_millis(250), "{ttfb:?}");
+        assert!(result.duration >= ttfb + Duration::from_millis(300));
+
+        // Time-budgeted downloads record it as well
+        let result = tester
+            .test_download_timed(Duration::from_secs(1), 1)
+            .await
+            .unwrap();
+        let ttfb = result.ttfb.unwrap();
+        assert!(ttfb >= delay, "{ttfb:?}");
+        assert!(ttfb < delay + Duration::from_millis(250), "{ttfb:?}");
+    }
+
+    /// Accept uploads, decoding chunked or sized bodies, and record the bytes each
+    /// request carried and whether it declared a Content-Length
+    async fn spawn_upload_server() -> (String, Arc<std::sync::Mutex<Vec<(usize, bool)>>>) {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let uploads = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = uploads.clone();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let uploads = uploads.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(socket);
+                    let mut content_length = None;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let line = line.trim_end().to_ascii_lowercase();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some(length) = line.strip_prefix("content-length:") {
+                            content_length = length.trim().parse::<usize>().ok();
+                        }
+                    }
+
+                    let received = match content_length {
+                        Some(length) => {
+                            let mut body = vec![0u8; length];
+                            reader.read_exact(&mut body).await.unwrap();
+                            length
+                        }
+                        None => {
+                            let mut received = 0;
+                            loop {
+                                let mut size = String::new();
+                                reader.read_line(&mut size).await.unwrap();
+                                let size = usize::from_str_radix(size.trim(), 16).unwrap();
+                                let mut chunk = vec![0u8; size + 2];
+                                reader.read_exact(&mut chunk).await.unwrap();
+                                if size == 0 {
+                                    break received;
+                                }
+                                received += size;
+                            }
+                        }
+                    };
+                    uploads
+                        .lock()
+                        .unwrap()
+                        .push((received, content_length.is_some()));
+
+                    let _ = reader
+                        .get_mut()
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                });
+            }
+        });
+
+        (format!("http://{addr}"), recorded)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_upload_splits_payload() {
+        let (server_url, uploads) = spawn_upload_server().await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let size = 1024 * 1024 + 3;
+
+        let result = concurrent_upload(
+            &client,
+            &CloudflareBackend::new(&server_url),
+            UploadPayload::Zero,
+            size,
+            4,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.bytes, size);
+        let mut uploads = uploads.lock().unwrap().clone();
+        uploads.sort();
+        // Three equal parts, the last connection carries the remainder
+        let sizes: Vec<_> = uploads.iter().map(|&(bytes, _)| bytes).collect();
+        assert_eq!(sizes, vec![262144, 262144, 262144, 262147]);
+        // Counting the body doesn't turn it into a chunked stream
+        assert!(uploads.iter().all(|&(_, sized)| sized));
+    }
+
+    /// Stream 16KB every 10ms per connection, with at most `max_streams` streaming at once
+    async fn spawn_capped_server(max_streams: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let streams = Arc::new(Semaphore::new(max_streams));
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let streams = streams.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let _permit = streams.acquire().await.unwrap();
+                    let header = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {TIMED_DOWNLOAD_REQUEST_SIZE}\r\n\r\n"
+                    );
+                    if socket.write_all(header.as_bytes()).await.is_err() {
+                        return;
+                    }
+                    let chunk = vec![0u8; 16 * 1024];
+                    while socket.write_all(&chunk).await.is_ok() {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                });
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    /// Serve the `bytes` a Cloudflare-style download asks for at 64KB every 10ms
+    async fn spawn_paced_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let read = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..read]);
+                    let Some(bytes) = request
+                        .split_once("bytes=")
+                        .and_then(|(_, rest)| rest.split(' ').next())
+                        .and_then(|n| n.parse::<usize>().ok())
+                    else {
+                        return;
+                    };
+
+                    let header = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {bytes}\r\nConnection: close\r\n\r\n"
+                    );
+                    if socket.write_all(header.as_bytes()).await.is_err() {
+                        return;
+                    }
+                    let chunk = vec![0u8; 64 * 1024];
+                    let mut remaining = bytes;
+                    while remaining > 0 {
+                        let n = remaining.min(chunk.len());
+                        if socket.write_all(&chunk[..n]).await.is_err() {
+                            return;
+                        }
+                        remaining -= n;
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                });
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_probe_download_size_hits_target_duration() {
+        let server_url = spawn_paced_server().await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let backend = CloudflareBackend::new(&server_url);
+        let target = Duration::from_secs(1);
+
+        let timeout = Duration::from_secs(10);
+        let (size, probe_bytes) =
+            probe_download_size(&client, &backend, Duration::ZERO, target, 1, timeout)
+                .await
+                .unwrap();
+        assert_eq!(probe_bytes, AUTO_SIZE_PROBE_BYTES);
+        // At most 6.4MB/s, sleep overshoot only makes the server slower
+        assert!(size > AUTO_SIZE_PROBE_BYTES);
+        assert!(size <= 7 * 1024 * 1024);
+
+        let start = Instant::now();
+        let body = client
+            .get(backend.download_url(size))
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(body.len(), size);
+        assert!(elapsed > Duration::from_millis(600), "took {elapsed:?}");
+        assert!(elapsed < Duration::from_millis(1500), "took {elapsed:?}");
+
+        // The probe size is the floor, and latency is taken out of both sides
+        assert_eq!(
+            auto_download_size(
+                1024,
+                Duration::from_secs(1),
+                Duration::ZERO,
+                target,
+                1,
+                timeout
+            ),
+            AUTO_SIZE_PROBE_BYTES
+        );
+        assert_eq!(
+            auto_download_size(
+                4 * 1024 * 1024,
+                Duration::from_millis(600),
+                Duration::from_millis(100),
+                Duration::from_millis(600),
+                1,
+                timeout
+            ),
+            4 * 1024 * 1024
+        );
+
+        // Each connection gets a target's worth, and the target stays inside the timeout
+        let mb = 1024 * 1024;
+        assert_eq!(
+            auto_download_size(
+                mb,
+                Duration::from_secs(1),
+                Duration::ZERO,
+                target,
+                4,
+                timeout
+            ),
+            4 * mb
+        );
+        assert_eq!(
+            auto_download_size(
+                mb,
+                Duration::from_secs(1),
+                Duration::ZERO,
+                Duration::from_secs(10),
+                1,
+                Duration::from_secs(5)
+            ),
+            4 * mb
+        );
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_download_stops_when_throughput_plateaus() {
+        let server_url = spawn_capped_server(2).await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        // 1 -> 2 connections doubles throughput, 4 only queues behind the cap
+        let (result, concurrency) = adaptive_download(
+            &client,
+            &CloudflareBackend::new(&server_url),
+            Duration::from_millis(600),
+            None,
+            DEFAULT_STALL_TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(concurrency, 2);
+        assert!(result.duration <= Duration::from_millis(600));
+        // Bytes add up the 1, 2 and 4 connection steps, well over what the peak moved
+        let peak_bytes = result.speed * result.duration.as_secs_f64();
+        assert!(result.bytes as f64 > peak_bytes * 1.5, "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn test_timed_download_stops_at_deadline() {
+        let server_url = spawn_slow_server().await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let start = Instant::now();
+        let backend = CloudflareBackend::new(&server_url);
+        let result = timed_download(
+            &client,
+            &backend,
+            Duration::from_millis(600),
+            Duration::ZERO,
+            2,
+            None,
+            DEFAULT_STALL_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        let elapsed = start.elapsed();
+
+        // The server would take 5 seconds to finish, the budget must cut it short
+        assert!(elapsed < Duration::from_secs(2));
+        assert!(result.duration <= Duration::from_millis(600));
+        assert!(result.bytes > 0);
+        assert!(result.bytes < 2 * 100 * 1024);
+        assert!(result.speed > 0.0);
+        // Roughly one sample every 200ms over the 600ms budget
+        assert!(!result.samples.is_empty());
+        assert!(result.samples.len() <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_timed_download_window_opens_with_the_first_slot() {
+        let server_url = spawn_slow_server().await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let backend = CloudflareBackend::new(&server_url);
+        let limit: ConnectionLimit = Arc::new(Semaphore::new(1));
+
+        let held = limit.clone().acquire_owned().await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            drop(held);
+        });
+
+        let start = Instant::now();
+        let result = timed_download(
+            &client,
+            &backend,
+            Duration::from_millis(600),
+            Duration::ZERO,
+            1,
+            Some(limit),
+            DEFAULT_STALL_TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(1100));
+        // About 12KB trickle in over the full window, not the 100ms left after the wait
+        assert!(result.bytes > 6 * 1024, "{}", result.bytes);
+        assert!(result.duration > Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_timed_download_discards_warmup() {
+        // 1MB arrives at once, then only the 1KB trickle
+        let server_url = spawn_trickle_server(1024 * 1024).await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let backend = CloudflareBackend::new(&server_url);
+
+        let result = timed_download(
+            &client,
+            &backend,
+            Duration::from_millis(400),
+            Duration::from_millis(300),
+            1,
+            None,
+            DEFAULT_STALL_TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+        // The burst landed during the warm-up, only the trickle after it counts
+        assert!(result.bytes > 0);
+        assert!(result.bytes < 100 * 1024);
+        assert!(result.duration <= Duration::from_millis(400));
+        assert!(result.speed < 100.0 * 1024.0);
+
+        // Without a warm-up the burst dominates the result
+        let result = timed_download(
+            &client,
+            &backend,
+            Duration::from_millis(400),
+            Duration::ZERO,
+            1,
+            None,
+            DEFAULT_STALL_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert!(result.bytes >= 1024 * 1024);
+    }
 }
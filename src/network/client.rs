@@ -1,23 +1,146 @@
 use crate::Result;
 use crate::config::{ProxyConfig, ProxyType};
-use crate::network::{BandwidthResult, BandwidthTester, LatencyResult, LatencyTester};
+use crate::network::{
+    BandwidthResult, BandwidthTester, ConnectionLimit, DEFAULT_STALL_TIMEOUT, DnsCache,
+    LatencyMethod, LatencyResult, LatencyTester, PingRequest, SharedBackend, UploadPayload,
+    backend::with_failover,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, warn};
 
+/// IP family used to reach proxies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum IpVersion {
+    /// Whatever the resolver and proxy pick
+    #[default]
+    Auto,
+    /// IPv4 only
+    #[value(name = "4")]
+    V4,
+    /// IPv6 only
+    #[value(name = "6")]
+    V6,
+}
+
+impl IpVersion {
+    /// Unspecified local address that binds outgoing sockets to this family
+    pub fn local_address(&self) -> Option<IpAddr> {
+        match self {
+            IpVersion::Auto => None,
+            IpVersion::V4 => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            IpVersion::V6 => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        }
+    }
+
+    /// Whether `ip` is of this family, any address is for `Auto`
+    pub fn matches(&self, ip: IpAddr) -> bool {
+        match self {
+            IpVersion::Auto => true,
+            IpVersion::V4 => ip.is_ipv4(),
+            IpVersion::V6 => ip.is_ipv6(),
+        }
+    }
+
+    /// Value of mihomo's per-proxy `ip-version` field
+    pub fn mihomo_ip_version(&self) -> Option<&'static str> {
+        match self {
+            IpVersion::Auto => None,
+            IpVersion::V4 => Some("ipv4"),
+            IpVersion::V6 => Some("ipv6"),
+        }
+    }
+}
+
+impl std::fmt::Display for IpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpVersion::Auto => write!(f, "auto"),
+            IpVersion::V4 => write!(f, "4"),
+            IpVersion::V6 => write!(f, "6"),
+        }
+    }
+}
+
+/// Socket-level options for the direct proxy client
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    pub ip_version: IpVersion,
+    /// Drop idle connections so every request pays the full TCP/TLS handshake (worst-case latency)
+    pub fresh_connections: bool,
+    /// Local address outgoing connections bind to, takes precedence over `ip_version`
+    pub bind_address: Option<IpAddr>,
+    /// Give up on reaching the proxy after this long instead of waiting out the request timeout
+    pub connect_timeout: Option<Duration>,
+    /// Resolve proxy hostnames through this cache instead of the system resolver
+    pub dns: Option<DnsCache>,
+}
+
+impl ClientOptions {
+    /// Local address to bind outgoing sockets to, if any
+    pub fn local_address(&self) -> Option<IpAddr> {
+        self.bind_address
+            .or_else(|| self.ip_version.local_address())
+    }
+}
+
 /// HTTP client configured for proxy usage
 #[derive(Clone)]
 pub struct ProxyClient {
     client: reqwest::Client,
     proxy_config: ProxyConfig,
+    options: ClientOptions,
 }
 
 impl ProxyClient {
     /// Create a new proxy client
     pub fn new(proxy_config: ProxyConfig, timeout: Duration) -> Result<Self> {
+        Self::new_with_ip_version(proxy_config, timeout, IpVersion::Auto)
+    }
+
+    /// Create a new proxy client whose connections only use the given IP family
+    pub fn new_with_ip_version(
+        proxy_config: ProxyConfig,
+        timeout: Duration,
+        ip_version: IpVersion,
+    ) -> Result<Self> {
+        let options = ClientOptions {
+            ip_version,
+            ..Default::default()
+        };
+        Self::new_with_options(proxy_config, timeout, options)
+    }
+
+    /// Create a new proxy client with the given socket options
+    pub fn new_with_options(
+        proxy_config: ProxyConfig,
+        timeout: Duration,
+        options: ClientOptions,
+    ) -> Result<Self> {
+        // reqwest can't layer one proxy on top of another or speak shadow-tls or SSR
+        if let Some(feature) = proxy_config.mihomo_only_feature() {
+            return Err(anyhow::anyhow!(
+                "Proxy '{}' uses {}, which direct testing can't reproduce. Run with --use-mihomo to test it",
+                proxy_config.name,
+                feature
+            ));
+        }
+
         let mut client_builder = reqwest::Client::builder()
             .timeout(timeout)
             .danger_accept_invalid_certs(true) // For testing purposes
+            .local_address(options.local_address())
             .no_proxy(); // We'll handle proxy ourselves
+        if options.fresh_connections {
+            client_builder = client_builder.pool_max_idle_per_host(0);
+        }
+        if let Some(connect_timeout) = options.connect_timeout {
+            client_builder = client_builder.connect_timeout(connect_timeout);
+        }
+        if let Some(ref dns) = options.dns {
+            client_builder = client_builder.dns_resolver(Arc::new(dns.clone()));
+        }
 
         // Configure proxy based on type
         let client = match &proxy_config.proxy_type {
@@ -45,19 +168,9 @@ impl ProxyClient {
                     "Setting up SOCKS5 proxy: {}:{}",
                     proxy_config.server, proxy_config.port
                 );
-                let proxy_url = format!("socks5://{}:{}", proxy_config.server, proxy_config.port);
-                let proxy = reqwest::Proxy::http(&proxy_url)?;
-
-                if let (Some(username), Some(password)) =
-                    (&proxy_config.config.username, &proxy_config.config.password)
-                {
-                    let proxy = proxy.basic_auth(username, password);
-                    client_builder = client_builder.proxy(proxy);
-                } else {
-                    client_builder = client_builder.proxy(proxy);
-                }
+                let proxy = socks_proxy(&proxy_config)?;
 
-                client_builder.build()?
+                client_builder.proxy(proxy).build()?
             }
             ProxyType::Trojan => {
                 debug!(
@@ -69,9 +182,11 @@ impl ProxyClient {
                 );
                 // For now, fall back to direct connection but log the configuration
                 debug!(
-                    "Trojan config - password: {:?}, network: {:?}, skip-cert-verify: {:?}",
+                    "Trojan config - password: {:?}, network: {:?}, ws-opts: {:?}, smux: {:?}, skip-cert-verify: {:?}",
                     proxy_config.config.password.is_some(),
                     proxy_config.config.network,
+                    proxy_config.config.ws_opts,
+                    proxy_config.config.smux,
                     proxy_config.config.skip_cert_verify
                 );
                 client_builder.build()?
@@ -93,6 +208,24 @@ impl ProxyClient {
                 );
                 client_builder.build()?
             }
+            ProxyType::Tuic => {
+                debug!(
+                    "TUIC proxy detected: {}:{}",
+                    proxy_config.server, proxy_config.port
+                );
+                warn!(
+                    "TUIC runs over QUIC, which only mihomo can speak - run with --use-mihomo to test '{}'; using direct connection for basic connectivity test",
+                    proxy_config.name
+                );
+                debug!(
+                    "TUIC config - uuid: {:?}, password: {:?}, congestion-controller: {:?}, udp-relay-mode: {:?}",
+                    proxy_config.config.uuid.is_some(),
+                    proxy_config.config.password.is_some(),
+                    proxy_config.config.extra.get("congestion-controller"),
+                    proxy_config.config.extra.get("udp-relay-mode")
+                );
+                client_builder.build()?
+            }
             ProxyType::AnyTLS => {
                 debug!(
                     "AnyTLS proxy detected: {}:{}",
@@ -111,7 +244,7 @@ impl ProxyClient {
                 );
                 client_builder.build()?
             }
-            ProxyType::Shadowsocks | ProxyType::ShadowsocksShort => {
+            ProxyType::Shadowsocks | ProxyType::ShadowsocksShort | ProxyType::ShadowsocksR => {
                 debug!(
                     "Shadowsocks proxy detected: {}:{}",
                     proxy_config.server, proxy_config.port
@@ -120,9 +253,15 @@ impl ProxyClient {
                     "Shadowsocks protocol requires special client implementation - using direct connection for basic connectivity test"
                 );
                 debug!(
-                    "Shadowsocks config - cipher: {:?}, password: {:?}",
+                    "Shadowsocks config - cipher: {:?}, password: {:?}, plugin: {:?}, plugin-opts: {:?}",
                     proxy_config.config.cipher,
-                    proxy_config.config.password.is_some()
+                    proxy_config.config.password.is_some(),
+                    proxy_config.config.plugin,
+                    proxy_config.config.plugin_opts.as_ref().map(|opts| {
+                        let mut keys: Vec<_> = opts.keys().collect();
+                        keys.sort();
+                        keys
+                    })
                 );
                 client_builder.build()?
             }
@@ -172,6 +311,7 @@ impl ProxyClient {
         Ok(Self {
             client,
             proxy_config,
+            options,
         })
     }
 
@@ -185,6 +325,26 @@ impl ProxyClient {
         &self.proxy_config
     }
 
+    /// Whether idle connections are dropped instead of reused
+    pub fn fresh_connections(&self) -> bool {
+        self.options.fresh_connections
+    }
+
+    /// Local address outgoing connections are bound to, if any
+    pub fn local_address(&self) -> Option<IpAddr> {
+        self.options.local_address()
+    }
+
+    /// IP family proxies are reached over
+    pub fn ip_version(&self) -> IpVersion {
+        self.options.ip_version
+    }
+
+    /// Cache resolving proxy hostnames, the system resolver's if none was given
+    pub fn dns_cache(&self) -> DnsCache {
+        self.options.dns.clone().unwrap_or_else(DnsCache::system)
+    }
+
     /// Make a GET request
     pub async fn get(&self, url: &str) -> Result<reqwest::Response> {
         debug!("Making GET request to: {}", url);
@@ -200,30 +360,156 @@ impl ProxyClient {
 
 /// Network tester that combines latency and bandwidth testing
 pub struct NetworkTester {
-    server_url: String,
+    backend: SharedBackend,
+    fallback_backends: Vec<SharedBackend>,
     download_timeout: Duration,
     upload_timeout: Duration,
+    connection_limit: Option<ConnectionLimit>,
+    upload_payload: UploadPayload,
+    client_options: ClientOptions,
+    warmup: Duration,
+    stall_timeout: Duration,
+    ping: PingRequest,
 }
 
 impl NetworkTester {
     /// Create a new network tester
-    pub fn new(server_url: String, download_timeout: Duration, upload_timeout: Duration) -> Self {
+    pub fn new(
+        backend: SharedBackend,
+        download_timeout: Duration,
+        upload_timeout: Duration,
+    ) -> Self {
         Self {
-            server_url,
+            backend,
+            fallback_backends: Vec::new(),
             download_timeout,
             upload_timeout,
+            connection_limit: None,
+            upload_payload: UploadPayload::Zero,
+            client_options: ClientOptions::default(),
+            warmup: Duration::ZERO,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            ping: PingRequest::default(),
         }
     }
 
+    /// Servers tried in order when a download or upload against the primary one fails
+    pub fn with_fallback_backends(mut self, backends: Vec<SharedBackend>) -> Self {
+        self.fallback_backends = backends;
+        self
+    }
+
+    /// Discard the first `warmup` of timed downloads
+    pub fn with_warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Abandon download chunks that receive no data for `timeout`
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Choose the bytes sent by upload tests
+    pub fn with_upload_payload(mut self, payload: UploadPayload) -> Self {
+        self.upload_payload = payload;
+        self
+    }
+
+    /// Only reach proxies over the given IP family
+    pub fn with_ip_version(mut self, ip_version: IpVersion) -> Self {
+        self.client_options.ip_version = ip_version;
+        self
+    }
+
+    /// Open a fresh connection for every request instead of reusing pooled ones
+    pub fn with_fresh_connections(mut self, fresh: bool) -> Self {
+        self.client_options.fresh_connections = fresh;
+        self
+    }
+
+    /// Send test traffic out of the interface owning `address`
+    pub fn with_bind_address(mut self, address: Option<IpAddr>) -> Self {
+        self.client_options.bind_address = address;
+        self
+    }
+
+    /// Fail connections to a proxy that aren't established within `timeout`
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.client_options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Resolve proxy hostnames through `dns`, sharing its answers across proxies
+    pub fn with_dns_cache(mut self, dns: DnsCache) -> Self {
+        self.client_options.dns = Some(dns);
+        self
+    }
+
+    /// Send HTTP latency pings as GET or HEAD requests
+    pub fn with_latency_method(mut self, method: LatencyMethod) -> Self {
+        self.ping = PingRequest::new(method);
+        self
+    }
+
+    /// Client for `proxy` using this tester's socket options
+    fn proxy_client(&self, proxy: &ProxyConfig, timeout: Duration) -> Result<ProxyClient> {
+        ProxyClient::new_with_options(proxy.clone(), timeout, self.client_options.clone())
+    }
+
+    /// Cap download and upload connections shared by every test run through this tester
+    pub fn with_connection_limit(mut self, limit: Option<ConnectionLimit>) -> Self {
+        self.connection_limit = limit;
+        self
+    }
+
     /// Test latency for a proxy
     pub async fn test_latency(
         &self,
         proxy: &ProxyConfig,
         iterations: usize,
     ) -> Result<LatencyResult> {
-        let client = ProxyClient::new(proxy.clone(), self.download_timeout)?;
-        let tester = LatencyTester::new(client, self.server_url.clone());
-        tester.test_latency(iterations).await
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let (result, server) = self
+            .with_failover(|backend| {
+                let tester = LatencyTester::new(client.clone(), backend)
+                    .with_ping_request(self.ping.for_proxy());
+                async move { tester.test_latency(iterations).await }
+            })
+            .await?;
+        Ok(result.with_server(server))
+    }
+
+    /// Test latency for a proxy while a download keeps the connection busy
+    pub async fn test_latency_under_load(
+        &self,
+        proxy: &ProxyConfig,
+        iterations: usize,
+    ) -> Result<LatencyResult> {
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let (result, server) = self
+            .with_failover(|backend| {
+                let tester = LatencyTester::new(client.clone(), backend)
+                    .with_connection_limit(self.connection_limit.clone());
+                async move { tester.test_latency_under_load(iterations).await }
+            })
+            .await?;
+        Ok(result.with_server(server))
+    }
+
+    /// Test TCP connect latency to a proxy's server:port, the speed test server
+    /// isn't involved so no fallback applies
+    pub async fn test_tcp_latency(
+        &self,
+        proxy: &ProxyConfig,
+        iterations: usize,
+    ) -> Result<LatencyResult> {
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let tester = LatencyTester::new(client, self.backend.clone());
+        tester
+            .test_tcp_latency(iterations, self.download_timeout)
+            .await
     }
 
     /// Test download bandwidth for a proxy
@@ -233,15 +519,485 @@ impl NetworkTester {
         size: usize,
         concurrent: usize,
     ) -> Result<BandwidthResult> {
-        let client = ProxyClient::new(proxy.clone(), self.download_timeout)?;
-        let tester = BandwidthTester::new(client, self.server_url.clone());
-        tester.test_download(size, concurrent).await
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let (result, server) = self
+            .with_failover(|backend| {
+                let tester = self.bandwidth_tester(client.clone(), backend);
+                async move { tester.test_download(size, concurrent).await }
+            })
+            .await?;
+        Ok(result.with_server(server))
+    }
+
+    /// Probe a proxy's download speed and pick the size a download over `concurrent`
+    /// connections takes about `target` for, returned with the bytes the probe used
+    pub async fn probe_download_size(
+        &self,
+        proxy: &ProxyConfig,
+        latency: Duration,
+        target: Duration,
+        concurrent: usize,
+    ) -> Result<(usize, usize)> {
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let timeout = self.download_timeout;
+        let (size, _) = self
+            .with_failover(|backend| {
+                let tester = self.bandwidth_tester(client.clone(), backend);
+                async move {
+                    tester
+                        .probe_download_size(latency, target, concurrent, timeout)
+                        .await
+                }
+            })
+            .await?;
+        Ok(size)
+    }
+
+    /// Test download bandwidth for a proxy within a fixed time budget
+    pub async fn test_download_timed(
+        &self,
+        proxy: &ProxyConfig,
+        duration: Duration,
+        concurrent: usize,
+    ) -> Result<BandwidthResult> {
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let (result, server) = self
+            .with_failover(|backend| {
+                let tester = self.bandwidth_tester(client.clone(), backend);
+                async move { tester.test_download_timed(duration, concurrent).await }
+            })
+            .await?;
+        Ok(result.with_server(server))
+    }
+
+    /// Ramp download concurrency for a proxy until throughput plateaus,
+    /// returning the peak result and the concurrency that reached it
+    pub async fn test_download_adaptive(
+        &self,
+        proxy: &ProxyConfig,
+        window: Duration,
+    ) -> Result<(BandwidthResult, usize)> {
+        let client = self.proxy_client(proxy, self.download_timeout)?;
+        let ((result, concurrency), server) = self
+            .with_failover(|backend| {
+                let tester = self.bandwidth_tester(client.clone(), backend);
+                async move { tester.test_download_adaptive(window).await }
+            })
+            .await?;
+        Ok((result.with_server(server), concurrency))
+    }
+
+    /// Test upload bandwidth for a proxy, splitting the upload across `concurrent` connections
+    pub async fn test_upload(
+        &self,
+        proxy: &ProxyConfig,
+        size: usize,
+        concurrent: usize,
+    ) -> Result<BandwidthResult> {
+        let client = self.proxy_client(proxy, self.upload_timeout)?;
+        let (result, server) = self
+            .with_failover(|backend| {
+                let tester = self.bandwidth_tester(client.clone(), backend);
+                async move { tester.test_upload(size, concurrent).await }
+            })
+            .await?;
+        Ok(result.with_server(server))
+    }
+
+    /// Bandwidth tester against `backend` sharing this tester's limits and settings
+    fn bandwidth_tester(&self, client: ProxyClient, backend: SharedBackend) -> BandwidthTester {
+        BandwidthTester::new(client, backend)
+            .with_connection_limit(self.connection_limit.clone())
+            .with_upload_payload(self.upload_payload)
+            .with_warmup(self.warmup)
+            .with_stall_timeout(self.stall_timeout)
+    }
+
+    /// Run `test` against the primary server, then each fallback in turn until one
+    /// succeeds, returning the result and the URL of the server that produced it
+    async fn with_failover<T, F, Fut>(&self, test: F) -> Result<(T, String)>
+    where
+        F: Fn(SharedBackend) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        with_failover(
+            std::iter::once(&self.backend).chain(&self.fallback_backends),
+            test,
+        )
+        .await
+    }
+}
+
+/// SOCKS5 proxy for `proxy_config`
+#[cfg(feature = "socks")]
+fn socks_proxy(proxy_config: &ProxyConfig) -> Result<reqwest::Proxy> {
+    // socks5h resolves hostnames on the proxy side. SOCKS auth travels in the
+    // URL userinfo; basic_auth only applies to HTTP proxies.
+    let auth = match (&proxy_config.config.username, &proxy_config.config.password) {
+        (Some(username), Some(password)) => format!(
+            "{}:{}@",
+            urlencoding::encode(username),
+            urlencoding::encode(password)
+        ),
+        _ => String::new(),
+    };
+    let proxy_url = format!(
+        "socks5h://{}{}:{}",
+        auth, proxy_config.server, proxy_config.port
+    );
+
+    Ok(reqwest::Proxy::all(&proxy_url)?)
+}
+
+/// Without reqwest's SOCKS support the client would ignore the proxy and test a direct
+/// connection, so refuse to build one
+#[cfg(not(feature = "socks"))]
+fn socks_proxy(proxy_config: &ProxyConfig) -> Result<reqwest::Proxy> {
+    Err(socks_disabled_error(proxy_config))
+}
+
+#[cfg(any(test, not(feature = "socks")))]
+fn socks_disabled_error(proxy_config: &ProxyConfig) -> anyhow::Error {
+    anyhow::anyhow!(
+        "SOCKS5 proxy '{}' can't be tested: this build lacks the `socks` feature. Rebuild with default features or run with --use-mihomo",
+        proxy_config.name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    #[cfg(feature = "socks")]
+    use {std::sync::Mutex, tokio::net::TcpStream};
+
+    #[cfg(feature = "socks")]
+    /// Minimal SOCKS5 server (RFC 1928/1929) requiring `user`/`pa:ss`. Records each
+    /// CONNECT target and answers the tunnelled HTTP request itself.
+    async fn spawn_socks5_server() -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let recorded = targets.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let targets = targets.clone();
+                tokio::spawn(async move {
+                    let _ = handle_socks5(socket, targets).await;
+                });
+            }
+        });
+
+        (port, recorded)
     }
 
-    /// Test upload bandwidth for a proxy
-    pub async fn test_upload(&self, proxy: &ProxyConfig, size: usize) -> Result<BandwidthResult> {
-        let client = ProxyClient::new(proxy.clone(), self.upload_timeout)?;
-        let tester = BandwidthTester::new(client, self.server_url.clone());
-        tester.test_upload(size).await
+    #[cfg(feature = "socks")]
+    async fn handle_socks5(
+        mut socket: TcpStream,
+        targets: Arc<Mutex<Vec<String>>>,
+    ) -> std::io::Result<()> {
+        // Greeting: only username/password auth is accepted
+        let mut header = [0u8; 2];
+        socket.read_exact(&mut header).await?;
+        let mut methods = vec![0u8; header[1] as usize];
+        socket.read_exact(&mut methods).await?;
+        if !methods.contains(&0x02) {
+            return socket.write_all(&[0x05, 0xff]).await;
+        }
+        socket.write_all(&[0x05, 0x02]).await?;
+
+        let mut version = [0u8; 1];
+        socket.read_exact(&mut version).await?;
+        let username = read_short_string(&mut socket).await?;
+        let password = read_short_string(&mut socket).await?;
+        if username != "user" || password != "pa:ss" {
+            return socket.write_all(&[0x01, 0x01]).await;
+        }
+        socket.write_all(&[0x01, 0x00]).await?;
+
+        // CONNECT request, expecting a domain name (ATYP 0x03)
+        let mut request = [0u8; 4];
+        socket.read_exact(&mut request).await?;
+        assert_eq!(request[1], 0x01, "expected CONNECT");
+        assert_eq!(request[3], 0x03, "expected remote DNS resolution");
+        let host = read_short_string(&mut socket).await?;
+        let mut port = [0u8; 2];
+        socket.read_exact(&mut port).await?;
+        targets
+            .lock()
+            .unwrap()
+            .push(format!("{}:{}", host, u16::from_be_bytes(port)));
+        socket
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await?;
+
+        let mut buf = [0u8; 4096];
+        let _ = socket.read(&mut buf).await?;
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+            .await
+    }
+
+    #[cfg(feature = "socks")]
+    async fn read_short_string(socket: &mut TcpStream) -> std::io::Result<String> {
+        let mut len = [0u8; 1];
+        socket.read_exact(&mut len).await?;
+        let mut bytes = vec![0u8; len[0] as usize];
+        socket.read_exact(&mut bytes).await?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Plain HTTP proxy answering absolute-form requests itself: 503 for
+    /// `primary.test` and a 1 KiB body for every other host
+    async fn spawn_failover_proxy() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let response = if request.starts_with("GET http://primary.test/") {
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    } else {
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: 1024\r\nConnection: close\r\n\r\n{}",
+                            "x".repeat(1024)
+                        )
+                    };
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_download_fails_over_to_next_server() {
+        let port = spawn_failover_proxy().await;
+        let proxy = ProxyConfig {
+            name: "http".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        let backend =
+            |url| -> SharedBackend { Arc::new(crate::network::CloudflareBackend::new(url)) };
+        let tester = NetworkTester::new(
+            backend("http://primary.test"),
+            Duration::from_secs(2),
+            Duration::from_secs(2),
+        )
+        .with_fallback_backends(vec![backend("http://backup.test")]);
+
+        let result = tester.test_download(&proxy, 1024, 1).await.unwrap();
+        assert_eq!(result.bytes, 1024);
+        assert_eq!(result.server.as_deref(), Some("http://backup.test"));
+
+        // Without a fallback the primary's failure surfaces
+        let primary_only = NetworkTester::new(
+            backend("http://primary.test"),
+            Duration::from_secs(2),
+            Duration::from_secs(2),
+        );
+        assert!(primary_only.test_download(&proxy, 1024, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_latency_fails_over_to_next_server() {
+        let port = spawn_failover_proxy().await;
+        let proxy = ProxyConfig {
+            name: "http".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: Default::default(),
+        };
+        let backend =
+            |url| -> SharedBackend { Arc::new(crate::network::CloudflareBackend::new(url)) };
+        let tester = NetworkTester::new(
+            backend("http://primary.test"),
+            Duration::from_secs(2),
+            Duration::from_secs(2),
+        )
+        .with_fallback_backends(vec![backend("http://backup.test")]);
+
+        let result = tester.test_latency(&proxy, 2).await.unwrap();
+        assert_eq!(result.samples.len(), 2);
+        assert_eq!(result.server.as_deref(), Some("http://backup.test"));
+        let loaded = tester.test_latency_under_load(&proxy, 2).await.unwrap();
+        assert_eq!(loaded.server.as_deref(), Some("http://backup.test"));
+    }
+
+    #[test]
+    fn test_mihomo_only_features_require_mihomo() {
+        let proxy = ProxyConfig {
+            name: "chained".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port: 8080,
+            config: crate::config::ProxyParameters {
+                dialer_proxy: Some("front".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let error = ProxyClient::new(proxy, Duration::from_secs(1))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("'front'"), "{error}");
+        assert!(error.contains("--use-mihomo"), "{error}");
+
+        // shadow-tls fails the same way instead of testing the bare server
+        let proxy = ProxyConfig {
+            name: "stls".to_string(),
+            proxy_type: ProxyType::Shadowsocks,
+            server: "127.0.0.1".to_string(),
+            port: 8388,
+            config: crate::config::ProxyParameters {
+                cipher: Some("aes-128-gcm".to_string()),
+                password: Some("secret".to_string()),
+                plugin: Some("shadow-tls".to_string()),
+                ..Default::default()
+            },
+        };
+        let error = ProxyClient::new(proxy, Duration::from_secs(1))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("shadow-tls"), "{error}");
+        assert!(error.contains("--use-mihomo"), "{error}");
+    }
+
+    #[test]
+    fn test_fresh_connections_disable_pooling() {
+        let proxy = ProxyConfig {
+            name: "http".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port: 8080,
+            config: Default::default(),
+        };
+
+        let pooled = ProxyClient::new(proxy.clone(), Duration::from_secs(1)).unwrap();
+        assert!(!pooled.fresh_connections());
+
+        let options = ClientOptions {
+            fresh_connections: true,
+            ..Default::default()
+        };
+        let fresh = ProxyClient::new_with_options(proxy, Duration::from_secs(1), options).unwrap();
+        assert!(fresh.fresh_connections());
+    }
+
+    #[cfg(feature = "socks")]
+    #[tokio::test]
+    async fn test_bind_address_applied() {
+        let (port, _) = spawn_socks5_server().await;
+        let proxy = ProxyConfig {
+            name: "socks".to_string(),
+            proxy_type: ProxyType::Socks5,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: crate::config::ProxyParameters {
+                username: Some("user".to_string()),
+                password: Some("pa:ss".to_string()),
+                ..Default::default()
+            },
+        };
+        let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+        let options = ClientOptions {
+            ip_version: IpVersion::V6,
+            bind_address: Some(loopback),
+            ..Default::default()
+        };
+
+        let client = ProxyClient::new_with_options(proxy, Duration::from_secs(2), options).unwrap();
+        // The bind address wins over the IP family
+        assert_eq!(client.local_address(), Some(loopback));
+        let response = client.get("http://speedtest.invalid/ping").await.unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[cfg(feature = "socks")]
+    #[tokio::test]
+    async fn test_socks5_traffic_traverses_proxy() {
+        let (port, targets) = spawn_socks5_server().await;
+        let proxy = ProxyConfig {
+            name: "socks".to_string(),
+            proxy_type: ProxyType::Socks5,
+            server: "127.0.0.1".to_string(),
+            port,
+            config: crate::config::ProxyParameters {
+                username: Some("user".to_string()),
+                password: Some("pa:ss".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let client = ProxyClient::new(proxy, Duration::from_secs(2)).unwrap();
+        // The hostname is unresolvable locally, so this only succeeds through the tunnel
+        let response = client.get("http://speedtest.invalid/ping").await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        assert_eq!(*targets.lock().unwrap(), vec!["speedtest.invalid:80"]);
+    }
+
+    #[test]
+    fn test_socks_requires_feature() {
+        let proxy = ProxyConfig {
+            name: "socks-node".to_string(),
+            proxy_type: ProxyType::Socks5,
+            server: "127.0.0.1".to_string(),
+            port: 1080,
+            config: Default::default(),
+        };
+        let message = socks_disabled_error(&proxy).to_string();
+        assert!(message.contains("socks-node"));
+        assert!(message.contains("`socks` feature"));
+
+        let client = ProxyClient::new(proxy, Duration::from_secs(1));
+        #[cfg(feature = "socks")]
+        assert!(client.is_ok());
+        #[cfg(not(feature = "socks"))]
+        assert_eq!(client.err().unwrap().to_string(), message);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_fails_fast() {
+        // Non-routable, so the SYN goes unanswered until the connect timeout
+        let proxy = ProxyConfig {
+            name: "blackhole".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "10.255.255.1".to_string(),
+            port: 8080,
+            config: Default::default(),
+        };
+        let options = ClientOptions {
+            connect_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let client =
+            ProxyClient::new_with_options(proxy, Duration::from_secs(30), options).unwrap();
+
+        let start = std::time::Instant::now();
+        assert!(client.get("http://speedtest.invalid/ping").await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(3));
     }
 }
@@ -0,0 +1,263 @@
+//! In-process DNS cache shared by the proxy tests of a run. Subscriptions often point
+//! many nodes at one domain, so each hostname is looked up once, and servers that
+//! don't resolve are found before any connection is attempted.
+
+use crate::Result;
+use crate::config::ProxyConfig;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use hickory_resolver::TokioResolver;
+use hickory_resolver::config::{NameServerConfig, ResolverConfig};
+use hickory_resolver::net::runtime::TokioRuntimeProvider;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// How long answers of the system resolver, which carry no TTL, are cached
+const SYSTEM_RESOLVER_TTL: Duration = Duration::from_secs(300);
+/// How long a failed lookup is remembered
+const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+/// Hostnames looked up at once by `pre_resolve`
+const PRE_RESOLVE_CONCURRENCY: usize = 16;
+
+/// Addresses a hostname resolved to and how long they stay valid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub ips: Vec<IpAddr>,
+    pub ttl: Duration,
+}
+
+/// Source of the answers a `DnsCache` stores
+pub trait HostResolver: Send + Sync {
+    fn lookup<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Lookup>>;
+}
+
+/// The operating system's resolver
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl HostResolver for SystemResolver {
+    fn lookup<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Lookup>> {
+        Box::pin(async move {
+            let ips = tokio::net::lookup_host((host, 0))
+                .await?
+                .map(|address| address.ip())
+                .collect();
+            Ok(Lookup {
+                ips,
+                ttl: SYSTEM_RESOLVER_TTL,
+            })
+        })
+    }
+}
+
+/// Queries one DNS server directly (`--dns-server`), keeping the TTLs it answers with
+pub struct NameServerResolver {
+    resolver: TokioResolver,
+}
+
+impl NameServerResolver {
+    pub fn new(server: IpAddr) -> Result<Self> {
+        let config = ResolverConfig::from_name_servers(vec![NameServerConfig::udp_and_tcp(server)]);
+        let resolver =
+            TokioResolver::builder_with_config(config, TokioRuntimeProvider::default()).build()?;
+        Ok(Self { resolver })
+    }
+}
+
+impl HostResolver for NameServerResolver {
+    fn lookup<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Lookup>> {
+        Box::pin(async move {
+            let lookup = self.resolver.lookup_ip(host).await?;
+            Ok(Lookup {
+                ips: lookup.iter().collect(),
+                ttl: lookup
+                    .valid_until()
+                    .saturating_duration_since(Instant::now()),
+            })
+        })
+    }
+}
+
+struct CacheEntry {
+    /// Addresses, or why the lookup failed
+    result: std::result::Result<Vec<IpAddr>, String>,
+    expires: Instant,
+}
+
+/// Hostname to IP cache in front of a `HostResolver`. Clones share the cache, and it
+/// doubles as the resolver of the direct proxy clients.
+#[derive(Clone)]
+pub struct DnsCache {
+    resolver: Arc<dyn HostResolver>,
+    entries: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl std::fmt::Debug for DnsCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DnsCache")
+            .field("entries", &self.entries.lock().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl DnsCache {
+    pub fn new(resolver: impl HostResolver + 'static) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            entries: Arc::default(),
+        }
+    }
+
+    /// Cache over the system resolver
+    pub fn system() -> Self {
+        Self::new(SystemResolver)
+    }
+
+    /// Cache over `server` if given, the system resolver otherwise
+    pub fn for_server(server: Option<IpAddr>) -> Result<Self> {
+        Ok(match server {
+            Some(server) => Self::new(NameServerResolver::new(server)?),
+            None => Self::system(),
+        })
+    }
+
+    /// Addresses of `host`, from the cache while its entry is fresh. IP literals are
+    /// returned as is.
+    pub async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.trim_matches(['[', ']']).parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+
+        if let Some(entry) = self.entries.lock().unwrap().get(host)
+            && entry.expires > Instant::now()
+        {
+            return entry.result.clone().map_err(anyhow::Error::msg);
+        }
+
+        let (result, ttl) = match self.resolver.lookup(host).await {
+            Ok(lookup) if lookup.ips.is_empty() => {
+                (Err(format!("no addresses found for {host}")), NEGATIVE_TTL)
+            }
+            Ok(lookup) => (Ok(lookup.ips), lookup.ttl),
+            Err(e) => (Err(format!("{host}: {e}")), NEGATIVE_TTL),
+        };
+        debug!("Resolved {} to {:?} (ttl {:?})", host, result, ttl);
+
+        self.entries.lock().unwrap().insert(
+            host.to_string(),
+            CacheEntry {
+                result: result.clone(),
+                expires: Instant::now() + ttl,
+            },
+        );
+        result.map_err(anyhow::Error::msg)
+    }
+
+    /// Resolve the distinct servers of `proxies` concurrently, filling the cache ahead
+    /// of the tests. Returns the hostnames that failed to resolve.
+    pub async fn pre_resolve(&self, proxies: &[ProxyConfig]) -> HashSet<String> {
+        let hosts: HashSet<&str> = proxies.iter().map(|p| p.server.as_str()).collect();
+        futures::stream::iter(hosts)
+            .map(|host| async move { self.resolve(host).await.err().map(|_| host.to_string()) })
+            .buffer_unordered(PRE_RESOLVE_CONCURRENCY)
+            .filter_map(|failed| async move { failed })
+            .collect()
+            .await
+    }
+}
+
+impl reqwest::dns::Resolve for DnsCache {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let cache = self.clone();
+        Box::pin(async move {
+            let ips = cache.resolve(name.as_str()).await?;
+            let addrs: reqwest::dns::Addrs =
+                Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers `known.test` with a fixed IP, fails everything else, counting lookups
+    struct FakeResolver {
+        lookups: Arc<AtomicUsize>,
+        ttl: Duration,
+    }
+
+    impl HostResolver for FakeResolver {
+        fn lookup<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Lookup>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                match host {
+                    "known.test" => Ok(Lookup {
+                        ips: vec!["203.0.113.7".parse().unwrap()],
+                        ttl: self.ttl,
+                    }),
+                    _ => Err(anyhow::anyhow!("NXDOMAIN")),
+                }
+            })
+        }
+    }
+
+    fn fake_cache(ttl: Duration) -> (DnsCache, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let resolver = FakeResolver {
+            lookups: lookups.clone(),
+            ttl,
+        };
+        (DnsCache::new(resolver), lookups)
+    }
+
+    fn proxy(name: &str, server: &str) -> ProxyConfig {
+        ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Http,
+            server: server.to_string(),
+            port: 8080,
+            config: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_resolves_each_host_once() {
+        let (cache, lookups) = fake_cache(Duration::from_secs(60));
+        let proxies = [
+            proxy("HK 01", "known.test"),
+            proxy("HK 02", "known.test"),
+            proxy("JP 01", "missing.test"),
+            proxy("US 01", "198.51.100.1"),
+        ];
+
+        let failed = cache.pre_resolve(&proxies).await;
+        assert_eq!(failed, HashSet::from(["missing.test".to_string()]));
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+        // Both answers and failures come from the cache afterwards
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(cache.resolve("known.test").await.unwrap(), [ip]);
+        let error = cache.resolve("missing.test").await.unwrap_err();
+        assert!(error.to_string().contains("NXDOMAIN"));
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+        // Clones share the cache
+        assert_eq!(cache.clone().resolve("known.test").await.unwrap(), [ip]);
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_expires_entries() {
+        let (cache, lookups) = fake_cache(Duration::ZERO);
+        cache.resolve("known.test").await.unwrap();
+        cache.resolve("known.test").await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,8 +1,160 @@
 use crate::Result;
 use crate::core::StatisticalAnalysis;
-use crate::network::ProxyClient;
+use crate::network::bandwidth::{ConnectionLimit, acquire_connection, stream_until};
+use crate::network::{
+    DEFAULT_STALL_TIMEOUT, DnsCache, IpVersion, ProxyClient, SharedBackend, SpeedTestBackend,
+};
+use reqwest::StatusCode;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
-use tracing::debug;
+use tokio::net::{TcpSocket, TcpStream};
+use tracing::{debug, warn};
+
+/// Head start the background download gets before the loaded pings fire
+const LOAD_RAMP_UP: Duration = Duration::from_millis(300);
+
+/// Bytes requested per background download request of a loaded latency test
+const LOAD_REQUEST_SIZE: usize = 100 * 1024 * 1024;
+
+/// Longest the background download of a loaded latency test may run
+const LOAD_MAX_DURATION: Duration = Duration::from_secs(30);
+
+/// Gap between the pings of a loaded latency test
+const LOAD_PING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How latency is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LatencyMode {
+    /// HTTP round trips to the speed test server through the proxy
+    #[default]
+    Http,
+    /// Raw TCP connect time to the proxy's server:port
+    Tcp,
+}
+
+impl std::fmt::Display for LatencyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LatencyMode::Http => write!(f, "http"),
+            LatencyMode::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+/// Which hop latency is measured to, see `--latency-target`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LatencyTarget {
+    /// Through the proxy to the speed test server
+    #[default]
+    Server,
+    /// Also the TCP connect time to the proxy's own server:port, the hop from this
+    /// machine to the proxy regardless of where the test server is
+    Proxy,
+}
+
+impl std::fmt::Display for LatencyTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LatencyTarget::Server => write!(f, "server"),
+            LatencyTarget::Proxy => write!(f, "proxy"),
+        }
+    }
+}
+
+/// HTTP method of latency pings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LatencyMethod {
+    /// GET the backend's empty-body latency URL
+    #[default]
+    Get,
+    /// HEAD the server root, no response body at all
+    Head,
+}
+
+impl std::fmt::Display for LatencyMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LatencyMethod::Get => write!(f, "get"),
+            LatencyMethod::Head => write!(f, "head"),
+        }
+    }
+}
+
+/// How HTTP pings are sent. Clones share whether HEAD was rejected, so the pings of one
+/// test fall back to GET once. Whether HEAD works depends on the proxy as much as on the
+/// server, each proxy and server pair starts over from `for_proxy`.
+#[derive(Debug, Clone, Default)]
+pub struct PingRequest {
+    method: LatencyMethod,
+    head_rejected: Arc<AtomicBool>,
+    /// Shared by every copy, the fallback is only worth one warning per run
+    warned: Arc<AtomicBool>,
+}
+
+impl PingRequest {
+    pub fn new(method: LatencyMethod) -> Self {
+        Self {
+            method,
+            head_rejected: Arc::new(AtomicBool::new(false)),
+            warned: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Copy for pinging through another proxy, which tries HEAD again
+    pub(crate) fn for_proxy(&self) -> Self {
+        Self {
+            method: self.method,
+            head_rejected: Arc::new(AtomicBool::new(false)),
+            warned: self.warned.clone(),
+        }
+    }
+
+    /// Time one ping. A server answering HEAD with 405 or 501 is pinged with GET from
+    /// then on, the rejected HEAD isn't counted in the round trip.
+    pub(crate) async fn send(
+        &self,
+        client: &reqwest::Client,
+        backend: &dyn SpeedTestBackend,
+    ) -> Result<Duration> {
+        if self.method == LatencyMethod::Head && !self.head_rejected.load(Ordering::Relaxed) {
+            let start = Instant::now();
+            let response = client.head(backend.server_url()).send().await?;
+            let status = response.status();
+            if !matches!(
+                status,
+                StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+            ) {
+                // A status error stays a reqwest error so failures can be classified
+                response.error_for_status()?;
+                return Ok(start.elapsed());
+            }
+            self.head_rejected.store(true, Ordering::Relaxed);
+            if !self.warned.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "{} doesn't support HEAD ({}), measuring latency with GET",
+                    backend.server_url(),
+                    status
+                );
+            } else {
+                debug!(
+                    "{} rejected HEAD ({}), measuring latency with GET",
+                    backend.server_url(),
+                    status
+                );
+            }
+        }
+
+        let start = Instant::now();
+        client
+            .get(backend.latency_url())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(start.elapsed())
+    }
+}
 
 /// Result of latency testing
 #[derive(Debug, Clone)]
@@ -12,24 +164,56 @@ pub struct LatencyResult {
     pub packet_loss: f64,
     pub min_latency: Duration,
     pub max_latency: Duration,
+    /// Round-trip time of every successful ping, in the order they were sent
+    pub samples: Vec<Duration>,
+    /// Speed test server that answered the pings, if known
+    pub server: Option<String>,
+}
+
+impl LatencyResult {
+    /// Record the speed test server that answered the pings
+    pub fn with_server(mut self, server: String) -> Self {
+        self.server = Some(server);
+        self
+    }
 }
 
 /// Latency tester for measuring round-trip time
 pub struct LatencyTester {
     client: ProxyClient,
-    server_url: String,
+    backend: SharedBackend,
+    ping: PingRequest,
+    connection_limit: Option<ConnectionLimit>,
 }
 
 impl LatencyTester {
     /// Create a new latency tester
-    pub fn new(client: ProxyClient, server_url: String) -> Self {
-        Self { client, server_url }
+    pub fn new(client: ProxyClient, backend: SharedBackend) -> Self {
+        Self {
+            client,
+            backend,
+            ping: PingRequest::default(),
+            connection_limit: None,
+        }
+    }
+
+    /// Take the background download of loaded latency tests from `limit`
+    pub fn with_connection_limit(mut self, limit: Option<ConnectionLimit>) -> Self {
+        self.connection_limit = limit;
+        self
+    }
+
+    /// Send HTTP pings with `ping` instead of GET requests
+    pub fn with_ping_request(mut self, ping: PingRequest) -> Self {
+        self.ping = ping;
+        self
     }
 
     /// Test latency with multiple iterations
     pub async fn test_latency(&self, iterations: usize) -> Result<LatencyResult> {
         let mut latencies = Vec::new();
         let mut failed_pings = 0;
+        let mut last_error = None;
 
         debug!("Starting latency test with {} iterations", iterations);
 
@@ -39,41 +223,68 @@ impl LatencyTester {
                 tokio::time::sleep(Duration::from_millis(100)).await;
             }
 
-            let start = Instant::now();
             match self.ping_server().await {
-                Ok(_) => {
-                    let latency = start.elapsed();
+                Ok(latency) => {
                     latencies.push(latency);
                     debug!("Ping {}: {}ms", i + 1, latency.as_millis());
                 }
                 Err(e) => {
                     failed_pings += 1;
                     debug!("Ping {} failed: {}", i + 1, e);
+                    last_error = Some(e);
                 }
             }
         }
 
-        Ok(self.calculate_result(latencies, failed_pings, iterations))
+        // Keep the underlying error so callers can tell transient failures apart
+        if latencies.is_empty()
+            && let Some(e) = last_error
+        {
+            return Err(e.context("All ping attempts failed"));
+        }
+
+        Ok(Self::calculate_result(latencies, failed_pings, iterations))
     }
 
-    /// Send a ping to the server (minimal data transfer)
-    async fn ping_server(&self) -> Result<()> {
-        let url = format!("{}/__down?bytes=0", self.server_url);
-        let response = self.client.get(&url).await?;
+    /// Test latency while a download saturates the connection (bufferbloat)
+    pub async fn test_latency_under_load(&self, iterations: usize) -> Result<LatencyResult> {
+        loaded_latency(
+            self.client.client(),
+            self.backend.as_ref(),
+            iterations,
+            self.connection_limit.clone(),
+        )
+        .await
+    }
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!(
-                "Server returned error: {}",
-                response.status()
-            ))
-        }
+    /// Test reachability by measuring TCP connect time to the proxy itself
+    pub async fn test_tcp_latency(
+        &self,
+        iterations: usize,
+        timeout: Duration,
+    ) -> Result<LatencyResult> {
+        let proxy = self.client.proxy_config();
+        tcp_connect_latency(
+            &self.client.dns_cache(),
+            &proxy.server,
+            proxy.port,
+            self.client.ip_version(),
+            self.client.local_address(),
+            iterations,
+            timeout,
+        )
+        .await
+    }
+
+    /// Send a ping to the server (minimal data transfer), returning its round trip
+    async fn ping_server(&self) -> Result<Duration> {
+        self.ping
+            .send(self.client.client(), self.backend.as_ref())
+            .await
     }
 
     /// Calculate latency statistics
     fn calculate_result(
-        &self,
         latencies: Vec<Duration>,
         failed_pings: usize,
         total_pings: usize,
@@ -87,6 +298,8 @@ impl LatencyTester {
                 packet_loss,
                 min_latency: Duration::ZERO,
                 max_latency: Duration::ZERO,
+                samples: Vec::new(),
+                server: None,
             };
         }
 
@@ -101,6 +314,456 @@ impl LatencyTester {
             packet_loss,
             min_latency,
             max_latency,
+            samples: latencies,
+            server: None,
+        }
+    }
+}
+
+/// Ping `iterations` times, one after another, while a background download keeps the
+/// link busy, the rise over idle latency shows how much the path buffers under load.
+/// The download takes a slot from `limit` like any other transfer.
+pub(crate) async fn loaded_latency(
+    client: &reqwest::Client,
+    backend: &dyn SpeedTestBackend,
+    iterations: usize,
+    limit: Option<ConnectionLimit>,
+) -> Result<LatencyResult> {
+    debug!("Starting loaded latency test with {} pings", iterations);
+
+    let permit = acquire_connection(&limit).await;
+    let load = tokio::spawn({
+        let client = client.clone();
+        let url = backend.download_url(LOAD_REQUEST_SIZE);
+        async move {
+            let _permit = permit;
+            let start = tokio::time::Instant::now();
+            let counter = AtomicUsize::new(0);
+            if let Err(e) = stream_until(
+                &client,
+                &url,
+                start,
+                start + LOAD_MAX_DURATION,
+                DEFAULT_STALL_TIMEOUT,
+                &counter,
+            )
+            .await
+            {
+                debug!("Background download for loaded latency failed: {}", e);
+            }
+        }
+    });
+    tokio::time::sleep(LOAD_RAMP_UP).await;
+
+    let url = backend.latency_url();
+    let ping = || async {
+        let start = Instant::now();
+        let response = client.get(&url).send().await?;
+        if response.status().is_success() {
+            Ok(start.elapsed())
+        } else {
+            Err(anyhow::anyhow!(
+                "Server returned error: {}",
+                response.status()
+            ))
+        }
+    };
+
+    // The first ping opens the connection the measured ones reuse, so they time the
+    // round trip rather than a handshake
+    if let Err(e) = ping().await {
+        debug!("Loaded warm-up ping failed: {}", e);
+    }
+    let mut outcomes = Vec::with_capacity(iterations);
+    for i in 0..iterations {
+        if i > 0 {
+            tokio::time::sleep(LOAD_PING_INTERVAL).await;
+        }
+        outcomes.push(ping().await);
+    }
+    load.abort();
+
+    let mut latencies = Vec::new();
+    let mut last_error = None;
+    for outcome in outcomes {
+        match outcome {
+            Ok(latency) => latencies.push(latency),
+            Err(e) => {
+                debug!("Loaded ping failed: {}", e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    if latencies.is_empty()
+        && let Some(e) = last_error
+    {
+        return Err(e.context("All loaded ping attempts failed"));
+    }
+
+    let failed = iterations - latencies.len();
+    Ok(LatencyTester::calculate_result(
+        latencies, failed, iterations,
+    ))
+}
+
+/// Time `iterations` TCP connects to `server:port`, with no proxy in between. Used for
+/// the hop to the proxy itself, which doesn't need a client for its protocol. Only
+/// addresses of `ip_version` are dialed, and sockets bind to `local_address` first when
+/// one is given, like the proxy client's do. The server is resolved once up front
+/// through `dns` so lookups aren't counted.
+pub(crate) async fn tcp_connect_latency(
+    dns: &DnsCache,
+    server: &str,
+    port: u16,
+    ip_version: IpVersion,
+    local_address: Option<IpAddr>,
+    iterations: usize,
+    timeout: Duration,
+) -> Result<LatencyResult> {
+    let address = format!("{server}:{port}");
+    let ips: Vec<_> = dns
+        .resolve(server)
+        .await?
+        .into_iter()
+        .filter(|ip| ip_version.matches(*ip))
+        .collect();
+    if ips.is_empty() {
+        anyhow::bail!("{} has no IPv{} address", server, ip_version);
+    }
+    let target = ips
+        .into_iter()
+        .find(|ip| local_address.is_none_or(|local| local.is_ipv4() == ip.is_ipv4()))
+        .map(|ip| SocketAddr::new(ip, port))
+        .ok_or_else(|| anyhow::anyhow!("{} has no address to connect to from here", address))?;
+    let mut latencies = Vec::new();
+    let mut failed_connects = 0;
+    let mut last_error = None;
+
+    debug!(
+        "Starting TCP latency test to {} with {} iterations",
+        address, iterations
+    );
+
+    for i in 0..iterations {
+        if i > 0 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let start = Instant::now();
+        match tokio::time::timeout(timeout, tcp_connect(target, local_address)).await {
+            Ok(Ok(_stream)) => {
+                let latency = start.elapsed();
+                latencies.push(latency);
+                debug!("TCP connect {}: {}ms", i + 1, latency.as_millis());
+            }
+            Ok(Err(e)) => {
+                failed_connects += 1;
+                debug!("TCP connect {} failed: {}", i + 1, e);
+                last_error = Some(e);
+            }
+            Err(_) => {
+                failed_connects += 1;
+                debug!("TCP connect {} timed out", i + 1);
+                last_error = Some(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "TCP connect timed out",
+                ));
+            }
+        }
+    }
+
+    if latencies.is_empty() {
+        let context = format!("All TCP connect attempts to {address} failed");
+        return Err(match last_error {
+            Some(e) => anyhow::Error::new(e).context(context),
+            None => anyhow::anyhow!(context),
+        });
+    }
+
+    Ok(LatencyTester::calculate_result(
+        latencies,
+        failed_connects,
+        iterations,
+    ))
+}
+
+/// Connect to `target`, from `local_address` if one is given
+async fn tcp_connect(
+    target: SocketAddr,
+    local_address: Option<IpAddr>,
+) -> std::io::Result<TcpStream> {
+    let Some(local) = local_address else {
+        return TcpStream::connect(target).await;
+    };
+    let socket = if target.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.bind(SocketAddr::new(local, 0))?;
+    socket.connect(target).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::CloudflareBackend;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve pings that take 200ms to answer while any download is streaming
+    async fn spawn_bufferbloat_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let downloads = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let downloads = downloads.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let Ok(n) = socket.read(&mut buf).await else {
+                        return;
+                    };
+                    let request = String::from_utf8_lossy(&buf[..n]);
+
+                    if request.contains("bytes=0 ") {
+                        if downloads.load(Ordering::SeqCst) > 0 {
+                            tokio::time::sleep(Duration::from_millis(200)).await;
+                        }
+                        let _ = socket
+                            .write_all(
+                                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            )
+                            .await;
+                        return;
+                    }
+
+                    downloads.fetch_add(1, Ordering::SeqCst);
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                    let chunk = vec![0u8; 1024];
+                    loop {
+                        if socket.write_all(b"400\r\n").await.is_err()
+                            || socket.write_all(&chunk).await.is_err()
+                            || socket.write_all(b"\r\n").await.is_err()
+                        {
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                    }
+                    downloads.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    /// Answer every request with an empty 200, or 405 for HEAD when `allow_head` is
+    /// false. Records each request line.
+    async fn spawn_ping_server(allow_head: bool) -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let Ok(n) = socket.read(&mut buf).await else {
+                        return;
+                    };
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let line = request.lines().next().unwrap_or_default().to_string();
+                    let status = if line.starts_with("HEAD") && !allow_head {
+                        "405 Method Not Allowed"
+                    } else {
+                        "200 OK"
+                    };
+                    requests.lock().unwrap().push(line);
+                    let response = format!(
+                        "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        (format!("http://{addr}"), recorded)
+    }
+
+    #[tokio::test]
+    async fn test_head_ping_falls_back_to_get() {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let (server_url, requests) = spawn_ping_server(true).await;
+        let backend = CloudflareBackend::new(&server_url);
+        let ping = PingRequest::new(LatencyMethod::Head);
+        ping.send(&client, &backend).await.unwrap();
+        assert_eq!(*requests.lock().unwrap(), vec!["HEAD / HTTP/1.1"]);
+
+        // Rejected HEAD is retried as GET, later pings (also through clones) skip HEAD
+        let (server_url, requests) = spawn_ping_server(false).await;
+        let backend = CloudflareBackend::new(&server_url);
+        let ping = PingRequest::new(LatencyMethod::Head);
+        ping.send(&client, &backend).await.unwrap();
+        ping.clone().send(&client, &backend).await.unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                "HEAD / HTTP/1.1",
+                "GET /__down?bytes=0 HTTP/1.1",
+                "GET /__down?bytes=0 HTTP/1.1",
+            ]
+        );
+
+        // The next proxy may well support HEAD, it tries again
+        requests.lock().unwrap().clear();
+        ping.for_proxy().send(&client, &backend).await.unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["HEAD / HTTP/1.1", "GET /__down?bytes=0 HTTP/1.1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_loaded_latency_includes_queueing_delay() {
+        let server_url = spawn_bufferbloat_server().await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let backend = CloudflareBackend::new(&server_url);
+
+        let limit: ConnectionLimit = Arc::new(tokio::sync::Semaphore::new(1));
+        let test = tokio::spawn({
+            let client = client.clone();
+            let limit = limit.clone();
+            async move { loaded_latency(&client, &backend, 4, Some(limit)).await }
+        });
+        // The background download holds the only connection slot while it runs
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(limit.available_permits(), 0);
+
+        let loaded = test.await.unwrap().unwrap();
+        assert_eq!(loaded.packet_loss, 0.0);
+        assert_eq!(loaded.samples.len(), 4);
+        assert!(loaded.min_latency >= Duration::from_millis(200));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(limit.available_permits(), 1);
+        let backend = CloudflareBackend::new(&server_url);
+
+        // The background download stops with the test, pings are fast again
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let start = Instant::now();
+        client.get(backend.latency_url()).send().await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_latency_binds_local_address() {
+        let dns = DnsCache::system();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let peers = tokio::spawn(async move {
+            let mut peers = Vec::new();
+            for _ in 0..2 {
+                peers.push(listener.accept().await.unwrap().1.ip());
+            }
+            peers
+        });
+
+        let local: IpAddr = "127.0.0.2".parse().unwrap();
+        let result = tcp_connect_latency(
+            &dns,
+            "127.0.0.1",
+            port,
+            IpVersion::Auto,
+            Some(local),
+            2,
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.packet_loss, 0.0);
+        assert_eq!(peers.await.unwrap(), [local, local]);
+
+        // An IPv6 bind address can't reach an IPv4-only server
+        let local: IpAddr = "::1".parse().unwrap();
+        let error = tcp_connect_latency(
+            &dns,
+            "127.0.0.1",
+            port,
+            IpVersion::Auto,
+            Some(local),
+            1,
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("no address"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_latency_resolves_through_dns_cache() {
+        /// Answers every hostname with the loopback address, counting lookups
+        struct Loopback(Arc<AtomicUsize>);
+        impl crate::network::HostResolver for Loopback {
+            fn lookup<'a>(
+                &'a self,
+                _host: &'a str,
+            ) -> futures::future::BoxFuture<'a, Result<crate::network::Lookup>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async {
+                    Ok(crate::network::Lookup {
+                        ips: vec!["127.0.0.1".parse().unwrap()],
+                        ttl: Duration::from_secs(60),
+                    })
+                })
+            }
         }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while listener.accept().await.is_ok() {} });
+
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let dns = DnsCache::new(Loopback(lookups.clone()));
+        for _ in 0..2 {
+            let result = tcp_connect_latency(
+                &dns,
+                "proxy.test",
+                port,
+                IpVersion::Auto,
+                None,
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+            assert_eq!(result.packet_loss, 0.0);
+        }
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        // --ip-version is honoured without a bind address too
+        let error = tcp_connect_latency(
+            &dns,
+            "proxy.test",
+            port,
+            IpVersion::V6,
+            None,
+            1,
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "proxy.test has no IPv6 address");
     }
 }
@@ -1,9 +1,20 @@
+pub mod backend;
 pub mod bandwidth;
 pub mod client;
+pub mod dns;
 pub mod latency;
 pub mod utils;
 
-pub use bandwidth::{BandwidthResult, BandwidthTester};
-pub use client::{NetworkTester, ProxyClient};
-pub use latency::{LatencyResult, LatencyTester};
-pub use utils::ZeroReader;
+pub use backend::{
+    BackendKind, CloudflareBackend, LibreSpeedBackend, SharedBackend, SpeedTestBackend,
+};
+pub use bandwidth::{
+    BandwidthResult, BandwidthTester, ConnectionLimit, DEFAULT_ADAPTIVE_WINDOW,
+    DEFAULT_AUTO_SIZE_TARGET, DEFAULT_STALL_TIMEOUT,
+};
+pub use client::{ClientOptions, IpVersion, NetworkTester, ProxyClient};
+pub use dns::{DnsCache, HostResolver, Lookup, NameServerResolver, SystemResolver};
+pub use latency::{
+    LatencyMethod, LatencyMode, LatencyResult, LatencyTarget, LatencyTester, PingRequest,
+};
+pub use utils::{RandomReader, UploadPayload, ZeroReader};
@@ -1,8 +1,67 @@
+use crate::utils::splitmix64;
+use futures::StreamExt;
 use std::io::{self, Read};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::task::{Context, Poll};
 use tokio::io::{AsyncRead, ReadBuf};
 
+/// Seed for random upload payloads, fixed so runs send identical bytes
+const RANDOM_PAYLOAD_SEED: u64 = 0x6d69_686f_6d6f_5254;
+
+/// Bytes sent as the upload test body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum UploadPayload {
+    /// All zeros, cheap but compressible by servers and middleboxes
+    #[default]
+    Zero,
+    /// Seeded pseudo-random bytes that don't compress
+    Random,
+}
+
+impl UploadPayload {
+    /// Request body of `size` bytes
+    pub fn body(&self, size: usize) -> reqwest::Body {
+        match self {
+            UploadPayload::Zero => ZeroReader::new(size).into(),
+            UploadPayload::Random => RandomReader::new(size, RANDOM_PAYLOAD_SEED).into(),
+        }
+    }
+
+    /// Like `body`, adding the bytes read from it to `counter` so the upload can be sampled
+    pub(crate) fn counted_body(&self, size: usize, counter: Arc<AtomicUsize>) -> reqwest::Body {
+        match self {
+            UploadPayload::Zero => counted_stream(ZeroReader::new(size), counter),
+            UploadPayload::Random => {
+                counted_stream(RandomReader::new(size, RANDOM_PAYLOAD_SEED), counter)
+            }
+        }
+    }
+}
+
+/// Stream `reader` as a request body, counting the bytes read
+fn counted_stream<R>(reader: R, counter: Arc<AtomicUsize>) -> reqwest::Body
+where
+    R: AsyncRead + Send + Sync + 'static,
+{
+    let stream = tokio_util::io::ReaderStream::new(reader).inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            counter.fetch_add(chunk.len(), Ordering::Relaxed);
+        }
+    });
+    reqwest::Body::wrap_stream(stream)
+}
+
+impl std::fmt::Display for UploadPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadPayload::Zero => write!(f, "zero"),
+            UploadPayload::Random => write!(f, "random"),
+        }
+    }
+}
+
 /// A reader that generates zero bytes for upload testing
 pub struct ZeroReader {
     remaining: usize,
@@ -73,6 +132,76 @@ impl From<ZeroReader> for reqwest::Body {
     }
 }
 
+/// A reader that generates incompressible pseudo-random bytes for upload testing.
+/// The same seed always yields the same bytes.
+pub struct RandomReader {
+    remaining: usize,
+    written: usize,
+    seed: u64,
+    word: u64,
+}
+
+impl RandomReader {
+    /// Create a new random reader with the specified size and seed
+    pub fn new(size: usize, seed: u64) -> Self {
+        Self {
+            remaining: size,
+            written: 0,
+            seed,
+            word: 0,
+        }
+    }
+
+    /// Get the number of bytes written so far
+    pub fn written_bytes(&self) -> usize {
+        self.written
+    }
+
+    /// Fill `buf` with the next bytes of the stream, one splitmix64 word per 8 bytes
+    fn fill(&mut self, buf: &mut [u8]) {
+        for byte in buf {
+            let offset = self.written % 8;
+            if offset == 0 {
+                let block = (self.written / 8) as u64;
+                self.word = splitmix64(self.seed.wrapping_add(block));
+            }
+            *byte = (self.word >> (offset * 8)) as u8;
+            self.written += 1;
+        }
+    }
+}
+
+impl Read for RandomReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let to_read = std::cmp::min(buf.len(), self.remaining);
+        self.fill(&mut buf[..to_read]);
+        self.remaining -= to_read;
+
+        Ok(to_read)
+    }
+}
+
+impl AsyncRead for RandomReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let to_read = std::cmp::min(buf.remaining(), self.remaining);
+        self.fill(&mut buf.initialize_unfilled_to(to_read)[..to_read]);
+        buf.advance(to_read);
+        self.remaining -= to_read;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl From<RandomReader> for reqwest::Body {
+    fn from(reader: RandomReader) -> Self {
+        reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(reader))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,6 +230,29 @@ mod tests {
         assert_eq!(reader.written_bytes(), 100);
     }
 
+    #[test]
+    fn test_random_reader() {
+        let mut reader = RandomReader::new(1000, 42);
+        let mut output = Vec::new();
+        let mut buffer = [0u8; 333];
+        loop {
+            let bytes_read = reader.read(&mut buffer).unwrap();
+            if bytes_read == 0 {
+                break;
+            }
+            output.extend_from_slice(&buffer[..bytes_read]);
+        }
+
+        assert_eq!(output.len(), 1000);
+        assert_eq!(reader.written_bytes(), 1000);
+        assert!(output.iter().any(|&b| b != 0));
+
+        // Reads of any size produce the same stream for the same seed
+        let mut again = vec![0u8; 1000];
+        RandomReader::new(1000, 42).read_exact(&mut again).unwrap();
+        assert_eq!(output, again);
+    }
+
     #[test]
     fn test_zero_reader_partial() {
         let mut reader = ZeroReader::new(75);
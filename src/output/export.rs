@@ -1,49 +1,624 @@
 use crate::Result;
-use crate::config::{ClashConfig, ProxyConfig};
+use crate::config::{ClashConfig, ProxyConfig, ProxyType};
 use crate::core::SpeedTestResult;
-use std::collections::HashMap;
-use std::path::Path;
+use crate::output::GeoIpDb;
+use crate::output::format_bytes_per_second;
+use crate::output::geoip::Country;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How long `export_json_history` waits for another writer's lock file
+const HISTORY_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Probe URL and interval of the generated "Auto" url-test group
+const AUTO_GROUP_URL: &str = "http://www.gstatic.com/generate_204";
+const AUTO_GROUP_INTERVAL: u64 = 300;
+
+/// Download speeds (bytes per second) splitting passing proxies into the generated
+/// "Fast" and "Medium" groups
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeedTiers {
+    pub fast: f64,
+    pub medium: f64,
+}
+
+impl Default for SpeedTiers {
+    fn default() -> Self {
+        Self {
+            fast: 20.0 * 1024.0 * 1024.0,
+            medium: 5.0 * 1024.0 * 1024.0,
+        }
+    }
+}
+
+/// Clash proxy group written by `export_clash_config_with_groups`
+#[derive(Serialize)]
+struct ProxyGroup<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    group_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval: Option<u64>,
+    proxies: Vec<String>,
+}
+
+/// Whether an export rewrote its output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    Written,
+    /// `--only-changed` found the same config already on disk
+    Unchanged,
+}
+
+/// One run recorded in a `--output-append` history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRun {
+    pub timestamp: DateTime<Utc>,
+    pub results: Vec<SpeedTestResult>,
+}
+
+/// Lock file held while a history file is rewritten, removed on drop
+struct HistoryLock {
+    path: PathBuf,
+}
+
+impl HistoryLock {
+    /// Create `<history>.lock`, waiting while another writer holds it
+    async fn acquire(history_path: &Path) -> Result<Self> {
+        let mut path = history_path.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        let deadline = tokio::time::Instant::now() + HISTORY_LOCK_TIMEOUT;
+        loop {
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if tokio::time::Instant::now() >= deadline {
+                        return Err(anyhow::anyhow!(
+                            "Timed out waiting for lock file {} (remove it if no other run is writing)",
+                            path.display()
+                        ));
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for HistoryLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Target format for exported proxy configs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Clash,
+    Surge,
+    Singbox,
+}
+
+impl ExportFormat {
+    /// Guess the format from an output path's extension, defaulting to Clash
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("json") => ExportFormat::Singbox,
+            Some("conf") => ExportFormat::Surge,
+            _ => ExportFormat::Clash,
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Clash => write!(f, "clash"),
+            ExportFormat::Surge => write!(f, "surge"),
+            ExportFormat::Singbox => write!(f, "singbox"),
+        }
+    }
+}
 
 /// Exporter for configuration files
 pub struct ConfigExporter;
 
 impl ConfigExporter {
-    /// Export successful proxies to a Clash config file
+    /// Export successful proxies to a Clash config file.
+    /// With `include_failed` the other proxies are kept too, renamed with `dead_prefix`.
     pub async fn export_clash_config<P: AsRef<Path>>(
         results: &[SpeedTestResult],
         original_proxies: &[ProxyConfig],
         output_path: P,
-    ) -> Result<()> {
-        // Filter successful results
-        let successful_names: std::collections::HashSet<_> = results
+        include_failed: bool,
+        dead_prefix: &str,
+        only_changed: bool,
+    ) -> Result<WriteOutcome> {
+        // Create Clash config structure
+        let config = ClashConfig {
+            proxies: Self::clash_proxies(
+                results,
+                original_proxies,
+                include_failed.then_some(dead_prefix),
+            ),
+            proxy_providers: None,
+            other: HashMap::new(),
+        };
+
+        Self::write_clash_config(&config, output_path.as_ref(), only_changed).await
+    }
+
+    /// Export successful proxies while keeping the original config's groups, rules and other settings.
+    /// Proxies that were filtered out are also removed from every proxy group, even
+    /// when `include_failed` keeps them in the proxy list under `dead_prefix`.
+    pub async fn export_clash_config_preserving<P: AsRef<Path>>(
+        original_config: &ClashConfig,
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        output_path: P,
+        include_failed: bool,
+        dead_prefix: &str,
+        only_changed: bool,
+    ) -> Result<WriteOutcome> {
+        let config = Self::preserve_clash_config(
+            original_config,
+            results,
+            original_proxies,
+            include_failed.then_some(dead_prefix),
+        );
+        Self::write_clash_config(&config, output_path.as_ref(), only_changed).await
+    }
+
+    /// Export passing proxies with generated groups: "Fast" and "Medium" select groups
+    /// by download speed tier, an "Auto" url-test over every passing proxy and a rule
+    /// sending all traffic to "Auto"
+    pub async fn export_clash_config_with_groups<P: AsRef<Path>>(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        output_path: P,
+        tiers: SpeedTiers,
+        only_changed: bool,
+    ) -> Result<WriteOutcome> {
+        let config = Self::clash_config_with_groups(results, original_proxies, tiers)?;
+        Self::write_clash_config(&config, output_path.as_ref(), only_changed).await
+    }
+
+    /// Write `config` as YAML. With `only_changed` an existing file holding the same
+    /// config is left untouched, so its mtime doesn't trigger downstream reloads. The
+    /// comparison is on the parsed YAML since the order of preserved keys isn't stable.
+    async fn write_clash_config(
+        config: &ClashConfig,
+        output_path: &Path,
+        only_changed: bool,
+    ) -> Result<WriteOutcome> {
+        if only_changed
+            && let Ok(existing) = tokio::fs::read_to_string(output_path).await
+            && serde_yaml::from_str::<serde_yaml::Value>(&existing).ok()
+                == Some(serde_yaml::to_value(config)?)
+        {
+            info!("⏭️ {} is unchanged, skipped writing", output_path.display());
+            return Ok(WriteOutcome::Unchanged);
+        }
+
+        tokio::fs::write(output_path, serde_yaml::to_string(config)?).await?;
+        if only_changed {
+            info!("📝 {} changed, rewrote it", output_path.display());
+        }
+        Ok(WriteOutcome::Written)
+    }
+
+    /// Build the config written by `export_clash_config_with_groups`, a tier without
+    /// proxies gets no group
+    fn clash_config_with_groups(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        tiers: SpeedTiers,
+    ) -> Result<ClashConfig> {
+        let proxies = Self::successful_proxies(results, original_proxies);
+        let speeds: HashMap<_, _> = results
+            .iter()
+            .map(|r| (r.proxy_name.as_str(), r.download_speed))
+            .collect();
+        let tier_members = |range: std::ops::Range<f64>| -> Vec<String> {
+            proxies
+                .iter()
+                .filter(|p| {
+                    speeds
+                        .get(p.name.as_str())
+                        .is_some_and(|s| range.contains(s))
+                })
+                .map(|p| p.name.clone())
+                .collect()
+        };
+
+        let mut auto_members: Vec<_> = proxies.iter().map(|p| p.name.clone()).collect();
+        if auto_members.is_empty() {
+            // An empty group is rejected by Clash
+            auto_members.push("DIRECT".to_string());
+        }
+
+        let mut groups = Vec::new();
+        for (name, members) in [
+            ("Fast", tier_members(tiers.fast..f64::INFINITY)),
+            ("Medium", tier_members(tiers.medium..tiers.fast)),
+        ] {
+            if !members.is_empty() {
+                groups.push(ProxyGroup {
+                    name,
+                    group_type: "select",
+                    url: None,
+                    interval: None,
+                    proxies: members,
+                });
+            }
+        }
+        groups.push(ProxyGroup {
+            name: "Auto",
+            group_type: "url-test",
+            url: Some(AUTO_GROUP_URL),
+            interval: Some(AUTO_GROUP_INTERVAL),
+            proxies: auto_members,
+        });
+
+        let mut other = HashMap::new();
+        other.insert("proxy-groups".to_string(), serde_yaml::to_value(&groups)?);
+        other.insert("rules".to_string(), serde_yaml::to_value(["MATCH,Auto"])?);
+
+        Ok(ClashConfig {
+            proxies,
+            proxy_providers: None,
+            other,
+        })
+    }
+
+    /// Build the preserved config written by `export_clash_config_preserving`
+    fn preserve_clash_config(
+        original_config: &ClashConfig,
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        dead_prefix: Option<&str>,
+    ) -> ClashConfig {
+        let successful = Self::successful_proxies(results, original_proxies);
+        let kept: HashSet<_> = successful.iter().map(|p| p.name.as_str()).collect();
+
+        // Only strip names of proxies we dropped, groups may also reference other groups or DIRECT
+        let removed: HashSet<_> = original_config
+            .proxies
+            .iter()
+            .chain(original_proxies)
+            .map(|p| p.name.as_str())
+            .filter(|name| !kept.contains(name))
+            .collect();
+
+        let mut other = original_config.other.clone();
+        if let Some(serde_yaml::Value::Sequence(groups)) = other.get_mut("proxy-groups") {
+            for group in groups {
+                let uses_providers = group.get("use").is_some();
+                if let Some(serde_yaml::Value::Sequence(members)) = group.get_mut("proxies") {
+                    let had_members = !members.is_empty();
+                    members.retain(|member| {
+                        member.as_str().is_none_or(|name| !removed.contains(name))
+                    });
+                    // An empty group is rejected by Clash, and dropping it would leave
+                    // dangling references in other groups and rules
+                    if had_members && members.is_empty() && !uses_providers {
+                        members.push(serde_yaml::Value::String("DIRECT".to_string()));
+                    }
+                }
+            }
+        }
+
+        ClashConfig {
+            proxies: Self::clash_proxies(results, original_proxies, dead_prefix),
+            proxy_providers: original_config.proxy_providers.clone(),
+            other,
+        }
+    }
+
+    /// Successful proxies, or with a `dead_prefix` every proxy in original order
+    /// with the prefix added to the names of those that didn't pass
+    fn clash_proxies(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        dead_prefix: Option<&str>,
+    ) -> Vec<ProxyConfig> {
+        let Some(prefix) = dead_prefix else {
+            return Self::successful_proxies(results, original_proxies);
+        };
+
+        let successful_names: HashSet<_> = results
+            .iter()
+            .filter(|r| r.is_successful())
+            .map(|r| &r.proxy_name)
+            .collect();
+
+        original_proxies
+            .iter()
+            .map(|proxy| {
+                let mut proxy = proxy.clone();
+                if !successful_names.contains(&proxy.name) {
+                    proxy.name = format!("{}{}", prefix, proxy.name);
+                }
+                proxy
+            })
+            .collect()
+    }
+
+    /// Keep only the original proxy configs whose test succeeded
+    fn successful_proxies(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+    ) -> Vec<ProxyConfig> {
+        let successful_names: HashSet<_> = results
             .iter()
             .filter(|r| r.is_successful())
             .map(|r| &r.proxy_name)
             .collect();
 
-        // Filter original proxy configs to keep only successful ones
-        let successful_proxies: Vec<_> = original_proxies
+        original_proxies
             .iter()
             .filter(|proxy| successful_names.contains(&proxy.name))
             .cloned()
+            .collect()
+    }
+
+    /// Export successful proxies as a sing-box config with one outbound per proxy
+    pub async fn export_singbox<P: AsRef<Path>>(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        output_path: P,
+    ) -> Result<()> {
+        let config = Self::format_singbox(results, original_proxies);
+        tokio::fs::write(output_path, serde_json::to_string_pretty(&config)?).await?;
+        Ok(())
+    }
+
+    /// Build the sing-box config, skipping proxy types we can't map
+    pub fn format_singbox(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+    ) -> serde_json::Value {
+        let outbounds: Vec<_> = Self::successful_proxies(results, original_proxies)
+            .iter()
+            .filter_map(|proxy| {
+                let outbound = Self::singbox_outbound(proxy);
+                if outbound.is_none() {
+                    warn!(
+                        "Skipping {} ({}): not supported by the sing-box exporter",
+                        proxy.name, proxy.proxy_type
+                    );
+                }
+                outbound
+            })
             .collect();
 
-        // Create Clash config structure
-        let config = ClashConfig {
-            proxies: successful_proxies,
-            proxy_providers: None,
-            other: HashMap::new(),
+        json!({ "outbounds": outbounds })
+    }
+
+    /// Map a proxy to a sing-box outbound
+    fn singbox_outbound(proxy: &ProxyConfig) -> Option<serde_json::Value> {
+        let params = &proxy.config;
+        // Plugins and other transports aren't mapped, the node would look valid but fail
+        if params.plugin.is_some()
+            || !matches!(
+                params.network.as_deref(),
+                None | Some("tcp" | "ws" | "grpc")
+            )
+        {
+            return None;
+        }
+        let mut outbound = json!({
+            "tag": proxy.name,
+            "server": proxy.server,
+            "server_port": proxy.port,
+        });
+
+        let fields = match proxy.proxy_type {
+            ProxyType::Shadowsocks | ProxyType::ShadowsocksShort => json!({
+                "type": "shadowsocks",
+                "method": params.cipher.as_deref()?,
+                "password": params.password.as_deref()?,
+            }),
+            ProxyType::VMess => json!({
+                "type": "vmess",
+                "uuid": params.uuid.as_deref()?,
+                "security": params
+                    .cipher
+                    .as_deref()
+                    .or(params.security.as_deref())
+                    .unwrap_or("auto"),
+                "alter_id": params.alter_id.unwrap_or(0),
+            }),
+            ProxyType::Trojan => json!({
+                "type": "trojan",
+                "password": params.password.as_deref()?,
+            }),
+            ProxyType::Socks5 | ProxyType::Socks => {
+                let mut fields = json!({ "type": "socks", "version": "5" });
+                if let (Some(username), Some(password)) = (&params.username, &params.password) {
+                    fields["username"] = json!(username);
+                    fields["password"] = json!(password);
+                }
+                fields
+            }
+            _ => return None,
         };
+        if let (Some(outbound), serde_json::Value::Object(fields)) =
+            (outbound.as_object_mut(), fields)
+        {
+            outbound.extend(fields);
+        }
 
-        // Serialize to YAML
-        let yaml_content = serde_yaml::to_string(&config)?;
+        // Trojan always runs over TLS
+        if params.tls == Some(true) || proxy.proxy_type == ProxyType::Trojan {
+            let mut tls = json!({ "enabled": true });
+            if let Some(sni) = &params.sni {
+                tls["server_name"] = json!(sni);
+            }
+            if params.skip_cert_verify == Some(true) {
+                tls["insecure"] = json!(true);
+            }
+            outbound["tls"] = tls;
+        }
+
+        match params.network.as_deref() {
+            Some("ws") => {
+                let opts = params.ws_opts.as_ref();
+                let mut transport = json!({ "type": "ws" });
+                if let Some(path) = opts.and_then(|o| o.get("path")).and_then(|v| v.as_str()) {
+                    transport["path"] = json!(path);
+                }
+                if let Some(host) = opts
+                    .and_then(|o| o.get("headers"))
+                    .and_then(|h| h.get("Host"))
+                    .and_then(|v| v.as_str())
+                {
+                    transport["headers"] = json!({ "Host": host });
+                }
+                outbound["transport"] = transport;
+            }
+            Some("grpc") => {
+                let service_name = params
+                    .grpc_opts
+                    .as_ref()
+                    .and_then(|o| o.get("grpc-service-name"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                outbound["transport"] = json!({ "type": "grpc", "service_name": service_name });
+            }
+            _ => {}
+        }
+
+        let smux_enabled = params
+            .smux
+            .as_ref()
+            .and_then(|smux| smux.get("enabled"))
+            .and_then(|v| v.as_bool());
+        if smux_enabled == Some(true) {
+            outbound["multiplex"] = json!({ "enabled": true });
+        }
 
-        // Write to file
-        tokio::fs::write(output_path, yaml_content).await?;
+        Some(outbound)
+    }
 
+    /// Export successful proxies as a Surge `[Proxy]` section
+    pub async fn export_surge<P: AsRef<Path>>(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        output_path: P,
+    ) -> Result<()> {
+        tokio::fs::write(output_path, Self::format_surge(results, original_proxies)).await?;
         Ok(())
     }
 
+    /// Render the Surge `[Proxy]` section, skipping proxy types we can't map
+    pub fn format_surge(results: &[SpeedTestResult], original_proxies: &[ProxyConfig]) -> String {
+        let mut output = String::from("[Proxy]\n");
+
+        for proxy in Self::successful_proxies(results, original_proxies) {
+            match Self::surge_line(&proxy) {
+                Some(line) => {
+                    output.push_str(&line);
+                    output.push('\n');
+                }
+                None => warn!(
+                    "Skipping {} ({}): not supported by the Surge exporter",
+                    proxy.name, proxy.proxy_type
+                ),
+            }
+        }
+
+        output
+    }
+
+    /// Map a proxy to a Surge proxy line
+    fn surge_line(proxy: &ProxyConfig) -> Option<String> {
+        let params = &proxy.config;
+        // Surge lines only carry plain and ws transports and no plugins
+        if params.plugin.is_some()
+            || !matches!(params.network.as_deref(), None | Some("tcp" | "ws"))
+        {
+            return None;
+        }
+        let mut fields = Vec::new();
+
+        let kind = match proxy.proxy_type {
+            ProxyType::Shadowsocks | ProxyType::ShadowsocksShort => {
+                fields.push(format!("encrypt-method={}", params.cipher.as_deref()?));
+                fields.push(format!("password={}", params.password.as_deref()?));
+                "ss"
+            }
+            ProxyType::VMess => {
+                fields.push(format!("username={}", params.uuid.as_deref()?));
+                "vmess"
+            }
+            ProxyType::Trojan => {
+                fields.push(format!("password={}", params.password.as_deref()?));
+                "trojan"
+            }
+            ProxyType::Socks5 | ProxyType::Socks => {
+                if let (Some(username), Some(password)) = (&params.username, &params.password) {
+                    fields.push(username.clone());
+                    fields.push(password.clone());
+                }
+                "socks5"
+            }
+            _ => return None,
+        };
+
+        if params.tls == Some(true) && proxy.proxy_type == ProxyType::VMess {
+            fields.push("tls=true".to_string());
+        }
+        if let Some(sni) = &params.sni {
+            fields.push(format!("sni={sni}"));
+        }
+        if params.skip_cert_verify == Some(true) {
+            fields.push("skip-cert-verify=true".to_string());
+        }
+        if params.network.as_deref() == Some("ws") {
+            fields.push("ws=true".to_string());
+            if let Some(path) = params
+                .ws_opts
+                .as_ref()
+                .and_then(|o| o.get("path"))
+                .and_then(|v| v.as_str())
+            {
+                fields.push(format!("ws-path={path}"));
+            }
+        }
+
+        // Surge's line format is comma and equals delimited
+        let name = proxy.name.replace([',', '='], " ");
+        let mut line = format!("{name} = {kind}, {}, {}", proxy.server, proxy.port);
+        for field in fields {
+            line.push_str(", ");
+            line.push_str(&field);
+        }
+        Some(line)
+    }
+
     /// Export results as JSON
     pub async fn export_json<P: AsRef<Path>>(
         results: &[SpeedTestResult],
@@ -54,10 +629,249 @@ impl ConfigExporter {
         Ok(())
     }
 
+    /// Append results as a timestamped run to a JSON history file holding an array of
+    /// `{ timestamp, results }` objects, creating it if missing
+    pub async fn export_json_history<P: AsRef<Path>>(
+        results: &[SpeedTestResult],
+        output_path: P,
+    ) -> Result<()> {
+        let output_path = output_path.as_ref();
+        let _lock = HistoryLock::acquire(output_path).await?;
+
+        let mut history = Self::load_json_history(output_path).await?;
+        history.push(HistoryRun {
+            timestamp: Utc::now(),
+            results: results.to_vec(),
+        });
+
+        // Write a sibling and rename it over the history, a crash mid-write leaves the
+        // previous runs intact
+        let json_content = serde_json::to_string_pretty(&history)?;
+        let mut temp_path = output_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        tokio::fs::write(&temp_path, json_content).await?;
+        if let Err(e) = tokio::fs::rename(&temp_path, output_path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Load the runs written by `export_json_history`, empty if the file doesn't exist yet
+    pub async fn load_json_history<P: AsRef<Path>>(input_path: P) -> Result<Vec<HistoryRun>> {
+        let input_path = input_path.as_ref();
+        let json_content = match tokio::fs::read_to_string(input_path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if json_content.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        serde_json::from_str(&json_content).map_err(|e| {
+            anyhow::anyhow!(
+                "{} is not a results history file: {}",
+                input_path.display(),
+                e
+            )
+        })
+    }
+
+    /// Export results as a Prometheus textfile (for node_exporter's textfile collector)
+    pub async fn export_prometheus<P: AsRef<Path>>(
+        results: &[SpeedTestResult],
+        output_path: P,
+    ) -> Result<()> {
+        tokio::fs::write(output_path, Self::format_prometheus(results)).await?;
+        Ok(())
+    }
+
+    /// Render results in the Prometheus text exposition format
+    pub fn format_prometheus(results: &[SpeedTestResult]) -> String {
+        let labels = |result: &SpeedTestResult| {
+            format!(
+                "name=\"{}\",type=\"{}\"",
+                Self::escape_label_value(&result.proxy_name),
+                Self::escape_label_value(&result.proxy_type.to_string())
+            )
+        };
+
+        type Metric = (
+            &'static str,
+            &'static str,
+            fn(&SpeedTestResult) -> Option<f64>,
+        );
+        let metrics: [Metric; 5] = [
+            ("proxy_latency_ms", "Average latency in milliseconds", |r| {
+                r.latency.map(|l| l.as_secs_f64() * 1000.0)
+            }),
+            (
+                "proxy_download_bytes_per_second",
+                "Download speed in bytes per second",
+                |r| r.is_successful().then_some(r.download_speed),
+            ),
+            (
+                "proxy_upload_bytes_per_second",
+                "Upload speed in bytes per second",
+                |r| r.is_successful().then_some(r.upload_speed),
+            ),
+            ("proxy_packet_loss", "Packet loss percentage", |r| {
+                Some(r.packet_loss)
+            }),
+            (
+                "proxy_test_success",
+                "Whether the proxy passed the speed test (1) or not (0)",
+                |r| Some(if r.is_successful() { 1.0 } else { 0.0 }),
+            ),
+        ];
+
+        let mut output = String::new();
+        for (name, help, value) in metrics {
+            output.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n"));
+            for result in results {
+                if let Some(value) = value(result) {
+                    output.push_str(&format!("{name}{{{}}} {value}\n", labels(result)));
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Escape a label value per the Prometheus text exposition format
+    fn escape_label_value(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+
+    /// Load results previously written by `export_json` or `--json`
+    pub async fn load_json<P: AsRef<Path>>(input_path: P) -> Result<Vec<SpeedTestResult>> {
+        let json_content = tokio::fs::read_to_string(input_path).await?;
+        Ok(serde_json::from_str(&json_content)?)
+    }
+
+    /// Point results at the new names of renamed proxies, so exporting `renamed_proxies`
+    /// still finds the proxies that passed. `renamed_proxies` must be the output of
+    /// `rename_proxies_with_stats`/`rename_proxies_with_geoip` for `original_proxies`.
+    pub fn rename_results(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        renamed_proxies: &[ProxyConfig],
+    ) -> Vec<SpeedTestResult> {
+        let new_names: HashMap<_, _> = original_proxies
+            .iter()
+            .zip(renamed_proxies)
+            .map(|(original, renamed)| (&original.name, &renamed.name))
+            .collect();
+
+        results
+            .iter()
+            .map(|result| {
+                let mut result = result.clone();
+                if let Some(name) = new_names.get(&result.proxy_name) {
+                    result.proxy_name = (*name).clone();
+                }
+                result
+            })
+            .collect()
+    }
+
+    /// Apply a rename to a full Clash config, so its proxy groups and rules keep
+    /// pointing at the same proxies under their new names
+    pub fn rename_config_references(
+        mut config: ClashConfig,
+        original_proxies: &[ProxyConfig],
+        renamed_proxies: &[ProxyConfig],
+    ) -> ClashConfig {
+        let new_names: HashMap<_, _> = original_proxies
+            .iter()
+            .zip(renamed_proxies)
+            .map(|(original, renamed)| (original.name.as_str(), renamed.name.as_str()))
+            .collect();
+        let rename = |name: &str| new_names.get(name).map(|name| name.to_string());
+
+        for proxy in &mut config.proxies {
+            if let Some(name) = rename(&proxy.name) {
+                proxy.name = name;
+            }
+        }
+        if let Some(serde_yaml::Value::Sequence(groups)) = config.other.get_mut("proxy-groups") {
+            for group in groups {
+                if let Some(serde_yaml::Value::Sequence(members)) = group.get_mut("proxies") {
+                    for member in members {
+                        if let Some(name) = member.as_str().and_then(rename) {
+                            *member = serde_yaml::Value::String(name);
+                        }
+                    }
+                }
+            }
+        }
+        // Rules are `TYPE,VALUE,TARGET[,OPTION]`, or `MATCH,TARGET`
+        if let Some(serde_yaml::Value::Sequence(rules)) = config.other.get_mut("rules") {
+            for rule in rules {
+                let Some(text) = rule.as_str() else {
+                    continue;
+                };
+                let mut fields: Vec<String> = text.split(',').map(str::to_string).collect();
+                let target = if fields[0].trim() == "MATCH" { 1 } else { 2 };
+                if let Some(name) = fields.get(target).and_then(|field| rename(field.trim())) {
+                    fields[target] = name;
+                    *rule = serde_yaml::Value::String(fields.join(","));
+                }
+            }
+        }
+        config
+    }
+
     /// Generate renamed proxies with speed and location info
     pub fn rename_proxies_with_stats(
         original_proxies: &[ProxyConfig],
         results: &[SpeedTestResult],
+    ) -> Vec<ProxyConfig> {
+        Self::rename_proxies(original_proxies, results, &HashMap::new())
+    }
+
+    /// Generate renamed proxies, taking each location from the GeoIP database when the server resolves
+    pub async fn rename_proxies_with_geoip(
+        original_proxies: &[ProxyConfig],
+        results: &[SpeedTestResult],
+        geoip: &GeoIpDb,
+    ) -> Vec<ProxyConfig> {
+        let mut locations = HashMap::new();
+        for proxy in original_proxies {
+            if let Some(country) = Self::lookup_server_country(geoip, &proxy.server).await {
+                locations.insert(proxy.name.clone(), country.label());
+            }
+        }
+
+        Self::rename_proxies(original_proxies, results, &locations)
+    }
+
+    /// Resolve a server (IP literal or hostname) to its country
+    async fn lookup_server_country(geoip: &GeoIpDb, server: &str) -> Option<Country> {
+        let ip = match server.trim_matches(['[', ']']).parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => match tokio::net::lookup_host((server, 0)).await {
+                Ok(mut addrs) => addrs.next()?.ip(),
+                Err(e) => {
+                    debug!("Failed to resolve {}: {}", server, e);
+                    return None;
+                }
+            },
+        };
+
+        geoip.lookup_country(ip)
+    }
+
+    /// Rename successful proxies, preferring a known location over name heuristics.
+    /// The output follows `original_proxies` one to one, whatever order `results` is in.
+    fn rename_proxies(
+        original_proxies: &[ProxyConfig],
+        results: &[SpeedTestResult],
+        locations: &HashMap<String, String>,
     ) -> Vec<ProxyConfig> {
         let results_map: HashMap<_, _> = results.iter().map(|r| (&r.proxy_name, r)).collect();
 
@@ -67,7 +881,11 @@ impl ConfigExporter {
                 if let Some(result) = results_map.get(&proxy.name) {
                     if result.is_successful() {
                         let mut renamed_proxy = proxy.clone();
-                        renamed_proxy.name = Self::generate_new_name(proxy, result);
+                        renamed_proxy.name = Self::generate_new_name(
+                            proxy,
+                            result,
+                            locations.get(&proxy.name).cloned(),
+                        );
                         renamed_proxy
                     } else {
                         proxy.clone()
@@ -80,19 +898,32 @@ impl ConfigExporter {
     }
 
     /// Generate a new proxy name with stats
-    fn generate_new_name(proxy: &ProxyConfig, result: &SpeedTestResult) -> String {
-        let speed_mbps = result.download_speed / (1024.0 * 1024.0);
+    fn generate_new_name(
+        proxy: &ProxyConfig,
+        result: &SpeedTestResult,
+        location: Option<String>,
+    ) -> String {
+        let speed = format_bytes_per_second(result.download_speed);
         let latency_ms = result.latency.map_or(0, |l| l.as_millis());
 
         // Try to extract location from original name or use server
-        let location = Self::extract_location(&proxy.name)
+        let location = location
+            .or_else(|| Self::extract_location(&proxy.name))
             .unwrap_or_else(|| Self::guess_location_from_server(&proxy.server));
 
-        format!("{location} | 📈 {speed_mbps:.1}MB/s | ⏱️ {latency_ms}ms")
+        format!("{location} | 📈 {speed} | ⏱️ {latency_ms}ms")
     }
 
     /// Extract location from proxy name
     fn extract_location(name: &str) -> Option<String> {
+        Self::known_location(name).or_else(|| {
+            // If no pattern matches, try to use the original name
+            (name.len() < 50).then(|| name.to_string())
+        })
+    }
+
+    /// Location label when the name mentions a known country or city
+    fn known_location(name: &str) -> Option<String> {
         // Simple heuristics to extract location
         let name_lower = name.to_lowercase();
 
@@ -124,37 +955,622 @@ impl ConfigExporter {
             ("tw", "🇹🇼 Taiwan"),
         ];
 
-        for (pattern, flag) in &patterns {
-            if name_lower.contains(pattern) {
-                return Some(flag.to_string());
-            }
-        }
-
-        // If no pattern matches, try to use the original name
-        if name.len() < 50 {
-            Some(name.to_string())
-        } else {
-            None
-        }
+        patterns
+            .iter()
+            .find(|(pattern, _)| name_lower.contains(pattern))
+            .map(|(_, flag)| flag.to_string())
     }
 
     /// Guess location from server hostname/IP
     fn guess_location_from_server(server: &str) -> String {
+        Self::server_location(server)
+            .unwrap_or_else(|| format!("🌐 {}", server.split('.').next().unwrap_or(server)))
+    }
+
+    /// Location implied by a server's TLD or hostname, if any
+    fn server_location(server: &str) -> Option<String> {
         // Simple TLD-based location guessing
-        if server.ends_with(".jp") || server.contains("japan") {
-            "🇯🇵 Japan".to_string()
+        let location = if server.ends_with(".jp") || server.contains("japan") {
+            "🇯🇵 Japan"
         } else if server.ends_with(".hk") || server.contains("hongkong") {
-            "🇭🇰 Hong Kong".to_string()
+            "🇭🇰 Hong Kong"
         } else if server.ends_with(".sg") || server.contains("singapore") {
-            "🇸🇬 Singapore".to_string()
+            "🇸🇬 Singapore"
         } else if server.ends_with(".us") || server.contains("usa") {
-            "🇺🇸 USA".to_string()
+            "🇺🇸 USA"
         } else if server.ends_with(".de") || server.contains("germany") {
-            "🇩🇪 Germany".to_string()
+            "🇩🇪 Germany"
         } else if server.ends_with(".uk") || server.contains("britain") {
-            "🇬🇧 UK".to_string()
+            "🇬🇧 UK"
         } else {
-            format!("🌐 {}", server.split('.').next().unwrap_or(server))
+            return None;
+        };
+        Some(location.to_string())
+    }
+
+    /// Region a proxy is grouped under by `top_per_region`: the flag of the location
+    /// detected from its name or server, or "unknown"
+    fn region(proxy: &ProxyConfig) -> String {
+        Self::known_location(&proxy.name)
+            .or_else(|| Self::server_location(&proxy.server))
+            .and_then(|location| location.split_whitespace().next().map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Keep only the `count` fastest successful proxies (by download speed) of each region.
+    /// Failed results are passed through untouched, order is preserved.
+    pub fn top_per_region(
+        results: &[SpeedTestResult],
+        original_proxies: &[ProxyConfig],
+        count: usize,
+    ) -> Vec<SpeedTestResult> {
+        let proxies: HashMap<_, _> = original_proxies.iter().map(|p| (&p.name, p)).collect();
+
+        let mut regions: HashMap<String, Vec<&SpeedTestResult>> = HashMap::new();
+        for result in results.iter().filter(|r| r.is_successful()) {
+            let region = proxies
+                .get(&result.proxy_name)
+                .map_or_else(|| "unknown".to_string(), |proxy| Self::region(proxy));
+            regions.entry(region).or_default().push(result);
+        }
+
+        let mut kept = HashSet::new();
+        for (region, mut members) in regions {
+            members.sort_by(|a, b| b.download_speed.total_cmp(&a.download_speed));
+            debug!(
+                "Region {}: keeping {} of {} proxies",
+                region,
+                members.len().min(count),
+                members.len()
+            );
+            kept.extend(members.into_iter().take(count).map(|r| &r.proxy_name));
+        }
+
+        results
+            .iter()
+            .filter(|r| !r.is_successful() || kept.contains(&r.proxy_name))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_prometheus() {
+        let success = SpeedTestResult {
+            latency: Some(Duration::from_millis(123)),
+            packet_loss: 0.0,
+            download_speed: 1048576.0,
+            upload_speed: 524288.0,
+            error: None,
+            ..SpeedTestResult::failed(
+                "HK \"Premium\" \\ 01".to_string(),
+                ProxyType::Trojan,
+                String::new(),
+            )
+        };
+        let failed =
+            SpeedTestResult::failed("JP".to_string(), ProxyType::VMess, "timeout".to_string());
+
+        let output = ConfigExporter::format_prometheus(&[success, failed]);
+        let lines: Vec<_> = output.lines().filter(|l| !l.starts_with('#')).collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                r#"proxy_latency_ms{name="HK \"Premium\" \\ 01",type="Trojan"} 123"#,
+                r#"proxy_download_bytes_per_second{name="HK \"Premium\" \\ 01",type="Trojan"} 1048576"#,
+                r#"proxy_upload_bytes_per_second{name="HK \"Premium\" \\ 01",type="Trojan"} 524288"#,
+                r#"proxy_packet_loss{name="HK \"Premium\" \\ 01",type="Trojan"} 0"#,
+                r#"proxy_packet_loss{name="JP",type="VMess"} 100"#,
+                r#"proxy_test_success{name="HK \"Premium\" \\ 01",type="Trojan"} 1"#,
+                r#"proxy_test_success{name="JP",type="VMess"} 0"#,
+            ]
+        );
+        assert!(output.contains("# TYPE proxy_latency_ms gauge"));
+    }
+
+    #[test]
+    fn test_preserve_clash_config_prunes_groups() {
+        let original: ClashConfig = serde_yaml::from_str(
+            r#"
+mixed-port: 7890
+proxies:
+  - { name: keep, type: http, server: 1.1.1.1, port: 80 }
+  - { name: drop, type: http, server: 2.2.2.2, port: 80 }
+proxy-groups:
+  - name: Auto
+    type: url-test
+    url: http://www.gstatic.com/generate_204
+    interval: 300
+    proxies: [keep, drop]
+  - name: Select
+    type: select
+    proxies: [Auto, drop, DIRECT]
+  - name: Dead
+    type: select
+    proxies: [drop]
+rules:
+  - DOMAIN-SUFFIX,example.com,Dead
+  - MATCH,Select
+"#,
+        )
+        .unwrap();
+
+        let results = [
+            SpeedTestResult {
+                latency: Some(Duration::from_millis(50)),
+                error: None,
+                ..SpeedTestResult::failed("keep".to_string(), ProxyType::Http, String::new())
+            },
+            SpeedTestResult::failed("drop".to_string(), ProxyType::Http, "timeout".to_string()),
+        ];
+
+        let config =
+            ConfigExporter::preserve_clash_config(&original, &results, &original.proxies, None);
+
+        let names: Vec<_> = config.proxies.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["keep"]);
+
+        let groups = config.other["proxy-groups"].as_sequence().unwrap();
+        let members = |index: usize| -> Vec<&str> {
+            groups[index]["proxies"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .filter_map(|v| v.as_str())
+                .collect()
+        };
+        assert_eq!(groups[0]["type"].as_str(), Some("url-test"));
+        assert_eq!(groups[0]["interval"].as_u64(), Some(300));
+        assert_eq!(members(0), ["keep"]);
+        assert_eq!(members(1), ["Auto", "DIRECT"]);
+        // A group left without members falls back to DIRECT, its rule stays valid
+        assert_eq!(members(2), ["DIRECT"]);
+        assert_eq!(config.other["rules"][1].as_str(), Some("MATCH,Select"));
+        assert_eq!(config.other["mixed-port"].as_u64(), Some(7890));
+    }
+
+    #[test]
+    fn test_rename_keeps_group_and_rule_references() {
+        let original: ClashConfig = serde_yaml::from_str(
+            r#"
+proxies:
+  - { name: HK 01, type: http, server: 1.1.1.1, port: 80 }
+proxy-groups:
+  - { name: Select, type: select, proxies: [HK 01, DIRECT] }
+rules:
+  - DOMAIN-SUFFIX,example.com,HK 01
+  - MATCH,Select
+"#,
+        )
+        .unwrap();
+        let mut renamed = original.proxies.clone();
+        renamed[0].name = "🇭🇰 Hong Kong 01".to_string();
+
+        let original_proxies = original.proxies.clone();
+        let config =
+            ConfigExporter::rename_config_references(original, &original_proxies, &renamed);
+        assert_eq!(config.proxies[0].name, "🇭🇰 Hong Kong 01");
+        let members = config.other["proxy-groups"][0]["proxies"]
+            .as_sequence()
+            .unwrap();
+        assert_eq!(members[0].as_str(), Some("🇭🇰 Hong Kong 01"));
+        assert_eq!(members[1].as_str(), Some("DIRECT"));
+        assert_eq!(
+            config.other["rules"][0].as_str(),
+            Some("DOMAIN-SUFFIX,example.com,🇭🇰 Hong Kong 01")
+        );
+        assert_eq!(config.other["rules"][1].as_str(), Some("MATCH,Select"));
+    }
+
+    #[test]
+    fn test_clash_config_with_groups() {
+        let passing = |name: &str, mbps: f64| SpeedTestResult {
+            latency: Some(Duration::from_millis(50)),
+            download_speed: mbps * 1024.0 * 1024.0,
+            error: None,
+            ..SpeedTestResult::failed(name.to_string(), ProxyType::Http, String::new())
+        };
+        let results = [
+            passing("rocket", 25.0),
+            passing("steady", 8.0),
+            passing("crawl", 1.0),
+            SpeedTestResult::failed("dead".to_string(), ProxyType::Http, "timeout".to_string()),
+        ];
+        let proxies: Vec<_> = ["rocket", "steady", "crawl", "dead"]
+            .iter()
+            .map(|name| ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Http,
+                server: "1.1.1.1".to_string(),
+                port: 80,
+                config: Default::default(),
+            })
+            .collect();
+
+        let config =
+            ConfigExporter::clash_config_with_groups(&results, &proxies, SpeedTiers::default())
+                .unwrap();
+
+        let names: Vec<_> = config.proxies.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["rocket", "steady", "crawl"]);
+
+        let groups = config.other["proxy-groups"].as_sequence().unwrap();
+        let group = |index: usize| -> (&str, &str, Vec<&str>) {
+            let members = groups[index]["proxies"]
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .filter_map(|v| v.as_str())
+                .collect();
+            (
+                groups[index]["name"].as_str().unwrap(),
+                groups[index]["type"].as_str().unwrap(),
+                members,
+            )
+        };
+        assert_eq!(group(0), ("Fast", "select", vec!["rocket"]));
+        assert_eq!(group(1), ("Medium", "select", vec!["steady"]));
+        assert_eq!(
+            group(2),
+            ("Auto", "url-test", vec!["rocket", "steady", "crawl"])
+        );
+        assert_eq!(groups[2]["interval"].as_u64(), Some(300));
+        assert_eq!(config.other["rules"][0].as_str(), Some("MATCH,Auto"));
+
+        // Raising the fast tier empties it, so no "Fast" group is written
+        let tiers = SpeedTiers {
+            fast: 30.0 * 1024.0 * 1024.0,
+            ..SpeedTiers::default()
+        };
+        let config = ConfigExporter::clash_config_with_groups(&results, &proxies, tiers).unwrap();
+        let groups = config.other["proxy-groups"].as_sequence().unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0]["name"].as_str(), Some("Medium"));
+        assert_eq!(groups[0]["proxies"].as_sequence().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_export_include_failed() {
+        let proxy = |name: &str| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port: 8080,
+            config: Default::default(),
+        };
+        let proxies = [proxy("alive"), proxy("dead"), proxy("untested")];
+        let results = [
+            SpeedTestResult {
+                latency: Some(Duration::from_millis(50)),
+                error: None,
+                ..SpeedTestResult::failed("alive".to_string(), ProxyType::Http, String::new())
+            },
+            SpeedTestResult::failed("dead".to_string(), ProxyType::Http, "timeout".to_string()),
+        ];
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.yaml");
+        let exported_names = |path: &Path| -> Vec<String> {
+            let config: ClashConfig =
+                serde_yaml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+            config.proxies.into_iter().map(|p| p.name).collect()
+        };
+
+        ConfigExporter::export_clash_config(&results, &proxies, &path, false, "[DEAD] ", false)
+            .await
+            .unwrap();
+        assert_eq!(exported_names(&path), ["alive"]);
+
+        ConfigExporter::export_clash_config(&results, &proxies, &path, true, "[DEAD] ", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            exported_names(&path),
+            ["alive", "[DEAD] dead", "[DEAD] untested"]
+        );
+
+        // An empty prefix keeps failed proxies under their own names
+        ConfigExporter::export_clash_config(&results, &proxies, &path, true, "", false)
+            .await
+            .unwrap();
+        assert_eq!(exported_names(&path), ["alive", "dead", "untested"]);
+    }
+
+    #[tokio::test]
+    async fn test_export_only_changed_skips_identical_config() {
+        let proxies = [ProxyConfig {
+            name: "alive".to_string(),
+            proxy_type: ProxyType::Http,
+            server: "127.0.0.1".to_string(),
+            port: 8080,
+            config: Default::default(),
+        }];
+        let results = [SpeedTestResult {
+            latency: Some(Duration::from_millis(50)),
+            error: None,
+            ..SpeedTestResult::failed("alive".to_string(), ProxyType::Http, String::new())
+        }];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.yaml");
+        let export =
+            || ConfigExporter::export_clash_config(&results, &proxies, &path, true, "", true);
+
+        assert_eq!(export().await.unwrap(), WriteOutcome::Written);
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(export().await.unwrap(), WriteOutcome::Unchanged);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+
+        // Different results rewrite the file
+        let outcome = ConfigExporter::export_clash_config(&[], &proxies, &path, false, "", true)
+            .await
+            .unwrap();
+        assert_eq!(outcome, WriteOutcome::Written);
+    }
+
+    #[tokio::test]
+    async fn test_rename_export_keeps_input_order() {
+        let proxies: Vec<_> = ["HK 01", "JP 02", "Singapore 03", "US 04", "DE 05"]
+            .into_iter()
+            .map(|name| ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Http,
+                server: "127.0.0.1".to_string(),
+                port: 8080,
+                config: Default::default(),
+            })
+            .collect();
+        // Results arrive fastest first, not in config order
+        let mut results: Vec<_> = proxies
+            .iter()
+            .enumerate()
+            .map(|(i, proxy)| SpeedTestResult {
+                latency: Some(Duration::from_millis(50)),
+                download_speed: (i + 1) as f64 * 1024.0 * 1024.0,
+                error: None,
+                ..SpeedTestResult::failed(proxy.name.clone(), ProxyType::Http, String::new())
+            })
+            .collect();
+        results.reverse();
+
+        let renamed = ConfigExporter::rename_proxies_with_stats(&proxies, &results);
+        let renamed_results = ConfigExporter::rename_results(&results, &proxies, &renamed);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.yaml");
+        ConfigExporter::export_clash_config(&renamed_results, &renamed, &path, false, "", false)
+            .await
+            .unwrap();
+
+        let config: ClashConfig =
+            serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let exported: Vec<_> = config.proxies.into_iter().map(|p| p.name).collect();
+        let expected: Vec<_> = renamed.iter().map(|p| p.name.clone()).collect();
+        assert_eq!(exported, expected);
+        assert_eq!(exported.len(), 5);
+        for (name, prefix) in exported.iter().zip(["🇭🇰", "🇯🇵", "🇸🇬", "🇺🇸", "🇩🇪"])
+        {
+            assert!(name.starts_with(prefix), "{name}");
         }
     }
+
+    #[test]
+    fn test_top_per_region() {
+        let proxy = |name: &str, server: &str| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Http,
+            server: server.to_string(),
+            port: 8080,
+            config: Default::default(),
+        };
+        let result = |name: &str, mbps: f64| SpeedTestResult {
+            latency: Some(Duration::from_millis(50)),
+            download_speed: mbps * 1024.0 * 1024.0,
+            error: None,
+            ..SpeedTestResult::failed(name.to_string(), ProxyType::Http, String::new())
+        };
+
+        let proxies = [
+            proxy("HK 01", "1.1.1.1"),
+            proxy("HK 02", "1.1.1.2"),
+            proxy("Hong Kong 03", "1.1.1.3"),
+            proxy("Tokyo 01", "2.2.2.1"),
+            proxy("alpha", "a.example.jp"),
+            proxy("omega", "3.3.3.1"),
+            proxy("HK dead", "1.1.1.4"),
+        ];
+        let results = [
+            result("HK 01", 10.0),
+            result("HK 02", 30.0),
+            result("Hong Kong 03", 20.0),
+            result("Tokyo 01", 5.0),
+            result("alpha", 8.0),
+            result("omega", 1.0),
+            SpeedTestResult::failed("HK dead".to_string(), ProxyType::Http, "timeout".into()),
+        ];
+
+        let kept: Vec<_> = ConfigExporter::top_per_region(&results, &proxies, 1)
+            .into_iter()
+            .map(|r| r.proxy_name)
+            .collect();
+        // Tokyo and the .jp server share the Japan flag, omega has no detectable region
+        assert_eq!(kept, ["HK 02", "alpha", "omega", "HK dead"]);
+
+        let kept = ConfigExporter::top_per_region(&results, &proxies, 2);
+        assert_eq!(kept.len(), 6);
+        assert!(!kept.iter().any(|r| r.proxy_name == "HK 01"));
+    }
+
+    #[tokio::test]
+    async fn test_export_json_history_appends_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+
+        let first = [SpeedTestResult {
+            latency: Some(Duration::from_millis(50)),
+            error: None,
+            ..SpeedTestResult::failed("hk".to_string(), ProxyType::Http, String::new())
+        }];
+        let second = [
+            SpeedTestResult::failed("hk".to_string(), ProxyType::Http, "timeout".to_string()),
+            SpeedTestResult::failed("jp".to_string(), ProxyType::Http, "timeout".to_string()),
+        ];
+
+        ConfigExporter::export_json_history(&first, &path)
+            .await
+            .unwrap();
+        ConfigExporter::export_json_history(&second, &path)
+            .await
+            .unwrap();
+
+        let history = ConfigExporter::load_json_history(&path).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].results.len(), 1);
+        assert_eq!(
+            history[0].results[0].latency,
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(history[1].results.len(), 2);
+        assert_eq!(history[1].results[1].proxy_name, "jp");
+        assert!(history[0].timestamp <= history[1].timestamp);
+
+        // The lock file is released after each write, no temporary file is left behind
+        assert!(!dir.path().join("history.json.lock").exists());
+        assert!(!dir.path().join("history.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_format_singbox_shadowsocks() {
+        let proxies = vec![
+            ProxyConfig {
+                name: "SS Node".to_string(),
+                proxy_type: ProxyType::Shadowsocks,
+                server: "1.2.3.4".to_string(),
+                port: 8388,
+                config: crate::config::ProxyParameters {
+                    cipher: Some("aes-256-gcm".to_string()),
+                    password: Some("secret".to_string()),
+                    ..Default::default()
+                },
+            },
+            ProxyConfig {
+                name: "WG Node".to_string(),
+                proxy_type: ProxyType::WireGuard,
+                server: "5.6.7.8".to_string(),
+                port: 51820,
+                config: Default::default(),
+            },
+        ];
+        let results: Vec<_> = proxies
+            .iter()
+            .map(|proxy| SpeedTestResult {
+                latency: Some(Duration::from_millis(80)),
+                error: None,
+                ..SpeedTestResult::failed(
+                    proxy.name.clone(),
+                    proxy.proxy_type.clone(),
+                    String::new(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            ConfigExporter::format_singbox(&results, &proxies),
+            json!({
+                "outbounds": [{
+                    "type": "shadowsocks",
+                    "tag": "SS Node",
+                    "server": "1.2.3.4",
+                    "server_port": 8388,
+                    "method": "aes-256-gcm",
+                    "password": "secret",
+                }]
+            })
+        );
+        assert_eq!(
+            ConfigExporter::format_surge(&results, &proxies),
+            "[Proxy]\nSS Node = ss, 1.2.3.4, 8388, encrypt-method=aes-256-gcm, password=secret\n"
+        );
+    }
+
+    #[test]
+    fn test_singbox_and_surge_skip_unmapped_features() {
+        let proxy = |name: &str, config: crate::config::ProxyParameters| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Shadowsocks,
+            server: "1.2.3.4".to_string(),
+            port: 8388,
+            config: crate::config::ProxyParameters {
+                cipher: Some("aes-256-gcm".to_string()),
+                password: Some("secret".to_string()),
+                ..config
+            },
+        };
+        let proxies = vec![
+            proxy(
+                "obfs",
+                crate::config::ProxyParameters {
+                    plugin: Some("obfs".to_string()),
+                    ..Default::default()
+                },
+            ),
+            proxy(
+                "h2",
+                crate::config::ProxyParameters {
+                    network: Some("h2".to_string()),
+                    ..Default::default()
+                },
+            ),
+            proxy(
+                "grpc",
+                crate::config::ProxyParameters {
+                    network: Some("grpc".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let results: Vec<_> = proxies
+            .iter()
+            .map(|proxy| SpeedTestResult {
+                latency: Some(Duration::from_millis(80)),
+                error: None,
+                ..SpeedTestResult::failed(
+                    proxy.name.clone(),
+                    proxy.proxy_type.clone(),
+                    String::new(),
+                )
+            })
+            .collect();
+
+        // Plugins and h2 are skipped by both, only sing-box carries grpc
+        let singbox = ConfigExporter::format_singbox(&results, &proxies);
+        let tags: Vec<_> = singbox["outbounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["tag"].as_str().unwrap())
+            .collect();
+        assert_eq!(tags, ["grpc"]);
+        assert_eq!(
+            ConfigExporter::format_surge(&results, &proxies),
+            "[Proxy]\n"
+        );
+    }
+
+    #[test]
+    fn test_export_format_from_path() {
+        assert_eq!(ExportFormat::from_path("out.json"), ExportFormat::Singbox);
+        assert_eq!(ExportFormat::from_path("out.conf"), ExportFormat::Surge);
+        assert_eq!(ExportFormat::from_path("out.yaml"), ExportFormat::Clash);
+    }
 }
@@ -1,28 +1,145 @@
-use crate::core::SpeedTestResult;
+use crate::core::{AggregatedResult, SpeedTestResult, StatisticalAnalysis};
+use crate::output::format_bytes_per_second;
+use chrono::{DateTime, Local, TimeZone, Utc};
 use comfy_table::{Cell, Color, ContentArrangement, Table, presets::UTF8_FULL};
 use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Output format for speed test results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+    Markdown,
+    JsonLines,
+    Yaml,
+    /// One aligned line per proxy, for scanning CI logs
+    Compact,
+}
+
+impl OutputFormat {
+    /// Whether the format is meant for machines rather than humans
+    pub fn is_machine_readable(&self) -> bool {
+        !matches!(self, OutputFormat::Table | OutputFormat::Compact)
+    }
+
+    /// Whether the summary and statistics follow the results. Markdown is pasted for
+    /// people to read, so it keeps them like the table does.
+    pub fn shows_summary(&self) -> bool {
+        !self.is_machine_readable() || *self == OutputFormat::Markdown
+    }
+}
+
+/// Time zone the human-readable output shows timestamps in, JSON stays RFC3339 UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DisplayTimezone {
+    #[default]
+    Local,
+    Utc,
+}
+
+impl std::fmt::Display for DisplayTimezone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisplayTimezone::Local => write!(f, "local"),
+            DisplayTimezone::Utc => write!(f, "utc"),
+        }
+    }
+}
+
+/// strftime format of displayed timestamps unless `--timestamp-format` is given
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+/// Characters of a failed proxy's error shown in compact output
+const COMPACT_ERROR_WIDTH: usize = 60;
+
+/// Sparkline glyphs from the lowest to the highest sample
+const SPARK_GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
 /// Formatter for speed test results
 pub struct ResultFormatter {
-    json_output: bool,
+    format: OutputFormat,
     use_colors: bool,
+    show_latency_range: bool,
+    show_stability: bool,
+    show_ttfb: bool,
+    show_loaded_latency: bool,
+    show_proxy_latency: bool,
+    timezone: DisplayTimezone,
+    timestamp_format: String,
 }
 
 impl ResultFormatter {
     /// Create a new result formatter
-    pub fn new(json_output: bool, use_colors: bool) -> Self {
+    pub fn new(format: OutputFormat, use_colors: bool) -> Self {
         Self {
-            json_output,
+            format,
             use_colors,
+            show_latency_range: false,
+            show_stability: false,
+            show_ttfb: false,
+            show_loaded_latency: false,
+            show_proxy_latency: false,
+            timezone: DisplayTimezone::Local,
+            timestamp_format: DEFAULT_TIMESTAMP_FORMAT.to_string(),
         }
     }
 
+    /// Show timestamps in `timezone` using the strftime `format`
+    pub fn with_timestamps(mut self, timezone: DisplayTimezone, format: &str) -> Self {
+        self.timezone = timezone;
+        self.timestamp_format = format.to_string();
+        self
+    }
+
+    /// Add a column with latency under load and its increase over idle
+    pub fn with_loaded_latency(mut self, show: bool) -> Self {
+        self.show_loaded_latency = show;
+        self
+    }
+
+    /// Add a Proxy RTT column with the connect time to the proxy itself
+    pub fn with_proxy_latency(mut self, show: bool) -> Self {
+        self.show_proxy_latency = show;
+        self
+    }
+
+    /// Add a Min/Max latency column to the table
+    pub fn with_latency_range(mut self, show: bool) -> Self {
+        self.show_latency_range = show;
+        self
+    }
+
+    /// Add a download Stability column to the table
+    pub fn with_stability(mut self, show: bool) -> Self {
+        self.show_stability = show;
+        self
+    }
+
+    /// Add a TTFB column with the time to the first downloaded byte
+    pub fn with_ttfb(mut self, show: bool) -> Self {
+        self.show_ttfb = show;
+        self
+    }
+
     /// Format results for display
     pub fn format_results(&self, results: &[SpeedTestResult]) -> String {
-        if self.json_output {
-            self.format_json(results)
-        } else {
-            self.format_table(results)
+        match self.format {
+            OutputFormat::Table => self.format_table(results),
+            OutputFormat::Json => self.format_json(results),
+            OutputFormat::Csv => self.format_csv(results),
+            OutputFormat::Markdown => self.format_markdown(results),
+            OutputFormat::Yaml => self.format_yaml(results),
+            OutputFormat::Compact => self.format_compact(results),
+            OutputFormat::JsonLines => results
+                .iter()
+                .filter_map(|result| serde_json::to_string(result).ok())
+                .collect::<Vec<_>>()
+                .join("\n"),
         }
     }
 
@@ -32,22 +149,244 @@ impl ResultFormatter {
             .unwrap_or_else(|_| "Error formatting JSON".to_string())
     }
 
+    /// Format results as a YAML list
+    pub fn format_yaml(&self, results: &[SpeedTestResult]) -> String {
+        serde_yaml::to_string(results).unwrap_or_else(|_| "Error formatting YAML".to_string())
+    }
+
+    /// Write a single result as one line of JSON and flush it right away
+    pub fn write_json_line<W: Write>(writer: &mut W, result: &SpeedTestResult) -> io::Result<()> {
+        serde_json::to_writer(&mut *writer, result)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+
+    /// Format results as CSV with raw numeric values
+    pub fn format_csv(&self, results: &[SpeedTestResult]) -> String {
+        let mut lines = vec![
+            "proxy_name,type,latency_ms,jitter_ms,packet_loss,download_mbps,upload_mbps,status,error,timestamp"
+                .to_string(),
+        ];
+
+        for result in results {
+            let status = if result.is_successful() {
+                "success"
+            } else {
+                "failed"
+            };
+
+            let fields = [
+                Self::escape_csv(&result.proxy_name),
+                Self::escape_csv(&result.proxy_type.to_string()),
+                result
+                    .latency
+                    .map_or(String::new(), |l| l.as_millis().to_string()),
+                result
+                    .jitter
+                    .map_or(String::new(), |j| j.as_millis().to_string()),
+                format!("{:.1}", result.packet_loss),
+                format!("{:.2}", result.download_speed / (1024.0 * 1024.0)),
+                format!("{:.2}", result.upload_speed / (1024.0 * 1024.0)),
+                status.to_string(),
+                Self::escape_csv(result.error.as_deref().unwrap_or_default()),
+                result.timestamp.to_rfc3339(),
+            ];
+            lines.push(fields.join(","));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Quote a CSV field if it contains separators, quotes or newlines
+    fn escape_csv(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Format results as a GitHub-flavored markdown table
+    pub fn format_markdown(&self, results: &[SpeedTestResult]) -> String {
+        let mut lines = vec![
+            "| Proxy Name | Type | Latency | Jitter | Loss % | Download | Upload | Status |"
+                .to_string(),
+            "| --- | --- | --- | --- | --- | --- | --- | --- |".to_string(),
+        ];
+
+        let speed = |speed: f64| {
+            if speed > 0.0 {
+                format_bytes_per_second(speed)
+            } else {
+                "—".to_string()
+            }
+        };
+
+        for result in results {
+            let fields = [
+                Self::escape_markdown(&result.proxy_name),
+                Self::escape_markdown(&result.proxy_type.to_string()),
+                result
+                    .latency
+                    .map_or("—".to_string(), |l| format!("{}ms", l.as_millis())),
+                result
+                    .jitter
+                    .map_or("—".to_string(), |j| format!("{}ms", j.as_millis())),
+                format!("{:.1}", result.packet_loss),
+                speed(result.download_speed),
+                speed(result.upload_speed),
+                if result.is_successful() {
+                    "Success"
+                } else {
+                    "Failed"
+                }
+                .to_string(),
+            ];
+            lines.push(format!("| {} |", fields.join(" | ")));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Escape pipes and flatten newlines so a value stays inside its markdown cell
+    fn escape_markdown(field: &str) -> String {
+        field.replace('|', "\\|").replace(['\r', '\n'], " ")
+    }
+
+    /// Format results one per line, e.g. `✓ name  123ms  45.20 MB/s↓  12.10 MB/s↑`,
+    /// padding every column so they line up
+    pub fn format_compact(&self, results: &[SpeedTestResult]) -> String {
+        let latencies: Vec<String> = results
+            .iter()
+            .map(|r| {
+                r.latency
+                    .map_or("—".to_string(), |l| format!("{}ms", l.as_millis()))
+            })
+            .collect();
+        let downloads: Vec<String> = results
+            .iter()
+            .map(|r| format!("{}↓", format_bytes_per_second(r.download_speed)))
+            .collect();
+
+        let width = |cells: Vec<&str>| cells.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+        let name_width = width(results.iter().map(|r| r.proxy_name.as_str()).collect());
+        let latency_width = width(latencies.iter().map(String::as_str).collect());
+        let download_width = width(downloads.iter().map(String::as_str).collect());
+
+        results
+            .iter()
+            .zip(latencies.iter().zip(&downloads))
+            .map(|(result, (latency, download))| {
+                if result.is_successful() {
+                    format!(
+                        "✓ {:<name_width$}  {:>latency_width$}  {:>download_width$}  {}↑",
+                        result.proxy_name,
+                        latency,
+                        download,
+                        format_bytes_per_second(result.upload_speed),
+                    )
+                } else {
+                    let error = result
+                        .error
+                        .as_deref()
+                        .unwrap_or("failed")
+                        .replace(['\r', '\n'], " ");
+                    format!(
+                        "✗ {:<name_width$}  {}",
+                        result.proxy_name,
+                        Self::truncate(&error, COMPACT_ERROR_WIDTH)
+                    )
+                }
+                .trim_end()
+                .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// One line per proxy with a sparkline of its individual pings and their range
+    pub fn format_latency_samples(&self, results: &[SpeedTestResult]) -> String {
+        let with_samples: Vec<_> = results
+            .iter()
+            .filter(|r| !r.latency_samples.is_empty())
+            .collect();
+        let name_width = with_samples
+            .iter()
+            .map(|r| r.proxy_name.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        with_samples
+            .iter()
+            .map(|result| {
+                let samples = &result.latency_samples;
+                let min = samples.iter().min().unwrap().as_millis();
+                let max = samples.iter().max().unwrap().as_millis();
+                format!(
+                    "{:<name_width$}  {}  {}–{}ms",
+                    result.proxy_name,
+                    Self::sparkline(samples),
+                    min,
+                    max
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// One glyph per sample, scaled between the smallest and largest sample
+    pub fn sparkline(samples: &[Duration]) -> String {
+        let (Some(min), Some(max)) = (samples.iter().min(), samples.iter().max()) else {
+            return String::new();
+        };
+        let range = (*max - *min).as_secs_f64();
+        let top = (SPARK_GLYPHS.len() - 1) as f64;
+
+        samples
+            .iter()
+            .map(|sample| {
+                if range == 0.0 {
+                    return SPARK_GLYPHS[0];
+                }
+                let level = (*sample - *min).as_secs_f64() / range * top;
+                SPARK_GLYPHS[level.round() as usize]
+            })
+            .collect()
+    }
+
+    /// Cut `text` to at most `max` characters, marking the cut with an ellipsis
+    fn truncate(text: &str, max: usize) -> String {
+        if text.chars().count() <= max {
+            text.to_string()
+        } else {
+            let kept: String = text.chars().take(max.saturating_sub(1)).collect();
+            format!("{kept}…")
+        }
+    }
+
     /// Format results as a table
     fn format_table(&self, results: &[SpeedTestResult]) -> String {
-        let mut table = Table::new();
-        table
-            .load_preset(UTF8_FULL)
-            .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header(vec![
-                "Proxy Name",
-                "Type",
-                "Latency",
-                "Jitter",
-                "Loss %",
-                "Download",
-                "Upload",
-                "Status",
-            ]);
+        let mut header = vec!["Proxy Name", "Type", "Latency", "Jitter"];
+        if self.show_latency_range {
+            header.push("Min/Max");
+        }
+        if self.show_loaded_latency {
+            header.push("Loaded");
+        }
+        if self.show_proxy_latency {
+            header.push("Proxy RTT");
+        }
+        header.extend(["Loss %", "Download"]);
+        if self.show_ttfb {
+            header.push("TTFB");
+        }
+        if self.show_stability {
+            header.push("Stability");
+        }
+        header.extend(["Upload", "Status"]);
+
+        let mut table = self.new_table();
+        table.set_header(header);
 
         for result in results {
             let latency_cell = self.format_latency_cell(result);
@@ -57,21 +396,293 @@ impl ResultFormatter {
             let upload_cell = self.format_speed_cell(result.upload_speed, 5.0 * 1024.0 * 1024.0);
             let status_cell = self.format_status_cell(result);
 
-            table.add_row(vec![
+            let mut row = vec![
                 Cell::new(&result.proxy_name),
                 Cell::new(result.proxy_type.to_string()),
                 latency_cell,
                 jitter_cell,
+            ];
+            if self.show_latency_range {
+                row.push(Self::format_latency_range_cell(result));
+            }
+            if self.show_loaded_latency {
+                row.push(Cell::new(result.format_loaded_latency()));
+            }
+            if self.show_proxy_latency {
+                row.push(Cell::new(result.format_proxy_latency()));
+            }
+            row.extend([
                 Cell::new(format!("{:.1}", result.packet_loss)),
                 download_cell,
-                upload_cell,
-                status_cell,
+            ]);
+            if self.show_ttfb {
+                row.push(Cell::new(result.format_ttfb()));
+            }
+            if self.show_stability {
+                row.push(Cell::new(result.format_download_stability()));
+            }
+            row.extend([upload_cell, status_cell]);
+            table.add_row(row);
+        }
+
+        table.to_string()
+    }
+
+    /// Format per-proxy statistics across repeated runs. JSON formats include every raw run.
+    pub fn format_aggregated(&self, aggregated: &[AggregatedResult]) -> String {
+        match self.format {
+            OutputFormat::Json => serde_json::to_string_pretty(aggregated)
+                .unwrap_or_else(|_| "Error formatting JSON".to_string()),
+            OutputFormat::JsonLines => aggregated
+                .iter()
+                .filter_map(|result| serde_json::to_string(result).ok())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            OutputFormat::Yaml => serde_yaml::to_string(aggregated)
+                .unwrap_or_else(|_| "Error formatting YAML".to_string()),
+            OutputFormat::Csv => self.format_aggregated_csv(aggregated),
+            OutputFormat::Markdown => self.format_aggregated_markdown(aggregated),
+            _ => self.format_aggregated_table(aggregated),
+        }
+    }
+
+    /// Per-proxy statistics as CSV with raw numeric values, a proxy that never
+    /// succeeded has empty latency fields
+    fn format_aggregated_csv(&self, aggregated: &[AggregatedResult]) -> String {
+        let mut lines = vec![
+            "proxy_name,type,runs,success_rate,latency_mean_ms,latency_std_dev_ms,download_mean_mbps,download_std_dev_mbps"
+                .to_string(),
+        ];
+
+        let optional = |value: Option<f64>| value.map_or(String::new(), |v| format!("{v:.1}"));
+        for result in aggregated {
+            let fields = [
+                Self::escape_csv(&result.proxy_name),
+                Self::escape_csv(&result.proxy_type.to_string()),
+                result.runs.to_string(),
+                format!("{:.2}", result.success_rate),
+                optional(result.latency_mean_ms),
+                optional(result.latency_std_dev_ms),
+                format!("{:.2}", result.download_mean / (1024.0 * 1024.0)),
+                format!("{:.2}", result.download_std_dev / (1024.0 * 1024.0)),
+            ];
+            lines.push(fields.join(","));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Per-proxy statistics as a GitHub-flavored markdown table
+    fn format_aggregated_markdown(&self, aggregated: &[AggregatedResult]) -> String {
+        let mut lines = vec![
+            "| Proxy Name | Runs | Success | Latency (mean ± sd) | Download (mean ± sd) |"
+                .to_string(),
+            "| --- | --- | --- | --- | --- |".to_string(),
+        ];
+
+        for result in aggregated {
+            let latency = match (result.latency_mean_ms, result.latency_std_dev_ms) {
+                (Some(mean), Some(std_dev)) => format!("{mean:.0} ± {std_dev:.0}ms"),
+                _ => "—".to_string(),
+            };
+            let fields = [
+                Self::escape_markdown(&result.proxy_name),
+                result.runs.to_string(),
+                format!("{:.0}%", result.success_rate * 100.0),
+                latency,
+                format!(
+                    "{} ± {}",
+                    format_bytes_per_second(result.download_mean),
+                    format_bytes_per_second(result.download_std_dev)
+                ),
+            ];
+            lines.push(format!("| {} |", fields.join(" | ")));
+        }
+
+        lines.join("\n")
+    }
+
+    fn format_aggregated_table(&self, aggregated: &[AggregatedResult]) -> String {
+        let mut table = self.new_table();
+        table.set_header(vec![
+            "Proxy Name",
+            "Runs",
+            "Success",
+            "Latency (mean ± sd)",
+            "Download (mean ± sd)",
+        ]);
+
+        for result in aggregated {
+            let latency = match (result.latency_mean_ms, result.latency_std_dev_ms) {
+                (Some(mean), Some(std_dev)) => format!("{mean:.0} ± {std_dev:.0}ms"),
+                _ => "Failed".to_string(),
+            };
+            let download = format!(
+                "{:.2} ± {:.2} MB/s",
+                result.download_mean / (1024.0 * 1024.0),
+                result.download_std_dev / (1024.0 * 1024.0)
+            );
+            let success_color = if result.success_rate >= 1.0 {
+                Color::Green
+            } else if result.success_rate > 0.0 {
+                Color::Yellow
+            } else {
+                Color::Red
+            };
+
+            table.add_row(vec![
+                Cell::new(&result.proxy_name),
+                Cell::new(result.runs),
+                self.colorize(
+                    Cell::new(format!("{:.0}%", result.success_rate * 100.0)),
+                    success_color,
+                ),
+                Cell::new(latency),
+                Cell::new(download),
+            ]);
+        }
+
+        table.to_string()
+    }
+
+    /// Format the best proxies picked by score, highest first
+    pub fn format_best(&self, best: &[(&SpeedTestResult, f64)]) -> String {
+        let mut table = self.new_table();
+        table.set_header(vec![
+            "Rank",
+            "Proxy Name",
+            "Score",
+            "Latency",
+            "Download",
+            "Upload",
+        ]);
+
+        for (rank, (result, score)) in best.iter().enumerate() {
+            table.add_row(vec![
+                Cell::new(rank + 1),
+                Cell::new(&result.proxy_name),
+                Cell::new(format!("{score:.1}")),
+                Cell::new(result.format_latency()),
+                Cell::new(result.format_download_speed()),
+                Cell::new(result.format_upload_speed()),
+            ]);
+        }
+
+        table.to_string()
+    }
+
+    /// Format a comparison of the current results against a baseline run, matching proxies by name
+    pub fn format_diff(&self, current: &[SpeedTestResult], baseline: &[SpeedTestResult]) -> String {
+        let baseline_map: HashMap<_, _> = baseline.iter().map(|r| (&r.proxy_name, r)).collect();
+        let current_names: HashSet<_> = current.iter().map(|r| &r.proxy_name).collect();
+
+        let mut table = self.new_table();
+        table.set_header(vec![
+            "Proxy Name",
+            "Latency",
+            "Δ Latency",
+            "Download",
+            "Δ Download",
+            "Change",
+        ]);
+
+        for result in current {
+            let (latency_delta, download_delta, change) = match baseline_map.get(&result.proxy_name)
+            {
+                Some(previous) => (
+                    self.format_latency_delta(result, previous),
+                    self.format_download_delta(result, previous),
+                    Cell::new("-"),
+                ),
+                None => (
+                    Cell::new("-"),
+                    Cell::new("-"),
+                    self.colorize(Cell::new("new"), Color::Cyan),
+                ),
+            };
+
+            table.add_row(vec![
+                Cell::new(&result.proxy_name),
+                Cell::new(result.format_latency()),
+                latency_delta,
+                Cell::new(result.format_download_speed()),
+                download_delta,
+                change,
+            ]);
+        }
+
+        for previous in baseline {
+            if current_names.contains(&previous.proxy_name) {
+                continue;
+            }
+
+            table.add_row(vec![
+                Cell::new(&previous.proxy_name),
+                Cell::new("-"),
+                Cell::new("-"),
+                Cell::new("-"),
+                Cell::new("-"),
+                self.colorize(Cell::new("gone"), Color::DarkGrey),
             ]);
         }
 
         table.to_string()
     }
 
+    /// Format the latency change, higher latency is a regression
+    fn format_latency_delta(&self, current: &SpeedTestResult, previous: &SpeedTestResult) -> Cell {
+        match (current.latency, previous.latency) {
+            (Some(now), Some(before)) => {
+                let delta = now.as_millis() as i128 - before.as_millis() as i128;
+                let cell = Cell::new(format!("{delta:+}ms"));
+                match delta {
+                    d if d > 0 => self.colorize(cell, Color::Red),
+                    d if d < 0 => self.colorize(cell, Color::Green),
+                    _ => cell,
+                }
+            }
+            _ => Cell::new("-"),
+        }
+    }
+
+    /// Format the download speed change, lower speed is a regression
+    fn format_download_delta(&self, current: &SpeedTestResult, previous: &SpeedTestResult) -> Cell {
+        if current.download_speed <= 0.0 || previous.download_speed <= 0.0 {
+            return Cell::new("-");
+        }
+
+        let delta = (current.download_speed - previous.download_speed) / (1024.0 * 1024.0);
+        let cell = Cell::new(format!("{delta:+.1} MB/s"));
+        if delta < -0.05 {
+            self.colorize(cell, Color::Red)
+        } else if delta > 0.05 {
+            self.colorize(cell, Color::Green)
+        } else {
+            cell
+        }
+    }
+
+    /// Empty table with the shared preset; colors were already resolved against the terminal
+    fn new_table(&self) -> Table {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic);
+        if self.use_colors {
+            table.enforce_styling();
+        }
+        table
+    }
+
+    /// Apply a color to a cell when colors are enabled
+    fn colorize(&self, cell: Cell, color: Color) -> Cell {
+        if self.use_colors {
+            cell.fg(color)
+        } else {
+            cell
+        }
+    }
+
     /// Format latency cell with color coding
     fn format_latency_cell(&self, result: &SpeedTestResult) -> Cell {
         match result.latency {
@@ -129,6 +740,16 @@ impl ResultFormatter {
         }
     }
 
+    /// Format min/max latency cell
+    fn format_latency_range_cell(result: &SpeedTestResult) -> Cell {
+        match (result.min_latency, result.max_latency) {
+            (Some(min), Some(max)) => {
+                Cell::new(format!("{}/{}ms", min.as_millis(), max.as_millis()))
+            }
+            _ => Cell::new("-"),
+        }
+    }
+
     /// Format speed cell with color coding
     fn format_speed_cell(&self, speed: f64, good_threshold: f64) -> Cell {
         if speed <= 0.0 {
@@ -140,19 +761,14 @@ impl ResultFormatter {
             };
         }
 
-        let speed_mbps = speed / (1024.0 * 1024.0);
-        let text = format!("{speed_mbps:.2} MB/s");
-
+        let cell = Cell::new(format_bytes_per_second(speed));
         if !self.use_colors {
-            return Cell::new(text);
+            return cell;
         }
 
-        let cell = Cell::new(text);
-        let good_threshold_mbps = good_threshold / (1024.0 * 1024.0);
-
-        if speed_mbps >= good_threshold_mbps {
+        if speed >= good_threshold {
             cell.fg(Color::Green)
-        } else if speed_mbps >= good_threshold_mbps * 0.5 {
+        } else if speed >= good_threshold * 0.5 {
             cell.fg(Color::Yellow)
         } else {
             cell.fg(Color::Red)
@@ -175,8 +791,32 @@ impl ResultFormatter {
         }
     }
 
-    /// Format a summary of the results
-    pub fn format_summary(&self, results: &[SpeedTestResult]) -> String {
+    /// Render a result timestamp in the configured time zone and format
+    pub fn format_timestamp(&self, timestamp: &DateTime<Utc>) -> String {
+        match self.timezone {
+            DisplayTimezone::Local => {
+                Self::format_timestamp_in(timestamp, &Local, &self.timestamp_format)
+            }
+            DisplayTimezone::Utc => {
+                Self::format_timestamp_in(timestamp, &Utc, &self.timestamp_format)
+            }
+        }
+    }
+
+    /// Render `timestamp` in an arbitrary time zone with a strftime `format`
+    pub fn format_timestamp_in<Tz: TimeZone>(
+        timestamp: &DateTime<Utc>,
+        timezone: &Tz,
+        format: &str,
+    ) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        timestamp.with_timezone(timezone).format(format).to_string()
+    }
+
+    /// Format a summary of the results, followed by the run's transfer totals
+    pub fn format_summary(&self, results: &[SpeedTestResult], totals: &RunTotals) -> String {
         let total = results.len();
         let successful = results.iter().filter(|r| r.is_successful()).count();
         let failed = total - successful;
@@ -194,13 +834,531 @@ impl ResultFormatter {
 
         let avg_download_speed = if successful > 0 {
             let total_speed: f64 = results.iter().map(|r| r.download_speed).sum();
-            (total_speed / successful as f64) / (1024.0 * 1024.0)
+            total_speed / successful as f64
         } else {
             0.0
         };
 
-        format!(
-            "\n📊 Summary:\n  Total: {total} | ✅ Success: {successful} | ❌ Failed: {failed}\n  📈 Avg Latency: {avg_latency}ms | 📊 Avg Download: {avg_download_speed:.2} MB/s"
-        )
+        let mut summary = format!(
+            "\n📊 Summary:\n  Total: {total} | ✅ Success: {successful} | ❌ Failed: {failed}\n  📈 Avg Latency: {avg_latency}ms | 📊 Avg Download: {}",
+            format_bytes_per_second(avg_download_speed)
+        );
+
+        // The mean is skewed by a few slow proxies, median and p95 show the typical and tail
+        let mut latencies: Vec<Duration> = results
+            .iter()
+            .filter(|r| r.is_successful())
+            .filter_map(|r| r.latency)
+            .collect();
+        if let (Some(median), Some(p95)) = (
+            StatisticalAnalysis::median_duration(&mut latencies),
+            StatisticalAnalysis::percentile_duration(&mut latencies, 95.0),
+        ) {
+            summary.push_str(&format!(
+                "\n  🕒 Latency median: {}ms | p95: {}ms",
+                median.as_millis(),
+                p95.as_millis()
+            ));
+        }
+
+        // Percentiles over the instantaneous throughput of every successful proxy
+        let successful = || results.iter().filter(|r| r.is_successful());
+        let download: Vec<f64> = successful()
+            .flat_map(|r| r.download_speed_samples.iter().copied())
+            .collect();
+        let upload: Vec<f64> = successful()
+            .flat_map(|r| r.upload_speed_samples.iter().copied())
+            .collect();
+        for (label, mut samples) in [("📶 Download", download), ("📤 Upload", upload)] {
+            if let (Some(p50), Some(p95)) = (
+                StatisticalAnalysis::percentile_f64(&mut samples, 50.0),
+                StatisticalAnalysis::percentile_f64(&mut samples, 95.0),
+            ) {
+                summary.push_str(&format!(
+                    "\n  {label} p50: {} | p95: {}",
+                    format_bytes_per_second(p50),
+                    format_bytes_per_second(p95)
+                ));
+            }
+        }
+
+        let first = results.iter().map(|r| r.timestamp).min();
+        let last = results.iter().map(|r| r.timestamp).max();
+        if let (Some(first), Some(last)) = (first, last) {
+            let (first, last) = (self.format_timestamp(&first), self.format_timestamp(&last));
+            if first == last {
+                summary.push_str(&format!("\n  🕐 Tested: {first}"));
+            } else {
+                summary.push_str(&format!("\n  🕐 Tested: {first} → {last}"));
+            }
+        }
+
+        summary.push_str(&format!(
+            "\n  💾 Transferred: {:.2} MB | ⏱️ Elapsed: {}",
+            totals.bytes as f64 / (1024.0 * 1024.0),
+            humantime::format_duration(Duration::from_secs(totals.elapsed.as_secs()))
+        ));
+
+        summary
+    }
+
+    /// Format count, average latency and average download of the successful results of
+    /// each proxy type, most common type first. Empty when nothing succeeded.
+    pub fn format_summary_by_type(&self, results: &[SpeedTestResult]) -> String {
+        // Display merges the `ss`/`socks` aliases into one protocol
+        let mut groups: HashMap<String, Vec<&SpeedTestResult>> = HashMap::new();
+        for result in results.iter().filter(|r| r.is_successful()) {
+            groups
+                .entry(result.proxy_type.to_string())
+                .or_default()
+                .push(result);
+        }
+        if groups.is_empty() {
+            return String::new();
+        }
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|(a_type, a), (b_type, b)| b.len().cmp(&a.len()).then(a_type.cmp(b_type)));
+
+        let mut summary = String::from("\n🧩 By type:");
+        for (proxy_type, group) in groups {
+            let latencies: Vec<u128> = group
+                .iter()
+                .filter_map(|r| r.latency)
+                .map(|l| l.as_millis())
+                .collect();
+            let avg_latency = if latencies.is_empty() {
+                "N/A".to_string()
+            } else {
+                format!(
+                    "{}ms",
+                    latencies.iter().sum::<u128>() / latencies.len() as u128
+                )
+            };
+            let avg_download =
+                group.iter().map(|r| r.download_speed).sum::<f64>() / group.len() as f64;
+
+            summary.push_str(&format!(
+                "\n  {proxy_type}: {} | 📈 Avg Latency: {avg_latency} | 📊 Avg Download: {}",
+                group.len(),
+                format_bytes_per_second(avg_download)
+            ));
+        }
+
+        summary
+    }
+}
+
+/// Data moved and wall time of a whole test run
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunTotals {
+    /// Download plus upload bytes across every tested proxy
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl RunTotals {
+    /// Sum the bytes transferred by `results`
+    pub fn new<'a>(
+        results: impl IntoIterator<Item = &'a SpeedTestResult>,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            bytes: results
+                .into_iter()
+                .map(|r| r.download_bytes + r.upload_bytes)
+                .sum(),
+            elapsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+    use crate::core::aggregate_runs;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_format_csv() {
+        let result = SpeedTestResult {
+            proxy_name: "HK, \"Premium\"".to_string(),
+            proxy_type: ProxyType::Shadowsocks,
+            latency: Some(Duration::from_millis(120)),
+            jitter: Some(Duration::from_millis(8)),
+            min_latency: Some(Duration::from_millis(100)),
+            max_latency: Some(Duration::from_millis(140)),
+            latency_idle: None,
+            latency_loaded: None,
+            proxy_latency: None,
+            latency_samples: Vec::new(),
+            packet_loss: 0.0,
+            download_speed: 10.0 * 1024.0 * 1024.0,
+            upload_speed: 2.5 * 1024.0 * 1024.0,
+            download_time: Some(Duration::from_secs(5)),
+            ttfb: None,
+            upload_time: Some(Duration::from_secs(8)),
+            download_speed_samples: vec![9.0 * 1024.0 * 1024.0, 11.0 * 1024.0 * 1024.0],
+            upload_speed_samples: Vec::new(),
+            download_bytes: 50 * 1024 * 1024,
+            upload_bytes: 20 * 1024 * 1024,
+            download_concurrency: None,
+            auto_download_size: None,
+            download_stability: None,
+            server_used: None,
+            timings: Default::default(),
+            error: None,
+            error_kind: None,
+            timestamp: Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap(),
+        };
+
+        let formatter = ResultFormatter::new(OutputFormat::Csv, false);
+        let csv = formatter.format_results(&[result]);
+        let mut lines = csv.lines();
+
+        assert_eq!(
+            lines.next(),
+            Some(
+                "proxy_name,type,latency_ms,jitter_ms,packet_loss,download_mbps,upload_mbps,status,error,timestamp"
+            )
+        );
+        assert_eq!(
+            lines.next(),
+            Some(
+                "\"HK, \"\"Premium\"\"\",Shadowsocks,120,8,0.0,10.00,2.50,success,,2025-01-02T03:04:05+00:00"
+            )
+        );
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_format_compact() {
+        let success = SpeedTestResult {
+            latency: Some(Duration::from_millis(123)),
+            download_speed: 45.2 * 1024.0 * 1024.0,
+            upload_speed: 12.1 * 1024.0 * 1024.0,
+            error: None,
+            ..SpeedTestResult::failed("HK-01".to_string(), ProxyType::VMess, String::new())
+        };
+        let failure = SpeedTestResult::failed(
+            "Japan-Premium".to_string(),
+            ProxyType::Trojan,
+            format!("connection refused\n{}", "x".repeat(100)),
+        );
+
+        // Speeds scale their unit like the table does
+        let slow = SpeedTestResult {
+            latency: Some(Duration::from_millis(80)),
+            download_speed: 800.0 * 1024.0,
+            upload_speed: 512.0,
+            error: None,
+            ..SpeedTestResult::failed("SG".to_string(), ProxyType::Trojan, String::new())
+        };
+
+        let formatter = ResultFormatter::new(OutputFormat::Compact, false);
+        let output = formatter.format_results(&[success, failure, slow]);
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(lines[0], "✓ HK-01          123ms  45.20 MB/s↓  12.10 MB/s↑");
+        let error = format!("connection refused {}…", "x".repeat(40));
+        assert_eq!(lines[1], format!("✗ Japan-Premium  {error}"));
+        assert_eq!(lines[2], "✓ SG              80ms  800.0 KB/s↓  512 B/s↑");
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_latency_sparkline() {
+        let samples: Vec<_> = [10, 20, 30, 40, 50]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+        let sparkline = ResultFormatter::sparkline(&samples);
+        assert_eq!(sparkline.chars().count(), samples.len());
+        assert_eq!(sparkline, "▁▃▅▆█");
+
+        // Flat samples sit on the baseline, order is kept
+        let flat = vec![Duration::from_millis(30); 3];
+        assert_eq!(ResultFormatter::sparkline(&flat), "▁▁▁");
+        let spike = [30, 90, 30].map(Duration::from_millis);
+        assert_eq!(ResultFormatter::sparkline(&spike), "▁█▁");
+        assert_eq!(ResultFormatter::sparkline(&[]), "");
+
+        let result = SpeedTestResult {
+            latency_samples: samples,
+            ..SpeedTestResult::failed("HK".to_string(), ProxyType::Http, String::new())
+        };
+        let formatter = ResultFormatter::new(OutputFormat::Table, false);
+        assert_eq!(
+            formatter.format_latency_samples(&[result]),
+            "HK  ▁▃▅▆█  10–50ms"
+        );
+    }
+
+    #[test]
+    fn test_format_diff() {
+        let measured = |name: &str, latency_ms: u64, download_mbps: f64| SpeedTestResult {
+            latency: Some(Duration::from_millis(latency_ms)),
+            download_speed: download_mbps * 1024.0 * 1024.0,
+            error: None,
+            ..SpeedTestResult::failed(name.to_string(), ProxyType::Http, String::new())
+        };
+
+        let baseline = vec![measured("kept", 100, 10.0), measured("removed", 50, 5.0)];
+        let current = vec![measured("kept", 112, 6.6), measured("added", 80, 8.0)];
+
+        let diff =
+            ResultFormatter::new(OutputFormat::Table, false).format_diff(&current, &baseline);
+        assert!(diff.contains("+12ms"));
+        assert!(diff.contains("-3.4 MB/s"));
+
+        let row = |name: &str| diff.lines().find(|l| l.contains(name)).unwrap().to_string();
+        assert!(row("added").contains("new"));
+        assert!(row("removed").contains("gone"));
+    }
+
+    #[test]
+    fn test_summary_transfer_totals() {
+        let mut first = SpeedTestResult::failed("a".to_string(), ProxyType::Http, String::new());
+        first.error = None;
+        first.latency = Some(Duration::from_millis(100));
+        first.download_bytes = 3 * 1024 * 1024;
+        first.upload_bytes = 1024 * 1024;
+        // Failed proxies can still have burned data before the error
+        let mut second = SpeedTestResult::failed("b".to_string(), ProxyType::Http, "x".into());
+        second.download_bytes = 512 * 1024;
+        let results = [first, second];
+
+        let totals = RunTotals::new(&results, Duration::from_millis(65_400));
+        assert_eq!(totals.bytes, 4 * 1024 * 1024 + 512 * 1024);
+
+        let summary =
+            ResultFormatter::new(OutputFormat::Table, false).format_summary(&results, &totals);
+        assert!(summary.ends_with("💾 Transferred: 4.50 MB | ⏱️ Elapsed: 1m 5s"));
+    }
+
+    #[test]
+    fn test_summary_by_type() {
+        let measured = |name: &str, proxy_type: ProxyType, latency_ms: u64, download_mbps: f64| {
+            SpeedTestResult {
+                latency: Some(Duration::from_millis(latency_ms)),
+                download_speed: download_mbps * 1024.0 * 1024.0,
+                error: None,
+                ..SpeedTestResult::failed(name.to_string(), proxy_type, String::new())
+            }
+        };
+        let results = vec![
+            measured("vmess-1", ProxyType::VMess, 100, 10.0),
+            measured("trojan-1", ProxyType::Trojan, 300, 4.0),
+            measured("vmess-2", ProxyType::VMess, 200, 20.0),
+            measured("ss-1", ProxyType::Shadowsocks, 50, 8.0),
+            measured("ss-2", ProxyType::ShadowsocksShort, 150, 2.0),
+            // Failures don't drag the averages down
+            SpeedTestResult::failed("vmess-3".to_string(), ProxyType::VMess, "x".into()),
+        ];
+
+        let summary =
+            ResultFormatter::new(OutputFormat::Table, false).format_summary_by_type(&results);
+        let lines: Vec<_> = summary.lines().skip(1).collect();
+        assert_eq!(lines[0], "🧩 By type:");
+        assert_eq!(
+            lines[1..],
+            [
+                "  Shadowsocks: 2 | 📈 Avg Latency: 100ms | 📊 Avg Download: 5.00 MB/s",
+                "  VMess: 2 | 📈 Avg Latency: 150ms | 📊 Avg Download: 15.00 MB/s",
+                "  Trojan: 1 | 📈 Avg Latency: 300ms | 📊 Avg Download: 4.00 MB/s",
+            ]
+        );
+
+        let failed = [SpeedTestResult::failed(
+            "a".to_string(),
+            ProxyType::Http,
+            "x".into(),
+        )];
+        assert_eq!(
+            ResultFormatter::new(OutputFormat::Table, false).format_summary_by_type(&failed),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp_in_zone() {
+        let instant = Utc.with_ymd_and_hms(2025, 1, 2, 23, 4, 5).unwrap();
+
+        let formatter = ResultFormatter::new(OutputFormat::Table, false)
+            .with_timestamps(DisplayTimezone::Utc, DEFAULT_TIMESTAMP_FORMAT);
+        assert_eq!(
+            formatter.format_timestamp(&instant),
+            "2025-01-02 23:04:05 +00:00"
+        );
+
+        // East of UTC the same instant is already the next day
+        let tokyo = chrono::FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(
+            ResultFormatter::format_timestamp_in(&instant, &tokyo, DEFAULT_TIMESTAMP_FORMAT),
+            "2025-01-03 08:04:05 +09:00"
+        );
+        assert_eq!(
+            ResultFormatter::format_timestamp_in(&instant, &tokyo, "%d/%m %H:%M"),
+            "03/01 08:04"
+        );
+
+        let mut result = SpeedTestResult::failed("a".to_string(), ProxyType::Http, String::new());
+        result.timestamp = instant;
+        let summary = formatter.format_summary(&[result], &RunTotals::default());
+        assert!(summary.contains("🕐 Tested: 2025-01-02 23:04:05 +00:00\n"));
+    }
+
+    #[test]
+    fn test_summary_latency_median_and_p95() {
+        let results: Vec<_> = [100, 110, 120, 130, 1000]
+            .into_iter()
+            .map(|ms| SpeedTestResult {
+                latency: Some(Duration::from_millis(ms)),
+                error: None,
+                ..SpeedTestResult::failed(format!("{ms}"), ProxyType::Http, String::new())
+            })
+            .collect();
+
+        let summary = ResultFormatter::new(OutputFormat::Table, false)
+            .format_summary(&results, &RunTotals::default());
+        // One slow outlier drags the mean far above the median
+        assert!(summary.contains("Avg Latency: 292ms"));
+        assert!(summary.contains("Latency median: 120ms | p95: 1000ms"));
+    }
+
+    #[test]
+    fn test_summary_throughput_percentiles() {
+        let mb = 1024.0 * 1024.0;
+        let result = SpeedTestResult {
+            latency: Some(Duration::from_millis(100)),
+            download_speed_samples: vec![1.0 * mb, 2.0 * mb, 10.0 * mb],
+            upload_speed_samples: vec![0.5 * mb, 1.0 * mb, 4.0 * mb],
+            error: None,
+            ..SpeedTestResult::failed("node".to_string(), ProxyType::Http, String::new())
+        };
+
+        let summary = ResultFormatter::new(OutputFormat::Table, false)
+            .format_summary(&[result], &RunTotals::default());
+        assert!(
+            summary.contains("Download p50: 2.00 MB/s | p95: 10.00 MB/s"),
+            "{summary}"
+        );
+        assert!(
+            summary.contains("Upload p50: 1.00 MB/s | p95: 4.00 MB/s"),
+            "{summary}"
+        );
+    }
+
+    #[test]
+    fn test_samples_in_json_only() {
+        let result = SpeedTestResult {
+            download_speed_samples: vec![1234.5, 2345.5],
+            ..SpeedTestResult::failed("node".to_string(), ProxyType::Http, "x".to_string())
+        };
+
+        let json = ResultFormatter::new(OutputFormat::Json, false)
+            .format_results(std::slice::from_ref(&result));
+        assert!(json.contains("download_speed_samples"));
+        assert!(json.contains("1234.5"));
+
+        let table = ResultFormatter::new(OutputFormat::Table, false).format_results(&[result]);
+        assert!(!table.contains("1234.5"));
+    }
+
+    #[test]
+    fn test_latency_serialized_as_millis() {
+        let result = SpeedTestResult {
+            latency: Some(Duration::from_millis(123)),
+            error: None,
+            ..SpeedTestResult::failed("node".to_string(), ProxyType::Http, String::new())
+        };
+        let results = [result];
+
+        let yaml = ResultFormatter::new(OutputFormat::Yaml, false).format_results(&results);
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed[0]["latency"].as_u64(), Some(123));
+        assert!(parsed[0]["jitter"].is_null());
+
+        let json = ResultFormatter::new(OutputFormat::Json, false).format_results(&results);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0]["latency"].as_u64(), Some(123));
+    }
+
+    #[test]
+    fn test_no_ansi_escapes_without_colors() {
+        let mut result = SpeedTestResult::failed("a".to_string(), ProxyType::Http, String::new());
+        result.error = None;
+        result.latency = Some(Duration::from_millis(100));
+        let results = [
+            result,
+            SpeedTestResult::failed("b".to_string(), ProxyType::Http, "x".into()),
+        ];
+
+        let plain = ResultFormatter::new(OutputFormat::Table, false).format_results(&results);
+        assert!(!plain.contains('\x1b'));
+
+        let colored = ResultFormatter::new(OutputFormat::Table, true).format_results(&results);
+        assert!(colored.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_format_markdown() {
+        let failed = SpeedTestResult::failed(
+            "JP | Tokyo".to_string(),
+            ProxyType::VMess,
+            "timeout".to_string(),
+        );
+
+        let formatter = ResultFormatter::new(OutputFormat::Markdown, false);
+        let markdown = formatter.format_results(&[failed]);
+        let lines: Vec<_> = markdown.lines().collect();
+
+        let columns = lines[0].matches(" | ").count() + 1;
+        assert_eq!(columns, 8);
+        assert_eq!(lines[1].matches("---").count(), columns);
+        assert_eq!(
+            lines[2],
+            "| JP \\| Tokyo | VMess | — | — | 100.0 | — | — | Failed |"
+        );
+
+        // Markdown is read by people and keeps the summary, JSON lines don't
+        assert!(OutputFormat::Markdown.shows_summary());
+        assert!(OutputFormat::Table.shows_summary());
+        assert!(!OutputFormat::JsonLines.shows_summary());
+        assert!(!OutputFormat::Json.shows_summary());
+    }
+
+    #[test]
+    fn test_format_aggregated_csv_and_markdown() {
+        let passed = SpeedTestResult {
+            latency: Some(Duration::from_millis(100)),
+            download_speed: 4.0 * 1024.0 * 1024.0,
+            error: None,
+            ..SpeedTestResult::failed("HK 01".to_string(), ProxyType::Trojan, String::new())
+        };
+        let failed = SpeedTestResult::failed(
+            "JP | 02".to_string(),
+            ProxyType::VMess,
+            "timeout".to_string(),
+        );
+        let aggregated = aggregate_runs(vec![
+            vec![passed.clone(), failed.clone()],
+            vec![passed, failed],
+        ]);
+
+        let csv = ResultFormatter::new(OutputFormat::Csv, false).format_aggregated(&aggregated);
+        let lines: Vec<_> = csv.lines().collect();
+        assert!(lines[0].starts_with("proxy_name,type,runs,success_rate"));
+        assert_eq!(lines[1], "HK 01,Trojan,2,1.00,100.0,0.0,4.00,0.00");
+        assert_eq!(lines[2], "JP | 02,VMess,2,0.00,,,0.00,0.00");
+
+        let markdown =
+            ResultFormatter::new(OutputFormat::Markdown, false).format_aggregated(&aggregated);
+        let lines: Vec<_> = markdown.lines().collect();
+        assert_eq!(lines[1], "| --- | --- | --- | --- | --- |");
+        assert!(lines[2].starts_with("| HK 01 | 2 | 100% | 100 ± 0ms |"));
+        assert!(lines[3].starts_with("| JP \\| 02 | 2 | 0% | — |"));
+        assert!(!markdown.contains('┌'));
     }
 }
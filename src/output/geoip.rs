@@ -0,0 +1,450 @@
+use crate::Result;
+use serde_json::{Map, Value};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Marker that precedes the metadata section of a MaxMind DB file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+/// Size of the zeroed separator between the search tree and the data section
+const DATA_SECTION_SEPARATOR: usize = 16;
+
+/// Deepest chain of nested maps, arrays and pointers decoded before the data is
+/// treated as corrupt, so a pointer cycle can't recurse forever
+const MAX_DECODE_DEPTH: usize = 32;
+
+/// Country information resolved from a GeoIP database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Country {
+    pub iso_code: String,
+    pub name: Option<String>,
+}
+
+impl Country {
+    /// Label used for renamed nodes, e.g. "🇯🇵 Japan"
+    pub fn label(&self) -> String {
+        let flag: String = self
+            .iso_code
+            .to_ascii_uppercase()
+            .chars()
+            .filter(char::is_ascii_uppercase)
+            .filter_map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+            .collect();
+
+        format!("{flag} {}", self.name.as_deref().unwrap_or(&self.iso_code))
+    }
+}
+
+/// Minimal reader for MaxMind DB (.mmdb) country databases such as GeoLite2-Country
+pub struct GeoIpDb {
+    data: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u64,
+    tree_size: usize,
+}
+
+impl GeoIpDb {
+    /// Open a database file
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = tokio::fs::read(path).await.map_err(|e| {
+            anyhow::anyhow!("Failed to read GeoIP database {}: {}", path.display(), e)
+        })?;
+        Self::from_bytes(data)
+    }
+
+    /// Parse a database from its raw bytes
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let metadata_start = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .map(|pos| pos + METADATA_MARKER.len())
+            .ok_or_else(|| anyhow::anyhow!("Not a MaxMind DB: metadata marker not found"))?;
+
+        let (metadata, _) = Decoder::new(&data, metadata_start).decode(metadata_start)?;
+        let field = |name: &str| {
+            metadata
+                .get(name)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| anyhow::anyhow!("GeoIP metadata is missing {}", name))
+        };
+
+        let node_count = field("node_count")? as usize;
+        let record_size = field("record_size")? as usize;
+        let ip_version = field("ip_version")?;
+        if !matches!(record_size, 24 | 28 | 32) {
+            return Err(anyhow::anyhow!("Unsupported record size {}", record_size));
+        }
+
+        let tree_size = node_count
+            .checked_mul(record_size * 2 / 8)
+            .ok_or_else(|| anyhow::anyhow!("GeoIP node count {} is too large", node_count))?;
+        if tree_size.saturating_add(DATA_SECTION_SEPARATOR) > metadata_start {
+            return Err(anyhow::anyhow!("GeoIP search tree exceeds file size"));
+        }
+
+        Ok(Self {
+            data,
+            node_count,
+            record_size,
+            ip_version,
+            tree_size,
+        })
+    }
+
+    /// Look up the country for an IP address
+    pub fn lookup_country(&self, ip: IpAddr) -> Option<Country> {
+        let record = self.lookup(ip)?;
+        let country = record
+            .get("country")
+            .or_else(|| record.get("registered_country"))?;
+
+        Some(Country {
+            iso_code: country.get("iso_code")?.as_str()?.to_string(),
+            name: country
+                .get("names")
+                .and_then(|names| names.get("en"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// Look up the raw data record for an IP address
+    pub fn lookup(&self, ip: IpAddr) -> Option<Value> {
+        let (address, width, mut node) = match ip {
+            IpAddr::V4(v4) if self.ip_version == 6 => {
+                // IPv4 addresses live under ::/96 in IPv6 databases
+                let mut node = 0;
+                for _ in 0..96 {
+                    if node >= self.node_count {
+                        break;
+                    }
+                    node = self.read_record(node, 0)?;
+                }
+                (u32::from(v4) as u128, 32, node)
+            }
+            IpAddr::V4(v4) => (u32::from(v4) as u128, 32, 0),
+            IpAddr::V6(v6) if self.ip_version == 6 => (u128::from(v6), 128, 0),
+            IpAddr::V6(_) => return None,
+        };
+
+        // Walk the tree most significant bit first
+        for shift in (0..width).rev() {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.read_record(node, ((address >> shift) & 1) as u8)?;
+        }
+
+        if node <= self.node_count {
+            // Equal to node_count means "no data"
+            return None;
+        }
+
+        // Records pointing into the separator are corrupt
+        let data_start = self.tree_size + DATA_SECTION_SEPARATOR;
+        let offset = data_start + (node - self.node_count).checked_sub(DATA_SECTION_SEPARATOR)?;
+        let decoder = Decoder::new(&self.data, data_start);
+        decoder.decode(offset).ok().map(|(value, _)| value)
+    }
+
+    /// Read the left (0) or right (1) record of a search tree node
+    fn read_record(&self, node: usize, bit: u8) -> Option<usize> {
+        let node_bytes = self.record_size * 2 / 8;
+        let start = node.checked_mul(node_bytes)?;
+        let bytes = self.data.get(start..start.checked_add(node_bytes)?)?;
+        let be = |b: &[u8]| {
+            b.iter()
+                .fold(0usize, |acc, &byte| (acc << 8) | byte as usize)
+        };
+
+        Some(match (self.record_size, bit) {
+            (24, 0) => be(&bytes[0..3]),
+            (24, _) => be(&bytes[3..6]),
+            (28, 0) => ((bytes[3] as usize & 0xF0) << 20) | be(&bytes[0..3]),
+            (28, _) => ((bytes[3] as usize & 0x0F) << 24) | be(&bytes[4..7]),
+            (_, 0) => be(&bytes[0..4]),
+            (_, _) => be(&bytes[4..8]),
+        })
+    }
+}
+
+/// Decoder for the MaxMind DB data section format
+struct Decoder<'a> {
+    data: &'a [u8],
+    /// Offset that pointers are relative to
+    base: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8], base: usize) -> Self {
+        Self { data, base }
+    }
+
+    fn byte(&self, offset: usize) -> Result<u8> {
+        self.data
+            .get(offset)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("GeoIP data truncated at offset {}", offset))
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| anyhow::anyhow!("GeoIP data truncated at offset {}", offset))
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Result<u128> {
+        if len > 16 {
+            return Err(anyhow::anyhow!(
+                "GeoIP integer of {} bytes at offset {}",
+                len,
+                offset
+            ));
+        }
+        Ok(self
+            .bytes(offset, len)?
+            .iter()
+            .fold(0u128, |acc, &byte| (acc << 8) | byte as u128))
+    }
+
+    /// Decode the value at `offset`, returning it with the offset just past it
+    fn decode(&self, offset: usize) -> Result<(Value, usize)> {
+        self.decode_nested(offset, 0)
+    }
+
+    /// Decode the value at `offset`, `depth` levels inside maps, arrays and pointers
+    fn decode_nested(&self, offset: usize, depth: usize) -> Result<(Value, usize)> {
+        if depth > MAX_DECODE_DEPTH {
+            return Err(anyhow::anyhow!(
+                "GeoIP data nested deeper than {} levels at offset {}",
+                MAX_DECODE_DEPTH,
+                offset
+            ));
+        }
+
+        let control = self.byte(offset)?;
+        let mut offset = offset + 1;
+
+        let mut kind = control >> 5;
+        if kind == 1 {
+            return self.decode_pointer(control, offset, depth);
+        }
+        if kind == 0 {
+            kind = self
+                .byte(offset)?
+                .checked_add(7)
+                .ok_or_else(|| anyhow::anyhow!("Invalid GeoIP data type at offset {}", offset))?;
+            offset += 1;
+        }
+
+        let (size, offset) = match control & 0x1F {
+            size @ 0..=28 => (size as usize, offset),
+            29 => (29 + self.byte(offset)? as usize, offset + 1),
+            30 => (285 + self.uint(offset, 2)? as usize, offset + 2),
+            _ => (65821 + self.uint(offset, 3)? as usize, offset + 3),
+        };
+
+        match kind {
+            // UTF-8 string
+            2 => {
+                let text = String::from_utf8_lossy(self.bytes(offset, size)?).to_string();
+                Ok((Value::String(text), offset + size))
+            }
+            // Double
+            3 => {
+                let bits = self.uint(offset, 8)? as u64;
+                Ok((Value::from(f64::from_bits(bits)), offset + 8))
+            }
+            // Bytes
+            4 => Ok((
+                Value::from(self.bytes(offset, size)?.to_vec()),
+                offset + size,
+            )),
+            // Unsigned integers
+            5 | 6 | 9 | 10 => {
+                let value = self.uint(offset, size)?;
+                let value = u64::try_from(value)
+                    .map(Value::from)
+                    .unwrap_or_else(|_| Value::String(value.to_string()));
+                Ok((value, offset + size))
+            }
+            // Map
+            7 => {
+                let mut map = Map::new();
+                let mut offset = offset;
+                for _ in 0..size {
+                    let (key, next) = self.decode_nested(offset, depth + 1)?;
+                    let (value, next) = self.decode_nested(next, depth + 1)?;
+                    let key = key
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("GeoIP map key is not a string"))?;
+                    map.insert(key.to_string(), value);
+                    offset = next;
+                }
+                Ok((Value::Object(map), offset))
+            }
+            // Signed 32-bit integer
+            8 => {
+                let value = self.uint(offset, size)? as u32 as i32;
+                Ok((Value::from(value), offset + size))
+            }
+            // Array
+            11 => {
+                // Every item takes at least a byte, don't trust a corrupt size
+                let mut items = Vec::with_capacity(size.min(self.data.len()));
+                let mut offset = offset;
+                for _ in 0..size {
+                    let (value, next) = self.decode_nested(offset, depth + 1)?;
+                    items.push(value);
+                    offset = next;
+                }
+                Ok((Value::Array(items), offset))
+            }
+            // Boolean, the size carries the value
+            14 => Ok((Value::Bool(size != 0), offset)),
+            // Float
+            15 => {
+                let bits = self.uint(offset, 4)? as u32;
+                Ok((Value::from(f32::from_bits(bits) as f64), offset + 4))
+            }
+            other => Err(anyhow::anyhow!("Unsupported GeoIP data type {}", other)),
+        }
+    }
+
+    /// Follow a pointer and decode its target, continuing after the pointer itself
+    fn decode_pointer(&self, control: u8, offset: usize, depth: usize) -> Result<(Value, usize)> {
+        let high = (control & 0x07) as usize;
+        let (target, next) = match (control >> 3) & 0x03 {
+            0 => ((high << 8) | self.uint(offset, 1)? as usize, offset + 1),
+            1 => (
+                ((high << 16) | self.uint(offset, 2)? as usize) + 2048,
+                offset + 2,
+            ),
+            2 => (
+                ((high << 24) | self.uint(offset, 3)? as usize) + 526336,
+                offset + 3,
+            ),
+            _ => (self.uint(offset, 4)? as usize, offset + 4),
+        };
+
+        let target = self
+            .base
+            .checked_add(target)
+            .ok_or_else(|| anyhow::anyhow!("GeoIP pointer out of range at offset {}", offset))?;
+        let (value, _) = self.decode_nested(target, depth + 1)?;
+        Ok((value, next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_string(out: &mut Vec<u8>, value: &str) {
+        out.push((2 << 5) | value.len() as u8);
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    fn encode_uint16(out: &mut Vec<u8>, value: u16) {
+        out.push((5 << 5) | 2);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn encode_map_header(out: &mut Vec<u8>, pairs: u8) {
+        out.push((7 << 5) | pairs);
+    }
+
+    /// IPv4 database with record size 24 that maps 1.1.1.0/24 to Australia
+    fn fixture_db() -> Vec<u8> {
+        let prefix = [1u8, 1, 1];
+        let node_count = 24usize;
+
+        let mut tree = Vec::new();
+        for depth in 0..node_count {
+            let bit = (prefix[depth / 8] >> (7 - depth % 8)) & 1;
+            let next = if depth + 1 == node_count {
+                // Data record at offset 0 of the data section
+                node_count + DATA_SECTION_SEPARATOR
+            } else {
+                depth + 1
+            };
+            let (left, right) = if bit == 0 {
+                (next, node_count)
+            } else {
+                (node_count, next)
+            };
+            tree.extend_from_slice(&(left as u32).to_be_bytes()[1..]);
+            tree.extend_from_slice(&(right as u32).to_be_bytes()[1..]);
+        }
+
+        let mut data = Vec::new();
+        encode_map_header(&mut data, 1);
+        encode_string(&mut data, "country");
+        encode_map_header(&mut data, 2);
+        encode_string(&mut data, "iso_code");
+        encode_string(&mut data, "AU");
+        encode_string(&mut data, "names");
+        encode_map_header(&mut data, 1);
+        encode_string(&mut data, "en");
+        encode_string(&mut data, "Australia");
+
+        let mut metadata = Vec::new();
+        encode_map_header(&mut metadata, 3);
+        encode_string(&mut metadata, "node_count");
+        encode_uint16(&mut metadata, node_count as u16);
+        encode_string(&mut metadata, "record_size");
+        encode_uint16(&mut metadata, 24);
+        encode_string(&mut metadata, "ip_version");
+        encode_uint16(&mut metadata, 4);
+
+        let mut db = tree;
+        db.extend_from_slice(&[0u8; DATA_SECTION_SEPARATOR]);
+        db.extend_from_slice(&data);
+        db.extend_from_slice(METADATA_MARKER);
+        db.extend_from_slice(&metadata);
+        db
+    }
+
+    #[test]
+    fn test_malformed_data_is_rejected() {
+        // Extended type byte that overflows the type number
+        assert!(Decoder::new(&[0x00, 0xFF], 0).decode(0).is_err());
+        // Pointer to itself
+        assert!(Decoder::new(&[0x20, 0x00], 0).decode(0).is_err());
+        // Map whose value points back at the map
+        let mut data = Vec::new();
+        encode_map_header(&mut data, 1);
+        encode_string(&mut data, "a");
+        data.extend_from_slice(&[0x20, 0x00]);
+        assert!(Decoder::new(&data, 0).decode(0).is_err());
+        // Truncated integer
+        assert!(Decoder::new(&[(5 << 5) | 2, 0x01], 0).decode(0).is_err());
+    }
+
+    #[test]
+    fn test_record_pointing_into_separator_is_ignored() {
+        let mut db = fixture_db();
+        // Point the last right record into the separator instead of the data section
+        let node_count = 24;
+        let last_right = (node_count - 1) * 6 + 3;
+        db[last_right..last_right + 3]
+            .copy_from_slice(&((node_count + 4) as u32).to_be_bytes()[1..]);
+        let db = GeoIpDb::from_bytes(db).unwrap();
+        assert_eq!(db.lookup("1.1.1.1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_lookup_country_in_fixture_db() {
+        let db = GeoIpDb::from_bytes(fixture_db()).unwrap();
+
+        let country = db.lookup_country("1.1.1.1".parse().unwrap()).unwrap();
+        assert_eq!(country.iso_code, "AU");
+        assert_eq!(country.label(), "🇦🇺 Australia");
+
+        assert_eq!(db.lookup_country("8.8.8.8".parse().unwrap()), None);
+        assert_eq!(db.lookup_country("::1".parse().unwrap()), None);
+    }
+}
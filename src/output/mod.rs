@@ -1,5 +1,13 @@
 pub mod export;
 pub mod formatter;
+pub mod geoip;
+pub mod sink;
+pub mod units;
 
-pub use export::ConfigExporter;
-pub use formatter::ResultFormatter;
+pub use export::{ConfigExporter, ExportFormat, HistoryRun, SpeedTiers, WriteOutcome};
+pub use formatter::{
+    DEFAULT_TIMESTAMP_FORMAT, DisplayTimezone, OutputFormat, ResultFormatter, RunTotals,
+};
+pub use geoip::GeoIpDb;
+pub use sink::{HistorySink, OutputSink, PrometheusSink, WebhookSink};
+pub use units::format_bytes_per_second;
@@ -0,0 +1,35 @@
+//! Human-readable units for values shown to users. JSON and CSV keep the raw numbers.
+
+const KB: f64 = 1024.0;
+const MB: f64 = KB * 1024.0;
+const GB: f64 = MB * 1024.0;
+
+/// Format a speed in bytes per second, scaled to B/s, KB/s, MB/s or GB/s
+pub fn format_bytes_per_second(speed: f64) -> String {
+    if speed >= GB {
+        format!("{:.2} GB/s", speed / GB)
+    } else if speed >= MB {
+        format!("{:.2} MB/s", speed / MB)
+    } else if speed >= KB {
+        format!("{:.1} KB/s", speed / KB)
+    } else {
+        format!("{speed:.0} B/s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes_per_second() {
+        assert_eq!(format_bytes_per_second(0.0), "0 B/s");
+        assert_eq!(format_bytes_per_second(800.0), "800 B/s");
+        assert_eq!(format_bytes_per_second(1023.0), "1023 B/s");
+        assert_eq!(format_bytes_per_second(KB), "1.0 KB/s");
+        assert_eq!(format_bytes_per_second(80.0 * KB), "80.0 KB/s");
+        assert_eq!(format_bytes_per_second(MB), "1.00 MB/s");
+        assert_eq!(format_bytes_per_second(8.0 * MB), "8.00 MB/s");
+        assert_eq!(format_bytes_per_second(2.0 * GB), "2.00 GB/s");
+    }
+}